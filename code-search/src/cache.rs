@@ -0,0 +1,566 @@
+use anyhow::{Context, Result};
+use common::models::IndexEvent;
+use common::schema::IncompatibleSchema;
+use log::debug;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const DEFAULT_TTL_SECS: u64 = 300;
+const DEFAULT_MAX_ENTRIES: u64 = 1000;
+const DEFAULT_MAX_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Bounds of the search cache, caching is off with `max_entries` at 0.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CacheConfig {
+    pub ttl: Duration,
+    pub max_entries: usize,
+    // Total size of the cached results, serialized.
+    pub max_bytes: usize,
+}
+
+impl CacheConfig {
+    /// Reads the bounds from `SEARCH_CACHE_TTL_SECS`, `SEARCH_CACHE_MAX_ENTRIES` and
+    /// `SEARCH_CACHE_MAX_BYTES`, 5 minutes, 1000 entries and 64MB if not set.
+    pub fn from_env() -> Result<Self> {
+        let var = |name: &str, default: u64| -> Result<u64> {
+            env::var(name)
+                .ok()
+                .map(|value| value.parse())
+                .transpose()
+                .with_context(|| format!("{} must be a number", name))
+                .map(|value| value.unwrap_or(default))
+        };
+
+        Ok(Self {
+            ttl: Duration::from_secs(var("SEARCH_CACHE_TTL_SECS", DEFAULT_TTL_SECS)?),
+            max_entries: var("SEARCH_CACHE_MAX_ENTRIES", DEFAULT_MAX_ENTRIES)? as usize,
+            max_bytes: var("SEARCH_CACHE_MAX_BYTES", DEFAULT_MAX_BYTES)? as usize,
+        })
+    }
+}
+
+/// What identifies a cached search: the endpoint, the repos searched with the commits their refs
+/// were indexed at, the normalized query and the other params of the request.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    endpoint: &'static str,
+    repos: Vec<(String, String)>,
+    query: String,
+    params: String,
+}
+
+/// Collapses the whitespace of a query, for the searches where it doesn't matter.
+pub fn normalize_query(query: &str) -> String {
+    query.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Counts of the cache since the service started.
+#[derive(Clone, Debug, Default, Serialize, PartialEq)]
+pub struct CacheMetrics {
+    pub hits: u64,
+    pub misses: u64,
+    pub inserts: u64,
+    /// Entries dropped to stay within the bounds, least recently used first.
+    pub evictions: u64,
+    pub expirations: u64,
+    /// Entries dropped because their repo was indexed again.
+    pub invalidations: u64,
+    pub entries: usize,
+    pub bytes: usize,
+    pub endpoints: BTreeMap<&'static str, EndpointMetrics>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, PartialEq)]
+pub struct EndpointMetrics {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+struct Entry {
+    value: Value,
+    size: usize,
+    inserted_at: Instant,
+    // The clock of the last access, the entry with the lowest is evicted first.
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct State {
+    entries: HashMap<CacheKey, Entry>,
+    // The commits the refs of a repo were indexed at, as reported by ingestion since the start.
+    commits: HashMap<String, BTreeMap<String, String>>,
+    // The number of times a repo was invalidated, a search only caches its result if the
+    // generations of its repos didn't change while it ran.
+    generations: HashMap<String, u64>,
+    // The schema checks of the repos, with when they ran: None if the repo can be searched.
+    schemas: HashMap<String, (Option<IncompatibleSchema>, Instant)>,
+    bytes: usize,
+    clock: u64,
+    metrics: CacheMetrics,
+}
+
+/// Caches the results of the searches, so that the identical searches of the agents don't go
+/// through the embedder and the indexes again. The results of a repo are dropped when ingestion
+/// reports it was indexed again, see `invalidate`.
+pub struct SearchCache {
+    config: CacheConfig,
+    state: Mutex<State>,
+}
+
+impl SearchCache {
+    pub fn new(config: CacheConfig) -> Self {
+        Self {
+            config,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// The key of a search of the repos, `query` being normalized by the caller. The params are
+    /// the fields of the request other than the query.
+    ///
+    /// The commits in the key are the ones reported by the `IndexEvent`s received since the
+    /// service started: this service can't read the records of ingestion. Until the first event
+    /// of a repo, its searches are keyed without a commit and their results are only bounded by
+    /// the TTL, so a repo indexed while no hook reached this service may be served stale results
+    /// until they expire.
+    pub fn key<T: Serialize>(
+        &self,
+        endpoint: &'static str,
+        repos: &[&str],
+        query: &str,
+        params: &T,
+    ) -> CacheKey {
+        let state = self.state.lock().unwrap();
+        let mut repos = repos
+            .iter()
+            .map(|repo| {
+                let commits = state.commits.get(*repo).map(|refs| {
+                    refs.iter()
+                        .map(|(branch, commit)| format!("{}@{}", branch, commit))
+                        .collect::<Vec<_>>()
+                        .join(",")
+                });
+                (repo.to_string(), commits.unwrap_or_default())
+            })
+            .collect::<Vec<_>>();
+        repos.sort();
+        repos.dedup();
+
+        let mut params = serde_json::to_value(params).unwrap_or_default();
+        if let Some(fields) = params.as_object_mut() {
+            fields.remove("query");
+        }
+
+        CacheKey {
+            endpoint,
+            repos,
+            query: query.to_string(),
+            params: params.to_string(),
+        }
+    }
+
+    /// The cached result of the search, or the result of `search`, cached if it succeeded.
+    pub async fn get_or_search<T, F>(&self, key: CacheKey, search: F) -> Result<Value>
+    where
+        T: Serialize,
+        F: Future<Output = Result<T>>,
+    {
+        if let Some(value) = self.get(&key) {
+            return Ok(value);
+        }
+        // a search running while one of its repos is invalidated may have read the old indexes.
+        let generation = self.state.lock().unwrap().generation(&key);
+        let value = serde_json::to_value(search.await?)?;
+        self.insert(key, value.clone(), generation);
+        Ok(value)
+    }
+
+    /// Runs the schema check of the repo, see `common::schema::check_repository`. Its verdict is
+    /// kept until the repo is invalidated or the TTL expires, so that the searches don't all go
+    /// through the vector store first. The checks that failed to run aren't kept.
+    pub async fn check_schema<F>(&self, repo_name: &str, check: F) -> Result<()>
+    where
+        F: Future<Output = Result<()>>,
+    {
+        let (checked, generation) = {
+            let state = self.state.lock().unwrap();
+            let checked = state
+                .schemas
+                .get(repo_name)
+                .filter(|(_, checked_at)| checked_at.elapsed() < self.config.ttl)
+                .map(|(incompatible, _)| incompatible.clone());
+            let generation = state.generations.get(repo_name).copied();
+            (checked, generation)
+        };
+        let incompatible = match checked {
+            Some(incompatible) => incompatible,
+            None => {
+                let incompatible = match check.await {
+                    Ok(()) => None,
+                    Err(e) => Some(e.downcast::<IncompatibleSchema>()?),
+                };
+                let mut state = self.state.lock().unwrap();
+                if state.generations.get(repo_name).copied() == generation {
+                    state.schemas.insert(
+                        repo_name.to_string(),
+                        (incompatible.clone(), Instant::now()),
+                    );
+                }
+                incompatible
+            }
+        };
+        match incompatible {
+            Some(incompatible) => Err(incompatible.into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Drops the results cached for the repo of the event and records the commit its ref was
+    /// indexed at, the searches of the repo get new keys. A deleted repo forgets its refs. The
+    /// searches of the repo still running don't cache their results. The schema of the repo is
+    /// checked again. Returns the number of results dropped.
+    pub fn invalidate(&self, event: &IndexEvent) -> usize {
+        let mut state = self.state.lock().unwrap();
+        state.schemas.remove(&event.repo_name);
+        *state
+            .generations
+            .entry(event.repo_name.clone())
+            .or_default() += 1;
+        match &event.branch {
+            Some(branch) => {
+                state
+                    .commits
+                    .entry(event.repo_name.clone())
+                    .or_default()
+                    .insert(branch.clone(), event.commit.clone().unwrap_or_default());
+            }
+            None => {
+                state.commits.remove(&event.repo_name);
+            }
+        }
+
+        let stale = state
+            .entries
+            .keys()
+            .filter(|key| key.repos.iter().any(|(repo, _)| *repo == event.repo_name))
+            .cloned()
+            .collect::<Vec<_>>();
+        for key in &stale {
+            state.remove(key);
+        }
+        state.metrics.invalidations += stale.len() as u64;
+        debug!(
+            "Dropped {} cached results of {}",
+            stale.len(),
+            event.repo_name
+        );
+        stale.len()
+    }
+
+    pub fn metrics(&self) -> CacheMetrics {
+        let state = self.state.lock().unwrap();
+        CacheMetrics {
+            entries: state.entries.len(),
+            bytes: state.bytes,
+            ..state.metrics.clone()
+        }
+    }
+
+    fn get(&self, key: &CacheKey) -> Option<Value> {
+        let mut state = self.state.lock().unwrap();
+        state.clock += 1;
+        let clock = state.clock;
+
+        let expired = match state.entries.get_mut(key) {
+            Some(entry) if entry.inserted_at.elapsed() < self.config.ttl => {
+                entry.last_used = clock;
+                false
+            }
+            Some(_) => true,
+            None => false,
+        };
+        if expired {
+            state.remove(key);
+            state.metrics.expirations += 1;
+        }
+
+        let value = state.entries.get(key).map(|entry| entry.value.clone());
+        let endpoint = state.metrics.endpoints.entry(key.endpoint).or_default();
+        if value.is_some() {
+            endpoint.hits += 1;
+            state.metrics.hits += 1;
+        } else {
+            endpoint.misses += 1;
+            state.metrics.misses += 1;
+        }
+        value
+    }
+
+    // Caches the result of a search, unless one of its repos was invalidated since `generation`.
+    fn insert(&self, key: CacheKey, value: Value, generation: u64) {
+        let size = value.to_string().len();
+        if self.config.max_entries == 0 || size > self.config.max_bytes {
+            return;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        if state.generation(&key) != generation {
+            debug!("Dropped a result of {:?} invalidated while searching", key.repos);
+            return;
+        }
+        state.clock += 1;
+        let entry = Entry {
+            value,
+            size,
+            inserted_at: Instant::now(),
+            last_used: state.clock,
+        };
+        state.remove(&key);
+        state.entries.insert(key, entry);
+        state.bytes += size;
+        state.metrics.inserts += 1;
+        state.evict(&self.config);
+    }
+}
+
+impl State {
+    // The invalidations of the repos of the key so far, it only grows.
+    fn generation(&self, key: &CacheKey) -> u64 {
+        key.repos
+            .iter()
+            .map(|(repo, _)| self.generations.get(repo).copied().unwrap_or_default())
+            .sum()
+    }
+
+    fn remove(&mut self, key: &CacheKey) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.bytes -= entry.size;
+        }
+    }
+
+    fn is_full(&self, config: &CacheConfig) -> bool {
+        self.entries.len() > config.max_entries || self.bytes > config.max_bytes
+    }
+
+    // Brings the cache back within its bounds, dropping the expired entries first and then the
+    // least recently used ones.
+    fn evict(&mut self, config: &CacheConfig) {
+        if !self.is_full(config) {
+            return;
+        }
+
+        let expired = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.inserted_at.elapsed() >= config.ttl)
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+        for key in &expired {
+            self.remove(key);
+        }
+        self.metrics.expirations += expired.len() as u64;
+
+        while self.is_full(config) {
+            let Some(key) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+            self.remove(&key);
+            self.metrics.evictions += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+    use serde_json::json;
+
+    fn new_cache(ttl: Duration, max_entries: usize, max_bytes: usize) -> SearchCache {
+        SearchCache::new(CacheConfig {
+            ttl,
+            max_entries,
+            max_bytes,
+        })
+    }
+
+    fn key(cache: &SearchCache, repo: &str, query: &str) -> CacheKey {
+        cache.key(
+            "search",
+            &[repo],
+            &normalize_query(query),
+            &json!({"query": query, "repo_name": repo, "limit": 10}),
+        )
+    }
+
+    async fn search(cache: &SearchCache, repo: &str, query: &str) -> Value {
+        let result = json!({"repo": repo, "query": query});
+        cache
+            .get_or_search(key(cache, repo, query), async { Ok(result) })
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_get_or_search() {
+        let cache = new_cache(Duration::from_secs(60), 10, 1024);
+        let first = search(&cache, "nezuko", "retry  failed jobs").await;
+        // the same search, with its whitespace normalized, is a hit.
+        let second = cache
+            .get_or_search(key(&cache, "nezuko", " retry failed\tjobs"), async {
+                Err::<Value, _>(anyhow!("not cached"))
+            })
+            .await
+            .unwrap();
+        assert_eq!(first, second);
+
+        // the failed searches aren't cached.
+        let failed = cache
+            .get_or_search(key(&cache, "nezuko", "backoff"), async {
+                Err::<Value, _>(anyhow!("search failed"))
+            })
+            .await;
+        assert!(failed.is_err());
+
+        let metrics = cache.metrics();
+        assert_eq!((metrics.hits, metrics.misses, metrics.inserts), (1, 2, 1));
+        assert_eq!(metrics.entries, 1);
+        assert_eq!(
+            metrics.endpoints["search"],
+            EndpointMetrics { hits: 1, misses: 2 }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bounds() {
+        // the entries expire right away.
+        let cache = new_cache(Duration::ZERO, 10, 1024);
+        search(&cache, "nezuko", "retry").await;
+        search(&cache, "nezuko", "retry").await;
+        let metrics = cache.metrics();
+        assert_eq!((metrics.hits, metrics.expirations), (0, 1));
+
+        let cache = new_cache(Duration::from_secs(60), 2, 1024);
+        search(&cache, "nezuko", "a").await;
+        search(&cache, "nezuko", "b").await;
+        search(&cache, "nezuko", "a").await;
+        // the least recently used search is evicted.
+        search(&cache, "nezuko", "c").await;
+        assert!(cache.get(&key(&cache, "nezuko", "a")).is_some());
+        assert!(cache.get(&key(&cache, "nezuko", "b")).is_none());
+        assert_eq!(cache.metrics().evictions, 1);
+
+        let size = json!({"repo": "nezuko", "query": "a"}).to_string().len();
+        let cache = new_cache(Duration::from_secs(60), 10, size * 2);
+        search(&cache, "nezuko", "a").await;
+        search(&cache, "nezuko", "b").await;
+        search(&cache, "nezuko", "c").await;
+        let metrics = cache.metrics();
+        assert_eq!((metrics.entries, metrics.bytes), (2, size * 2));
+
+        let cache = new_cache(Duration::from_secs(60), 0, 1024);
+        search(&cache, "nezuko", "a").await;
+        assert_eq!(cache.metrics().entries, 0);
+    }
+
+    #[tokio::test]
+    async fn test_invalidate() {
+        let cache = new_cache(Duration::from_secs(60), 10, 1024);
+        search(&cache, "nezuko", "retry").await;
+        search(&cache, "tanjiro", "retry").await;
+        let before = key(&cache, "nezuko", "retry");
+
+        let event = IndexEvent {
+            repo_name: "nezuko".to_string(),
+            branch: Some("main".to_string()),
+            commit: Some("a1b2c3".to_string()),
+        };
+        assert_eq!(cache.invalidate(&event), 1);
+        assert_ne!(key(&cache, "nezuko", "retry"), before);
+        assert!(cache.get(&key(&cache, "tanjiro", "retry")).is_some());
+
+        let metrics = cache.metrics();
+        assert_eq!((metrics.entries, metrics.invalidations), (1, 1));
+
+        // a deleted repo forgets the commits of its refs.
+        cache.invalidate(&IndexEvent {
+            branch: None,
+            commit: None,
+            ..event
+        });
+        assert_eq!(key(&cache, "nezuko", "retry"), before);
+    }
+
+    #[tokio::test]
+    async fn test_invalidate_during_search() {
+        let cache = new_cache(Duration::from_secs(60), 10, 1024);
+        let event = IndexEvent {
+            repo_name: "nezuko".to_string(),
+            branch: None,
+            commit: None,
+        };
+
+        // the repo is deleted while the search runs, its result isn't cached.
+        let stale = cache
+            .get_or_search(key(&cache, "nezuko", "retry"), async {
+                cache.invalidate(&event);
+                Ok(json!("stale"))
+            })
+            .await
+            .unwrap();
+        assert_eq!(stale, json!("stale"));
+        assert!(cache.get(&key(&cache, "nezuko", "retry")).is_none());
+        assert_eq!(cache.metrics().inserts, 0);
+
+        // the next search caches its result.
+        search(&cache, "nezuko", "retry").await;
+        assert!(cache.get(&key(&cache, "nezuko", "retry")).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_check_schema() {
+        let cache = new_cache(Duration::from_secs(60), 10, 1024);
+        let incompatible = IncompatibleSchema {
+            repo_name: "nezuko".to_string(),
+            schema_version: 1,
+        };
+        let refuse = || {
+            let incompatible = incompatible.clone();
+            async move { Err(incompatible.into()) }
+        };
+
+        // the failed checks are run again.
+        let failed = cache
+            .check_schema("nezuko", async { Err(anyhow!("vector store unreachable")) })
+            .await;
+        assert!(!failed.unwrap_err().is::<IncompatibleSchema>());
+
+        let refused = cache.check_schema("nezuko", refuse()).await.unwrap_err();
+        assert_eq!(refused.downcast_ref::<IncompatibleSchema>(), Some(&incompatible));
+        // the verdict is kept, the check doesn't run again.
+        let refused = cache
+            .check_schema("nezuko", async { Ok(()) })
+            .await
+            .unwrap_err();
+        assert_eq!(refused.downcast_ref::<IncompatibleSchema>(), Some(&incompatible));
+
+        // the repo is migrated.
+        cache.invalidate(&IndexEvent {
+            repo_name: "nezuko".to_string(),
+            branch: Some("main".to_string()),
+            commit: Some("a1b2c3".to_string()),
+        });
+        cache.check_schema("nezuko", async { Ok(()) }).await.unwrap();
+        cache.check_schema("nezuko", refuse()).await.unwrap();
+    }
+}
@@ -0,0 +1,34 @@
+use common::models::IndexEvent;
+use log::info;
+
+use std::convert::Infallible;
+use std::sync::Arc;
+use warp::{self, http::StatusCode};
+
+use crate::AppState;
+use anyhow::Result;
+
+pub async fn invalidate_cache(
+    event: IndexEvent,
+    app_state: Arc<AppState>,
+) -> Result<impl warp::Reply, Infallible> {
+    let invalidated = app_state.cache.invalidate(&event);
+    info!(
+        "{}@{} indexed at {}, dropped {} cached results",
+        event.repo_name,
+        event.branch.as_deref().unwrap_or("*"),
+        event.commit.as_deref().unwrap_or("-"),
+        invalidated
+    );
+    Ok(warp::reply::with_status(
+        warp::reply::json(&serde_json::json!({ "invalidated": invalidated })),
+        StatusCode::OK,
+    ))
+}
+
+pub async fn cache_metrics(app_state: Arc<AppState>) -> Result<impl warp::Reply, Infallible> {
+    Ok(warp::reply::with_status(
+        warp::reply::json(&app_state.cache.metrics()),
+        StatusCode::OK,
+    ))
+}
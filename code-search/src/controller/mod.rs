@@ -1,5 +1,38 @@
 pub mod symbol;
+pub mod search;
 pub mod span;
 pub mod parentscope;
 pub mod navigator;
+pub mod query;
+pub mod cache;
 
+
+use common::schema::{self, IncompatibleSchema};
+use warp::http::StatusCode;
+use warp::reply::{Json, WithStatus};
+
+use crate::AppState;
+
+// Refuses the searches of the repos whose indexes this service can't read, legacy ones included,
+// the other repos are still served. See `common::schema`. The searches are unavailable while the
+// vector store can't tell the schema of a repo. The verdicts are kept by the cache.
+async fn check_schemas(app_state: &AppState, repos: &[&str]) -> Option<WithStatus<Json>> {
+    let vector_store = app_state.db_connection.semantic.vector_store.as_ref();
+    for repo_name in repos {
+        let check = schema::check_repository(vector_store, repo_name);
+        if let Err(e) = app_state.cache.check_schema(repo_name, check).await {
+            let status = if e.is::<IncompatibleSchema>() {
+                log::error!("Refusing to search {}: {:#}", repo_name, e);
+                StatusCode::CONFLICT
+            } else {
+                log::error!("Failed to check the schema of {}: {:#}", repo_name, e);
+                StatusCode::SERVICE_UNAVAILABLE
+            };
+            return Some(warp::reply::with_status(
+                warp::reply::json(&format!("Error: {:#}", e)),
+                status,
+            ));
+        }
+    }
+    None
+}
@@ -12,6 +12,7 @@ use common::{
     TokenInfoRequest,
 };
 use compact_str::CompactString;
+use futures::TryStreamExt;
 use reqwest::StatusCode;
 use smallvec::SmallVec;
 
@@ -19,12 +20,25 @@ use crate::{
     code_navigation::{CodeNavigationContext, FileSymbols, Occurrence, OccurrenceKind, Token},
     search::{
         code_search::get_file_content,
-        quikwit::{get_all_files_for_repo, search_quickwit},
+        quikwit::{branch_clause, search_quickwit, stream_repo_files},
     },
     snippet::Snipper,
     AppState,
 };
 
+// Fields of the documents read by the navigation, the symbol names aren't needed.
+const NAVIGATION_FIELDS: [&str; 7] = [
+    "repo_name",
+    "repo_ref",
+    "relative_path",
+    "lang",
+    "line_end_indices",
+    "content",
+    "symbol_locations",
+];
+// Number of documents returned by the search based navigation.
+const SEARCH_NAV_MAX_HITS: usize = 100;
+
 pub async fn handle_token_info_fetcher_wrapper(
     request: TokenInfoRequest,
     app_state: Arc<AppState>,
@@ -53,6 +67,7 @@ async fn handle_token_info_fetcher(
     let source_document = match get_file_content(
         &request.relative_path.clone(),
         &request.repo_ref.clone(),
+        request.branch.as_deref(),
         app_state.clone(),
     )
     .await
@@ -64,18 +79,38 @@ async fn handle_token_info_fetcher(
         }
     };
 
-    let all_docs = match get_all_files_for_repo(
+    // the repo wide definitions and references of a token are in the files containing it, only
+    // those are read rather than every file of the repo.
+    let token = source_document
+        .content
+        .get(request.start..request.end)
+        .ok_or_else(|| anyhow!("Invalid token range {}..{}", request.start, request.end))?;
+    let mut all_docs = match stream_repo_files(
         &generate_quikwit_index_name(&request.repo_ref.clone()),
         &request.repo_ref.clone(),
+        request.branch.as_deref(),
+        Some(&content_clause(token)),
+        &NAVIGATION_FIELDS,
         app_state.clone(),
     )
+    .try_collect::<Vec<_>>()
     .await
     {
         Ok(docs) => docs,
         Err(e) => {
-            return Err(anyhow!("Failed to fetch all files: {}", e));
+            return Err(anyhow!(
+                "Failed to fetch the files containing {}: {}",
+                token,
+                e
+            ));
         }
     };
+    if !all_docs
+        .iter()
+        .any(|doc| doc.relative_path == source_document.relative_path)
+    {
+        all_docs.push(source_document.clone());
+    }
 
     match get_token_info(
         request.clone(),
@@ -169,7 +204,14 @@ async fn search_nav(
         branch.map(|b| vec![b]),
         associated_langs.to_vec(),
     );
-    let results = match search_quickwit(&repo_ref, &query, app_state).await {
+    let results = match search_quickwit(
+        &generate_quikwit_index_name(&repo_ref),
+        &query,
+        SEARCH_NAV_MAX_HITS,
+        app_state,
+    )
+    .await
+    {
         Ok(results) => results,
         Err(e) => {
             return Err(anyhow!("Failed to search quickwit: {}", e));
@@ -267,12 +309,10 @@ pub fn build_quickwit_query(
         .collect::<Vec<_>>()
         .join(" OR ");
 
-    #[allow(unused)] // TODO: Remove this once branch queries are supported
     let branch_queries = match branches {
         Some(brs) => brs
             .iter()
-            .flat_map(|branch| trigrams(branch))
-            .map(|trigram| format!("branches:{}", trigram))
+            .map(|branch| branch_clause(branch))
             .collect::<Vec<_>>()
             .join(" OR "),
         None => String::new(),
@@ -284,20 +324,25 @@ pub fn build_quickwit_query(
         .collect::<Vec<_>>()
         .join(" OR ");
 
-    let query_parts = vec![
+    let mut query_parts = vec![
         repo_ref_query,
         format!("({})", content_query),
         format!("({})", lang_queries),
     ];
 
-    // TODO: Uncomment this when we start saving branch information in Quickwit
-    // if !branch_queries.is_empty() {
-    //     query_parts.insert(2, format!("({})", branch_queries));
-    // }
+    if !branch_queries.is_empty() {
+        query_parts.insert(2, format!("({})", branch_queries));
+    }
 
     format!("({})", query_parts.join(" AND "))
 }
 
+// Matches the files containing the token, identifiers are split by the tokenizer and matched
+// as phrases.
+fn content_clause(token: &str) -> String {
+    format!("content:\"{}\"", token.replace('"', "\\\""))
+}
+
 pub fn trigrams(s: &str) -> impl Iterator<Item = CompactString> {
     let mut chars = s.chars().collect::<SmallVec<[char; 6]>>();
 
@@ -325,7 +370,7 @@ mod tests {
 
         let query = build_quickwit_query(repo_ref, hovered_text, branches, associated_langs);
 
-        let expected_query = "(repo_ref:aider AND (content:scr OR content:cru OR content:rub OR content:ub_ OR content:b_s OR content:_se OR content:sen OR content:ens OR content:nsi OR content:sit OR content:iti OR content:tiv OR content:ive OR content:ve_ OR content:e_i OR content:_in OR content:inf OR content:nfo) AND (lang:Python))";
+        let expected_query = r#"(repo_ref:aider AND (content:scr OR content:cru OR content:rub OR content:ub_ OR content:b_s OR content:_se OR content:sen OR content:ens OR content:nsi OR content:sit OR content:iti OR content:tiv OR content:ive OR content:ve_ OR content:e_i OR content:_in OR content:inf OR content:nfo) AND (branches:"main") AND (lang:Python))"#;
         assert_eq!(
             query, expected_query,
             "The generated Quickwit query does not match the expected output."
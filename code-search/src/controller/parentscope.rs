@@ -20,7 +20,8 @@ pub async fn parent_scope_search(
         path, params.start_line, params.end_line
     );
     // Attempt to retrieve the file content asynchronously based on the provided path and repository name.
-    let source_document = get_file_content(&path, &repo_name, app_state).await;
+    let source_document =
+        get_file_content(&path, &repo_name, params.branch.as_deref(), app_state).await;
 
     match source_document {
         Ok(content) => {
@@ -0,0 +1,56 @@
+use log::error;
+
+use std::convert::Infallible;
+use std::sync::Arc;
+use warp::{self, http::StatusCode};
+
+use super::check_schemas;
+use crate::models::QueryRequest;
+use crate::parser::query::parse;
+use crate::search::query::QueryPlan;
+use crate::AppState;
+use anyhow::Result;
+
+pub async fn query_search(
+    query_request: QueryRequest,
+    app_state: Arc<AppState>,
+) -> Result<impl warp::Reply, Infallible> {
+    let parsed = parse(&query_request.query)
+        .and_then(|query| Ok((QueryPlan::new(&query, &query_request)?, query)));
+    let (plan, query) = match parsed {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            return Ok(warp::reply::with_status(
+                warp::reply::json(&format!("Error: {}", e)),
+                StatusCode::BAD_REQUEST,
+            ))
+        }
+    };
+
+    let repos = plan.repos().iter().map(String::as_str).collect::<Vec<_>>();
+    if let Some(refusal) = check_schemas(&app_state, &repos).await {
+        return Ok(refusal);
+    }
+
+    // the parsed query is the normalized one, e.g. `a AND (b)` and `a b` are the same query.
+    let key = app_state
+        .cache
+        .key("query", &repos, &format!("{:?}", query), &query_request);
+    match app_state
+        .cache
+        .get_or_search(key, plan.run(app_state.clone()))
+        .await
+    {
+        Ok(files) => Ok(warp::reply::with_status(
+            warp::reply::json(&files),
+            StatusCode::OK,
+        )),
+        Err(e) => {
+            error!("Query {} failed: {:?}", query_request.query, e);
+            Ok(warp::reply::with_status(
+                warp::reply::json(&format!("Error: {}", e)),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ))
+        }
+    }
+}
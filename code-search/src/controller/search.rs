@@ -0,0 +1,60 @@
+use log::error;
+
+use std::convert::Infallible;
+use std::sync::Arc;
+use warp::{self, http::StatusCode};
+
+use super::check_schemas;
+use crate::cache::normalize_query;
+use crate::models::HybridSearchRequest;
+use crate::search::hybrid;
+use crate::AppState;
+use anyhow::Result;
+
+pub async fn hybrid_search(
+    search_request: HybridSearchRequest,
+    app_state: Arc<AppState>,
+) -> Result<impl warp::Reply, Infallible> {
+    if search_request.query.trim().is_empty() {
+        return Ok(warp::reply::with_status(
+            warp::reply::json(&"The query is empty"),
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+    // the sources contribute `weight / (rrf_k + rank)`, a constant that isn't positive breaks
+    // the fusion.
+    if let Some(rrf_k) = search_request.rrf_k.filter(|k| !(k.is_finite() && *k > 0.0)) {
+        return Ok(warp::reply::with_status(
+            warp::reply::json(&format!("rrf_k must be positive, got {}", rrf_k)),
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+
+    if let Some(refusal) = check_schemas(&app_state, &[&search_request.repo_name]).await {
+        return Ok(refusal);
+    }
+
+    let key = app_state.cache.key(
+        "search",
+        &[&search_request.repo_name],
+        &normalize_query(&search_request.query),
+        &search_request,
+    );
+    let search = hybrid::hybrid_search(&search_request, app_state.clone());
+    match app_state.cache.get_or_search(key, search).await {
+        Ok(hits) => Ok(warp::reply::with_status(
+            warp::reply::json(&hits),
+            StatusCode::OK,
+        )),
+        Err(e) => {
+            error!(
+                "Hybrid search of {} failed: {:?}",
+                search_request.repo_name, e
+            );
+            Ok(warp::reply::with_status(
+                warp::reply::json(&format!("Error: {}", e)),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ))
+        }
+    }
+}
@@ -23,7 +23,8 @@ pub async fn span_search(
     let repo_name = params.repo.clone();
 
     // Attempt to retrieve the file content asynchronously based on the provided path and repository name.
-    let source_document = get_file_content(&path, &repo_name, app_state).await;
+    let source_document =
+        get_file_content(&path, &repo_name, params.branch.as_deref(), app_state).await;
 
     match source_document {
         Ok(content) => {
@@ -1,77 +1,73 @@
-use anyhow::Error;
 use common::hasher::generate_qdrant_index_name;
-use log::{debug, error, info};
-use reqwest::header::HeaderValue;
-use reqwest::Client;
+use log::error;
 
 use std::convert::Infallible;
 use std::sync::Arc;
 use warp::{self, http::StatusCode};
 
+use super::check_schemas;
+use crate::cache::normalize_query;
 use crate::models::SymbolSearchRequest;
 use crate::search::code_search::code_search;
 use crate::AppState;
 use anyhow::Result;
-use reqwest;
-use serde::{Deserialize, Serialize};
-
-#[derive(Debug, Serialize, Deserialize)]
-struct CollectionStatus {
-    status: String,
-    optimizer_status: String,
-    vectors_count: u64,
-    indexed_vectors_count: u64,
-    points_count: u64,
-    segments_count: u64,
-}
 
 pub async fn symbol_search(
     search_request: SymbolSearchRequest,
     app_state: Arc<AppState>,
 ) -> Result<impl warp::Reply, Infallible> {
-    let config = app_state.configuration.clone();
-    // Qdrant key is only set while using Qdrant Cloud, otherwise we'll be using the local Qdrant instance.
-    // access the qdrant key from the app_state
-
-    let environment = config.environment.clone();
-    let qdrant_key = config.qdrant_api_key.clone();
-
-    // namespace is set to repo name from the search request if the qdrant key is not set
+    // namespace is set to repo name from the search request
     let namespace = generate_qdrant_index_name(&search_request.repo_name);
 
-    // check if the collection is available, use app state to access the configuration
-
-    let is_collection_available = get_collection_status(
-        config.semantic_db_url,
-        &namespace, // &search_request.repo_name,
-        qdrant_key,
-    )
-    .await;
+    // check if the collection is available before searching it
+    let is_collection_available = app_state
+        .db_connection
+        .semantic
+        .vector_store
+        .collection_dimension(&namespace)
+        .await;
+
+    match is_collection_available {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            error!("Collection {} doesn't exist", namespace);
+            return Ok(warp::reply::with_status(
+                warp::reply::json(&format!("Collection {} doesn't exist", namespace)),
+                warp::http::StatusCode::NOT_FOUND,
+            ));
+        }
+        // if there is error finding the collection status return the API error
+        Err(e) => {
+            error!("Collection doesn't exist");
+            let response = format!("Error validating if the collection exists: {}", e);
+            return Ok(warp::reply::with_status(
+                warp::reply::json(&response),
+                warp::http::StatusCode::NOT_FOUND,
+            ));
+        }
+    }
 
-    // if there is error finding the collection status return the API error
-    if is_collection_available.is_err() {
-        error!("Collection doesn't exist");
-        let response = format!(
-            "Error validating if the collection exists: {}",
-            is_collection_available.err().unwrap()
-        );
-        return Ok(warp::reply::with_status(
-            warp::reply::json(&response),
-            warp::http::StatusCode::NOT_FOUND,
-        ));
+    if let Some(refusal) = check_schemas(&app_state, &[&search_request.repo_name]).await {
+        return Ok(refusal);
     }
 
     let app_state_clone = Arc::clone(&app_state);
     let db = &app_state_clone.db_connection;
 
-    match code_search(
+    let key = app_state_clone.cache.key(
+        "symbols",
+        &[&search_request.repo_name],
+        &normalize_query(&search_request.query),
+        &search_request,
+    );
+    let search = code_search(
         &search_request.query,
         &search_request.repo_name,
+        search_request.branch.as_deref(),
         &db,
         app_state,
-    )
-    .await
-    {
+    );
+    match app_state_clone.cache.get_or_search(key, search).await {
         Ok(chunks) => Ok(warp::reply::with_status(
             warp::reply::json(&chunks),
             StatusCode::OK,
@@ -82,50 +78,3 @@ pub async fn symbol_search(
         )),
     }
 }
-
-// check if qdrant collection is available
-async fn get_collection_status(
-    mut base_url: String,
-    collection_name: &String,
-    apikey: Option<String>,
-) -> Result<bool, Error> {
-    // Check if the base URL contains the port 6334 and replace it with 6333
-    if base_url.contains(":6334") {
-        base_url = base_url.replace(":6334", ":6333");
-    }
-
-    // The collection name seems to be hardcoded, so you might not need the `field` parameter.
-    let url = format!("{}/collections/{}", base_url, collection_name);
-    let api_key = apikey;
-
-    info!("Checking status for collection {}", collection_name);
-    info!("Url : {}", url);
-
-    let client = Client::new().get(&url);
-
-    let client = if let Some(key) = api_key {
-        match HeaderValue::from_str(&key) {
-            Ok(header_value) => client.header("Api-Key", header_value),
-            Err(e) => {
-                // log and return the error
-                error!("Error occurred while checking collection status: {}", e);
-                return Err(e.into());
-            }
-        }
-    } else {
-        client
-    };
-
-    let response = client.send().await;
-
-    match response {
-        Ok(resp) => return Ok(resp.status().is_success()),
-        Err(e) => {
-            error!(
-                "Error occurred during call to check vector db status: {}",
-                e
-            );
-            return Err(e.into());
-        }
-    }
-}
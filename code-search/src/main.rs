@@ -1,10 +1,17 @@
 use anyhow::Context;
+use cache::{CacheConfig, SearchCache};
+use common::embedding::EmbedderConfig;
+use common::lexical::{LexicalIndex, LexicalIndexConfig};
+use common::schema;
+use common::vector::VectorStoreConfig;
 use dotenv::dotenv;
 use log::{error, info};
+use search::ranking::RankingConfig;
 use std::env;
 use std::sync::Arc;
 use warp;
 
+mod cache;
 mod code_navigation;
 mod controller;
 mod db;
@@ -28,44 +35,74 @@ lazy_static! {
 pub struct Configuration {
     environment: String,
     symbol_collection_name: String,
-    semantic_db_url: String,
-    tokenizer_path: String,
-    model_path: String,
-    qdrant_api_key: Option<String>,
+    // the embedding backend has to be the one the repos were indexed with.
+    embedder: EmbedderConfig,
+    // likewise for the vector store backend.
+    vector_store: VectorStoreConfig,
     quikwit_db_url: String,
+    // the lexical index backend has to be the one the repos were indexed with.
+    lexical_index: LexicalIndexConfig,
+    ranking: RankingConfig,
+    cache: CacheConfig,
 }
 
 struct AppState {
     configuration: Configuration,
     db_connection: db::DbConnect, // Assuming DbConnection is your database connection type
+    lexical_index: Arc<dyn LexicalIndex>,
+    // Results of the searches, dropped when ingestion reports a repo was indexed again.
+    cache: SearchCache,
 }
 
 async fn init_state() -> Result<AppState, anyhow::Error> {
     // load using dotenv
     dotenv().ok();
+    let quikwit_db_url = env::var("QUICKWIT_DB_URL").context("QUICKWIT_DB_URL must be set")?;
     let configuration = Configuration {
         environment: env::var("ENVIRONMENT").context("ENVRINOMENT must be set")?,
         symbol_collection_name: env::var("SYMBOL_COLLECTION_NAME")
             .context("SYMBOL_COLLECTION_NAME must be set")?,
-        semantic_db_url: env::var("SEMANTIC_DB_URL").context("SEMANTIC_DB_URL must be set")?,
-        tokenizer_path: env::var("TOKENIZER_PATH").context("TOKENIZER_PATH must be set")?,
-        model_path: env::var("MODEL_PATH").context("MODEL_PATH must be set")?,
-        quikwit_db_url: env::var("QUICKWIT_DB_URL").context("QUICKWIT_DB_URL must be set")?,
-        qdrant_api_key: env::var("QDRANT_CLOUD_API_KEY").ok(),
+        embedder: EmbedderConfig::from_env()?,
+        vector_store: VectorStoreConfig::from_env(
+            &env::var("SEMANTIC_DB_URL").context("SEMANTIC_DB_URL must be set")?,
+            env::var("QDRANT_CLOUD_API_KEY").ok(),
+        )?,
+        lexical_index: LexicalIndexConfig::from_env(&quikwit_db_url)?,
+        ranking: RankingConfig::from_env()?,
+        cache: CacheConfig::from_env()?,
+        quikwit_db_url,
     };
 
     info!("Configuration: {:#?}", configuration);
     let db_connection = db::init_db(configuration.clone()).await?;
+    // report the indexes this build can't read, their searches are refused, see `common::schema`.
+    schema::check_compatibility(db_connection.semantic.vector_store.as_ref())
+        .await
+        .context("Index schema check failed")?;
+    let lexical_index = configuration.lexical_index.build()?;
+    let cache = SearchCache::new(configuration.cache.clone());
 
     Ok(AppState {
         configuration,
         db_connection,
+        lexical_index,
+        cache,
     })
 }
 
 #[tokio::main]
 async fn main() {
     env_logger::init();
+    // `code-search evaluate-ranking` scores a ranking config offline, without the indexes.
+    let args = env::args().collect::<Vec<_>>();
+    if args.get(1).map(String::as_str) == Some("evaluate-ranking") {
+        if let Err(err) = search::evaluation::run(&args[2..]) {
+            error!("Failed to evaluate the ranking: {:#}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     // initialize the env configurations and database connection.
     let app_state = init_state().await;
 
@@ -73,7 +110,7 @@ async fn main() {
     let app_state = match app_state {
         Ok(app_state) => Arc::new(app_state),
         Err(err) => {
-            error!("Failed to initialize the app state: {}", err);
+            error!("Failed to initialize the app state: {:#}", err);
             //println!("Failed to initialize the app state: {}", err);
             std::process::exit(1);
         }
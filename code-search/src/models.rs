@@ -4,6 +4,66 @@ use serde::{Deserialize, Serialize};
 pub struct SymbolSearchRequest {
     pub query: String,
     pub repo_name: String,
+    /// An optional branch or tag to search. Every indexed branch is searched if not provided.
+    #[serde(default)]
+    pub branch: Option<String>,
+}
+
+/// A hybrid search of a repo, fusing its lexical, chunk and symbol search results.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct HybridSearchRequest {
+    pub query: String,
+    pub repo_name: String,
+    /// An optional branch or tag to search. Every indexed branch is searched if not provided.
+    #[serde(default)]
+    pub branch: Option<String>,
+    /// The number of hits returned, 10 if not provided.
+    #[serde(default)]
+    pub limit: Option<usize>,
+    /// The weight of each source in the fusion. The sources weigh the same if not provided.
+    #[serde(default)]
+    pub weights: FusionWeights,
+    /// The reciprocal rank fusion constant, 60 if not provided. Larger values flatten the
+    /// differences between ranks.
+    #[serde(default)]
+    pub rrf_k: Option<f32>,
+}
+
+/// A search with the query language, see `parser/grammar.pest` for the syntax.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct QueryRequest {
+    pub query: String,
+    /// The repository searched if the query has no `repo:` term.
+    #[serde(default)]
+    pub repo_name: Option<String>,
+    /// The number of files returned, 20 if not provided.
+    #[serde(default)]
+    pub limit: Option<usize>,
+    /// Whether the literals and regexes are matched case sensitively, false if not provided.
+    #[serde(default)]
+    pub case_sensitive: bool,
+    /// The lines of context around the highlighted lines, 1 if not provided.
+    #[serde(default)]
+    pub context: Option<usize>,
+}
+
+/// The weights of the hybrid search sources, a source weighing 0 or less isn't searched.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct FusionWeights {
+    pub lexical: f32,
+    pub chunks: f32,
+    pub symbols: f32,
+}
+
+impl Default for FusionWeights {
+    fn default() -> Self {
+        Self {
+            lexical: 1.0,
+            chunks: 1.0,
+            symbols: 1.0,
+        }
+    }
 }
 
 /// Represents a request to fetch the parent scope of a specified code range within a file.
@@ -13,6 +73,9 @@ pub struct ParentScopeRequest {
     pub repo: String,
     /// The file path within the repository.
     pub file: String,
+    /// An optional branch or tag the file is read from. Any indexed branch is used if not provided.
+    #[serde(default)]
+    pub branch: Option<String>,
     /// The starting line number of the code range.
     pub start_line: usize,
     /// The ending line number of the code range.
@@ -1 +1,2 @@
 pub mod literal;
+pub mod query;
@@ -0,0 +1,217 @@
+use anyhow::{anyhow, Result};
+use pest::iterators::Pair;
+use pest::Parser;
+use regex::Regex;
+
+use crate::parser::literal::Literal;
+
+#[derive(pest_derive::Parser)]
+#[grammar = "parser/grammar.pest"]
+struct QueryParser;
+
+/// A parsed search query, see `grammar.pest` for the syntax.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Query {
+    Target(Target),
+    Not(Box<Query>),
+    And(Vec<Query>),
+    Or(Vec<Query>),
+}
+
+/// What a term of a query matches. Plain repo and branch names have to match whole, the other
+/// literals match anywhere in the text.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Target {
+    Content(Literal<'static>),
+    Repo(Literal<'static>),
+    Path(Literal<'static>),
+    Lang(String),
+    Symbol(Literal<'static>),
+    Branch(Literal<'static>),
+}
+
+/// Parses a query, regex literals are checked to be valid regexes.
+pub fn parse(query: &str) -> Result<Query> {
+    let union = QueryParser::parse(Rule::query, query)
+        .map_err(|e| anyhow!("Invalid query: {}", e))?
+        .next()
+        .ok_or_else(|| anyhow!("Empty query"))?;
+    parse_union(union)
+}
+
+fn parse_union(pair: Pair<Rule>) -> Result<Query> {
+    let mut parts = pair
+        .into_inner()
+        .filter(|pair| pair.as_rule() == Rule::intersection)
+        .map(parse_intersection)
+        .collect::<Result<Vec<_>>>()?;
+    Ok(if parts.len() == 1 {
+        parts.pop().unwrap()
+    } else {
+        Query::Or(parts)
+    })
+}
+
+fn parse_intersection(pair: Pair<Rule>) -> Result<Query> {
+    let mut parts = pair
+        .into_inner()
+        .filter(|pair| pair.as_rule() != Rule::and)
+        .map(parse_element)
+        .collect::<Result<Vec<_>>>()?;
+    Ok(if parts.len() == 1 {
+        parts.pop().unwrap()
+    } else {
+        Query::And(parts)
+    })
+}
+
+fn parse_element(pair: Pair<Rule>) -> Result<Query> {
+    let rule = pair.as_rule();
+    let target = match rule {
+        Rule::negation => {
+            return Ok(Query::Not(Box::new(parse_element(inner(pair)?)?)));
+        }
+        Rule::group => return parse_union(inner(pair)?),
+        Rule::lang => Target::Lang(inner(pair)?.as_str().to_string()),
+        Rule::repo => Target::Repo(parse_literal(inner(pair)?)?),
+        Rule::path => Target::Path(parse_literal(inner(pair)?)?),
+        Rule::symbol => Target::Symbol(parse_literal(inner(pair)?)?),
+        Rule::branch => Target::Branch(parse_literal(inner(pair)?)?),
+        Rule::content => Target::Content(parse_literal(inner(pair)?)?),
+        Rule::quoted_literal | Rule::regex_literal | Rule::unquoted_literal => {
+            Target::Content(parse_literal(pair)?)
+        }
+        rule => return Err(anyhow!("Unexpected {:?} in the query", rule)),
+    };
+    Ok(Query::Target(target))
+}
+
+fn inner(pair: Pair<Rule>) -> Result<Pair<Rule>> {
+    let rule = pair.as_rule();
+    pair.into_inner()
+        .next()
+        .ok_or_else(|| anyhow!("Empty {:?} in the query", rule))
+}
+
+fn parse_literal(pair: Pair<Rule>) -> Result<Literal<'static>> {
+    Ok(match pair.as_rule() {
+        Rule::regex_literal => {
+            let regex = pair.as_str().replace("\\/", "/");
+            Regex::new(&regex).map_err(|e| anyhow!("Invalid regex /{}/: {}", regex, e))?;
+            Literal::Regex(regex.into())
+        }
+        Rule::quoted_literal => Literal::Plain(unescape(pair.as_str()).into()),
+        _ => Literal::Plain(pair.as_str().to_string().into()),
+    })
+}
+
+// Drops the backslashes escaping the characters of a quoted literal.
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unescaped.extend(chars.next()),
+            c => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+impl Query {
+    /// Calls `visit` with the targets of the query and whether they are negated.
+    pub fn visit<'a>(&'a self, negated: bool, visit: &mut impl FnMut(&'a Target, bool)) {
+        match self {
+            Query::Target(target) => visit(target, negated),
+            Query::Not(query) => query.visit(!negated, visit),
+            Query::And(parts) | Query::Or(parts) => {
+                for part in parts {
+                    part.visit(negated, visit);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plain(text: &str) -> Literal<'static> {
+        Literal::Plain(text.to_string().into())
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            parse("run_agent").unwrap(),
+            Query::Target(Target::Content(plain("run_agent")))
+        );
+
+        let query =
+            parse(r#"repo:nezuko lang:rust (run_agent OR /spawn_\w+\/x/) -path:tests "a \"b\"""#)
+                .unwrap();
+        assert_eq!(
+            query,
+            Query::And(vec![
+                Query::Target(Target::Repo(plain("nezuko"))),
+                Query::Target(Target::Lang("rust".to_string())),
+                Query::Or(vec![
+                    Query::Target(Target::Content(plain("run_agent"))),
+                    Query::Target(Target::Content(Literal::Regex(
+                        r"spawn_\w+/x".to_string().into()
+                    ))),
+                ]),
+                Query::Not(Box::new(Query::Target(Target::Path(plain("tests"))))),
+                Query::Target(Target::Content(plain(r#"a "b""#))),
+            ])
+        );
+
+        // AND binds tighter than OR, and is implied between terms.
+        assert_eq!(
+            parse("a AND b or c d").unwrap(),
+            Query::Or(vec![
+                Query::And(vec![
+                    Query::Target(Target::Content(plain("a"))),
+                    Query::Target(Target::Content(plain("b"))),
+                ]),
+                Query::And(vec![
+                    Query::Target(Target::Content(plain("c"))),
+                    Query::Target(Target::Content(plain("d"))),
+                ]),
+            ])
+        );
+        assert_eq!(
+            parse("symbol:Agent branch:\"feature/x\" orange").unwrap(),
+            Query::And(vec![
+                Query::Target(Target::Symbol(plain("Agent"))),
+                Query::Target(Target::Branch(plain("feature/x"))),
+                Query::Target(Target::Content(plain("orange"))),
+            ])
+        );
+
+        assert!(parse("").is_err());
+        assert!(parse("a OR").is_err());
+        assert!(parse("(a b").is_err());
+        assert!(parse("/spawn(/").is_err());
+    }
+
+    #[test]
+    fn test_visit() {
+        let query = parse("repo:a -(repo:b OR -repo:c)").unwrap();
+        let mut repos = Vec::new();
+        query.visit(false, &mut |target, negated| {
+            if let Target::Repo(literal) = target {
+                repos.push((literal.clone().unwrap().to_string(), negated));
+            }
+        });
+        assert_eq!(
+            repos,
+            vec![
+                ("a".to_string(), false),
+                ("b".to_string(), true),
+                ("c".to_string(), false)
+            ]
+        );
+    }
+}
@@ -1,21 +1,25 @@
 extern crate common;
-use common::models::CodeSpanRequest;
+use common::models::{CodeSpanRequest, IndexEvent};
 use common::TokenInfoRequest;
 
 use std::convert::Infallible;
 use std::sync::Arc;
 use warp::{self, Filter};
 
-use crate::controller::{navigator, parentscope, span, symbol};
+use crate::controller::{cache, navigator, parentscope, query, search, span, symbol};
 use crate::db::DbConnect;
 // use crate::graph::symbol_ops;
-use crate::models::{ParentScopeRequest, SymbolSearchRequest};
+use crate::models::{HybridSearchRequest, ParentScopeRequest, QueryRequest, SymbolSearchRequest};
 use crate::AppState;
 
 pub fn search_routes(
     app_state: Arc<AppState>,
 ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
     symbol_search(app_state.clone())
+        .or(hybrid_search(app_state.clone()))
+        .or(query_search(app_state.clone()))
+        .or(cache_invalidate(app_state.clone()))
+        .or(cache_metrics(app_state.clone()))
         .or(span_code_chunk_retrieve(app_state.clone()))
         .or(parent_scope_retrieve(app_state.clone()))
         .or(token_info_fetcher(app_state.clone()))
@@ -35,6 +39,120 @@ fn symbol_search(
         .and_then(symbol::symbol_search)
 }
 
+/// POST /search
+///
+/// Searches a repo with BM25 over the lexical index, vector search over its code chunks and
+/// vector search over its symbols, and fuses the three rankings with reciprocal rank fusion.
+///
+/// # Request Body
+/// - `query`: The search query. This field is required.
+/// - `repo_name`: The repository to search. This field is required.
+/// - `branch`: An optional branch or tag to search.
+/// - `limit`: An optional number of hits, 10 by default.
+/// - `weights`: Optional `lexical`, `chunks` and `symbols` weights, 1.0 each by default. A
+///   source weighing 0 isn't searched.
+/// - `rrf_k`: An optional fusion constant, 60 by default. It has to be positive.
+///
+/// # Responses
+/// The hits best first: the `path`, `snippet`, `start` and `end` of a code chunk, its fused
+/// `score` and, under `sources`, the rank, score and contribution of every source that found it.
+///
+/// # Example Request
+/// ```sh
+/// curl -X POST "http://localhost:3003/search" \
+///      -H "Content-Type: application/json" \
+///      -d '{"query":"retry failed jobs", "repo_name":"example-repo", "weights":{"lexical":0.5}}'
+/// ```
+fn hybrid_search(
+    app_state: Arc<AppState>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path("search")
+        .and(warp::post())
+        .and(
+            warp::body::content_length_limit(1024 * 16)
+                .and(warp::body::json::<HybridSearchRequest>()),
+        )
+        .and(warp::any().map(move || app_state.clone()))
+        .and_then(search::hybrid_search)
+}
+
+/// POST /query
+///
+/// Searches repos with the query language: terms are matched against the content and can be
+/// narrowed with `repo:`, `path:`, `lang:`, `symbol:` and `branch:` filters. A literal is a
+/// word, a quoted phrase or a `/regex/`, a term is negated with `-`, terms are implicitly ANDed
+/// and can be grouped with parentheses and `OR`.
+///
+/// # Request Body
+/// - `query`: The query. This field is required.
+/// - `repo_name`: The repository searched if the query has no `repo:` term.
+/// - `limit`: An optional number of files, 20 by default.
+/// - `case_sensitive`: Whether the matching is case sensitive, false by default.
+/// - `context`: An optional number of lines around the highlighted lines, 1 by default.
+///
+/// # Responses
+/// The matching files with the snippets highlighting the content and symbol terms of the query.
+/// An invalid query is rejected with a 400.
+///
+/// # Example Request
+/// ```sh
+/// curl -X POST "http://localhost:3003/query" \
+///      -H "Content-Type: application/json" \
+///      -d '{"query":"repo:example-repo lang:rust (/retry_\\w+/ OR backoff) -path:tests"}'
+/// ```
+fn query_search(
+    app_state: Arc<AppState>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path("query")
+        .and(warp::post())
+        .and(
+            warp::body::content_length_limit(1024 * 16)
+                .and(warp::body::json::<QueryRequest>()),
+        )
+        .and(warp::any().map(move || app_state.clone()))
+        .and_then(query::query_search)
+}
+
+/// POST /cache/invalidate
+///
+/// Drops the search results cached for a repo, called by ingestion after it indexed a ref of
+/// the repo or deleted it. The searches of the repo are keyed by the commits its refs were
+/// indexed at from then on.
+///
+/// # Request Body
+/// - `repo_name`: The repository indexed. This field is required.
+/// - `branch`: The indexed ref, null if the repo was deleted.
+/// - `commit`: The commit the ref was indexed at, null for the sources without commits.
+///
+/// # Responses
+/// The number of cached results dropped, under `invalidated`.
+fn cache_invalidate(
+    app_state: Arc<AppState>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("cache" / "invalidate")
+        .and(warp::post())
+        .and(
+            warp::body::content_length_limit(1024 * 16)
+                .and(warp::body::json::<IndexEvent>()),
+        )
+        .and(warp::any().map(move || app_state.clone()))
+        .and_then(cache::invalidate_cache)
+}
+
+/// GET /cache/metrics
+///
+/// The hits, misses, inserts, evictions, expirations and invalidations of the search cache
+/// since the start, its number of entries and size in bytes, and the hits and misses by
+/// endpoint.
+fn cache_metrics(
+    app_state: Arc<AppState>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("cache" / "metrics")
+        .and(warp::get())
+        .and(warp::any().map(move || app_state.clone()))
+        .and_then(cache::cache_metrics)
+}
+
 /// Handles the POST request for retrieving code chunks for given spans (code range, e.g., line 15..35) within a repository's specific file and, optionally, a specific branch.
 ///
 /// This endpoint listens for POST requests at the "/span" path and expects parameters
@@ -9,7 +9,7 @@ extern crate common;
 use crate::db::DbConnect;
 use crate::parser::literal::Literal;
 use crate::search::payload::{CodeExtractMeta, PathExtractMeta, SymbolPayload};
-use crate::search::ranking::rank_symbol_payloads;
+use crate::search::ranking::rank_symbols;
 use crate::AppState;
 use common::models::CodeChunk;
 
@@ -23,6 +23,7 @@ const CODE_SEARCH_LIMIT: u64 = 10;
 pub async fn code_search(
     query: &String,
     repo_name: &String,
+    branch: Option<&str>,
     db_client: &DbConnect,
     app_state: Arc<AppState>,
 ) -> Result<Vec<CodeChunk>> {
@@ -36,6 +37,7 @@ pub async fn code_search(
         true,
         db_client,
         repo_name,
+        branch,
     )
     .await?;
 
@@ -48,7 +50,7 @@ pub async fn code_search(
             );
     }
 
-    let ranked_symbols = rank_symbol_payloads(&results_symbol);
+    let ranked_symbols = rank_symbols(&results_symbol, repo_name, app_state.clone()).await;
 
     // iterate and print the top paths with score
     for meta in ranked_symbols.iter().take(10) {
@@ -58,6 +60,7 @@ pub async fn code_search(
     let extracted_chunks = process_paths(
         ranked_symbols.iter().cloned().take(10).collect(),
         repo_name,
+        branch,
         app_state,
     )
     .await?;
@@ -103,11 +106,20 @@ async fn semantic_search_symbol<'a>(
     retrieve_more: bool,
     db_client: &DbConnect,
     repo_name: &String,
+    branch: Option<&str>,
 ) -> Result<Vec<SymbolPayload>> {
     debug!("Repo name inside semantic search symbol: {:?}", repo_name);
     let semantic_result = db_client
         .semantic
-        .search_symbol(query, limit, offset, threshold, retrieve_more, repo_name)
+        .search_symbol(
+            query,
+            limit,
+            offset,
+            threshold,
+            retrieve_more,
+            repo_name,
+            branch,
+        )
         .await;
 
     match semantic_result {
@@ -119,9 +131,12 @@ async fn semantic_search_symbol<'a>(
     }
 }
 
-async fn process_paths(
+// Expands the best symbol occurrences of each path into code chunks, reading the files as
+// indexed from the given branch or tag if any.
+pub async fn process_paths(
     path_extract_meta: Vec<PathExtractMeta>,
     repo_name: &String,
+    branch: Option<&str>,
     app_state: Arc<AppState>,
 ) -> Result<Vec<ExtractedContent>, anyhow::Error> {
     // Initialize an empty vector to store the extracted contents.
@@ -135,7 +150,7 @@ async fn process_paths(
         // Fetch the content of the file for the current path.
         let app_state_clone = Arc::clone(&app_state);
 
-        let source_document = get_file_content(path, repo_name, app_state_clone).await?;
+        let source_document = get_file_content(path, repo_name, branch, app_state_clone).await?;
 
         // log the error and continue to the next path if the file content is not found.
         if source_document.is_none() {
@@ -196,14 +211,16 @@ async fn process_paths(
     Ok(results)
 }
 
+// Fetches a file of the repo from quickwit, as indexed from the given branch or tag if any.
 pub async fn get_file_content(
     path: &str,
     repo_name: &String,
+    branch: Option<&str>,
     app_state: Arc<AppState>,
 ) -> Result<Option<ContentDocument>> {
     let config = app_state.configuration.clone();
     let new_index_id = generate_quikwit_index_name(repo_name);
 
     log::debug!("fetching file content {}\n", path);
-    get_file_from_quickwit(&new_index_id, "relative_path", path, app_state).await
+    get_file_from_quickwit(&new_index_id, "relative_path", path, branch, app_state).await
 }
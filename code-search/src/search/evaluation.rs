@@ -0,0 +1,198 @@
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+
+use crate::search::payload::SymbolPayload;
+use crate::search::ranking::{rank_symbol_payloads, RankingConfig};
+
+/// Number of ranked paths the recall and nDCG are computed on.
+pub const EVALUATION_DEPTH: usize = 10;
+
+/// A line of an evaluation set: the paths expected for a query, and the symbols the symbol search
+/// found for it, so that rankings can be compared without the indexes.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Judgment {
+    pub query: String,
+    pub expected_paths: Vec<String>,
+    pub candidates: Vec<Candidate>,
+    /// The last commit dates of the paths, in seconds since the epoch.
+    #[serde(default)]
+    pub commit_dates: HashMap<String, i64>,
+    /// When the candidates were recorded, the recency of the paths is computed from it.
+    #[serde(default)]
+    pub now: i64,
+}
+
+/// A symbol found by the symbol search with its similarity to the query.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Candidate {
+    #[serde(flatten)]
+    pub payload: SymbolPayload,
+    pub score: f32,
+}
+
+/// How well a ranking config ranks the expected paths of an evaluation set, averaged over the
+/// queries.
+#[derive(Clone, Debug, Default, Serialize, PartialEq)]
+pub struct Evaluation {
+    pub queries: usize,
+    /// Mean reciprocal rank of the first expected path.
+    pub mrr: f32,
+    /// Share of the expected paths ranked in the first `EVALUATION_DEPTH`.
+    pub recall: f32,
+    pub ndcg: f32,
+    pub judgments: Vec<QueryEvaluation>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, PartialEq)]
+pub struct QueryEvaluation {
+    pub query: String,
+    /// Rank of the first expected path, from 1, if it was ranked at all.
+    pub first_rank: Option<usize>,
+    pub recall: f32,
+    pub ndcg: f32,
+}
+
+/// Reads an evaluation set, one JSON judgment per line. Blank lines are skipped.
+pub fn read_judgments(path: &str) -> Result<Vec<Judgment>> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read the evaluation set {}", path))?;
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(number, line)| {
+            serde_json::from_str(line)
+                .with_context(|| format!("Invalid judgment on line {} of {}", number + 1, path))
+        })
+        .collect()
+}
+
+/// Ranks the candidates of every judgment with the config and scores the rankings.
+pub fn evaluate(config: &RankingConfig, judgments: &[Judgment]) -> Evaluation {
+    let judgments = judgments
+        .iter()
+        .map(|judgment| evaluate_judgment(config, judgment))
+        .collect::<Vec<_>>();
+    let mean = |metric: fn(&QueryEvaluation) -> f32| {
+        judgments.iter().map(metric).sum::<f32>() / judgments.len().max(1) as f32
+    };
+
+    Evaluation {
+        queries: judgments.len(),
+        mrr: mean(|judgment| judgment.first_rank.map_or(0.0, |rank| 1.0 / rank as f32)),
+        recall: mean(|judgment| judgment.recall),
+        ndcg: mean(|judgment| judgment.ndcg),
+        judgments,
+    }
+}
+
+/// Runs `evaluate-ranking <judgments.jsonl> [ranking.json]`, printing the evaluation as JSON.
+/// The default ranking config is evaluated if none is given.
+pub fn run(args: &[String]) -> Result<()> {
+    let (judgments, config) = match args {
+        [judgments] => (judgments, RankingConfig::default()),
+        [judgments, config] => (judgments, RankingConfig::from_file(config)?),
+        _ => {
+            return Err(anyhow!(
+                "Usage: code-search evaluate-ranking <judgments.jsonl> [ranking.json]"
+            ))
+        }
+    };
+
+    let evaluation = evaluate(&config, &read_judgments(judgments)?);
+    println!("{}", serde_json::to_string_pretty(&evaluation)?);
+    Ok(())
+}
+
+fn evaluate_judgment(config: &RankingConfig, judgment: &Judgment) -> QueryEvaluation {
+    let payloads = judgment
+        .candidates
+        .iter()
+        .map(|candidate| SymbolPayload {
+            score: Some(candidate.score),
+            ..candidate.payload.clone()
+        })
+        .collect::<Vec<_>>();
+    let ranked = rank_symbol_payloads(&payloads, config, &judgment.commit_dates, judgment.now)
+        .into_iter()
+        .map(|meta| meta.path)
+        .collect::<Vec<_>>();
+
+    let expected = judgment
+        .expected_paths
+        .iter()
+        .map(String::as_str)
+        .collect::<HashSet<_>>();
+    let relevant = ranked
+        .iter()
+        .take(EVALUATION_DEPTH)
+        .map(|path| expected.contains(path.as_str()))
+        .collect::<Vec<_>>();
+
+    // binary relevance, discounted by the log of the rank.
+    let dcg = |hits: &mut dyn Iterator<Item = bool>| {
+        hits.enumerate()
+            .filter(|(_, relevant)| *relevant)
+            .map(|(rank, _)| 1.0 / (rank as f32 + 2.0).log2())
+            .sum::<f32>()
+    };
+    let ideal = dcg(&mut std::iter::repeat(true).take(expected.len().min(EVALUATION_DEPTH)));
+    let found = relevant.iter().filter(|&&relevant| relevant).count();
+
+    QueryEvaluation {
+        query: judgment.query.clone(),
+        first_rank: ranked
+            .iter()
+            .position(|path| expected.contains(path.as_str()))
+            .map(|rank| rank + 1),
+        recall: if expected.is_empty() {
+            0.0
+        } else {
+            found as f32 / expected.len() as f32
+        },
+        ndcg: if ideal > 0.0 {
+            dcg(&mut relevant.into_iter()) / ideal
+        } else {
+            0.0
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const JUDGMENTS: &str = r#"
+{"query": "retry failed jobs", "expected_paths": ["src/jobs.rs"], "candidates": [{"repo_name": "nezuko", "symbol": "retry", "symbol_types": ["function", "variable"], "lang_ids": ["Rust", "Rust"], "is_globals": [false, false], "start_bytes": [0, 0], "end_bytes": [10, 10], "relative_paths": ["tests/jobs.rs", "src/jobs.rs"], "node_kinds": ["def", "def"], "score": 0.3}]}
+{"query": "parse config", "expected_paths": ["src/config.rs", "src/env.rs"], "candidates": [{"repo_name": "nezuko", "symbol": "parse", "symbol_types": ["function"], "lang_ids": ["Rust"], "is_globals": [false], "start_bytes": [0], "end_bytes": [10], "relative_paths": ["src/config.rs"], "node_kinds": ["def"], "score": 0.3}]}
+"#;
+
+    fn judgments() -> Vec<Judgment> {
+        JUDGMENTS
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let evaluation = evaluate(&RankingConfig::default(), &judgments());
+        assert_eq!(evaluation.queries, 2);
+        // the test function outranks the variable.
+        assert_eq!(evaluation.judgments[0].first_rank, Some(2));
+        assert_eq!(evaluation.judgments[1].first_rank, Some(1));
+        assert!((evaluation.mrr - 0.75).abs() < 1e-5);
+        assert!((evaluation.recall - 0.75).abs() < 1e-5);
+        assert!((evaluation.judgments[1].ndcg - 1.0 / (1.0 + 1.0 / 3f32.log2())).abs() < 1e-5);
+
+        // penalizing the test files ranks the expected path first.
+        let mut config = RankingConfig::default();
+        config.default.test_file = 0.9;
+        let evaluation = evaluate(&config, &judgments());
+        assert_eq!(evaluation.judgments[0].first_rank, Some(1));
+        assert!((evaluation.mrr - 1.0).abs() < 1e-5);
+    }
+}
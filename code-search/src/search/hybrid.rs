@@ -0,0 +1,483 @@
+use anyhow::Result;
+use common::hasher::{
+    generate_chunks_collection_name, generate_qdrant_index_name, generate_quikwit_index_name,
+};
+use common::lexical::{branch_clause, LexicalDocument};
+use common::models::CodeChunk;
+use log::{debug, error};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use crate::models::{FusionWeights, HybridSearchRequest};
+use crate::search::code_search::process_paths;
+use crate::search::payload::{Embedding, Payload, SymbolPayload};
+use crate::search::ranking::rank_symbols;
+use crate::AppState;
+
+pub const DEFAULT_SEARCH_LIMIT: usize = 10;
+// The constant of reciprocal rank fusion, a hit ranked r by a source gets weight / (k + r).
+pub const DEFAULT_RRF_K: f32 = 60.0;
+// Number of candidates retrieved from each source per returned hit.
+const CANDIDATES_PER_HIT: usize = 3;
+// Number of lines of a lexical hit, around its best matching lines.
+const LEXICAL_WINDOW_LINES: usize = 20;
+// Number of ranked paths whose symbol occurrences are expanded into chunks.
+const SYMBOL_PATHS: usize = 10;
+// Fields a query term is looked up in.
+const LEXICAL_FIELDS: [&str; 3] = ["content", "symbols", "relative_path"];
+
+/// A retrieval method fused by the hybrid search.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Source {
+    /// BM25 search of the files in the lexical index.
+    Lexical,
+    /// Vector search of the code chunks.
+    Chunks,
+    /// Vector search of the symbols, ranked by `rank_symbols`.
+    Symbols,
+}
+
+impl FusionWeights {
+    fn weight(&self, source: Source) -> f32 {
+        match source {
+            Source::Lexical => self.lexical,
+            Source::Chunks => self.chunks,
+            Source::Symbols => self.symbols,
+        }
+    }
+}
+
+/// How a source ranked a hit and what it added to the fused score.
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct SourceScore {
+    pub source: Source,
+    // 1-based rank of the hit among the results of the source.
+    pub rank: usize,
+    // The score the source gave the hit: the cosine similarity for chunks, the path score for
+    // symbols and the number of query term matches for lexical hits, as the lexical backends
+    // don't return their BM25 scores.
+    pub score: f32,
+    pub contribution: f32,
+}
+
+/// A fused hit, with the sources that found it.
+#[derive(Clone, Debug, Serialize)]
+pub struct SearchHit {
+    #[serde(flatten)]
+    pub chunk: CodeChunk,
+    pub score: f32,
+    pub sources: Vec<SourceScore>,
+}
+
+// A hit of a single source, the candidates of a source are kept in the order it ranked them.
+#[derive(Clone, Debug)]
+struct Candidate {
+    chunk: CodeChunk,
+    score: f32,
+}
+
+/// Runs the lexical, chunk and symbol searches concurrently and fuses their rankings with
+/// reciprocal rank fusion. A source that fails is logged and left out, the search only fails if
+/// every source does.
+pub async fn hybrid_search(
+    request: &HybridSearchRequest,
+    app_state: Arc<AppState>,
+) -> Result<Vec<SearchHit>> {
+    let limit = request.limit.unwrap_or(DEFAULT_SEARCH_LIMIT).max(1);
+    let candidates = limit * CANDIDATES_PER_HIT;
+
+    // the query is embedded once for both vector searches.
+    let vector = if request.weights.chunks > 0.0 || request.weights.symbols > 0.0 {
+        Some(
+            app_state
+                .db_connection
+                .semantic
+                .embed(&request.query)
+                .await?,
+        )
+    } else {
+        None
+    };
+
+    let (lexical, chunks, symbols) = tokio::join!(
+        lexical_candidates(request, candidates, app_state.clone()),
+        chunk_candidates(request, vector.as_ref(), candidates, app_state.clone()),
+        symbol_candidates(request, vector.as_ref(), candidates, app_state.clone()),
+    );
+
+    // the chunks go first, their snippets are kept when the hits of several sources overlap.
+    let mut ranked = Vec::new();
+    let mut failure = None;
+    for (source, result) in [
+        (Source::Chunks, chunks),
+        (Source::Symbols, symbols),
+        (Source::Lexical, lexical),
+    ] {
+        match result {
+            Ok(candidates) => {
+                debug!("{:?} search returned {} hits", source, candidates.len());
+                ranked.push((source, candidates));
+            }
+            Err(e) => {
+                error!(
+                    "{:?} search of {} failed: {:?}",
+                    source, request.repo_name, e
+                );
+                failure.get_or_insert(e);
+            }
+        }
+    }
+    match failure {
+        Some(e) if ranked.is_empty() => return Err(e),
+        _ => {}
+    }
+
+    Ok(fuse(
+        ranked,
+        &request.weights,
+        request.rrf_k.unwrap_or(DEFAULT_RRF_K),
+        limit,
+    ))
+}
+
+async fn lexical_candidates(
+    request: &HybridSearchRequest,
+    limit: usize,
+    app_state: Arc<AppState>,
+) -> Result<Vec<Candidate>> {
+    let terms = query_terms(&request.query);
+    if request.weights.lexical <= 0.0 || terms.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let query = lexical_query(&terms, request.branch.as_deref());
+    let documents = app_state
+        .lexical_index
+        .search(
+            &generate_quikwit_index_name(&request.repo_name),
+            &query,
+            limit,
+        )
+        .await?;
+
+    // without a branch a path indexed from several refs only counts once.
+    let mut seen_paths = HashSet::new();
+    Ok(documents
+        .into_iter()
+        .filter(|document| !document.is_directory && document.repo_name == request.repo_name)
+        .filter(|document| seen_paths.insert(document.relative_path.clone()))
+        .map(|document| lexical_candidate(&document, &terms))
+        .collect())
+}
+
+async fn chunk_candidates(
+    request: &HybridSearchRequest,
+    vector: Option<&Embedding>,
+    limit: usize,
+    app_state: Arc<AppState>,
+) -> Result<Vec<Candidate>> {
+    let vector = match vector {
+        Some(vector) if request.weights.chunks > 0.0 => vector.clone(),
+        _ => return Ok(Vec::new()),
+    };
+
+    let points = app_state
+        .db_connection
+        .semantic
+        .search_collection(
+            &generate_chunks_collection_name(&request.repo_name),
+            vector,
+            limit as u64,
+            &request.repo_name,
+            request.branch.as_deref(),
+        )
+        .await?;
+
+    Ok(points
+        .into_iter()
+        .map(Payload::from_point)
+        .map(|payload| Candidate {
+            score: payload.score.unwrap_or_default(),
+            chunk: CodeChunk {
+                path: payload.relative_path,
+                snippet: payload.text,
+                start_line: payload.start_line as usize,
+                end_line: payload.end_line as usize,
+            },
+        })
+        .collect())
+}
+
+async fn symbol_candidates(
+    request: &HybridSearchRequest,
+    vector: Option<&Embedding>,
+    limit: usize,
+    app_state: Arc<AppState>,
+) -> Result<Vec<Candidate>> {
+    let vector = match vector {
+        Some(vector) if request.weights.symbols > 0.0 => vector.clone(),
+        _ => return Ok(Vec::new()),
+    };
+
+    let payloads = app_state
+        .db_connection
+        .semantic
+        .search_collection(
+            &generate_qdrant_index_name(&request.repo_name),
+            vector,
+            limit as u64,
+            &request.repo_name,
+            request.branch.as_deref(),
+        )
+        .await?
+        .into_iter()
+        .map(SymbolPayload::from_point)
+        .collect::<Vec<_>>();
+
+    let ranked_paths = rank_symbols(&payloads, &request.repo_name, app_state.clone()).await;
+    let path_scores = ranked_paths
+        .iter()
+        .map(|meta| (meta.path.clone(), meta.score))
+        .collect::<HashMap<_, _>>();
+    let extracted = process_paths(
+        ranked_paths.into_iter().take(SYMBOL_PATHS).collect(),
+        &request.repo_name,
+        request.branch.as_deref(),
+        app_state,
+    )
+    .await?;
+
+    Ok(extracted
+        .into_iter()
+        .take(limit)
+        .map(|content| Candidate {
+            score: path_scores.get(&content.path).copied().unwrap_or_default(),
+            chunk: CodeChunk {
+                path: content.path,
+                snippet: content.content,
+                start_line: content.start_line,
+                end_line: content.end_line,
+            },
+        })
+        .collect())
+}
+
+// Fuses the rankings of the sources: a hit ranked r by a source scores weight / (k + r), and
+// the hits of different sources that overlap on the same path are merged into one.
+fn fuse(
+    ranked: Vec<(Source, Vec<Candidate>)>,
+    weights: &FusionWeights,
+    k: f32,
+    limit: usize,
+) -> Vec<SearchHit> {
+    let mut hits: Vec<SearchHit> = Vec::new();
+    for (source, candidates) in ranked {
+        let weight = weights.weight(source);
+        if weight <= 0.0 {
+            continue;
+        }
+
+        for (index, candidate) in candidates.into_iter().enumerate() {
+            let source_score = SourceScore {
+                source,
+                rank: index + 1,
+                score: candidate.score,
+                contribution: weight / (k + (index + 1) as f32),
+            };
+            match hits
+                .iter_mut()
+                .find(|hit| overlaps(&hit.chunk, &candidate.chunk))
+            {
+                // only the best ranked of the overlapping hits of a source counts.
+                Some(hit) if hit.sources.iter().any(|score| score.source == source) => {}
+                Some(hit) => {
+                    hit.score += source_score.contribution;
+                    hit.sources.push(source_score);
+                }
+                None => hits.push(SearchHit {
+                    chunk: candidate.chunk,
+                    score: source_score.contribution,
+                    sources: vec![source_score],
+                }),
+            }
+        }
+    }
+
+    hits.sort_by(|a, b| {
+        b.score
+            .partial_cmp(&a.score)
+            .unwrap()
+            .then_with(|| a.chunk.path.cmp(&b.chunk.path))
+            .then(a.chunk.start_line.cmp(&b.chunk.start_line))
+    });
+    hits.truncate(limit);
+    hits
+}
+
+fn overlaps(a: &CodeChunk, b: &CodeChunk) -> bool {
+    a.path == b.path && a.start_line <= b.end_line && b.start_line <= a.end_line
+}
+
+// The lowercased words of the query, identifiers are kept whole.
+fn query_terms(query: &str) -> Vec<String> {
+    let mut seen = HashSet::new();
+    query
+        .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .filter(|term| term.chars().count() > 1)
+        .map(str::to_lowercase)
+        .filter(|term| seen.insert(term.clone()))
+        .collect()
+}
+
+// Matches the files containing any of the terms in their content, symbols or path.
+fn lexical_query(terms: &[String], branch: Option<&str>) -> String {
+    let clauses = terms
+        .iter()
+        .flat_map(|term| {
+            LEXICAL_FIELDS
+                .iter()
+                .map(move |field| format!("{}:{}", field, term))
+        })
+        .collect::<Vec<_>>()
+        .join(" OR ");
+
+    match branch {
+        Some(branch) => format!("({}) AND {}", clauses, branch_clause(branch)),
+        None => format!("({})", clauses),
+    }
+}
+
+// Narrows a matching file to the window of lines with the most term matches, the top of the
+// file if the terms only matched its symbols or path.
+fn lexical_candidate(document: &LexicalDocument, terms: &[String]) -> Candidate {
+    let lines = document.content.lines().collect::<Vec<_>>();
+    let matches = lines
+        .iter()
+        .map(|line| {
+            let line = line.to_lowercase();
+            terms
+                .iter()
+                .filter(|term| line.contains(term.as_str()))
+                .count()
+        })
+        .collect::<Vec<_>>();
+
+    let (start_line, score) = best_window(&matches, LEXICAL_WINDOW_LINES);
+    let end_line = (start_line + LEXICAL_WINDOW_LINES).min(lines.len());
+    Candidate {
+        chunk: CodeChunk {
+            path: document.relative_path.clone(),
+            snippet: lines[start_line..end_line].join("\n"),
+            start_line,
+            end_line: end_line.saturating_sub(1),
+        },
+        score: score as f32,
+    }
+}
+
+// The first line and the number of matches of the window with the most matches, the earliest
+// one on ties.
+fn best_window(matches: &[usize], window: usize) -> (usize, usize) {
+    let mut total = matches.iter().take(window).sum::<usize>();
+    let mut best = (0, total);
+    for start in 1..=matches.len().saturating_sub(window) {
+        total = total + matches[start + window - 1] - matches[start - 1];
+        if total > best.1 {
+            best = (start, total);
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(path: &str, start_line: usize, end_line: usize, score: f32) -> Candidate {
+        Candidate {
+            chunk: CodeChunk {
+                path: path.to_string(),
+                snippet: String::new(),
+                start_line,
+                end_line,
+            },
+            score,
+        }
+    }
+
+    #[test]
+    fn test_fuse() {
+        let ranked = vec![
+            (
+                Source::Chunks,
+                vec![
+                    candidate("src/agent.rs", 10, 30, 0.9),
+                    candidate("src/main.rs", 0, 20, 0.8),
+                ],
+            ),
+            (
+                Source::Symbols,
+                vec![
+                    candidate("src/main.rs", 5, 12, 40.0),
+                    candidate("src/main.rs", 15, 18, 20.0),
+                ],
+            ),
+            (Source::Lexical, vec![candidate("src/lib.rs", 0, 19, 3.0)]),
+        ];
+        let hits = fuse(ranked, &FusionWeights::default(), 60.0, 10);
+
+        // the symbol hits overlap the second chunk, only the best ranked one counts.
+        assert_eq!(hits.len(), 3);
+        assert_eq!(hits[0].chunk.path, "src/main.rs");
+        assert_eq!(hits[0].chunk.start_line, 0);
+        assert_eq!(
+            hits[0]
+                .sources
+                .iter()
+                .map(|score| (score.source, score.rank))
+                .collect::<Vec<_>>(),
+            vec![(Source::Chunks, 2), (Source::Symbols, 1)]
+        );
+        assert!((hits[0].score - (1.0 / 62.0 + 1.0 / 61.0)).abs() < 1e-6);
+        // the single source hits ranked first tie and are ordered by path.
+        assert_eq!(hits[1].chunk.path, "src/agent.rs");
+        assert_eq!(hits[2].chunk.path, "src/lib.rs");
+        assert_eq!(hits[2].sources[0].score, 3.0);
+
+        let weights = FusionWeights {
+            lexical: 4.0,
+            chunks: 1.0,
+            symbols: 0.0,
+        };
+        let ranked = vec![
+            (Source::Chunks, vec![candidate("src/agent.rs", 10, 30, 0.9)]),
+            (Source::Symbols, vec![candidate("src/main.rs", 5, 12, 40.0)]),
+            (Source::Lexical, vec![candidate("src/lib.rs", 0, 19, 3.0)]),
+        ];
+        let hits = fuse(ranked, &weights, 60.0, 1);
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].chunk.path, "src/lib.rs");
+    }
+
+    #[test]
+    fn test_lexical_query() {
+        let terms = query_terms("Where is run_agent() called? run_agent");
+        assert_eq!(terms, vec!["where", "is", "run_agent", "called"]);
+
+        let query = lexical_query(&terms[2..3], Some("main"));
+        assert_eq!(
+            query,
+            r#"(content:run_agent OR symbols:run_agent OR relative_path:run_agent) AND branches:"main""#
+        );
+    }
+
+    #[test]
+    fn test_best_window() {
+        assert_eq!(best_window(&[], 3), (0, 0));
+        assert_eq!(best_window(&[1, 0], 3), (0, 1));
+        assert_eq!(best_window(&[1, 0, 0, 0, 2, 2, 1], 3), (4, 5));
+        assert_eq!(best_window(&[0, 0, 0, 0], 2), (0, 0));
+    }
+}
@@ -1,5 +1,8 @@
 pub mod payload;
 pub mod ranking;
 pub mod code_search;
+pub mod hybrid;
 pub mod semantic;
 pub mod quikwit;
+pub mod query;
+pub mod evaluation;
@@ -1,13 +1,11 @@
 // import all the necessary modules.
-use std::{borrow::Cow, collections::HashMap, str};
+use std::{borrow::Cow, str};
 
-use qdrant_client::qdrant::{
-    point_id::PointIdOptions, vectors::VectorsOptions, PointId, ScoredPoint, Value, Vectors,
-};
+use common::vector::{PointPayload, ScoredPoint};
 
 pub type Embedding = Vec<f32>;
 
-// Payload format to write and deserialize data in and from qdrant.
+// Payload format to write and deserialize data in and from the vector store.
 #[derive(Default, Clone, Debug, serde::Deserialize, serde::Serialize)]
 pub struct SymbolPayload {
     pub repo_name: String,
@@ -50,50 +48,24 @@ pub struct PathExtractMeta {
 }
 
 impl SymbolPayload {
-    pub fn from_qdrant(orig: ScoredPoint) -> SymbolPayload {
+    pub fn from_point(orig: ScoredPoint) -> SymbolPayload {
         let ScoredPoint {
             id,
             payload,
             score,
-            vectors,
-            ..
+            vector,
         } = orig;
 
-        parse_symbol_payload(id, vectors, payload, score)
+        parse_symbol_payload(id, vector, payload, score)
     }
 }
 
 fn parse_symbol_payload(
-    id: Option<PointId>,
-    vectors: Option<Vectors>,
-    payload: HashMap<String, Value>,
+    id: String,
+    embedding: Option<Embedding>,
+    mut converted: PointPayload,
     score: f32,
 ) -> SymbolPayload {
-    let Some(PointId {
-        point_id_options: Some(PointIdOptions::Uuid(id)),
-    }) = id
-    else {
-        // unless the db was corrupted/written by someone else,
-        // this shouldn't happen
-        unreachable!("corrupted db");
-    };
-
-    let embedding = match vectors {
-        None => None,
-        Some(Vectors {
-            vectors_options: Some(VectorsOptions::Vector(v)),
-        }) => Some(v.data),
-        _ => {
-            // this also should probably never happen
-            unreachable!("got non-vector value");
-        }
-    };
-
-    let mut converted = payload
-        .into_iter()
-        .map(|(key, value)| (key, kind_to_value(value.kind)))
-        .collect::<HashMap<String, serde_json::Value>>();
-
     SymbolPayload {
         repo_name: val_str!(converted, "repo_name"),
         symbol: val_str!(converted, "symbol"),
@@ -121,6 +93,8 @@ pub struct Payload {
     pub end_line: u64,
     pub start_byte: u64,
     pub end_byte: u64,
+    // Labels of the scopes enclosing the chunk, empty if the repo wasn't chunked by syntax.
+    pub scope_path: String,
 
     #[serde(skip)]
     pub id: Option<String>,
@@ -131,16 +105,15 @@ pub struct Payload {
 }
 
 impl Payload {
-    pub fn from_qdrant(orig: ScoredPoint) -> Payload {
+    pub fn from_point(orig: ScoredPoint) -> Payload {
         let ScoredPoint {
             id,
             payload,
             score,
-            vectors,
-            ..
+            vector,
         } = orig;
 
-        parse_payload(id, vectors, payload, score)
+        parse_payload(id, vector, payload, score)
     }
 }
 
@@ -170,58 +143,12 @@ macro_rules! val_parse_str(($hash:ident, $val:expr) => {
 });
 pub(crate) use val_str;
 
-fn kind_to_value(kind: Option<qdrant_client::qdrant::value::Kind>) -> serde_json::Value {
-    use qdrant_client::qdrant::value::Kind;
-    match kind {
-        Some(Kind::NullValue(_)) => serde_json::Value::Null,
-        Some(Kind::BoolValue(v)) => serde_json::Value::Bool(v),
-        Some(Kind::DoubleValue(v)) => {
-            serde_json::Value::Number(serde_json::Number::from_f64(v).unwrap())
-        }
-        Some(Kind::IntegerValue(v)) => serde_json::Value::Number(v.into()),
-        Some(Kind::StringValue(v)) => serde_json::Value::String(v),
-        Some(Kind::ListValue(v)) => serde_json::Value::Array(
-            v.values
-                .into_iter()
-                .map(|v| kind_to_value(v.kind))
-                .collect(),
-        ),
-        Some(Kind::StructValue(_v)) => todo!(),
-        None => serde_json::Value::Null,
-    }
-}
-
 fn parse_payload(
-    id: Option<PointId>,
-    vectors: Option<Vectors>,
-    payload: HashMap<String, Value>,
+    id: String,
+    embedding: Option<Embedding>,
+    mut converted: PointPayload,
     score: f32,
 ) -> Payload {
-    let Some(PointId {
-        point_id_options: Some(PointIdOptions::Uuid(id)),
-    }) = id
-    else {
-        // unless the db was corrupted/written by someone else,
-        // this shouldn't happen
-        unreachable!("corrupted db");
-    };
-
-    let embedding = match vectors {
-        None => None,
-        Some(Vectors {
-            vectors_options: Some(VectorsOptions::Vector(v)),
-        }) => Some(v.data),
-        _ => {
-            // this also should probably never happen
-            unreachable!("got non-vector value");
-        }
-    };
-
-    let mut converted = payload
-        .into_iter()
-        .map(|(key, value)| (key, kind_to_value(value.kind)))
-        .collect::<HashMap<String, serde_json::Value>>();
-
     Payload {
         lang: val_str!(converted, "lang"),
         repo_name: val_str!(converted, "repo_name"),
@@ -232,6 +159,11 @@ fn parse_payload(
         end_line: val_parse_str!(converted, "end_line"),
         start_byte: val_parse_str!(converted, "start_byte"),
         end_byte: val_parse_str!(converted, "end_byte"),
+        // older points have no scope path.
+        scope_path: converted
+            .remove("scope_path")
+            .and_then(|value| serde_json::from_value(value).ok())
+            .unwrap_or_default(),
 
         id: Some(id),
         score: Some(score),
@@ -0,0 +1,369 @@
+use anyhow::{anyhow, Result};
+use common::ast::graph_code_pluck::ContentDocument;
+use common::hasher::generate_quikwit_index_name;
+use common::lexical::{branch_clause, repo_clause, LexicalDocument};
+use futures::future::ready;
+use futures::pin_mut;
+use futures::TryStreamExt;
+use log::{debug, error};
+use regex::{Regex, RegexBuilder};
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use crate::models::QueryRequest;
+use crate::parser::literal::Literal;
+use crate::parser::query::{Query, Target};
+use crate::search::quikwit::stream_documents;
+use crate::snippet::{SnippedFile, Snipper};
+use crate::AppState;
+
+pub const DEFAULT_QUERY_LIMIT: usize = 20;
+// Lines of context around the highlighted lines.
+const DEFAULT_CONTEXT_LINES: usize = 1;
+// Number of documents fetched per request while filtering.
+const QUERY_PAGE_SIZE: usize = 100;
+
+// A query compiled for the documents: the literals become regexes.
+enum Filter {
+    Content(Regex),
+    Path(Regex),
+    Symbol(Regex),
+    Repo(Name),
+    Branch(Name),
+    Lang(String),
+    Not(Box<Filter>),
+    And(Vec<Filter>),
+    Or(Vec<Filter>),
+}
+
+// Plain repo and branch names match whole, regexes anywhere.
+enum Name {
+    Exact(String),
+    Pattern(Regex),
+}
+
+/// How a parsed query is run: the repos whose indexes are searched, the lexical query narrowing
+/// their documents down, and the filter and highlights applied to each document.
+pub struct QueryPlan {
+    repos: Vec<String>,
+    lexical_query: Option<String>,
+    filter: Filter,
+    // Regexes of the literals that aren't negated, highlighted in the content and the symbols.
+    content_highlight: Option<String>,
+    symbol_highlight: Option<String>,
+    snipper: Snipper,
+    limit: usize,
+}
+
+impl QueryPlan {
+    /// The repos searched are the ones named by plain `repo:` terms that aren't negated, or
+    /// `repo_name` of the request if the query doesn't name any.
+    pub fn new(query: &Query, request: &QueryRequest) -> Result<Self> {
+        let mut repos = Vec::new();
+        let mut content_highlights = Vec::new();
+        let mut symbol_highlights = Vec::new();
+        query.visit(false, &mut |target, negated| match (target, negated) {
+            (Target::Repo(Literal::Plain(name)), false) if !repos.contains(&name.to_string()) => {
+                repos.push(name.to_string())
+            }
+            (Target::Content(literal), false) => content_highlights.push(literal.regex_str()),
+            (Target::Symbol(literal), false) => symbol_highlights.push(literal.regex_str()),
+            _ => {}
+        });
+        if repos.is_empty() {
+            repos.extend(request.repo_name.clone());
+        }
+        if repos.is_empty() {
+            return Err(anyhow!(
+                "The query doesn't name a repo, add a `repo:` term or set `repo_name`"
+            ));
+        }
+
+        let case_sensitive = request.case_sensitive;
+        let context = request.context.unwrap_or(DEFAULT_CONTEXT_LINES);
+        Ok(Self {
+            repos,
+            lexical_query: lexical_clause(query),
+            filter: Filter::new(query, case_sensitive)?,
+            content_highlight: alternation(&content_highlights),
+            symbol_highlight: alternation(&symbol_highlights),
+            snipper: Snipper::default()
+                .context(context, context)
+                .case_sensitive(case_sensitive),
+            limit: request.limit.unwrap_or(DEFAULT_QUERY_LIMIT),
+        })
+    }
+
+    pub fn repos(&self) -> &[String] {
+        &self.repos
+    }
+
+    /// Reads the documents of the repos matching the lexical query, keeps the ones passing the
+    /// filter and highlights them. Without a `branch:` term, a path indexed from several
+    /// branches only shows up once.
+    pub async fn run(&self, app_state: Arc<AppState>) -> Result<Vec<SnippedFile>> {
+        let mut files = Vec::new();
+        for repo_name in &self.repos {
+            let mut query = repo_clause(repo_name);
+            if let Some(clause) = &self.lexical_query {
+                query = format!("{} AND {}", query, clause);
+            }
+            debug!("Querying {} with {}", repo_name, query);
+
+            let mut seen_paths = HashSet::new();
+            let documents = stream_documents(
+                &generate_quikwit_index_name(repo_name),
+                &query,
+                QUERY_PAGE_SIZE,
+                &[],
+                app_state.clone(),
+            )
+            .try_filter(|document| {
+                ready(
+                    !document.is_directory
+                        && document.repo_name == *repo_name
+                        && self.filter.matches(document)
+                        && seen_paths.insert(document.relative_path.clone()),
+                )
+            });
+            pin_mut!(documents);
+
+            while let Some(document) = documents.try_next().await? {
+                files.push(self.snip(ContentDocument::from(document)));
+                if files.len() >= self.limit {
+                    return Ok(files);
+                }
+            }
+        }
+        Ok(files)
+    }
+
+    // Highlights the literals of the query in the document. Documents matched by their path,
+    // language or other terms only are returned without snippets.
+    fn snip(&self, document: ContentDocument) -> SnippedFile {
+        let highlights = [
+            (&self.content_highlight, false),
+            (&self.symbol_highlight, true),
+        ];
+        let mut file: Option<SnippedFile> = None;
+        for (regex, find_symbols) in highlights {
+            let Some(regex) = regex else {
+                continue;
+            };
+            let snipped = match self
+                .snipper
+                .find_symbols(find_symbols)
+                .all_for_doc(regex, &document)
+            {
+                Ok(snipped) => snipped,
+                Err(e) => {
+                    error!("Failed to highlight {}: {:?}", document.relative_path, e);
+                    None
+                }
+            };
+            file = match (file, snipped) {
+                (Some(file), Some(snipped)) => Some(file.merge(snipped)),
+                (file, snipped) => file.or(snipped),
+            };
+        }
+
+        file.unwrap_or_else(|| SnippedFile {
+            relative_path: document.relative_path,
+            repo_name: document.repo_name,
+            repo_ref: document.repo_ref,
+            lang: document.lang,
+            snippets: Vec::new(),
+        })
+    }
+}
+
+impl Filter {
+    fn new(query: &Query, case_sensitive: bool) -> Result<Self> {
+        let regex = |literal: &Literal| {
+            RegexBuilder::new(&literal.regex_str())
+                .multi_line(true)
+                .case_insensitive(!case_sensitive)
+                .build()
+        };
+        let name = |literal: &Literal| -> Result<Name> {
+            Ok(match literal {
+                Literal::Plain(name) => Name::Exact(name.to_string()),
+                Literal::Regex(_) => Name::Pattern(regex(literal)?),
+            })
+        };
+
+        Ok(match query {
+            Query::Target(Target::Content(literal)) => Filter::Content(regex(literal)?),
+            Query::Target(Target::Path(literal)) => Filter::Path(regex(literal)?),
+            Query::Target(Target::Symbol(literal)) => Filter::Symbol(regex(literal)?),
+            Query::Target(Target::Repo(literal)) => Filter::Repo(name(literal)?),
+            Query::Target(Target::Branch(literal)) => Filter::Branch(name(literal)?),
+            Query::Target(Target::Lang(lang)) => Filter::Lang(lang.clone()),
+            Query::Not(query) => Filter::Not(Box::new(Filter::new(query, case_sensitive)?)),
+            Query::And(parts) => Filter::And(
+                parts
+                    .iter()
+                    .map(|part| Filter::new(part, case_sensitive))
+                    .collect::<Result<_>>()?,
+            ),
+            Query::Or(parts) => Filter::Or(
+                parts
+                    .iter()
+                    .map(|part| Filter::new(part, case_sensitive))
+                    .collect::<Result<_>>()?,
+            ),
+        })
+    }
+
+    fn matches(&self, document: &LexicalDocument) -> bool {
+        match self {
+            Filter::Content(regex) => regex.is_match(&document.content),
+            Filter::Path(regex) => regex.is_match(&document.relative_path),
+            Filter::Symbol(regex) => document
+                .symbols
+                .lines()
+                .any(|symbol| regex.is_match(symbol)),
+            Filter::Repo(name) => name.matches(&document.repo_name),
+            Filter::Branch(name) => document.branches.iter().any(|branch| name.matches(branch)),
+            Filter::Lang(lang) => document.lang.eq_ignore_ascii_case(lang),
+            Filter::Not(filter) => !filter.matches(document),
+            Filter::And(filters) => filters.iter().all(|filter| filter.matches(document)),
+            Filter::Or(filters) => filters.iter().any(|filter| filter.matches(document)),
+        }
+    }
+}
+
+impl Name {
+    fn matches(&self, text: &str) -> bool {
+        match self {
+            Name::Exact(name) => name == text,
+            Name::Pattern(regex) => regex.is_match(text),
+        }
+    }
+}
+
+// The lexical query selecting the candidate documents, None if it can't narrow them down. Plain
+// literals are looked up as phrases of the index, so they only match on token boundaries;
+// regexes, languages and negations are only checked on the documents read.
+fn lexical_clause(query: &Query) -> Option<String> {
+    match query {
+        Query::Target(target) => match target {
+            Target::Content(Literal::Plain(text)) if !text.is_empty() => {
+                Some(format!("content:{}", phrase(text)))
+            }
+            Target::Path(Literal::Plain(text)) if !text.is_empty() => {
+                Some(format!("relative_path:{}", phrase(text)))
+            }
+            Target::Symbol(Literal::Plain(text)) if !text.is_empty() => {
+                Some(format!("symbols:{}", phrase(text)))
+            }
+            Target::Repo(Literal::Plain(name)) => Some(repo_clause(name)),
+            Target::Branch(Literal::Plain(name)) => Some(branch_clause(name)),
+            _ => None,
+        },
+        Query::Not(_) => None,
+        Query::And(parts) => {
+            let clauses = parts.iter().filter_map(lexical_clause).collect::<Vec<_>>();
+            (!clauses.is_empty()).then(|| format!("({})", clauses.join(" AND ")))
+        }
+        Query::Or(parts) => parts
+            .iter()
+            .map(lexical_clause)
+            .collect::<Option<Vec<_>>>()
+            .map(|clauses| format!("({})", clauses.join(" OR "))),
+    }
+}
+
+fn phrase(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn alternation<T: AsRef<str>>(regexes: &[T]) -> Option<String> {
+    (!regexes.is_empty()).then(|| {
+        regexes
+            .iter()
+            .map(|regex| format!("(?:{})", regex.as_ref()))
+            .collect::<Vec<_>>()
+            .join("|")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::query::parse;
+
+    fn query_plan(query: &str) -> QueryPlan {
+        let request = QueryRequest {
+            query: query.to_string(),
+            repo_name: Some("nezuko".to_string()),
+            ..Default::default()
+        };
+        QueryPlan::new(&parse(query).unwrap(), &request).unwrap()
+    }
+
+    #[test]
+    fn test_lexical_clause() {
+        assert_eq!(
+            query_plan(r#"repo:nezuko run_agent -path:tests"#).lexical_query,
+            Some(r#"(repo_name:"nezuko" AND content:"run_agent")"#.to_string())
+        );
+        assert_eq!(
+            query_plan(r#"symbol:Agent OR branch:main"#).lexical_query,
+            Some(r#"(symbols:"Agent" OR branches:"main")"#.to_string())
+        );
+        // a regex can't narrow the documents down, neither can the alternatives including one.
+        assert_eq!(query_plan(r"/spawn_\w+/ OR run_agent").lexical_query, None);
+        assert_eq!(query_plan(r"lang:rust").lexical_query, None);
+    }
+
+    #[test]
+    fn test_filter() {
+        let lib = LexicalDocument {
+            repo_name: "nezuko".to_string(),
+            branches: vec!["main".to_string()],
+            relative_path: "src/lib.rs".to_string(),
+            lang: "Rust".to_string(),
+            content: "pub fn run_agent() {}".to_string(),
+            symbols: "run_agent\nAgent".to_string(),
+            ..Default::default()
+        };
+        let test = LexicalDocument {
+            branches: vec!["feature/x".to_string()],
+            relative_path: "tests/agent.rs".to_string(),
+            content: "fn spawn_agent() {}".to_string(),
+            ..lib.clone()
+        };
+
+        let plan = query_plan(r"lang:rust (RUN_AGENT OR /spawn_\w+/) -path:tests");
+        assert!(plan.filter.matches(&lib));
+        assert!(!plan.filter.matches(&test));
+
+        let plan = query_plan(r"branch:/^feature/ symbol:agent");
+        assert!(!plan.filter.matches(&lib));
+        assert!(plan.filter.matches(&test));
+
+        let plan = query_plan(r"repo:nezuko lang:python");
+        assert_eq!(plan.repos, vec!["nezuko"]);
+        assert!(!plan.filter.matches(&lib));
+    }
+
+    #[test]
+    fn test_plan() {
+        let query = parse("-repo:nezuko run_agent").unwrap();
+        assert!(QueryPlan::new(&query, &QueryRequest::default()).is_err());
+
+        let plan = query_plan("repo:a (repo:b OR -repo:c) run_agent symbol:Agent");
+        assert_eq!(plan.repos, vec!["a", "b"]);
+        assert_eq!(plan.content_highlight.as_deref(), Some("(?:run_agent)"));
+        assert_eq!(plan.symbol_highlight.as_deref(), Some("(?:Agent)"));
+
+        let file = plan.snip(ContentDocument::from(LexicalDocument {
+            relative_path: "src/lib.rs".to_string(),
+            content: "fn main() {}".to_string(),
+            ..Default::default()
+        }));
+        assert_eq!(file.relative_path, "src/lib.rs");
+        assert!(file.snippets.is_empty());
+    }
+}
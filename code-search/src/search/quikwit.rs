@@ -1,196 +1,200 @@
-use crate::{AppState, CLIENT};
-use anyhow::{Error, Result};
+use crate::AppState;
+use anyhow::Result;
 use common::ast::graph_code_pluck::ContentDocument;
-use log::{debug, error};
-use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use common::lexical::{repo_clause, LexicalDocument};
+use futures::future::ready;
+use futures::pin_mut;
+use futures::stream::{self, Stream, StreamExt, TryStreamExt};
+use log::debug;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
-#[derive(Debug, Serialize, Deserialize)]
-struct BodyRes {
-    query: String,
-    max_hits: i32,
-}
+pub use common::lexical::branch_clause;
 
-#[derive(Debug, Serialize, Deserialize)]
-struct ApiResponse {
-    num_hits: i32,            // Change the type to i32 or another appropriate numeric type
-    elapsed_time_micros: i64, // Change the type to i32 or another appropriate numeric type
-    hits: Vec<ResultItem>,
-    errors: Vec<String>,
-}
+// Number of documents fetched per request when listing the files of a repo.
+const FILES_PAGE_SIZE: usize = 500;
+// Number of documents fetched per request when looking a path up, the exact match usually comes
+// first.
+const LOOKUP_PAGE_SIZE: usize = 20;
+// Number of documents looked at before giving up on a path.
+const LOOKUP_MAX_HITS: usize = 200;
+
+/// Streams the documents matching the query, fetching them page by page. Only the `fields` of
+/// the documents are kept, all of them if `fields` is empty; see `LexicalIndex::search_page` for
+/// what the selection saves.
+pub fn stream_documents(
+    index_name: &str,
+    query: &str,
+    page_size: usize,
+    fields: &[&str],
+    app_state: Arc<AppState>,
+) -> impl Stream<Item = Result<LexicalDocument>> {
+    let index_name = index_name.to_string();
+    let query = query.to_string();
+    let fields = fields
+        .iter()
+        .map(|field| field.to_string())
+        .collect::<Vec<_>>();
 
-#[derive(Debug, Serialize, Deserialize)]
-struct ResultItem {
-    relative_path: String,
-    repo_name: String,
-    lang: Option<String>,
-    content: String,
-    symbols: String,
-    line_end_indices: Vec<u8>,
-    symbol_locations: Vec<u8>,
-    is_directory: bool,
-    last_commit: String,
-    repo_ref: String,
-    repo_disk_path: String,
-    unique_hash: String,
+    stream::try_unfold(Some(0), move |offset| {
+        let app_state = app_state.clone();
+        let index_name = index_name.clone();
+        let query = query.clone();
+        let fields = fields.clone();
+        async move {
+            let Some(offset) = offset else {
+                return Ok(None);
+            };
+            let fields = fields.iter().map(String::as_str).collect::<Vec<_>>();
+            let page = app_state
+                .lexical_index
+                .search_page(&index_name, &query, offset, page_size, &fields)
+                .await?;
+            debug!(
+                "Fetched {} documents of {} from {}",
+                page.documents.len(),
+                index_name,
+                offset
+            );
+            Ok(Some((page.documents, page.next_offset)))
+        }
+    })
+    .map_ok(|documents| stream::iter(documents.into_iter().map(Ok)))
+    .try_flatten()
 }
 
-// FIXME: This is likely very inefficient for large numbers of files, Quickwit surprisingly
-// doesn't choke for 100-150 files in a non-concurrent environment. For larger repos we may
-// want to go with a paginated approach. We will revisit this once we start choking.
-pub async fn get_all_files_for_repo(
+/// Streams the files and directories of the repo, as indexed from the branch or tag if one is
+/// given. Without a branch, a path indexed from several branches only shows up once.
+///
+/// `clause` narrows the files down further, e.g. to the ones containing a term. Only the
+/// `fields` of the documents are kept, all of them if `fields` is empty.
+pub fn stream_repo_files(
     index_name: &str,
     repo_name: &str,
+    branch: Option<&str>,
+    clause: Option<&str>,
+    fields: &[&str],
     app_state: Arc<AppState>,
-) -> Result<Vec<ContentDocument>> {
-    let config = app_state.configuration.clone();
-
-    let base_url = config.quikwit_db_url.clone();
-
-    let query = "*".to_string();
-
-    let json_data = BodyRes {
-        query,
-        max_hits: 1000, // This is a very high number, if we actually have to serve for 1000 files, this will surly die. We should consider pagination in the near future.
-    };
-
-    let json_string = serde_json::to_string(&json_data).expect("Failed to serialize object");
-
-    let url = format!("{}/api/v1/{}/search", base_url, index_name);
-
-    let response = CLIENT
-        .post(url)
-        .header("Content-Type", "application/json")
-        .body(json_string)
-        .send()
-        .await?;
-
-    let mut response_array: Vec<ContentDocument> = Vec::new();
-
-    if response.status().is_success() {
-        let response_text: String = response.text().await?;
-
-        let parsed_response: Result<ApiResponse, serde_json::Error> =
-            serde_json::from_str(&response_text);
+) -> impl Stream<Item = Result<ContentDocument>> {
+    let mut query = repo_clause(repo_name);
+    if let Some(clause) = clause {
+        query = format!("{} AND ({})", query, clause);
+    }
+    if let Some(branch) = branch {
+        query = format!("{} AND {}", query, branch_clause(branch));
+    }
 
-        match parsed_response {
-            Ok(api_response) => {
-                debug!("Number of files found: {:?}", api_response.hits.len());
-                for result_item in api_response.hits {
-                    if repo_name == result_item.repo_name {
-                        response_array.push(ContentDocument {
-                            relative_path: result_item.relative_path,
-                            repo_name: result_item.repo_name,
-                            lang: result_item.lang,
-                            content: result_item.content,
-                            repo_ref: result_item.repo_ref,
-                            line_end_indices: result_item.line_end_indices,
-                            symbol_locations: result_item.symbol_locations,
-                            symbols: result_item.symbols,
-                        });
-                    }
-                }
-            }
-            Err(err) => {
-                error!("Failed to parse JSON response: {}", err);
+    // the repo clause also matches the repos whose name starts with this one, the names and
+    // paths are always read to tell them apart.
+    let mut fields = fields.to_vec();
+    if !fields.is_empty() {
+        for field in ["repo_name", "relative_path"] {
+            if !fields.contains(&field) {
+                fields.push(field);
             }
         }
-    } else {
-        error!("Request was not successful: {}", response.status());
     }
 
-    if !response_array.is_empty() {
-        Ok(response_array.clone())
-    } else {
-        Ok(vec![])
-    }
+    let repo_name = repo_name.to_string();
+    let mut seen_paths = HashSet::new();
+    stream_documents(index_name, &query, FILES_PAGE_SIZE, &fields, app_state)
+        .try_filter(move |hit| {
+            ready(hit.repo_name == repo_name && seen_paths.insert(hit.relative_path.clone()))
+        })
+        .map_ok(ContentDocument::from)
 }
 
 pub async fn get_file_from_quickwit(
     index_name: &str,
     search_field: &str,
     search_query: &str,
+    branch: Option<&str>,
     app_state: Arc<AppState>,
 ) -> Result<Option<ContentDocument>> {
-    let query = if !search_field.is_empty() {
+    let mut query = if !search_field.is_empty() {
         format!("{}:{}", search_field, search_query)
     } else {
         search_query.to_owned()
     };
-    let response_array = search_quickwit(index_name, &query, app_state).await?;
-    let filtered_response_array: Vec<ContentDocument> = response_array
-        .into_iter()
-        .filter((|doc| doc.relative_path == search_query))
-        .collect();
+    if let Some(branch) = branch {
+        query = format!("{} AND {}", query, branch_clause(branch));
+    }
 
-    let paths: Vec<_> = filtered_response_array
-        .clone()
-        .into_iter()
-        .map(|c| c.relative_path)
-        .collect::<HashSet<_>>() // Removes duplicates
-        .into_iter()
-        .collect::<Vec<_>>();
-    debug!("Quickwit paths: {:?}", paths);
+    // the paths are matched as phrases, the pages are read until the exact path shows up.
+    let documents = stream_documents(index_name, &query, LOOKUP_PAGE_SIZE, &[], app_state)
+        .take(LOOKUP_MAX_HITS)
+        .try_filter(|doc| ready(doc.relative_path == search_query));
+    pin_mut!(documents);
+    let document = documents.try_next().await?;
+    debug!("Found {} in quickwit: {}", search_query, document.is_some());
 
-    return Ok(filtered_response_array.first().or(None).cloned());
+    Ok(document.map(ContentDocument::from))
 }
 
-pub async fn search_quickwit(
+/// Reads the last commit dates of the paths of the repo, in seconds since the epoch. A path
+/// indexed from several branches gets its latest date, the paths without history are missing.
+pub async fn get_commit_dates(
     index_name: &str,
-    query: &str,
+    repo_name: &str,
+    paths: &[&str],
     app_state: Arc<AppState>,
-) -> Result<Vec<ContentDocument>, Error> {
-    let config = app_state.configuration.clone();
-
-    let base_url = config.quikwit_db_url.clone();
-
-    let json_data = BodyRes {
-        query: query.to_string(),
-        max_hits: 10,
-    };
-
-    let json_string = serde_json::to_string(&json_data).expect("Failed to serialize object");
-
-    let url = format!("{}/api/v1/{}/search", base_url, index_name);
-
-    let response = CLIENT
-        .post(url)
-        .header("Content-Type", "application/json")
-        .body(json_string)
-        .send()
-        .await?;
-
-    let mut response_array: Vec<ContentDocument> = Vec::new();
-
-    if response.status().is_success() {
-        let response_text: String = response.text().await?;
-
-        let parsed_response: Result<ApiResponse, serde_json::Error> =
-            serde_json::from_str(&response_text);
-
-        match parsed_response {
-            Ok(api_response) => {
-                for result_item in api_response.hits {
-                    response_array.push(ContentDocument {
-                        relative_path: result_item.relative_path,
-                        repo_name: result_item.repo_name,
-                        lang: result_item.lang,
-                        content: result_item.content,
-                        repo_ref: result_item.repo_ref,
-                        line_end_indices: result_item.line_end_indices,
-                        symbol_locations: result_item.symbol_locations,
-                        symbols: result_item.symbols,
-                    });
-                }
-            }
-            Err(err) => {
-                error!("Failed to parse JSON response: {}", err);
-            }
-        }
-    } else {
-        error!("Request was not successful: {}", response.status());
+) -> Result<HashMap<String, i64>> {
+    if paths.is_empty() {
+        return Ok(HashMap::new());
     }
+    let clause = paths
+        .iter()
+        .map(|path| format!("relative_path:\"{}\"", path.replace('"', "\\\"")))
+        .collect::<Vec<_>>()
+        .join(" OR ");
+    let query = format!("{} AND ({})", repo_clause(repo_name), clause);
+
+    // the paths are matched as phrases, the pages are read until the exact paths show up.
+    let mut dates = HashMap::new();
+    stream_documents(
+        index_name,
+        &query,
+        FILES_PAGE_SIZE,
+        &["repo_name", "relative_path", "last_commit_date"],
+        app_state,
+    )
+    .take(LOOKUP_MAX_HITS * paths.len())
+    .try_for_each(|doc| {
+        if doc.repo_name == repo_name
+            && doc.last_commit_date > 0
+            && paths.contains(&doc.relative_path.as_str())
+        {
+            let date = dates.entry(doc.relative_path).or_insert(0);
+            *date = doc.last_commit_date.max(*date);
+        }
+        ready(Ok(()))
+    })
+    .await?;
+    debug!(
+        "Found the commit dates of {} paths of {}",
+        dates.len(),
+        repo_name
+    );
+
+    Ok(dates)
+}
 
-    Ok(response_array)
+// Returns up to `max_hits` documents matching the query, best first.
+pub async fn search_quickwit(
+    index_name: &str,
+    query: &str,
+    max_hits: usize,
+    app_state: Arc<AppState>,
+) -> Result<Vec<ContentDocument>> {
+    stream_documents(
+        index_name,
+        query,
+        max_hits.min(FILES_PAGE_SIZE),
+        &[],
+        app_state,
+    )
+    .take(max_hits)
+    .map_ok(ContentDocument::from)
+    .try_collect()
+    .await
 }
@@ -1,222 +1,310 @@
-extern crate hashbrown;
 extern crate strsim;
 
 use crate::search::payload::{CodeExtractMeta, PathExtractMeta, SymbolPayload};
-use hashbrown::HashMap;
+use crate::search::quikwit::get_commit_dates;
+use crate::AppState;
+use anyhow::{Context, Result};
+use common::hasher::generate_quikwit_index_name;
+use log::{debug, error};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 use strsim::levenshtein;
 
 // declare global variable for POWF_FACTOR
 const POWF_FACTOR: f32 = 3.0;
+const SECONDS_PER_DAY: f32 = 24.0 * 3600.0;
+// Directories and file name affixes of the test files.
+const TEST_DIRS: [&str; 6] = ["test", "tests", "__tests__", "spec", "specs", "testdata"];
+const TEST_PREFIXES: [&str; 1] = ["test_"];
+const TEST_SUFFIXES: [&str; 4] = ["_test", ".test", "_spec", ".spec"];
 
-// create a table for weights of symbols
-pub fn symbol_weights() -> HashMap<String, f32> {
-    let mut weights = HashMap::new();
-    weights.insert("variable".to_string(), 1.0);
-    weights.insert("function".to_string(), 9.0);
-    weights.insert("module".to_string(), 8.0);
-    weights.insert("struct".to_string(), 8.0);
-    weights.insert("field".to_string(), 3.0);
-    weights.insert("unknown".to_string(), 2.0);
-    weights.clone()
+/// How the symbols found by the symbol search and the paths containing them are scored. The
+/// config is read from a JSON file, see `ranking.json` at the root of the crate.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct RankingConfig {
+    /// The weights of the languages without their own.
+    pub default: RankingWeights,
+    /// The weights of a language, keyed by language id, e.g. `Rust`. The weights missing from a
+    /// language take their default value, not the one of `default`.
+    pub languages: HashMap<String, RankingWeights>,
 }
 
-pub fn rank_symbol_payloads(payloads: &Vec<SymbolPayload>) -> Vec<PathExtractMeta> {
+/// The weights of the ranking features. The default weights only score the symbols, the path
+/// features are off.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct RankingWeights {
+    /// Score of a symbol by its type, e.g. `function`, scaled by its similarity to the query.
+    pub symbol_kinds: HashMap<String, f32>,
+    /// Score of the symbol types missing from `symbol_kinds`.
+    pub unknown_symbol_kind: f32,
+    /// Factor of the score of a symbol by its node kind, `def` or `ref`, 1 if missing.
+    pub node_kinds: HashMap<String, f32>,
+    /// Bonus of the symbols defined in the root scope of their file.
+    pub global: Bonus,
+    /// Similarity to the query above which the score of a symbol is boosted.
+    pub semantic_threshold: f32,
+    /// Bonus of a symbol found again in the same path.
+    pub repeat: Bonus,
+    /// Bonus of a symbol found more than `frequent_after` times in the same path, replacing its
+    /// score.
+    pub frequent: Bonus,
+    pub frequent_after: usize,
+    /// Bonus of a symbol containing, or contained in, another symbol found.
+    pub substring: Bonus,
+    /// Bonus of a symbol less than `similar_distance` edits away from another symbol found.
+    pub similar: Bonus,
+    pub similar_distance: usize,
+    /// Penalty of a path per directory level, its score is divided by `1 + path_depth * depth`.
+    pub path_depth: f32,
+    /// Share of its score a test file loses, from 0 to 1.
+    pub test_file: f32,
+    /// Boost of the recently changed paths.
+    pub recency: Recency,
+}
+
+/// A bonus growing with the similarity of a symbol to the query: `weight * similarity^exponent`.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+pub struct Bonus {
+    pub weight: f32,
+    pub exponent: f32,
+}
+
+/// The share of its score a path changed right now gains, halved every `half_life_days`.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct Recency {
+    pub weight: f32,
+    pub half_life_days: f32,
+}
+
+impl Default for RankingWeights {
+    fn default() -> Self {
+        Self {
+            symbol_kinds: [
+                ("variable", 1.0),
+                ("function", 9.0),
+                ("module", 8.0),
+                ("struct", 8.0),
+                ("field", 3.0),
+            ]
+            .into_iter()
+            .map(|(kind, weight)| (kind.to_string(), weight))
+            .collect(),
+            unknown_symbol_kind: 2.0,
+            node_kinds: HashMap::new(),
+            global: Bonus::new(500.0, 5.0),
+            semantic_threshold: 0.35,
+            repeat: Bonus::new(200.0, 5.0),
+            frequent: Bonus::new(1000.0, 5.0),
+            frequent_after: 3,
+            substring: Bonus::new(10.0, POWF_FACTOR),
+            similar: Bonus::new(5.0, POWF_FACTOR),
+            similar_distance: 3,
+            path_depth: 0.0,
+            test_file: 0.0,
+            recency: Recency::default(),
+        }
+    }
+}
+
+impl Default for Recency {
+    fn default() -> Self {
+        Self {
+            weight: 0.0,
+            half_life_days: 90.0,
+        }
+    }
+}
+
+impl Bonus {
+    fn new(weight: f32, exponent: f32) -> Self {
+        Self { weight, exponent }
+    }
+
+    fn score(&self, similarity: f32) -> f32 {
+        self.weight * similarity.powf(self.exponent)
+    }
+}
+
+impl RankingConfig {
+    /// Reads the config from the JSON file at `RANKING_CONFIG_PATH`, the default one if the
+    /// variable isn't set.
+    pub fn from_env() -> Result<Self> {
+        match env::var("RANKING_CONFIG_PATH") {
+            Ok(path) => Self::from_file(&path),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    pub fn from_file(path: &str) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read the ranking config {}", path))?;
+        serde_json::from_str(&content).with_context(|| format!("Invalid ranking config {}", path))
+    }
+
+    /// The weights of a language, matched case insensitively.
+    pub fn weights(&self, lang: &str) -> &RankingWeights {
+        self.languages
+            .iter()
+            .find(|(id, _)| id.eq_ignore_ascii_case(lang))
+            .map(|(_, weights)| weights)
+            .unwrap_or(&self.default)
+    }
+
+    /// Whether the paths are boosted by recency, the commit dates of the paths are only needed
+    /// then.
+    pub fn uses_recency(&self) -> bool {
+        std::iter::once(&self.default)
+            .chain(self.languages.values())
+            .any(|weights| weights.recency.weight > 0.0)
+    }
+}
+
+impl RankingWeights {
+    fn symbol_kind(&self, symbol_type: &str) -> f32 {
+        self.symbol_kinds
+            .get(symbol_type)
+            .copied()
+            .unwrap_or(self.unknown_symbol_kind)
+    }
+
+    // Scales the score of a path by its path features, recording their effect in `history`.
+    fn score_path(
+        &self,
+        path: &str,
+        mut score: f32,
+        commit_date: Option<i64>,
+        now: i64,
+        history: &mut Vec<String>,
+    ) -> f32 {
+        if self.path_depth > 0.0 {
+            let depth = path.matches('/').count();
+            score /= 1.0 + self.path_depth * depth as f32;
+            history.push(format!("Scaled to {} for path depth {}", score, depth));
+        }
+
+        if self.test_file > 0.0 && is_test_path(path) {
+            score *= 1.0 - self.test_file.min(1.0);
+            history.push(format!("Scaled to {} for test file", score));
+        }
+
+        if let Some(date) = commit_date.filter(|&date| self.recency.weight > 0.0 && date > 0) {
+            let age_days = (now - date).max(0) as f32 / SECONDS_PER_DAY;
+            let boost = self.recency.weight * 0.5f32.powf(age_days / self.recency.half_life_days);
+            score *= 1.0 + boost;
+            history.push(format!(
+                "Scaled to {} for last change {} days ago",
+                score, age_days as i64
+            ));
+        }
+
+        score
+    }
+}
+
+/// Ranks the paths with the ranking config of the app, reading the commit dates of the paths
+/// from the lexical index if the config boosts recently changed paths.
+pub async fn rank_symbols(
+    payloads: &[SymbolPayload],
+    repo_name: &str,
+    app_state: Arc<AppState>,
+) -> Vec<PathExtractMeta> {
+    let config = &app_state.configuration.ranking;
+    let mut commit_dates = HashMap::new();
+    if config.uses_recency() {
+        let mut paths = payloads
+            .iter()
+            .flat_map(|payload| payload.relative_paths.iter().map(String::as_str))
+            .collect::<Vec<_>>();
+        paths.sort_unstable();
+        paths.dedup();
+        let index_name = generate_quikwit_index_name(repo_name);
+        match get_commit_dates(&index_name, repo_name, &paths, app_state.clone()).await {
+            Ok(dates) => commit_dates = dates,
+            // the paths are still ranked, without recency.
+            Err(e) => error!("Failed to read the commit dates of {}: {:?}", repo_name, e),
+        }
+    }
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs() as i64);
+    rank_symbol_payloads(payloads, config, &commit_dates, now)
+}
+
+/// Ranks the paths containing the symbols found by the symbol search. `commit_dates` has the
+/// last commit dates of the paths, in seconds since the epoch like `now`, and is only read if
+/// the config boosts recently changed paths.
+pub fn rank_symbol_payloads(
+    payloads: &[SymbolPayload],
+    config: &RankingConfig,
+    commit_dates: &HashMap<String, i64>,
+    now: i64,
+) -> Vec<PathExtractMeta> {
     let mut path_scores: HashMap<String, f32> = HashMap::new();
     let mut path_history: HashMap<String, Vec<String>> = HashMap::new();
+    // the language of a path, the one of the first symbol found in it.
+    let mut path_langs: HashMap<String, String> = HashMap::new();
     // create map to store the relative_path + symbol string and count the number of times it appears.
     let mut path_symbol_set: HashMap<String, usize> = HashMap::new();
 
     let mut code_extract_meta_map: HashMap<String, Vec<CodeExtractMeta>> = HashMap::new();
 
-    for (i, val) in payloads.iter().enumerate() {
-        let payload = &payloads[i];
+    for (i, payload) in payloads.iter().enumerate() {
         let score = payload.score.unwrap_or(0.0);
-        // check if node_type is "ref"
         for (index, path) in payload.relative_paths.iter().enumerate() {
-            let mut path_score = 0.0;
+            let lang = payload.lang_ids.get(index).map_or("", String::as_str);
+            let weights = config.weights(lang);
+            let node_kind = &payload.node_kinds[index];
+            let symbol_type = &payload.symbol_types[index];
             let mut history = Vec::new();
-            // print the node type and path
-            println!(" in loop {}: {}", payload.node_kinds[index], path);
-            // print symbol type and is global
-            println!(
-                "in loop {}: {}",
-                payload.symbol_types[index], payload.is_globals[index]
+            debug!(
+                "Ranking {} {} {} in {}, global: {}",
+                node_kind, symbol_type, payload.symbol, path, payload.is_globals[index]
             );
+            path_langs
+                .entry(path.clone())
+                .or_insert_with(|| lang.to_string());
 
-            // print if the symbol type is ref
-            if payload.node_kinds[index] == "ref".to_string() {
-                println!("xxxxxx ref is here");
-            }
-            // concatenate the relative_path and symbol string and store in path_symbol
-            let path_symbol = format!("{}{}", path, payload.symbol);
-            // check if path_symbol is in path_symbol_set
-            if path_symbol_set.contains_key(&path_symbol) {
-                // if it is, increment the count by 1
-                *path_symbol_set.get_mut(&path_symbol).unwrap() += 1;
-            } else {
-                // if it is not, add it to the map with a count of 1
-                path_symbol_set.insert(path_symbol.clone(), 1);
-            }
+            // count the number of times the symbol was found in the path.
+            let count = path_symbol_set
+                .entry(format!("{}{}", path, payload.symbol))
+                .or_insert(0);
+            *count += 1;
+            let count = *count;
 
-            // if path_symbol is greater than 3 just print and continue
-            if path_symbol_set[&path_symbol] > 3 {
-                let repeat_bonus = 1000.0 * score.powf(5.0);
-                // add a bonus score of 1000.0
-                path_score += repeat_bonus;
-                // push to history
+            let mut path_score = if count > weights.frequent_after {
+                let frequent_bonus = weights.frequent.score(score);
                 history.push(format!(
                     "Scored {} for repeat symbol {} with score {}",
-                    repeat_bonus, payload.symbol, score
-                ));
-
-                // store the metadata of a symbol for a given path,
-                // and the contribution of the symbol to the path's score.
-                let code_extract_meta = CodeExtractMeta {
-                    symbol: payload.symbol.clone(),
-                    node_kind: payload.node_kinds[index].clone(),
-                    symbol_type: payload.symbol_types[index].clone(),
-                    is_global: payload.is_globals[index],
-                    score: path_score,
-                    start_byte: payload.start_bytes[index],
-                    end_byte: payload.end_bytes[index],
-                };
-
-                // store the metadata in the code_extract_meta map
-                code_extract_meta_map
-                    .entry(path.clone())
-                    .or_insert(Vec::new())
-                    .push(code_extract_meta);
-
-                *path_scores.entry(path.clone()).or_insert(0.0) += path_score;
-                path_history
-                    .entry(path.clone())
-                    .or_insert(Vec::new())
-                    .append(&mut history);
-                println!(
-                    "Greater than 3 {}: {}: {}",
-                    path_symbol, path_symbol_set[&path_symbol], repeat_bonus
-                );
-                continue;
-            }
-
-            // Score based on the type of symbol
-            match payload.symbol_types[index].as_str() {
-                "variable" => path_score += 1.0,
-                "function" => path_score += 9.0,
-                "module" => path_score += 8.0,
-                "struct" => path_score += 8.0,
-                "field" => path_score += 3.0,
-                // print the type and add score of 2.0
-                _ => {
-                    println!("Unknown symbol type: {}", payload.symbol_types[index]);
-                    path_score += 2.0;
-                }
-            }
-
-            // multiple path_score by score power of three
-            path_score = path_score * score;
-            history.push(format!(
-                "Scored {} for symbol {} symbol type {}",
-                path_score, payload.symbol, payload.symbol_types[index]
-            ));
-
-            // Score based on is_global
-            if payload.is_globals[index] {
-                let global_score = 500.0 * score.powf(5.0);
-                path_score += global_score;
-                history.push(format!(
-                    "Scored {} for global symbol {} with score {}",
-                    global_score, payload.symbol, score
+                    frequent_bonus, payload.symbol, score
                 ));
-            }
-
-            // Score based on the semantic score, if available
-            if let Some(semantic_score) = payload.score {
-                if semantic_score > 0.35 {
-                    let bonus = (semantic_score.powf(2.0) * (1.0 + score).powf(POWF_FACTOR))
-                        * (path_score / 10.0);
-                    path_score += bonus;
-                    history.push(format!(
-                        "Scored {} for semantic score {} for symbol {}",
-                        bonus, semantic_score, payload.symbol
-                    ));
-                }
-            }
+                frequent_bonus
+            } else {
+                score_symbol(payloads, i, index, count, weights, &mut history)
+            };
 
-            // If path_symbol is greater than 1 continue the loop, dont do any operation
-            if path_symbol_set[&path_symbol] > 1 {
-                // give a bonus and continue
-                let repeat_bonus = 200.0 * score.powf(5.0);
-                println!(
-                    "Greater than 1 {}: {}: {}",
-                    path_symbol, path_symbol_set[&path_symbol], repeat_bonus
-                );
-                // push to history and print
-                path_score += repeat_bonus;
+            // Score based on the node kind, definitions usually matter more than references
+            if let Some(&factor) = weights.node_kinds.get(node_kind) {
+                path_score *= factor;
                 history.push(format!(
-                    "Scored {} for repeat symbol {} with score {}",
-                    repeat_bonus, payload.symbol, score
+                    "Scaled to {} for node kind {} of symbol {}",
+                    path_score, node_kind, payload.symbol
                 ));
-                println!("Skipping {} because it is greater than 1", path_symbol);
-                // store the metadata of a symbol for a given path,
-                // and the contribution of the symbol to the path's score.
-                let code_extract_meta = CodeExtractMeta {
-                    symbol: payload.symbol.clone(),
-                    node_kind: payload.node_kinds[index].clone(),
-                    symbol_type: payload.symbol_types[index].clone(),
-                    is_global: payload.is_globals[index],
-                    score: path_score,
-                    start_byte: payload.start_bytes[index],
-                    end_byte: payload.end_bytes[index],
-                };
-
-                // store the metadata in the code_extract_meta map
-                code_extract_meta_map
-                    .entry(path.clone())
-                    .or_insert(Vec::new())
-                    .push(code_extract_meta);
-
-                *path_scores.entry(path.clone()).or_insert(0.0) += path_score;
-                path_history
-                    .entry(path.clone())
-                    .or_insert(Vec::new())
-                    .append(&mut history);
-                continue;
-            }
-
-            // Check for symbol similarities with other payloads
-            for j in (i + 1)..payloads.len() {
-                let other_payload = &payloads[j];
-
-                // Check if one symbol is a substring of another
-                if payload.symbol.contains(&other_payload.symbol)
-                    || other_payload.symbol.contains(&payload.symbol)
-                {
-                    let substr_score = 10.0 * score.powf(POWF_FACTOR);
-                    path_score += substr_score;
-                    history.push(format!(
-                        "Scored {} for symbol {} being a substring of {}, in parent path {}",
-                        substr_score, payload.symbol, other_payload.symbol, path
-                    ));
-                }
-
-                let distance = levenshtein(&payload.symbol, &other_payload.symbol);
-                if distance < 3 {
-                    let levenshtein_score = 5.0 * score.powf(POWF_FACTOR);
-                    path_score += levenshtein_score;
-                    history.push(format!(
-                        "Scored {} for low Levenshtein distance of {} between symbols {} and {}",
-                        levenshtein_score, distance, payload.symbol, other_payload.symbol
-                    ));
-                }
             }
 
             // store the metadata of a symbol for a given path,
             // and the contribution of the symbol to the path's score.
             let code_extract_meta = CodeExtractMeta {
                 symbol: payload.symbol.clone(),
-                node_kind: payload.node_kinds[index].clone(),
-                symbol_type: payload.symbol_types[index].clone(),
+                node_kind: node_kind.clone(),
+                symbol_type: symbol_type.clone(),
                 is_global: payload.is_globals[index],
                 score: path_score,
                 start_byte: payload.start_bytes[index],
@@ -239,16 +327,24 @@ pub fn rank_symbol_payloads(payloads: &Vec<SymbolPayload>) -> Vec<PathExtractMet
 
     // contruct PathExtractMeta from the data
     let mut final_scores: Vec<PathExtractMeta> = path_scores
-        .iter()
-        .map(|(path, &score)| {
+        .into_iter()
+        .map(|(path, score)| {
+            let mut history = path_history.remove(&path).unwrap_or_default();
+            let score = config.weights(&path_langs[&path]).score_path(
+                &path,
+                score,
+                commit_dates.get(&path).copied(),
+                now,
+                &mut history,
+            );
             // get the value for path from code_extract_meta_map and sort the value array by score
-            let mut code_extract_meta = code_extract_meta_map.get(path).unwrap().clone();
+            let mut code_extract_meta = code_extract_meta_map.remove(&path).unwrap_or_default();
             code_extract_meta.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
             PathExtractMeta {
-                path: path.clone(),
-                score: score,
-                history: path_history.get(path).unwrap().clone(),
-                code_extract_meta: code_extract_meta.clone(),
+                path,
+                score,
+                history,
+                code_extract_meta,
             }
         })
         .collect();
@@ -258,3 +354,200 @@ pub fn rank_symbol_payloads(payloads: &Vec<SymbolPayload>) -> Vec<PathExtractMet
 
     final_scores
 }
+
+// Scores the `index`th occurrence of the `i`th symbol, found `count` times in its path so far.
+fn score_symbol(
+    payloads: &[SymbolPayload],
+    i: usize,
+    index: usize,
+    count: usize,
+    weights: &RankingWeights,
+    history: &mut Vec<String>,
+) -> f32 {
+    let payload = &payloads[i];
+    let path = &payload.relative_paths[index];
+    let score = payload.score.unwrap_or(0.0);
+
+    // Score based on the type of symbol, scaled by the similarity to the query
+    let symbol_type = &payload.symbol_types[index];
+    let mut path_score = weights.symbol_kind(symbol_type) * score;
+    history.push(format!(
+        "Scored {} for symbol {} symbol type {}",
+        path_score, payload.symbol, symbol_type
+    ));
+
+    // Score based on is_global
+    if payload.is_globals[index] {
+        let global_score = weights.global.score(score);
+        path_score += global_score;
+        history.push(format!(
+            "Scored {} for global symbol {} with score {}",
+            global_score, payload.symbol, score
+        ));
+    }
+
+    // Score based on the semantic score, if available
+    if let Some(semantic_score) = payload.score {
+        if semantic_score > weights.semantic_threshold {
+            let bonus =
+                (semantic_score.powf(2.0) * (1.0 + score).powf(POWF_FACTOR)) * (path_score / 10.0);
+            path_score += bonus;
+            history.push(format!(
+                "Scored {} for semantic score {} for symbol {}",
+                bonus, semantic_score, payload.symbol
+            ));
+        }
+    }
+
+    // A symbol found again in the path gets a bonus, its similarities were already scored
+    if count > 1 {
+        let repeat_bonus = weights.repeat.score(score);
+        path_score += repeat_bonus;
+        history.push(format!(
+            "Scored {} for repeat symbol {} with score {}",
+            repeat_bonus, payload.symbol, score
+        ));
+        return path_score;
+    }
+
+    // Check for symbol similarities with other payloads
+    for other_payload in &payloads[i + 1..] {
+        // Check if one symbol is a substring of another
+        if payload.symbol.contains(&other_payload.symbol)
+            || other_payload.symbol.contains(&payload.symbol)
+        {
+            let substr_score = weights.substring.score(score);
+            path_score += substr_score;
+            history.push(format!(
+                "Scored {} for symbol {} being a substring of {}, in parent path {}",
+                substr_score, payload.symbol, other_payload.symbol, path
+            ));
+        }
+
+        let distance = levenshtein(&payload.symbol, &other_payload.symbol);
+        if distance < weights.similar_distance {
+            let levenshtein_score = weights.similar.score(score);
+            path_score += levenshtein_score;
+            history.push(format!(
+                "Scored {} for low Levenshtein distance of {} between symbols {} and {}",
+                levenshtein_score, distance, payload.symbol, other_payload.symbol
+            ));
+        }
+    }
+
+    path_score
+}
+
+// Whether the path is a test file, by its directories or its file name.
+fn is_test_path(path: &str) -> bool {
+    let mut components = path.rsplit('/');
+    let file_name = components.next().unwrap_or_default().to_ascii_lowercase();
+    let stem = file_name
+        .rsplit_once('.')
+        .map_or(file_name.as_str(), |(stem, _)| stem);
+
+    TEST_PREFIXES.iter().any(|prefix| stem.starts_with(prefix))
+        || TEST_SUFFIXES.iter().any(|suffix| stem.ends_with(suffix))
+        || components.any(|dir| TEST_DIRS.contains(&dir.to_ascii_lowercase().as_str()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload(symbol: &str, score: f32, occurrences: &[(&str, &str, &str)]) -> SymbolPayload {
+        SymbolPayload {
+            repo_name: "nezuko".to_string(),
+            symbol: symbol.to_string(),
+            symbol_types: occurrences.iter().map(|o| o.1.to_string()).collect(),
+            lang_ids: occurrences.iter().map(|o| o.2.to_string()).collect(),
+            is_globals: vec![false; occurrences.len()],
+            start_bytes: vec![0; occurrences.len()],
+            end_bytes: vec![10; occurrences.len()],
+            relative_paths: occurrences.iter().map(|o| o.0.to_string()).collect(),
+            node_kinds: vec!["def".to_string(); occurrences.len()],
+            score: Some(score),
+            ..Default::default()
+        }
+    }
+
+    fn ranked_paths(payloads: &[SymbolPayload], config: &RankingConfig) -> Vec<String> {
+        rank_symbol_payloads(payloads, config, &HashMap::new(), 0)
+            .into_iter()
+            .map(|meta| meta.path)
+            .collect()
+    }
+
+    #[test]
+    fn test_symbol_kinds() {
+        let payloads = vec![
+            payload("retry", 0.3, &[("src/jobs.rs", "function", "Rust")]),
+            payload("backoff", 0.3, &[("src/config.rs", "variable", "Rust")]),
+        ];
+        let ranked = rank_symbol_payloads(&payloads, &RankingConfig::default(), &HashMap::new(), 0);
+        assert_eq!(ranked[0].path, "src/jobs.rs");
+        assert!((ranked[0].score - 9.0 * 0.3).abs() < 1e-5);
+        assert!((ranked[1].score - 0.3).abs() < 1e-5);
+
+        // the weights of a language only apply to its symbols.
+        let config: RankingConfig =
+            serde_json::from_str(r#"{"languages": {"rust": {"symbol_kinds": {"variable": 20}}}}"#)
+                .unwrap();
+        assert_eq!(
+            ranked_paths(&payloads, &config),
+            vec!["src/config.rs", "src/jobs.rs"]
+        );
+        assert_eq!(config.weights("Python"), &RankingWeights::default());
+        assert_eq!(config.weights("Rust").unknown_symbol_kind, 2.0);
+    }
+
+    #[test]
+    fn test_path_features() {
+        let payloads = vec![payload(
+            "retry",
+            0.3,
+            &[
+                ("tests/jobs.rs", "function", "Rust"),
+                ("src/jobs/retry/mod.rs", "function", "Rust"),
+                ("src/jobs.rs", "function", "Rust"),
+            ],
+        )];
+
+        let mut config = RankingConfig::default();
+        config.default.test_file = 0.5;
+        assert_eq!(
+            ranked_paths(&payloads, &config)[2],
+            "tests/jobs.rs".to_string()
+        );
+
+        config.default.path_depth = 0.5;
+        assert_eq!(
+            ranked_paths(&payloads, &config),
+            vec!["src/jobs.rs", "src/jobs/retry/mod.rs", "tests/jobs.rs"]
+        );
+
+        // a recent change outweighs the depth of the path.
+        config.default.recency.weight = 2.0;
+        assert!(config.uses_recency());
+        let now = 1_700_000_000;
+        let commit_dates = HashMap::from([("src/jobs/retry/mod.rs".to_string(), now)]);
+        let ranked = rank_symbol_payloads(&payloads, &config, &commit_dates, now);
+        assert_eq!(ranked[0].path, "src/jobs/retry/mod.rs");
+        assert!(ranked[0]
+            .history
+            .last()
+            .unwrap()
+            .contains("last change 0 days ago"));
+    }
+
+    #[test]
+    fn test_is_test_path() {
+        assert!(is_test_path("tests/jobs.rs"));
+        assert!(is_test_path("src/__tests__/jobs.ts"));
+        assert!(is_test_path("src/jobs_test.go"));
+        assert!(is_test_path("src/jobs.spec.ts"));
+        assert!(is_test_path("test_jobs.py"));
+        assert!(!is_test_path("src/testing.rs"));
+        assert!(!is_test_path("src/contest/jobs.rs"));
+    }
+}
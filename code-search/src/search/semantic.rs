@@ -1,8 +1,7 @@
-use crate::search::semantic::SemanticError::QdrantInitializationError;
+use crate::search::semantic::SemanticError::VectorStoreInitializationError;
 use anyhow::Result;
 use common::hasher::generate_qdrant_index_name;
-use log::debug;
-use std::{str, time::Duration};
+use log::{debug, error};
 use thiserror::Error;
 
 use crate::{
@@ -11,33 +10,22 @@ use crate::{
 };
 use std::sync::Arc;
 
-use ndarray::Axis;
-use ort::tensor::OrtOwnedTensor;
-use ort::value::Value;
-use ort::{Environment, ExecutionProvider, GraphOptimizationLevel, LoggingLevel, SessionBuilder};
-use qdrant_client::{
-    prelude::{QdrantClient, QdrantClientConfig},
-    qdrant::{
-        r#match::MatchValue, with_payload_selector, with_vectors_selector, Condition,
-        FieldCondition, Filter, Match, ScoredPoint, SearchPoints, WithPayloadSelector,
-        WithVectorsSelector,
-    },
-};
+use common::embedding::Embedder;
+use common::vector::{FieldMatch, ScoredPoint, SearchRequest, VectorFilter, VectorStore};
 
 use crate::Configuration;
 
 pub struct Semantic {
     pub qdrant_collection_name: String,
-    pub qdrant: QdrantClient,
-    pub tokenizer: tokenizers::Tokenizer,
-    pub session: ort::Session,
+    pub vector_store: Arc<dyn VectorStore>,
+    pub embedder: Arc<dyn Embedder>,
 }
 
 #[derive(Error, Debug)]
 pub enum SemanticError {
-    /// Represents failure to initialize Qdrant client
-    #[error("Qdrant initialization failed. Is Qdrant running on `qdrant-url`?")]
-    QdrantInitializationError,
+    /// Represents failure to initialize the vector store
+    #[error("Vector store initialization failed. Is Qdrant running on `qdrant-url`?")]
+    VectorStoreInitializationError,
 
     #[error("ONNX runtime error")]
     OnnxRuntimeError {
@@ -52,111 +40,24 @@ pub enum SemanticError {
     },
 }
 
-// fetch the qdrant client
-pub async fn get_qdrant_client(config: &Configuration) -> Result<QdrantClient, SemanticError> {
-    // if api key is not set, then initialize the qdrant client without the api key
-    if config.qdrant_api_key.is_none() {
-        let qdrant = QdrantClient::new(Some(
-            QdrantClientConfig::from_url(&config.semantic_db_url)
-                .with_timeout(Duration::from_secs(30))
-                .with_connect_timeout(Duration::from_secs(30)),
-        ))?;
-        return Ok(qdrant);
-    }
-    let qdrant = QdrantClient::new(Some(
-        QdrantClientConfig::from_url(&config.semantic_db_url)
-            .with_timeout(Duration::from_secs(30))
-            .with_connect_timeout(Duration::from_secs(30))
-            .with_api_key(config.qdrant_api_key.clone()),
-    ))?;
-
-    Ok(qdrant)
-}
-
 impl Semantic {
     pub async fn initialize(config: Configuration) -> Result<Self, SemanticError> {
-        let qdrant = get_qdrant_client(&config).await;
-
-        if qdrant.is_err() {
-            return Err(QdrantInitializationError);
-        }
-
-        let qdrant = qdrant.unwrap();
-        let environment = Arc::new(
-            Environment::builder()
-                .with_name("Encode")
-                .with_log_level(LoggingLevel::Warning)
-                .with_execution_providers([ExecutionProvider::CPU(Default::default())])
-                .with_telemetry(false)
-                .build()?,
-        );
-
-        let threads = if let Ok(v) = std::env::var("NUM_OMP_THREADS") {
-            str::parse(&v).unwrap_or(1)
-        } else {
-            1
-        };
+        let vector_store = config.vector_store.build().map_err(|e| {
+            error!("Failed to build the vector store: {:?}", e);
+            VectorStoreInitializationError
+        })?;
 
         Ok(Self {
-            qdrant: qdrant.into(),
-            tokenizer: tokenizers::Tokenizer::from_file(config.tokenizer_path.as_str())
-                .unwrap()
-                .into(),
-            session: SessionBuilder::new(&environment)?
-                .with_optimization_level(GraphOptimizationLevel::Level3)?
-                .with_intra_threads(threads)?
-                .with_model_from_file(config.model_path)?
-                .into(),
+            vector_store,
+            embedder: config.embedder.build().await?,
             qdrant_collection_name: config.symbol_collection_name,
         })
     }
 
-    pub fn embed(&self, sequence: &str) -> anyhow::Result<Embedding> {
-        let tokenizer_output = self.tokenizer.encode(sequence, true).unwrap();
-
-        let input_ids = tokenizer_output.get_ids();
-        let attention_mask = tokenizer_output.get_attention_mask();
-        let token_type_ids = tokenizer_output.get_type_ids();
-        let length = input_ids.len();
-        println!("embedding {} tokens {:?}", length, sequence);
-
-        let inputs_ids_array = ndarray::Array::from_shape_vec(
-            (1, length),
-            input_ids.iter().map(|&x| x as i64).collect(),
-        )?;
-
-        let attention_mask_array = ndarray::Array::from_shape_vec(
-            (1, length),
-            attention_mask.iter().map(|&x| x as i64).collect(),
-        )?;
-
-        let token_type_ids_array = ndarray::Array::from_shape_vec(
-            (1, length),
-            token_type_ids.iter().map(|&x| x as i64).collect(),
-        )?;
-
-        let outputs = self.session.run(vec![
-            Value::from_array(
-                self.session.allocator(),
-                &ndarray::CowArray::from(inputs_ids_array).into_dyn(),
-            )
-            .unwrap(),
-            Value::from_array(
-                self.session.allocator(),
-                &ndarray::CowArray::from(attention_mask_array).into_dyn(),
-            )
-            .unwrap(),
-            Value::from_array(
-                self.session.allocator(),
-                &ndarray::CowArray::from(token_type_ids_array).into_dyn(),
-            )
-            .unwrap(),
-        ])?;
-
-        let output_tensor: OrtOwnedTensor<f32, _> = outputs[0].try_extract().unwrap();
-        let sequence_embedding = &*output_tensor.view();
-        let pooled = sequence_embedding.mean_axis(Axis(1)).unwrap();
-        Ok(pooled.to_owned().as_slice().unwrap().to_vec())
+    // Embeds the query with the same backend the documents were indexed with.
+    pub async fn embed(&self, sequence: &str) -> anyhow::Result<Embedding> {
+        debug!("embedding {:?}", sequence);
+        self.embedder.embed(sequence).await
     }
 
     // function to perform semantic search on the symbols.
@@ -168,9 +69,10 @@ impl Semantic {
         threshold: f32,
         retrieve_more: bool,
         repo_name: &String,
+        branch: Option<&str>,
     ) -> anyhow::Result<Vec<SymbolPayload>> {
         let query = parsed_query.as_plain().unwrap();
-        let vector = self.embed(&query)?;
+        let vector = self.embed(&query).await?;
 
         // TODO: Remove the need for `retrieve_more`. It's here because:
         // In /q `limit` is the maximum number of results returned (the actual number will often be lower due to deduplication)
@@ -183,11 +85,12 @@ impl Semantic {
                 offset,
                 threshold,
                 repo_name,
+                branch,
             )
             .await
             .map(|raw| {
                 raw.into_iter()
-                    .map(SymbolPayload::from_qdrant)
+                    .map(SymbolPayload::from_point)
                     .collect::<Vec<_>>()
             })?;
         Ok(results)
@@ -201,34 +104,24 @@ impl Semantic {
         offset: u64,
         threshold: f32,
         repo_name: &String,
+        branch: Option<&str>,
     ) -> anyhow::Result<Vec<ScoredPoint>> {
-        let mut conditions: Vec<Condition> = Vec::new();
-
-        conditions.push(make_kv_keyword_filter("repo_name", repo_name).into());
-
-        let search_request = &SearchPoints {
-            limit,
+        let request = SearchRequest {
             vector,
-            collection_name: generate_qdrant_index_name(repo_name),
-            offset: Some(offset),
+            filter: Some(repo_filter(repo_name, branch)),
+            limit,
+            offset,
             score_threshold: Some(threshold),
-            with_payload: Some(WithPayloadSelector {
-                selector_options: Some(with_payload_selector::SelectorOptions::Enable(true)),
-            }),
-            filter: Some(Filter {
-                must: conditions,
-                ..Default::default()
-            }),
-            with_vectors: Some(WithVectorsSelector {
-                selector_options: Some(with_vectors_selector::SelectorOptions::Enable(true)),
-            }),
-            ..Default::default()
+            with_vectors: true,
         };
 
-        let response = self.qdrant.search_points(search_request).await?;
+        let response = self
+            .vector_store
+            .search(&generate_qdrant_index_name(repo_name), request)
+            .await?;
 
         // iterate through the results and print the score and payload from each entry in the results
-        let mut results = response.result.clone();
+        let mut results = response.clone();
         results.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
 
         let _acc = results
@@ -245,19 +138,45 @@ impl Semantic {
             })
             .collect::<Vec<_>>();
 
-        Ok(response.result)
+        Ok(response)
+    }
+
+    // Searches a collection of the repo with an embedded query, restricted to the points indexed
+    // from the branch or tag if one is given.
+    pub async fn search_collection(
+        &self,
+        collection_name: &str,
+        vector: Embedding,
+        limit: u64,
+        repo_name: &str,
+        branch: Option<&str>,
+    ) -> anyhow::Result<Vec<ScoredPoint>> {
+        let filter = repo_filter(repo_name, branch);
+        let mut results = self
+            .vector_store
+            .search(
+                collection_name,
+                SearchRequest {
+                    vector,
+                    filter: Some(filter),
+                    limit,
+                    offset: 0,
+                    score_threshold: None,
+                    with_vectors: false,
+                },
+            )
+            .await?;
+        results.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
+        Ok(results)
     }
 }
 
-// Exact match filter
-pub(crate) fn make_kv_keyword_filter(key: &str, value: &str) -> FieldCondition {
-    let key = key.to_owned();
-    let value = value.to_owned();
-    FieldCondition {
-        key,
-        r#match: Some(Match {
-            match_value: MatchValue::Keyword(value).into(),
-        }),
-        ..Default::default()
+// Restricts a search to the points of the repo, indexed from the branch or tag if one is given.
+// The chunks and the symbols both record their refs under `branches`.
+fn repo_filter(repo_name: &str, branch: Option<&str>) -> VectorFilter {
+    let mut filter = VectorFilter::must("repo_name", repo_name);
+    if let Some(branch) = branch {
+        filter.must.push(FieldMatch::new("branches", branch));
     }
+    filter
 }
@@ -18,4 +18,7 @@ pub enum Action {
         query: String,
         paths: Vec<usize>,
     },
+    History {
+        query: String,
+    },
 }
\ No newline at end of file
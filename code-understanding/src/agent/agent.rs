@@ -52,6 +52,8 @@ pub struct FileDocument {
     pub repo_name: String,
     pub repo_ref: String,
     pub lang: Option<String>,
+    // Commit time of the last change to the file in seconds since the epoch, 0 if unknown.
+    pub last_commit_date: i64,
 }
 
 
@@ -203,6 +205,7 @@ impl Agent {
             Action::Path { query } => self.path_search(query).await?,
             Action::Code { query } => self.code_search(query).await?,
             Action::Proc { query, paths } => self.process_files(query, paths).await?,
+            Action::History { query } => self.history_search(query).await?,
         };
 
         let functions = serde_json::from_value::<Vec<llm_gateway::api::Function>>(
@@ -269,6 +272,10 @@ impl Agent {
                             "code".to_owned(),
                             format!("{{\n \"query\": \"{query}\"\n}}"),
                         ),
+                        SearchStep::History { query, .. } => (
+                            "history".to_owned(),
+                            format!("{{\n \"query\": \"{query}\"\n}}"),
+                        ),
                         SearchStep::Proc { query, paths, .. } => (
                             "proc".to_owned(),
                             format!(
@@ -400,6 +407,9 @@ pub enum Action {
         query: String,
         paths: Vec<usize>,
     },
+    History {
+        query: String,
+    },
 }
 
 impl Action {
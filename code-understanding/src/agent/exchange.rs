@@ -58,6 +58,7 @@ impl Exchange {
                 (Some(l @ SearchStep::Path { .. }), r @ SearchStep::Path { .. }) => *l = r,
                 (Some(l @ SearchStep::Code { .. }), r @ SearchStep::Code { .. }) => *l = r,
                 (Some(l @ SearchStep::Proc { .. }), r @ SearchStep::Proc { .. }) => *l = r,
+                (Some(l @ SearchStep::History { .. }), r @ SearchStep::History { .. }) => *l = r,
                 _ => panic!("Tried to replace a step that was not found"),
             },
             Update::Article(full_text) => {
@@ -127,6 +128,10 @@ pub enum SearchStep {
         paths: Vec<String>,
         response: String,
     },
+    History {
+        query: String,
+        response: String,
+    },
 }
 
 impl SearchStep {
@@ -148,6 +153,10 @@ impl SearchStep {
                 paths: paths.clone(),
                 response: "[hidden, compressed]".into(),
             },
+            Self::History { query, .. } => Self::History {
+                query: query.clone(),
+                response: "[hidden, compressed]".into(),
+            },
         }
     }
 
@@ -156,6 +165,7 @@ impl SearchStep {
             Self::Path { response, .. } => response.clone(),
             Self::Code { response, .. } => response.clone(),
             Self::Proc { response, .. } => response.clone(),
+            Self::History { response, .. } => response.clone(),
         }
     }
 }
@@ -5,6 +5,7 @@ pub mod transform;
 pub mod tools {
     pub mod answer;
     pub mod code;
+    pub mod history;
     pub mod path;
     pub mod proc;
 }
\ No newline at end of file
@@ -7,6 +7,9 @@ use tracing::instrument;
 
 use log::{error, debug};
 
+// Number of documentation sections added to the code results.
+const DOC_SECTION_LIMIT: u64 = 5;
+
 impl Agent {
     #[instrument(skip(self))]
     pub async fn code_search(&mut self, query: &String) -> Result<String> {
@@ -24,13 +27,24 @@ impl Agent {
             return Err(err);
         }
 
+        // the documentation sections are searched alongside the code, failing to search them
+        // only leaves them out.
+        let doc_sections = self
+            .docs_search(query, DOC_SECTION_LIMIT)
+            .await
+            .unwrap_or_else(|err| {
+                error!("Docs search failed: {:?}", err);
+                Vec::new()
+            });
+
         // return error if the result is empty
-        if results_symbol.as_ref().unwrap().is_empty() {
+        if results_symbol.as_ref().unwrap().is_empty() && doc_sections.is_empty() {
             let err = "No results found for symbol search API call";
             error!("{}", err);
             return Err(anyhow::Error::msg(err));
         }
         let code_snippet = results_symbol.unwrap();
+        debug!("{} doc sections found for {}", doc_sections.len(), query);
 
         // println!("Size of semantic search: {}", results.len());
 
@@ -47,6 +61,13 @@ impl Agent {
                 }
             })
             .collect::<Vec<_>>();
+        code_chunks.extend(doc_sections.into_iter().map(|section| CodeChunk {
+            alias: self.get_path_alias(&section.relative_path),
+            path: section.relative_path,
+            snippet: section.text,
+            start_line: section.start_line as usize,
+            end_line: section.end_line as usize,
+        }));
 
         code_chunks.sort_by(|a, b| a.alias.cmp(&b.alias).then(a.start_line.cmp(&b.start_line)));
 
@@ -0,0 +1,69 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use log::debug;
+use tracing::instrument;
+
+use crate::agent::agent::Agent;
+
+use crate::agent::exchange::{SearchStep, Update};
+
+// Number of commits returned to the model.
+const COMMIT_LIMIT: u64 = 10;
+// Number of changed paths listed per commit.
+const COMMIT_PATH_LIMIT: usize = 10;
+
+impl Agent {
+    #[instrument(skip(self))]
+    pub async fn history_search(&mut self, query: &String) -> Result<String> {
+        self.update(Update::StartStep(SearchStep::History {
+            query: query.clone(),
+            response: String::new(),
+        }))?;
+
+        let commits = self.commits_search(query, COMMIT_LIMIT).await?;
+        debug!("{} commits found for {}", commits.len(), query);
+
+        // the changed paths get aliases, so that the model can read them with proc.
+        let response = if commits.is_empty() {
+            "No commits found, the commit messages of this repository may not be indexed"
+                .to_string()
+        } else {
+            commits
+                .iter()
+                .map(|commit| {
+                    let date = DateTime::<Utc>::from_timestamp(commit.date, 0)
+                        .map(|date| date.format("%Y-%m-%d").to_string())
+                        .unwrap_or_default();
+                    let mut paths = commit
+                        .paths
+                        .iter()
+                        .take(COMMIT_PATH_LIMIT)
+                        .map(|path| format!("{}: {}", self.get_path_alias(path), path))
+                        .collect::<Vec<_>>();
+                    if commit.paths.len() > COMMIT_PATH_LIMIT {
+                        paths.push(format!(
+                            "and {} more",
+                            commit.paths.len() - COMMIT_PATH_LIMIT
+                        ));
+                    }
+                    format!(
+                        "commit {} by {} on {}\n{}\npaths:\n{}",
+                        &commit.commit[..commit.commit.len().min(12)],
+                        commit.author,
+                        date,
+                        commit.message,
+                        paths.join("\n")
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n\n")
+        };
+
+        self.update(Update::ReplaceStep(SearchStep::History {
+            query: query.clone(),
+            response: response.clone(),
+        }))?;
+        let result = "OK";
+        Ok(result.to_string())
+    }
+}
@@ -1,4 +1,5 @@
 pub mod answer;
 pub mod code;
+pub mod history;
 pub mod path;
 pub mod proc;
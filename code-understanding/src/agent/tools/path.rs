@@ -1,12 +1,16 @@
 use std::collections::HashSet;
 
 use anyhow::Result;
+use log::error;
 use tracing::instrument;
 
 use crate::agent::agent::Agent;
 
 use crate::agent::exchange::{SearchStep, Update};
 
+// Number of documentation sections whose files are added to the paths.
+const DOC_SECTION_LIMIT: u64 = 10;
+
 impl Agent {
     #[instrument(skip(self))]
     pub async fn path_search(&mut self, query: &String) -> Result<String> {
@@ -15,13 +19,14 @@ impl Agent {
             response: String::new(),
         }))?;
 
-        // First, perform a lexical search for the path
+        // First, perform a lexical search for the path. Its results are ranked, recently changed
+        // files first among equal matches, so duplicates are dropped without reordering.
+        let mut seen = HashSet::new();
         let mut paths = self
             .fuzzy_path_search(query)
             .await
             .map(|c| c.relative_path)
-            .collect::<HashSet<_>>() // TODO: This shouldn't be necessary. Path search should return unique results.
-            .into_iter()
+            .filter(|path| seen.insert(path.clone()))
             .collect::<Vec<_>>();
 
         // If there are no lexical results, perform a semantic search.
@@ -38,11 +43,27 @@ impl Agent {
             paths = semantic_paths;
         }
 
-        let mut paths = paths
+        // Add the documentation files whose sections match the query, failing to search them
+        // only leaves them out.
+        let doc_paths = self
+            .docs_search(query, DOC_SECTION_LIMIT)
+            .await
+            .unwrap_or_else(|err| {
+                error!("Docs search failed: {:?}", err);
+                Vec::new()
+            })
+            .into_iter()
+            .map(|section| section.relative_path);
+        for path in doc_paths {
+            if !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+
+        let paths = paths
             .iter()
             .map(|p| (self.get_path_alias(p), p.to_string()))
             .collect::<Vec<_>>();
-        paths.sort_by(|a: &(usize, String), b| a.0.cmp(&b.0)); // Sort by alias
 
         let response = paths
             .iter()
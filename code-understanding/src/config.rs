@@ -1,13 +1,20 @@
+use common::embedding::EmbedderConfig;
+use common::lexical::LexicalIndexConfig;
+use common::vector::VectorStoreConfig;
+
 #[derive(Clone, Debug)]
 pub struct Config {
-    pub semantic_url: String,
-    pub qdrant_api_key: Option<String>,
-    pub tokenizer_path: String,
-    pub model_path: String,
+    // Qdrant at `SEMANTIC_URL` unless `VECTOR_BACKEND` says otherwise, it has to be the store the
+    // repos were indexed into.
+    pub vector_store: VectorStoreConfig,
+    // the embedding backend has to be the one the repos were indexed with.
+    pub embedder: EmbedderConfig,
     pub openai_key: String,
     pub openai_url: String,
     pub openai_model: String,
     pub quickwit_url: String,
+    // the lexical index backend has to be the one the repos were indexed with.
+    pub lexical_index: LexicalIndexConfig,
     pub semantic_collection_name: String,
     pub search_server_url: String,
 }
@@ -20,24 +27,24 @@ impl Config {
         // Directly use `?` to propagate the error if the environment variable is not set.
         let semantic_url = std::env::var("SEMANTIC_URL")?;
         let qdrant_api_key = std::env::var("QDRANT_API_KEY").ok();
-        let tokenizer_path = std::env::var("TOKENIZER_PATH")?;
-        let model_path = std::env::var("MODEL_PATH")?;
+        let vector_store = VectorStoreConfig::from_env(&semantic_url, qdrant_api_key)?;
+        let embedder = EmbedderConfig::from_env()?;
         let openai_key = std::env::var("OPENAI_KEY")?;
         let openai_url = std::env::var("OPENAI_URL")?;
         let openai_model = std::env::var("OPENAI_MODEL")?;
         let quickwit_url = std::env::var("QUICKWIT_URL")?;
+        let lexical_index = LexicalIndexConfig::from_env(&quickwit_url)?;
         let semantic_collection_name = std::env::var("SEMANTIC_COLLECTION_NAME")?;
         let search_server_url = std::env::var("SEARCH_SERVER_URL")?;
 
         Ok(Config {
-            semantic_url,
-            qdrant_api_key,
-            tokenizer_path,
-            model_path,
+            vector_store,
+            embedder,
             openai_key,
             openai_url,
             openai_model,
             quickwit_url,
+            lexical_index,
             semantic_collection_name,
             search_server_url,
         })
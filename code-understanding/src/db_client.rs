@@ -5,60 +5,47 @@ use crate::helpers::case_permutations::case_permutations;
 use crate::helpers::trigrams::trigrams;
 use crate::search;
 use common::hasher::generate_quikwit_index_name;
+use common::lexical::{LexicalDocument, LexicalIndex};
 use compact_str::CompactString;
 use log::{error, debug, info};
 use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
 
 use anyhow::Result;
 
-use serde::{Deserialize, Serialize};
-use serde_json;
+use reqwest::Client;
 
-use reqwest::{Client, Error};
-pub struct DbConnect {
-    pub semantic: search::semantic::Semantic,
-    pub http_client: Client,
-}
+// Score added to a path changed right now, halved every `RECENCY_HALF_LIFE_SECS`. It stays below
+// one trigram hit, so recency only orders paths matching the query equally well.
+const RECENCY_WEIGHT: f64 = 0.9;
+const RECENCY_HALF_LIFE_SECS: f64 = 90.0 * 24.0 * 3600.0;
 
-#[derive(Debug, Serialize, Deserialize)]
-struct BodyRes {
-    query: String,
-    max_hits: i32,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct ApiResponse {
-    num_hits: i32,            // Change the type to i32 or another appropriate numeric type
-    elapsed_time_micros: i64, // Change the type to i32 or another appropriate numeric type
-    hits: Vec<ResultItem>,
-    errors: Vec<String>,
+// Boost of a file last changed at `date`, 0 for the files without history.
+fn recency_boost(date: i64, now: i64) -> f64 {
+    if date <= 0 {
+        return 0.0;
+    }
+    let age = (now - date).max(0) as f64;
+    RECENCY_WEIGHT * 0.5f64.powf(age / RECENCY_HALF_LIFE_SECS)
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct ResultItem {
-    relative_path: String,
-    repo_name: String,
-    lang: Option<String>,
-    content: String,
-    symbols: String,
-    line_end_indices: Vec<u8>,
-    symbol_locations: Vec<u8>,
-    is_directory: bool,
-    last_commit: String,
-    repo_ref: String,
-    repo_disk_path: String,
-    unique_hash: String,
+pub struct DbConnect {
+    pub semantic: search::semantic::Semantic,
+    pub http_client: Client,
+    pub lexical_index: Arc<dyn LexicalIndex>,
 }
 
 impl DbConnect {
     pub async fn new(config: &Config) -> Result<Self, anyhow::Error> {
         let http_client = reqwest::Client::new();
+        let lexical_index = config.lexical_index.build()?;
 
         let semantic = search::semantic::Semantic::initialize(config).await;
         match semantic {
             Ok(semantic) => Ok(Self {
                 semantic,
                 http_client,
+                lexical_index,
             }),
             Err(err) => {
                 error!("Failed to initialize semantic search: {}", err);
@@ -89,82 +76,53 @@ impl DbConnect {
         Ok(response_array)
     }
 
-    pub async fn search_quickwit(
+    async fn search_lexical(
         &self,
         index_name: &str,
         search_field: &str,
         search_query: &str,
-    ) -> Result<Option<ContentDocument>, Error> {
-        let configuration = Config::new().unwrap();
-        let base_url = configuration.quickwit_url.clone();
-
+        max_hits: usize,
+    ) -> Result<Vec<LexicalDocument>> {
         let query = if !search_field.is_empty() {
             format!("{}:{}", search_field, search_query)
         } else {
             search_query.to_owned()
         };
 
-        let json_data = BodyRes {
-            query,
-            max_hits: 10,
-        };
+        self.lexical_index
+            .search(&generate_quikwit_index_name(index_name), &query, max_hits)
+            .await
+    }
 
-        let json_string = serde_json::to_string(&json_data).expect("Failed to serialize object");
-
-        let var_name = format!(
-            "{}/api/v1/{}/search",
-            base_url,
-            generate_quikwit_index_name(index_name)
-        );
-        let url = var_name;
-
-        let response = self
-            .http_client
-            .post(url)
-            .header("Content-Type", "application/json")
-            .body(json_string)
-            .send()
+    pub async fn search_quickwit(
+        &self,
+        index_name: &str,
+        search_field: &str,
+        search_query: &str,
+    ) -> Result<Option<ContentDocument>> {
+        let hits = self
+            .search_lexical(index_name, search_field, search_query, 10)
             .await?;
 
-        let mut response_array: Vec<ContentDocument> = Vec::new();
-
-        if response.status().is_success() {
-            let response_text = response.text().await?;
-
-            let parsed_response: Result<ApiResponse, serde_json::Error> =
-                serde_json::from_str(&response_text);
-
-            match parsed_response {
-                Ok(api_response) => {
-                    for result_item in api_response.hits {
-                        if search_query == result_item.relative_path {
-                            info!("Found a match: {}", search_query);
-                            response_array.push(ContentDocument {
-                                relative_path: result_item.relative_path,
-                                repo_name: result_item.repo_name,
-                                lang: result_item.lang,
-                                content: result_item.content,
-                                repo_ref: result_item.repo_ref,
-                                line_end_indices: result_item.line_end_indices,
-                                symbol_locations: result_item.symbol_locations,
-                                symbols: result_item.symbols,
-                            });
-                        }
-                    }
-                }
-                Err(err) => {
-                    error!("Failed to parse JSON response: {}", err);
+        // Return the first ContentDocument
+        let document = hits
+            .into_iter()
+            .find(|hit| search_query == hit.relative_path)
+            .map(|hit| {
+                info!("Found a match: {}", search_query);
+                ContentDocument {
+                    relative_path: hit.relative_path,
+                    repo_name: hit.repo_name,
+                    lang: (!hit.lang.is_empty()).then_some(hit.lang),
+                    content: hit.content,
+                    repo_ref: hit.repo_ref,
+                    line_end_indices: hit.line_end_indices,
+                    symbol_locations: hit.symbol_locations,
+                    symbols: hit.symbols,
                 }
-            }
-        } else {
-            error!("Request was not successful: {}", response.status());
-        }
+            });
 
-        if !response_array.is_empty() {
-            Ok(Some(response_array[0].clone())) // Return the first ContentDocument
-        } else {
-            Ok(None) // No ContentDocument found
-        }
+        Ok(document)
     }
 
     async fn search_api(
@@ -172,67 +130,24 @@ impl DbConnect {
         index_name: &str,
         search_field: &str,
         search_query: &str,
-    ) -> Result<Vec<FileDocument>, Error> {
-        let client = Client::new();
-        let configuration = Config::new().unwrap();
-        let base_url = configuration.quickwit_url.clone();
-
+    ) -> Result<Vec<FileDocument>> {
         info!("search_query {}", search_query);
 
-        let query = if !search_field.is_empty() {
-            format!("{}:{}", search_field, search_query)
-        } else {
-            search_query.to_owned()
-        };
-
-        let json_data = BodyRes {
-            query,
-            max_hits: 100,
-        };
-
-        let json_string = serde_json::to_string(&json_data).expect("Failed to serialize object");
-
-        let url = format!(
-            "{}/api/v1/{}/search",
-            base_url,
-            generate_quikwit_index_name(index_name)
-        );
-
-        let response = client
-            .post(url)
-            .header("Content-Type", "application/json")
-            .body(json_string)
-            .send()
+        let hits = self
+            .search_lexical(index_name, search_field, search_query, 100)
             .await?;
+        debug!("{} files found for {}", hits.len(), search_query);
 
-        let mut response_array: Vec<FileDocument> = Vec::new();
-
-        if response.status().is_success() {
-            let response_text = response.text().await?;
-
-            let parsed_response: Result<ApiResponse, serde_json::Error> =
-                serde_json::from_str(&response_text);
-
-            match parsed_response {
-                Ok(api_response) => {
-                    for result_item in api_response.hits {
-                        response_array.push(FileDocument {
-                            relative_path: result_item.relative_path,
-                            repo_name: result_item.repo_name,
-                            lang: result_item.lang,
-                            repo_ref: result_item.repo_ref,
-                        });
-                    }
-                }
-                Err(err) => {
-                    error!("Failed to parse JSON response: {}", err);
-                }
-            }
-        } else {
-            error!("Request was not successful: {}", response.status());
-        }
-
-        Ok(response_array)
+        Ok(hits
+            .into_iter()
+            .map(|hit| FileDocument {
+                relative_path: hit.relative_path,
+                repo_name: hit.repo_name,
+                lang: (!hit.lang.is_empty()).then_some(hit.lang),
+                repo_ref: hit.repo_ref,
+                last_commit_date: hit.last_commit_date,
+            })
+            .collect())
     }
 
     async fn search_with_async(
@@ -240,7 +155,7 @@ impl DbConnect {
         index_name: &str,
         search_field: &str,
         token: CompactString,
-    ) -> Result<Vec<FileDocument>, Error> {
+    ) -> Result<Vec<FileDocument>> {
         let result = self
             .search_api(index_name, search_field, token.as_str())
             .await?;
@@ -280,17 +195,25 @@ impl DbConnect {
             }
         }
 
-        // Convert the HashMap into a Vec<(FileDocument, usize)>
-        let mut new_hit: Vec<(FileDocument, usize)> = counts.into_iter().collect();
-
-        new_hit.sort_by(|(this_doc, this_count), (other_doc, other_count)| {
-            let order_count_desc = other_count.cmp(this_count);
+        // Convert the HashMap into a Vec<(FileDocument, f64)>, recently changed files first
+        // among the ones with as many hits.
+        let now = chrono::Utc::now().timestamp();
+        let mut new_hit: Vec<(FileDocument, f64)> = counts
+            .into_iter()
+            .map(|(doc, count)| {
+                let score = count as f64 + recency_boost(doc.last_commit_date, now);
+                (doc, score)
+            })
+            .collect();
+
+        new_hit.sort_by(|(this_doc, this_score), (other_doc, other_score)| {
+            let order_score_desc = other_score.total_cmp(this_score);
             let order_path_asc = this_doc
                 .relative_path
                 .as_str()
                 .cmp(other_doc.relative_path.as_str());
 
-            order_count_desc.then(order_path_asc)
+            order_score_desc.then(order_path_asc)
         });
 
         let regex_filter = build_fuzzy_regex_filter(search_query);
@@ -327,3 +250,19 @@ impl DbConnect {
         result
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recency_boost() {
+        let now = 1_700_000_000;
+        assert_eq!(recency_boost(0, now), 0.0);
+        assert_eq!(recency_boost(now, now), RECENCY_WEIGHT);
+        let half_life = RECENCY_HALF_LIFE_SECS as i64;
+        assert!((recency_boost(now - half_life, now) - RECENCY_WEIGHT / 2.0).abs() < 1e-9);
+        // never worth a trigram hit.
+        assert!(recency_boost(now + 3600, now) < 1.0);
+    }
+}
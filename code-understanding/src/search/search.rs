@@ -1,13 +1,9 @@
 use crate::agent::agent::Agent;
-use crate::search::payload::Payload;
-use crate::search::semantic::{
-    deduplicate_snippets, make_kv_keyword_filter, Semantic, 
-};
+use crate::search::payload::{CommitPayload, DocPayload, Payload};
+use crate::search::semantic::{deduplicate_snippets, Semantic};
 use anyhow::Result;
-use qdrant_client::qdrant::{
-    with_payload_selector, with_vectors_selector, Condition, Filter, ScoredPoint, SearchPoints,
-    WithPayloadSelector, WithVectorsSelector,
-};
+use common::hasher::{generate_commits_collection_name, generate_docs_collection_name};
+use common::vector::{ScoredPoint, SearchRequest, VectorFilter};
 use tracing::debug;
 
 pub type Embedding = Vec<f32>;
@@ -58,7 +54,89 @@ impl Agent {
     }
 }
 
+impl Agent {
+    // Searches the documentation sections of the repo, best matches first.
+    pub async fn docs_search(&self, query: &str, limit: u64) -> Result<Vec<DocPayload>> {
+        debug!(?query, "executing docs query");
+        self.app_state
+            .db_connection
+            .semantic
+            .search_docs(query, limit, 0.0, &self.repo_name)
+            .await
+    }
+
+    // Searches the commit messages of the repo, best matches first.
+    pub async fn commits_search(&self, query: &str, limit: u64) -> Result<Vec<CommitPayload>> {
+        debug!(?query, "executing commits query");
+        self.app_state
+            .db_connection
+            .semantic
+            .search_commits(query, limit, 0.0, &self.repo_name)
+            .await
+    }
+}
+
 impl Semantic {
+    // Searches the Markdown sections in the docs collection of the repo. Repos indexed before the
+    // docs collections existed have none, they get no results.
+    pub async fn search_docs(
+        &self,
+        query: &str,
+        limit: u64,
+        threshold: f32,
+        repo_name: &str,
+    ) -> anyhow::Result<Vec<DocPayload>> {
+        let collection_name = generate_docs_collection_name(repo_name);
+        if self
+            .vector_store
+            .collection_dimension(&collection_name)
+            .await?
+            .is_none()
+        {
+            return Ok(Vec::new());
+        }
+
+        let vector = self.embed(query).await?;
+        let mut sections = self
+            .search_with(&collection_name, vector, limit, 0, threshold, repo_name)
+            .await?
+            .into_iter()
+            .map(DocPayload::from_point)
+            .collect::<Vec<_>>();
+        sections.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
+        Ok(sections)
+    }
+
+    // Searches the commit messages in the commits collection of the repo. Only the repos indexed
+    // with their commits have one, the others get no results.
+    pub async fn search_commits(
+        &self,
+        query: &str,
+        limit: u64,
+        threshold: f32,
+        repo_name: &str,
+    ) -> anyhow::Result<Vec<CommitPayload>> {
+        let collection_name = generate_commits_collection_name(repo_name);
+        if self
+            .vector_store
+            .collection_dimension(&collection_name)
+            .await?
+            .is_none()
+        {
+            return Ok(Vec::new());
+        }
+
+        let vector = self.embed(query).await?;
+        let mut commits = self
+            .search_with(&collection_name, vector, limit, 0, threshold, repo_name)
+            .await?
+            .into_iter()
+            .map(CommitPayload::from_point)
+            .collect::<Vec<_>>();
+        commits.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
+        Ok(commits)
+    }
+
     pub async fn search_with<'a>(
         &self,
         collection_name: &str,
@@ -68,34 +146,23 @@ impl Semantic {
         threshold: f32,
         repo_name: &str,
     ) -> anyhow::Result<Vec<ScoredPoint>> {
-        let mut conditions: Vec<Condition> = Vec::new();
-
-        conditions.push(make_kv_keyword_filter("repo_name", repo_name).into());
-
         let response = self
-            .qdrant
-            .search_points(&SearchPoints {
-                limit,
-                vector,
-                collection_name: collection_name.to_owned().to_string(),
-                offset: Some(offset),
-                score_threshold: Some(threshold),
-                with_payload: Some(WithPayloadSelector {
-                    selector_options: Some(with_payload_selector::SelectorOptions::Enable(true)),
-                }),
-                filter: Some(Filter {
-                    must: conditions,
-                    ..Default::default()
-                }),
-                with_vectors: Some(WithVectorsSelector {
-                    selector_options: Some(with_vectors_selector::SelectorOptions::Enable(true)),
-                }),
-                ..Default::default()
-            })
+            .vector_store
+            .search(
+                collection_name,
+                SearchRequest {
+                    vector,
+                    filter: Some(VectorFilter::must("repo_name", repo_name)),
+                    limit,
+                    offset,
+                    score_threshold: Some(threshold),
+                    with_vectors: true,
+                },
+            )
             .await?;
 
         // iterate through the results and print the score and payload from each entry in the results
-        let mut results = response.result.clone();
+        let mut results = response.clone();
         results.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
 
         println!("---------xxxxxxxxxxxxxxx----------------");
@@ -116,7 +183,7 @@ impl Semantic {
             })
             .collect::<Vec<_>>();
 
-        Ok(response.result)
+        Ok(response)
     }
 
     pub async fn search<'a>(
@@ -128,7 +195,7 @@ impl Semantic {
         retrieve_more: bool,
         repo_name: &str,
     ) -> anyhow::Result<Vec<Payload>> {
-        let vector = self.embed(&query)?;
+        let vector = self.embed(&query).await?;
 
         // TODO: Remove the need for `retrieve_more`. It's here because:
         // In /q `limit` is the maximum number of results returned (the actual number will often be lower due to deduplication)
@@ -145,7 +212,7 @@ impl Semantic {
             .await
             .map(|raw| {
                 raw.into_iter()
-                    .map(Payload::from_qdrant)
+                    .map(Payload::from_point)
                     .collect::<Vec<_>>()
             })?;
         Ok(deduplicate_snippets(results, vector, limit))
@@ -9,34 +9,24 @@ use std::{
 // import anyhow from anyhow
 use crate::config::Config;
 use anyhow::Result;
-use log::{error, info};
+use log::error;
 use crate::search::payload::{Embedding, Payload};
 use std::sync::Arc;
 
-use ndarray::Axis;
-use ort::tensor::OrtOwnedTensor;
-use ort::value::Value;
-use ort::{Environment, ExecutionProvider, GraphOptimizationLevel, LoggingLevel, SessionBuilder};
-use qdrant_client::{
-    prelude::QdrantClient,
-    qdrant::{
-        r#match::MatchValue, FieldCondition, Match,
-    },
-};
+use common::embedding::Embedder;
+use common::vector::VectorStore;
 
 pub struct Semantic {
     pub qdrant_collection_name: String,
-    pub qdrant: QdrantClient,
-    pub tokenizer: tokenizers::Tokenizer,
-    pub session: ort::Session,
+    pub vector_store: Arc<dyn VectorStore>,
+    pub embedder: Arc<dyn Embedder>,
 }
 
 #[derive(Error, Debug)]
 pub enum SemanticError {
-    /// Represents failure to initialize Qdrant client
-    #[allow(unused)]
-    #[error("Qdrant initialization failed. Is Qdrant running on `qdrant-url`?")]
-    QdrantInitializationError,
+    /// Represents failure to initialize the vector store
+    #[error("Vector store initialization failed. Is Qdrant running on `qdrant-url`?")]
+    VectorStoreInitializationError,
 
     #[error("ONNX runtime error")]
     OnnxRuntimeError {
@@ -55,112 +45,24 @@ impl Semantic {
     // Define an asynchronous function 'initialize' that takes a reference to a Config object and returns a Result.
     // This function initializes the struct it belongs to.
     pub async fn initialize(config: &Config) -> Result<Self, SemanticError> {
-        // Retrieve the Qdrant URL from the config object. We use a reference here to avoid ownership issues.
-        let qdrant_url = &config.semantic_url;
-
-        // Start building the Qdrant client with the URL.
-        let mut qdrant_client_builder = QdrantClient::from_url(qdrant_url);
-
-        // Check if the qdrant_api_key is present in the config. If it is, add it to the client builder.
-        if let Some(ref key) = config.qdrant_api_key {
-            info!("Using Qdrant API key. Using Qdrant with authentication.");
-            qdrant_client_builder = qdrant_client_builder.with_api_key(key.as_str());
-        } else {
-            info!("No Qdrant API key found. Using Qdrant without authentication.")
-        }
-
-        // Finalize building the Qdrant client. If this fails, the error will be propagated by `?`.
-        let qdrant = qdrant_client_builder.build()?;
-
-        // Create a new environment for the ONNX session, configuring various parameters.
-        let environment = Arc::new(
-            Environment::builder()
-                .with_name("Encode")
-                .with_log_level(LoggingLevel::Warning)
-                .with_execution_providers([ExecutionProvider::CPU(Default::default())])
-                .with_telemetry(false)
-                .build()?,
-        );
-
-        // Determine the number of threads to use based on the NUM_OMP_THREADS environment variable.
-        let threads = std::env::var("NUM_OMP_THREADS")
-            .map(|v| v.parse().unwrap_or(1))
-            .unwrap_or(1);
+        // Connect to the vector store backend the repos were indexed into.
+        let vector_store = config.vector_store.build().map_err(|e| {
+            error!("Failed to build the vector store: {:?}", e);
+            SemanticError::VectorStoreInitializationError
+        })?;
 
         // Construct and return the new instance, initializing each field.
         Ok(Self {
-            qdrant: qdrant.into(),
-            tokenizer: tokenizers::Tokenizer::from_file(&config.tokenizer_path)
-                .unwrap()
-                .into(),
-            session: SessionBuilder::new(&environment)?
-                .with_optimization_level(GraphOptimizationLevel::Level3)?
-                .with_intra_threads(threads)?
-                .with_model_from_file(&config.model_path)?
-                .into(),
+            vector_store,
+            embedder: config.embedder.build().await?,
             qdrant_collection_name: config.semantic_collection_name.clone(),
         })
     }
 
-    pub fn embed(&self, sequence: &str) -> anyhow::Result<Embedding> {
-        let tokenizer_output = self.tokenizer.encode(sequence, true).unwrap();
-
-        let input_ids = tokenizer_output.get_ids();
-        let attention_mask = tokenizer_output.get_attention_mask();
-        let token_type_ids = tokenizer_output.get_type_ids();
-        let length = input_ids.len();
-        println!("embedding {} tokens {:?}", length, sequence);
-
-        let inputs_ids_array = ndarray::Array::from_shape_vec(
-            (1, length),
-            input_ids.iter().map(|&x| x as i64).collect(),
-        )?;
-
-        let attention_mask_array = ndarray::Array::from_shape_vec(
-            (1, length),
-            attention_mask.iter().map(|&x| x as i64).collect(),
-        )?;
-
-        let token_type_ids_array = ndarray::Array::from_shape_vec(
-            (1, length),
-            token_type_ids.iter().map(|&x| x as i64).collect(),
-        )?;
-
-        let outputs = self.session.run(vec![
-            Value::from_array(
-                self.session.allocator(),
-                &ndarray::CowArray::from(inputs_ids_array).into_dyn(),
-            )
-            .unwrap(),
-            Value::from_array(
-                self.session.allocator(),
-                &ndarray::CowArray::from(attention_mask_array).into_dyn(),
-            )
-            .unwrap(),
-            Value::from_array(
-                self.session.allocator(),
-                &ndarray::CowArray::from(token_type_ids_array).into_dyn(),
-            )
-            .unwrap(),
-        ])?;
-
-        let output_tensor: OrtOwnedTensor<f32, _> = outputs[0].try_extract().unwrap();
-        let sequence_embedding = &*output_tensor.view();
-        let pooled = sequence_embedding.mean_axis(Axis(1)).unwrap();
-        Ok(pooled.to_owned().as_slice().unwrap().to_vec())
-    }
-}
-
-// Exact match filter
-pub(crate) fn make_kv_keyword_filter(key: &str, value: &str) -> FieldCondition {
-    let key = key.to_owned();
-    let value = value.to_owned();
-    FieldCondition {
-        key,
-        r#match: Some(Match {
-            match_value: MatchValue::Keyword(value).into(),
-        }),
-        ..Default::default()
+    // Embeds the query with the same backend the documents were indexed with.
+    pub async fn embed(&self, sequence: &str) -> anyhow::Result<Embedding> {
+        debug!("embedding {:?}", sequence);
+        self.embedder.embed(sequence).await
     }
 }
 
@@ -526,6 +526,71 @@ impl ScopeGraph {
         smallest_scope_node.or(largest_adjacent_node)
     }
 
+    /// Produce the scopes of the file that carry a name: functions, classes and the like, along
+    /// with the scopes enclosing them (e.g. impl blocks), ordered by their start.
+    ///
+    /// A scope is named by a definition declared in its parent scope but spanning part of it,
+    /// like the name of a function. Each scope is labelled with its header: the source from its
+    /// start up to the end of that name, or up to the first `{` or newline for enclosing scopes.
+    pub fn named_scopes(&self, src: &[u8]) -> Vec<NamedScope> {
+        let mut named = HashMap::new();
+        for idx in self.graph.node_indices() {
+            let NodeKind::Def(def) = &self.graph[idx] else {
+                continue;
+            };
+            let Some(value_idx) = self.value_of_definition(idx) else {
+                continue;
+            };
+            let defining_scope = self
+                .graph
+                .edges_directed(idx, Direction::Outgoing)
+                .find(|edge| *edge.weight() == EdgeKind::DefToScope)
+                .map(|edge| edge.target());
+
+            let value_range = self.graph[value_idx].range();
+            if value_idx == self.root_idx
+                || defining_scope == Some(value_idx)
+                || !value_range.contains(&def.range)
+            {
+                continue;
+            }
+            named.entry(value_idx).or_insert_with(|| {
+                scope_label(src, value_range.start.byte, Some(def.range.end.byte))
+            });
+        }
+
+        // the scopes enclosing a named scope are named after their header, if they have one.
+        let mut enclosing = HashMap::new();
+        for &idx in named.keys() {
+            let mut parent = self.parent_scope(idx);
+            while let Some(parent_idx) = parent.filter(|&p| p != self.root_idx) {
+                if !named.contains_key(&parent_idx) && !enclosing.contains_key(&parent_idx) {
+                    let label = scope_label(src, self.graph[parent_idx].range().start.byte, None);
+                    enclosing.insert(parent_idx, label);
+                }
+                parent = self.parent_scope(parent_idx);
+            }
+        }
+
+        let mut scopes = named
+            .into_iter()
+            .chain(enclosing)
+            .filter(|(_, label)| !label.is_empty())
+            .map(|(idx, label)| NamedScope {
+                label,
+                range: self.graph[idx].range(),
+            })
+            .collect::<Vec<_>>();
+        scopes.sort_by_key(|scope| {
+            (
+                scope.range.start.byte,
+                std::cmp::Reverse(scope.range.end.byte),
+            )
+        });
+        scopes.dedup_by_key(|scope| (scope.range.start.byte, scope.range.end.byte));
+        scopes
+    }
+
     // print n nodes and edges of the scope graph along with their line ranges
     pub fn print_graph(&self, n: usize) {
         log::debug!(" Printing Scope Graph:");
@@ -595,6 +660,37 @@ impl ScopeGraph {
     }
 }
 
+/// A scope of a file that carries a name, see [`ScopeGraph::named_scopes`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NamedScope {
+    /// The header of the scope, e.g. `impl Agent` or `fn step`
+    pub label: String,
+    pub range: TextRange,
+}
+
+// Longest header kept as the label of a scope, in characters.
+const MAX_SCOPE_LABEL_LEN: usize = 80;
+
+// The header of the scope starting at `start`: up to `name_end` if given, otherwise up to the
+// first `{` or newline. Whitespace is collapsed and trailing `:` (python) dropped.
+fn scope_label(src: &[u8], start: usize, name_end: Option<usize>) -> String {
+    let end = name_end.unwrap_or_else(|| {
+        src[start..]
+            .iter()
+            .position(|&b| b == b'{' || b == b'\n')
+            .map_or(src.len(), |offset| start + offset)
+    });
+    String::from_utf8_lossy(&src[start..end])
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .trim_end_matches(':')
+        .trim_end()
+        .chars()
+        .take(MAX_SCOPE_LABEL_LEN)
+        .collect()
+}
+
 fn scope_res_generic(
     query: &Query,
     root_node: Node<'_>,
@@ -1207,4 +1303,39 @@ foo + 1"#
         let hoverable_ranges = s.hoverable_ranges().collect::<Vec<_>>();
         assert_eq!(hoverable_ranges, vec![r(4, 5), r(11, 12)])
     }
+
+    #[test]
+    fn named_scopes() {
+        let src = r#"struct Agent {
+    steps: usize,
+}
+
+impl Agent {
+    pub fn step(&mut self) -> usize {
+        self.steps += 1;
+        self.steps
+    }
+}
+"#
+        .as_bytes();
+
+        let scope_graph = crate::ast::CodeFileAST::build_ast(src, "Rust")
+            .and_then(crate::ast::CodeFileAST::scope_graph)
+            .unwrap();
+        let scopes = scope_graph
+            .named_scopes(src)
+            .into_iter()
+            .map(|scope| (scope.label, scope.range.start.line, scope.range.end.line))
+            .collect::<Vec<_>>();
+
+        // the impl block encloses `step`, the blocks and parameters aren't named.
+        assert_eq!(
+            scopes,
+            vec![
+                ("struct Agent".to_string(), 0, 2),
+                ("impl Agent".to_string(), 4, 9),
+                ("pub fn step".to_string(), 5, 8),
+            ]
+        );
+    }
 }
@@ -95,6 +95,22 @@ impl TSLanguage {
             })
             .map_or(Language::Unsupported, Language::Supported)
     }
+
+    /// Find a tree-sitter language configuration from a file extension, without the leading dot.
+    ///
+    /// Extensions shared by several languages (e.g. `h`) resolve to the first one.
+    pub fn from_extension(extension: &str) -> Self {
+        ALL_LANGUAGES
+            .iter()
+            .copied()
+            .find(|target| {
+                target
+                    .file_extensions
+                    .iter()
+                    .any(|&ext| ext.eq_ignore_ascii_case(extension))
+            })
+            .map_or(Language::Unsupported, Language::Supported)
+    }
 }
 
 pub static ALL_LANGUAGES: &[&TSLanguageConfig] = &[
@@ -0,0 +1,234 @@
+//! Embedding backends shared by the indexer and the search services, so that document and query
+//! vectors always come from the same model.
+
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::sync::Arc;
+
+mod ollama;
+mod onnx;
+mod openai;
+
+pub use ollama::OllamaEmbedder;
+pub use onnx::OnnxEmbedder;
+pub use openai::OpenAiEmbedder;
+
+pub type Embedding = Vec<f32>;
+
+const DEFAULT_TOKENIZER_PATH: &str = "./model/tokenizer.json";
+const DEFAULT_MODEL_PATH: &str = "./model/model.onnx";
+const DEFAULT_OPENAI_URL: &str = "https://api.openai.com/v1";
+const DEFAULT_OPENAI_MODEL: &str = "text-embedding-3-small";
+const DEFAULT_OLLAMA_URL: &str = "http://localhost:11434";
+const DEFAULT_OLLAMA_MODEL: &str = "nomic-embed-text";
+
+/// Turns text into vectors.
+#[async_trait]
+pub trait Embedder: Send + Sync {
+    /// Size of the vectors produced by the model, used as the dimension of the Qdrant collections.
+    fn dimension(&self) -> usize;
+
+    /// Embeds the given sequences, in order.
+    async fn embed_batch(&self, sequences: &[&str]) -> Result<Vec<Embedding>>;
+
+    async fn embed(&self, sequence: &str) -> Result<Embedding> {
+        self.embed_batch(&[sequence])
+            .await?
+            .pop()
+            .ok_or_else(|| anyhow!("embedding backend returned no vector"))
+    }
+}
+
+/// How the token embeddings of the ONNX model are reduced to a single vector.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Pooling {
+    #[default]
+    Mean,
+    // Embedding of the first ([CLS]) token.
+    Cls,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct OnnxConfig {
+    pub tokenizer_path: String,
+    pub model_path: String,
+    // Number of intra-op threads of the ONNX session.
+    pub threads: i16,
+    #[serde(default)]
+    pub pooling: Pooling,
+}
+
+impl Default for OnnxConfig {
+    fn default() -> Self {
+        Self {
+            tokenizer_path: DEFAULT_TOKENIZER_PATH.to_string(),
+            model_path: DEFAULT_MODEL_PATH.to_string(),
+            threads: 1,
+            pooling: Pooling::Mean,
+        }
+    }
+}
+
+/// Settings of the HTTP embedding backends.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct RemoteEmbedderConfig {
+    pub url: String,
+    pub model: String,
+    #[serde(default)]
+    pub api_key: Option<String>,
+    // Size of the vectors returned by the model. Probed with a first request when not set.
+    #[serde(default)]
+    pub dimension: Option<usize>,
+    // Tokenizer used to size the code chunks, the remote model's own tokenizer isn't available.
+    pub tokenizer_path: String,
+}
+
+/// The embedding backend to use and its settings.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(tag = "backend", rename_all = "snake_case")]
+pub enum EmbedderConfig {
+    Onnx(OnnxConfig),
+    #[serde(rename = "openai")]
+    OpenAi(RemoteEmbedderConfig),
+    Ollama(RemoteEmbedderConfig),
+}
+
+impl Default for EmbedderConfig {
+    fn default() -> Self {
+        EmbedderConfig::Onnx(OnnxConfig::default())
+    }
+}
+
+impl EmbedderConfig {
+    /// Reads the backend from `EMBEDDING_BACKEND` (`onnx`, `openai` or `ollama`, defaults to `onnx`).
+    ///
+    /// The ONNX backend reads `TOKENIZER_PATH`, `MODEL_PATH`, `NUM_OMP_THREADS` and
+    /// `EMBEDDING_POOLING`; the HTTP backends read `EMBEDDING_URL`, `EMBEDDING_MODEL`,
+    /// `EMBEDDING_API_KEY`, `EMBEDDING_DIMENSION` and `TOKENIZER_PATH`.
+    pub fn from_env() -> Result<Self> {
+        let tokenizer_path =
+            env::var("TOKENIZER_PATH").unwrap_or_else(|_| DEFAULT_TOKENIZER_PATH.to_string());
+        let backend = env::var("EMBEDDING_BACKEND").unwrap_or_else(|_| "onnx".to_string());
+
+        let remote = |default_url: &str, default_model: &str| -> Result<RemoteEmbedderConfig> {
+            Ok(RemoteEmbedderConfig {
+                url: env::var("EMBEDDING_URL").unwrap_or_else(|_| default_url.to_string()),
+                model: env::var("EMBEDDING_MODEL").unwrap_or_else(|_| default_model.to_string()),
+                api_key: env::var("EMBEDDING_API_KEY").ok(),
+                dimension: env::var("EMBEDDING_DIMENSION")
+                    .ok()
+                    .map(|v| v.parse())
+                    .transpose()
+                    .context("EMBEDDING_DIMENSION must be a number")?,
+                tokenizer_path: tokenizer_path.clone(),
+            })
+        };
+
+        let config = match backend.to_ascii_lowercase().as_str() {
+            "onnx" => EmbedderConfig::Onnx(OnnxConfig {
+                tokenizer_path: tokenizer_path.clone(),
+                model_path: env::var("MODEL_PATH")
+                    .unwrap_or_else(|_| DEFAULT_MODEL_PATH.to_string()),
+                threads: env::var("NUM_OMP_THREADS")
+                    .map(|v| v.parse().unwrap_or(1))
+                    .unwrap_or(1),
+                pooling: match env::var("EMBEDDING_POOLING").as_deref() {
+                    Ok("cls") => Pooling::Cls,
+                    Ok("mean") | Err(_) => Pooling::Mean,
+                    Ok(other) => bail!("unknown EMBEDDING_POOLING `{}`", other),
+                },
+            }),
+            "openai" => EmbedderConfig::OpenAi(remote(DEFAULT_OPENAI_URL, DEFAULT_OPENAI_MODEL)?),
+            "ollama" => EmbedderConfig::Ollama(remote(DEFAULT_OLLAMA_URL, DEFAULT_OLLAMA_MODEL)?),
+            other => bail!("unknown EMBEDDING_BACKEND `{}`", other),
+        };
+
+        Ok(config)
+    }
+
+    /// Path of the tokenizer used to split documents into chunks that fit the model.
+    pub fn tokenizer_path(&self) -> &str {
+        match self {
+            EmbedderConfig::Onnx(config) => &config.tokenizer_path,
+            EmbedderConfig::OpenAi(config) | EmbedderConfig::Ollama(config) => {
+                &config.tokenizer_path
+            }
+        }
+    }
+
+    /// Identifies the model the vectors come from, e.g. `openai/text-embedding-3-small` or
+    /// `onnx/./model/model.onnx`. Vectors of different models can't be compared.
+    pub fn model_name(&self) -> String {
+        match self {
+            EmbedderConfig::Onnx(config) => format!("onnx/{}", config.model_path),
+            EmbedderConfig::OpenAi(config) => format!("openai/{}", config.model),
+            EmbedderConfig::Ollama(config) => format!("ollama/{}", config.model),
+        }
+    }
+
+    /// Loads the model or connects to the backend.
+    pub async fn build(&self) -> Result<Arc<dyn Embedder>> {
+        let embedder: Arc<dyn Embedder> = match self {
+            EmbedderConfig::Onnx(config) => Arc::new(OnnxEmbedder::new(config)?),
+            EmbedderConfig::OpenAi(config) => Arc::new(OpenAiEmbedder::new(config).await?),
+            EmbedderConfig::Ollama(config) => Arc::new(OllamaEmbedder::new(config).await?),
+        };
+        log::info!(
+            "Using {:?} embeddings with {} dimensions",
+            self,
+            embedder.dimension()
+        );
+        Ok(embedder)
+    }
+}
+
+// Embeds a short text to find out the size of the vectors of a model.
+async fn probe_dimension(embedder: &dyn Embedder) -> Result<usize> {
+    let vector = embedder
+        .embed("dimension probe")
+        .await
+        .context("failed to probe the embedding dimension")?;
+    Ok(vector.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_embedder_config_serde() {
+        let config: EmbedderConfig = serde_json::from_str(
+            r#"{"backend": "openai", "url": "http://localhost:8080/v1", "model": "bge-small", "tokenizer_path": "tokenizer.json"}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config,
+            EmbedderConfig::OpenAi(RemoteEmbedderConfig {
+                url: "http://localhost:8080/v1".to_string(),
+                model: "bge-small".to_string(),
+                api_key: None,
+                dimension: None,
+                tokenizer_path: "tokenizer.json".to_string(),
+            })
+        );
+        assert_eq!(config.tokenizer_path(), "tokenizer.json");
+
+        let onnx: EmbedderConfig = serde_json::from_str(
+            r#"{"backend": "onnx", "tokenizer_path": "t.json", "model_path": "m.onnx", "threads": 4}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            onnx,
+            EmbedderConfig::Onnx(OnnxConfig {
+                tokenizer_path: "t.json".to_string(),
+                model_path: "m.onnx".to_string(),
+                threads: 4,
+                pooling: Pooling::Mean,
+            })
+        );
+    }
+}
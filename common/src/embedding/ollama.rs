@@ -0,0 +1,83 @@
+use anyhow::{anyhow, bail, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use super::{probe_dimension, Embedder, Embedding, RemoteEmbedderConfig};
+
+/// Embeds text with the `/api/embed` endpoint of an Ollama server.
+pub struct OllamaEmbedder {
+    client: reqwest::Client,
+    url: String,
+    model: String,
+    dimension: usize,
+}
+
+#[derive(Serialize)]
+struct EmbedRequest<'a> {
+    model: &'a str,
+    input: &'a [&'a str],
+}
+
+#[derive(Deserialize)]
+struct EmbedResponse {
+    embeddings: Vec<Embedding>,
+}
+
+impl OllamaEmbedder {
+    pub async fn new(config: &RemoteEmbedderConfig) -> Result<Self> {
+        let mut embedder = Self {
+            client: reqwest::Client::new(),
+            url: format!("{}/api/embed", config.url.trim_end_matches('/')),
+            model: config.model.clone(),
+            dimension: config.dimension.unwrap_or_default(),
+        };
+        if config.dimension.is_none() {
+            embedder.dimension = probe_dimension(&embedder).await?;
+        }
+        Ok(embedder)
+    }
+}
+
+#[async_trait]
+impl Embedder for OllamaEmbedder {
+    fn dimension(&self) -> usize {
+        self.dimension
+    }
+
+    async fn embed_batch(&self, sequences: &[&str]) -> Result<Vec<Embedding>> {
+        if sequences.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let response = self
+            .client
+            .post(&self.url)
+            .json(&EmbedRequest {
+                model: &self.model,
+                input: sequences,
+            })
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            bail!(
+                "embedding request to {} failed with {}: {}",
+                self.url,
+                status,
+                body
+            );
+        }
+
+        let embeddings = response.json::<EmbedResponse>().await?.embeddings;
+        if embeddings.len() != sequences.len() {
+            return Err(anyhow!(
+                "expected {} embeddings from {}, got {}",
+                sequences.len(),
+                self.url,
+                embeddings.len()
+            ));
+        }
+        Ok(embeddings)
+    }
+}
@@ -0,0 +1,195 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use ndarray::{s, Array2, Axis, CowArray, Ix3};
+use ort::tensor::OrtOwnedTensor;
+use ort::value::Value;
+use ort::{Environment, ExecutionProvider, GraphOptimizationLevel, LoggingLevel, SessionBuilder};
+use std::sync::Arc;
+use tokenizers::Encoding;
+
+use super::{Embedder, Embedding, OnnxConfig, Pooling};
+
+// Upper bound on the padded tokens (sequences × longest sequence) run through the model at once.
+const MAX_BATCH_TOKENS: usize = 8192;
+
+/// Embeds text locally with an ONNX sentence-transformer model.
+///
+/// Sequences are padded into batches sized by their token count and the model runs on the
+/// blocking thread pool, so concurrent calls embed in parallel without stalling the runtime.
+pub struct OnnxEmbedder {
+    model: Arc<OnnxModel>,
+    dimension: usize,
+}
+
+struct OnnxModel {
+    tokenizer: tokenizers::Tokenizer,
+    session: ort::Session,
+    pooling: Pooling,
+}
+
+impl OnnxEmbedder {
+    pub fn new(config: &OnnxConfig) -> Result<Self> {
+        let environment = Arc::new(
+            Environment::builder()
+                .with_name("Encode")
+                .with_log_level(LoggingLevel::Warning)
+                .with_execution_providers([ExecutionProvider::CPU(Default::default())])
+                .with_telemetry(false)
+                .build()?,
+        );
+
+        let tokenizer = tokenizers::Tokenizer::from_file(&config.tokenizer_path)
+            .map_err(|e| anyhow!("failed to load tokenizer {}: {}", config.tokenizer_path, e))?;
+
+        let session = SessionBuilder::new(&environment)?
+            .with_optimization_level(GraphOptimizationLevel::Level3)?
+            .with_intra_threads(config.threads)?
+            .with_model_from_file(&config.model_path)?;
+
+        let model = OnnxModel {
+            tokenizer,
+            session,
+            pooling: config.pooling,
+        };
+        let dimension = model
+            .embed_sequences(&["dimension probe".to_string()])?
+            .pop()
+            .map_or(0, |embedding| embedding.len());
+
+        Ok(Self {
+            model: Arc::new(model),
+            dimension,
+        })
+    }
+}
+
+impl OnnxModel {
+    fn embed_sequences(&self, sequences: &[String]) -> Result<Vec<Embedding>> {
+        let encodings = sequences
+            .iter()
+            .map(|sequence| {
+                self.tokenizer
+                    .encode(sequence.as_str(), true)
+                    .map_err(|e| anyhow!("failed to tokenize sequence: {}", e))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let lengths = encodings.iter().map(Encoding::len).collect::<Vec<_>>();
+        let mut embeddings = vec![Vec::new(); sequences.len()];
+        for batch in plan_batches(&lengths, MAX_BATCH_TOKENS) {
+            let batch_encodings = batch.iter().map(|&i| &encodings[i]).collect::<Vec<_>>();
+            let pooled = self.run_batch(&batch_encodings)?;
+            for (i, embedding) in batch.into_iter().zip(pooled) {
+                embeddings[i] = embedding;
+            }
+        }
+        Ok(embeddings)
+    }
+
+    // Runs the model on a padded batch and pools the token embeddings of each sequence.
+    fn run_batch(&self, encodings: &[&Encoding]) -> Result<Vec<Embedding>> {
+        let batch_size = encodings.len();
+        let length = encodings.iter().map(|e| e.len()).max().unwrap_or(0);
+
+        // padding is on the right and masked out by the attention mask.
+        let mut input_ids = Array2::<i64>::zeros((batch_size, length));
+        let mut attention_mask = Array2::<i64>::zeros((batch_size, length));
+        let mut token_type_ids = Array2::<i64>::zeros((batch_size, length));
+        for (row, encoding) in encodings.iter().enumerate() {
+            for (col, &id) in encoding.get_ids().iter().enumerate() {
+                input_ids[[row, col]] = id as i64;
+            }
+            for (col, &mask) in encoding.get_attention_mask().iter().enumerate() {
+                attention_mask[[row, col]] = mask as i64;
+            }
+            for (col, &type_id) in encoding.get_type_ids().iter().enumerate() {
+                token_type_ids[[row, col]] = type_id as i64;
+            }
+        }
+
+        let outputs = self.session.run(vec![
+            Value::from_array(
+                self.session.allocator(),
+                &CowArray::from(input_ids).into_dyn(),
+            )?,
+            Value::from_array(
+                self.session.allocator(),
+                &CowArray::from(attention_mask).into_dyn(),
+            )?,
+            Value::from_array(
+                self.session.allocator(),
+                &CowArray::from(token_type_ids).into_dyn(),
+            )?,
+        ])?;
+
+        let output_tensor: OrtOwnedTensor<f32, _> = outputs[0].try_extract()?;
+        let output_view = output_tensor.view();
+        let token_embeddings = output_view.view().into_dimensionality::<Ix3>()?;
+
+        encodings
+            .iter()
+            .enumerate()
+            .map(|(row, encoding)| {
+                let tokens = token_embeddings.index_axis(Axis(0), row);
+                let pooled = match self.pooling {
+                    Pooling::Mean => tokens
+                        .slice(s![..encoding.len(), ..])
+                        .mean_axis(Axis(0))
+                        .ok_or_else(|| anyhow!("empty model output"))?,
+                    Pooling::Cls => tokens.index_axis(Axis(0), 0).to_owned(),
+                };
+                Ok(pooled.to_vec())
+            })
+            .collect()
+    }
+}
+
+// Groups sequence indices into batches whose padded size stays within `max_tokens`. Sequences are
+// sorted by length first so that each batch pads as little as possible; a sequence longer than
+// the budget gets a batch of its own.
+fn plan_batches(lengths: &[usize], max_tokens: usize) -> Vec<Vec<usize>> {
+    let mut order = (0..lengths.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&i| lengths[i]);
+
+    let mut batches: Vec<Vec<usize>> = Vec::new();
+    for i in order {
+        match batches.last_mut() {
+            // the sequence being added is the longest of the batch so far.
+            Some(batch) if lengths[i] * (batch.len() + 1) <= max_tokens => batch.push(i),
+            _ => batches.push(vec![i]),
+        }
+    }
+    batches
+}
+
+#[async_trait]
+impl Embedder for OnnxEmbedder {
+    fn dimension(&self) -> usize {
+        self.dimension
+    }
+
+    async fn embed_batch(&self, sequences: &[&str]) -> Result<Vec<Embedding>> {
+        if sequences.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let model = self.model.clone();
+        let sequences = sequences.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        tokio::task::spawn_blocking(move || model.embed_sequences(&sequences)).await?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan_batches() {
+        let lengths = [10, 300, 20, 100, 5000, 30];
+        let batches = plan_batches(&lengths, 600);
+
+        // sorted by length: 10, 20, 30, 100 fit together (4 × 100), 300 would pad to 1500.
+        assert_eq!(batches, vec![vec![0, 2, 5, 3], vec![1], vec![4]]);
+        assert!(plan_batches(&[], 600).is_empty());
+    }
+}
@@ -0,0 +1,93 @@
+use anyhow::{anyhow, bail, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use super::{probe_dimension, Embedder, Embedding, RemoteEmbedderConfig};
+
+/// Embeds text with an OpenAI-compatible `/embeddings` endpoint.
+pub struct OpenAiEmbedder {
+    client: reqwest::Client,
+    url: String,
+    model: String,
+    api_key: Option<String>,
+    dimension: usize,
+}
+
+#[derive(Serialize)]
+struct EmbeddingsRequest<'a> {
+    model: &'a str,
+    input: &'a [&'a str],
+}
+
+#[derive(Deserialize)]
+struct EmbeddingsResponse {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingData {
+    index: usize,
+    embedding: Embedding,
+}
+
+impl OpenAiEmbedder {
+    pub async fn new(config: &RemoteEmbedderConfig) -> Result<Self> {
+        let mut embedder = Self {
+            client: reqwest::Client::new(),
+            url: format!("{}/embeddings", config.url.trim_end_matches('/')),
+            model: config.model.clone(),
+            api_key: config.api_key.clone(),
+            dimension: config.dimension.unwrap_or_default(),
+        };
+        if config.dimension.is_none() {
+            embedder.dimension = probe_dimension(&embedder).await?;
+        }
+        Ok(embedder)
+    }
+}
+
+#[async_trait]
+impl Embedder for OpenAiEmbedder {
+    fn dimension(&self) -> usize {
+        self.dimension
+    }
+
+    async fn embed_batch(&self, sequences: &[&str]) -> Result<Vec<Embedding>> {
+        if sequences.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut request = self.client.post(&self.url).json(&EmbeddingsRequest {
+            model: &self.model,
+            input: sequences,
+        });
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            bail!(
+                "embedding request to {} failed with {}: {}",
+                self.url,
+                status,
+                body
+            );
+        }
+
+        // the response isn't guaranteed to be in the order of the inputs.
+        let mut data = response.json::<EmbeddingsResponse>().await?.data;
+        data.sort_by_key(|item| item.index);
+        if data.len() != sequences.len() {
+            return Err(anyhow!(
+                "expected {} embeddings from {}, got {}",
+                sequences.len(),
+                self.url,
+                data.len()
+            ));
+        }
+        Ok(data.into_iter().map(|item| item.embedding).collect())
+    }
+}
@@ -10,6 +10,21 @@ pub fn generate_quikwit_index_name(namespace: &str) -> String {
     return index_name.to_string();
 }
 
+// The collection holding the code chunks of a repo.
+pub fn generate_chunks_collection_name(namespace: &str) -> String {
+    format!("{}-documents", generate_quikwit_index_name(namespace))
+}
+
+// The collection holding the Markdown sections of a repo.
+pub fn generate_docs_collection_name(namespace: &str) -> String {
+    format!("{}-docs", generate_quikwit_index_name(namespace))
+}
+
+// The collection holding the commit messages of a repo.
+pub fn generate_commits_collection_name(namespace: &str) -> String {
+    format!("{}-commits", generate_quikwit_index_name(namespace))
+}
+
 pub fn generate_qdrant_index_name(namespace: &str) -> String {
     let repo_name = namespace.split("/").last().unwrap();
     let version = namespace.split("/").nth(0).unwrap();
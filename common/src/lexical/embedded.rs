@@ -0,0 +1,422 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use log::{debug, info};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tantivy::collector::{Count, TopDocs};
+use tantivy::directory::MmapDirectory;
+use tantivy::query::QueryParser;
+use tantivy::schema::{Field, Schema, FAST, INDEXED, STORED, STRING, TEXT};
+use tantivy::{Document, Index, IndexReader, IndexWriter, ReloadPolicy};
+
+use super::{parse_document, LexicalDocument, LexicalIndex, SearchPage, DEFAULT_SEARCH_FIELDS};
+
+// Memory budget of an index writer, tantivy needs at least 15MB.
+const WRITER_MEMORY_BYTES: usize = 50_000_000;
+
+// Stored field holding the whole document as JSON, the other fields are only indexed.
+const DOCUMENT_FIELD: &str = "document";
+
+/// Documents stored in on-disk Tantivy indexes, in a sub-directory per index under `root`.
+///
+/// Writers are only opened on the first write, so several processes can search the same indexes
+/// while a single one writes to them.
+pub struct TantivyIndex {
+    root: PathBuf,
+    indexes: Mutex<HashMap<String, Arc<OpenIndex>>>,
+}
+
+struct OpenIndex {
+    index: Index,
+    reader: IndexReader,
+    fields: Fields,
+    writer: Mutex<Option<IndexWriter>>,
+}
+
+struct Fields {
+    repo_name: Field,
+    repo_disk_path: Field,
+    relative_path: Field,
+    repo_ref: Field,
+    branches: Field,
+    last_commit: Field,
+    last_author: Field,
+    last_commit_date: Field,
+    churn: Field,
+    lang: Field,
+    is_directory: Field,
+    symbols: Field,
+    content: Field,
+    unique_hash: Field,
+    document: Field,
+}
+
+// Mirrors the Quickwit doc mapping: `text` fields use the default tokenizer, `raw` ones STRING.
+fn schema() -> Schema {
+    let mut builder = Schema::builder();
+    builder.add_text_field("repo_name", TEXT);
+    builder.add_text_field("repo_disk_path", STRING);
+    builder.add_text_field("relative_path", TEXT);
+    builder.add_text_field("repo_ref", STRING);
+    builder.add_text_field("branches", STRING);
+    builder.add_text_field("last_commit", STRING);
+    builder.add_text_field("last_author", STRING | FAST);
+    builder.add_i64_field("last_commit_date", INDEXED | FAST);
+    builder.add_u64_field("churn", INDEXED | FAST);
+    builder.add_text_field("lang", STRING);
+    builder.add_bool_field("is_directory", INDEXED);
+    builder.add_text_field("symbols", TEXT);
+    builder.add_text_field("content", TEXT);
+    builder.add_text_field("unique_hash", STRING);
+    builder.add_text_field(DOCUMENT_FIELD, STORED);
+    builder.build()
+}
+
+impl Fields {
+    fn new(schema: &Schema) -> Result<Self> {
+        Ok(Self {
+            repo_name: schema.get_field("repo_name")?,
+            repo_disk_path: schema.get_field("repo_disk_path")?,
+            relative_path: schema.get_field("relative_path")?,
+            repo_ref: schema.get_field("repo_ref")?,
+            branches: schema.get_field("branches")?,
+            last_commit: schema.get_field("last_commit")?,
+            last_author: schema.get_field("last_author")?,
+            last_commit_date: schema.get_field("last_commit_date")?,
+            churn: schema.get_field("churn")?,
+            lang: schema.get_field("lang")?,
+            is_directory: schema.get_field("is_directory")?,
+            symbols: schema.get_field("symbols")?,
+            content: schema.get_field("content")?,
+            unique_hash: schema.get_field("unique_hash")?,
+            document: schema.get_field(DOCUMENT_FIELD)?,
+        })
+    }
+
+    fn to_tantivy(&self, document: &LexicalDocument) -> Result<Document> {
+        let mut doc = Document::default();
+        doc.add_text(self.repo_name, &document.repo_name);
+        doc.add_text(self.repo_disk_path, &document.repo_disk_path);
+        doc.add_text(self.relative_path, &document.relative_path);
+        doc.add_text(self.repo_ref, &document.repo_ref);
+        for branch in &document.branches {
+            doc.add_text(self.branches, branch);
+        }
+        doc.add_text(self.last_commit, &document.last_commit);
+        doc.add_text(self.last_author, &document.last_author);
+        doc.add_i64(self.last_commit_date, document.last_commit_date);
+        doc.add_u64(self.churn, document.churn);
+        doc.add_text(self.lang, &document.lang);
+        doc.add_bool(self.is_directory, document.is_directory);
+        doc.add_text(self.symbols, &document.symbols);
+        doc.add_text(self.content, &document.content);
+        doc.add_text(self.unique_hash, &document.unique_hash);
+        doc.add_text(self.document, serde_json::to_string(document)?);
+        Ok(doc)
+    }
+
+    // Only the selected fields are kept, all of them if none are.
+    fn from_tantivy(&self, doc: &Document, fields: &[&str]) -> Result<LexicalDocument> {
+        let json = doc
+            .get_first(self.document)
+            .and_then(|value| value.as_text())
+            .ok_or_else(|| anyhow!("document without a stored source"))?;
+        parse_document(serde_json::from_str(json)?, fields)
+    }
+}
+
+impl OpenIndex {
+    // Runs the update with the writer of the index, opened on first use, then commits it and
+    // reloads the reader so that the changes are visible to the next search.
+    fn write(&self, update: impl FnOnce(&IndexWriter, &Fields) -> Result<()>) -> Result<()> {
+        let mut writer = self.writer.lock().unwrap();
+        if writer.is_none() {
+            *writer = Some(self.index.writer(WRITER_MEMORY_BYTES)?);
+        }
+        let writer = writer.as_mut().unwrap();
+
+        if let Err(e) = update(writer, &self.fields) {
+            writer.rollback()?;
+            return Err(e);
+        }
+        writer.commit()?;
+        self.reader.reload()?;
+        Ok(())
+    }
+
+    fn parse_query(&self, query: &str) -> Result<Box<dyn tantivy::query::Query>> {
+        let schema = self.index.schema();
+        let default_fields = DEFAULT_SEARCH_FIELDS
+            .iter()
+            .map(|name| schema.get_field(name))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut parser = QueryParser::for_index(&self.index, default_fields);
+        // same as quickwit, all the terms of a query have to match.
+        parser.set_conjunction_by_default();
+        parser
+            .parse_query(query)
+            .map_err(|e| anyhow!("Invalid query `{}`: {}", query, e))
+    }
+}
+
+impl TantivyIndex {
+    pub fn new(root: &str) -> Result<Self> {
+        let root = PathBuf::from(root);
+        fs::create_dir_all(&root)?;
+        info!("Storing the tantivy indexes under {}", root.display());
+        Ok(Self {
+            root,
+            indexes: Mutex::new(HashMap::new()),
+        })
+    }
+
+    // Opens the index, creating it on disk if asked to. Opened indexes are kept for the next calls.
+    fn open(&self, index_id: &str, create: bool) -> Result<Arc<OpenIndex>> {
+        let mut indexes = self.indexes.lock().unwrap();
+        if let Some(index) = indexes.get(index_id) {
+            return Ok(index.clone());
+        }
+
+        let path = self.root.join(index_id);
+        if !path.exists() {
+            if !create {
+                return Err(anyhow!("Index {} doesn't exist", index_id));
+            }
+            info!("Creating tantivy index {}", path.display());
+            fs::create_dir_all(&path)?;
+        }
+
+        let index = Index::open_or_create(MmapDirectory::open(&path)?, schema())?;
+        let reader = index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::OnCommit)
+            .try_into()?;
+        let fields = Fields::new(&index.schema())?;
+        let index = Arc::new(OpenIndex {
+            index,
+            reader,
+            fields,
+            writer: Mutex::new(None),
+        });
+
+        indexes.insert(index_id.to_string(), index.clone());
+        Ok(index)
+    }
+}
+
+#[async_trait]
+impl LexicalIndex for TantivyIndex {
+    async fn ensure_index(&self, index_id: &str) -> Result<()> {
+        self.open(index_id, true)?;
+        Ok(())
+    }
+
+    async fn ingest(&self, index_id: &str, documents: &[LexicalDocument]) -> Result<()> {
+        if documents.is_empty() {
+            return Ok(());
+        }
+
+        let index = self.open(index_id, true)?;
+        let documents = documents.to_vec();
+        tokio::task::spawn_blocking(move || {
+            index.write(|writer, fields| {
+                for document in &documents {
+                    writer.add_document(fields.to_tantivy(document)?)?;
+                }
+                debug!("Added {} documents", documents.len());
+                Ok(())
+            })
+        })
+        .await?
+    }
+
+    async fn delete(&self, index_id: &str, query: &str) -> Result<()> {
+        let index = self.open(index_id, true)?;
+        let query = index.parse_query(query)?;
+        tokio::task::spawn_blocking(move || {
+            index.write(|writer, _| {
+                writer.delete_query(query)?;
+                Ok(())
+            })
+        })
+        .await?
+    }
+
+    async fn delete_index(&self, index_id: &str) -> Result<()> {
+        // dropping the open index releases its writer lock before the files are removed.
+        let index = self.indexes.lock().unwrap().remove(index_id);
+        drop(index);
+
+        let path = self.root.join(index_id);
+        if path.exists() {
+            fs::remove_dir_all(&path)?;
+            info!("Deleted tantivy index {}", path.display());
+        }
+        Ok(())
+    }
+
+    async fn search_page(
+        &self,
+        index_id: &str,
+        query: &str,
+        offset: usize,
+        max_hits: usize,
+        fields: &[&str],
+    ) -> Result<SearchPage> {
+        let index = self.open(index_id, false)?;
+        let query = index.parse_query(query)?;
+        let fields = fields
+            .iter()
+            .map(|field| field.to_string())
+            .collect::<Vec<_>>();
+        tokio::task::spawn_blocking(move || {
+            let fields = fields.iter().map(String::as_str).collect::<Vec<_>>();
+            let searcher = index.reader.searcher();
+            let (top_docs, count) = searcher.search(
+                &query,
+                &(
+                    TopDocs::with_limit(max_hits.max(1)).and_offset(offset),
+                    Count,
+                ),
+            )?;
+            let documents = top_docs
+                .into_iter()
+                .take(max_hits)
+                .map(|(_score, address)| {
+                    index.fields.from_tantivy(&searcher.doc(address)?, &fields)
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            let next_offset = offset + documents.len();
+            Ok(SearchPage {
+                next_offset: (!documents.is_empty() && next_offset < count).then_some(next_offset),
+                documents,
+            })
+        })
+        .await?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexical::branch_clause;
+
+    fn document(path: &str, branch: &str, content: &str) -> LexicalDocument {
+        LexicalDocument {
+            repo_name: "nezuko".to_string(),
+            repo_disk_path: "/repos/nezuko".to_string(),
+            repo_ref: "nezuko".to_string(),
+            branches: vec![branch.to_string()],
+            relative_path: path.to_string(),
+            last_commit: String::new(),
+            last_author: String::new(),
+            last_commit_date: 0,
+            churn: 0,
+            lang: "Rust".to_string(),
+            is_directory: false,
+            avg_line_length: 12.5,
+            line_end_indices: vec![12, 0, 0, 0],
+            content: content.to_string(),
+            symbol_locations: Vec::new(),
+            unique_hash: format!("{}@{}", path, branch),
+            symbols: String::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tantivy_index() {
+        let root = std::env::temp_dir().join(format!("lexical-{}", uuid::Uuid::new_v4()));
+        let root_str = root.to_str().unwrap();
+        let index = TantivyIndex::new(root_str).unwrap();
+
+        // searching an index that was never created fails rather than creating it.
+        assert!(index.search("v2-nezuko", "*", 10).await.is_err());
+
+        index.ensure_index("v2-nezuko").await.unwrap();
+        let main = document("src/main.rs", "main", "fn main() { run_agent(); }");
+        let lib = document("src/lib.rs", "main", "pub fn run_agent() {}");
+        let feature = document("src/lib.rs", "feature/x", "pub fn run_agent_v2() {}");
+        index
+            .ingest("v2-nezuko", &[main.clone(), lib.clone(), feature.clone()])
+            .await
+            .unwrap();
+
+        let all = index.search("v2-nezuko", "*", 10).await.unwrap();
+        assert_eq!(all.len(), 3);
+        assert!(all.contains(&main));
+
+        let on_feature = index
+            .search("v2-nezuko", &branch_clause("feature/x"), 10)
+            .await
+            .unwrap();
+        assert_eq!(on_feature, vec![feature.clone()]);
+
+        // identifiers are split by the default tokenizer and matched as phrases.
+        let by_content = index.search("v2-nezuko", "run_agent", 10).await.unwrap();
+        assert_eq!(by_content.len(), 3);
+        let by_content = index.search("v2-nezuko", "run_agent_v2", 10).await.unwrap();
+        assert_eq!(by_content, vec![feature.clone()]);
+        assert_eq!(index.search("v2-nezuko", "*", 1).await.unwrap().len(), 1);
+
+        // the pages don't overlap and the last one has no next offset.
+        let first = index
+            .search_page("v2-nezuko", "*", 0, 2, &["relative_path", "branches"])
+            .await
+            .unwrap();
+        assert_eq!(first.documents.len(), 2);
+        assert_eq!(first.next_offset, Some(2));
+        assert!(first.documents[0].content.is_empty());
+        assert!(!first.documents[0].relative_path.is_empty());
+        let last = index
+            .search_page("v2-nezuko", "*", 2, 2, &["unique_hash"])
+            .await
+            .unwrap();
+        assert_eq!(last.documents.len(), 1);
+        assert_eq!(last.next_offset, None);
+        let mut hashes = first
+            .documents
+            .iter()
+            .map(|document| format!("{}@{}", document.relative_path, document.branches[0]))
+            .chain(
+                last.documents
+                    .iter()
+                    .map(|document| document.unique_hash.clone()),
+            )
+            .collect::<Vec<_>>();
+        hashes.sort();
+        assert_eq!(
+            hashes,
+            vec![
+                "src/lib.rs@feature/x",
+                "src/lib.rs@main",
+                "src/main.rs@main"
+            ]
+        );
+
+        index
+            .delete("v2-nezuko", &format!("unique_hash:\"{}\"", lib.unique_hash))
+            .await
+            .unwrap();
+
+        // the documents are persisted, a new instance sees the same state.
+        let reopened = TantivyIndex::new(root_str).unwrap();
+        let remaining = reopened
+            .search("v2-nezuko", &branch_clause("main"), 10)
+            .await
+            .unwrap();
+        assert_eq!(remaining, vec![main]);
+
+        assert!(reopened.search("v2-nezuko", "content:(", 10).await.is_err());
+
+        reopened.delete_index("v2-nezuko").await.unwrap();
+        assert!(reopened.search("v2-nezuko", "*", 10).await.is_err());
+        // deleting a missing index is a no-op.
+        reopened.delete_index("v2-nezuko").await.unwrap();
+
+        let _ = fs::remove_dir_all(root);
+    }
+}
@@ -0,0 +1,193 @@
+//! Lexical (full-text) index backends shared by the indexer and the search services.
+//!
+//! Queries use the syntax Quickwit and Tantivy have in common: `field:value`, quoted phrases,
+//! `AND`/`OR` and `*` to match every document.
+
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::env;
+use std::sync::Arc;
+
+use crate::ast::graph_code_pluck::ContentDocument;
+
+mod embedded;
+mod quickwit;
+
+pub use embedded::TantivyIndex;
+pub use quickwit::QuickwitIndex;
+
+const DEFAULT_TANTIVY_PATH: &str = "./tantivy";
+
+// Fields searched by queries that don't name a field.
+const DEFAULT_SEARCH_FIELDS: [&str; 5] =
+    ["relative_path", "repo_name", "content", "lang", "symbols"];
+
+/// A file or directory of an indexed repo, as stored in the lexical index.
+///
+/// The fields left out of a search with a field selection are empty, see
+/// `LexicalIndex::search_page`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct LexicalDocument {
+    pub repo_name: String,
+    pub repo_disk_path: String,
+    pub repo_ref: String,
+    // The branches and tags the document was indexed from, missing on documents indexed before
+    // refs were recorded.
+    #[serde(default)]
+    pub branches: Vec<String>,
+    pub relative_path: String,
+    // The last commit of the indexed ref that modified the file, its author and commit time, and
+    // the number of commits that modified it in the walked history. Empty on documents indexed
+    // before the history was recorded and on the sources without git history.
+    pub last_commit: String,
+    #[serde(default)]
+    pub last_author: String,
+    // Seconds since the epoch.
+    #[serde(default)]
+    pub last_commit_date: i64,
+    #[serde(default)]
+    pub churn: u64,
+    #[serde(default)]
+    pub lang: String,
+    pub is_directory: bool,
+    pub avg_line_length: f64,
+    pub line_end_indices: Vec<u8>,
+    pub content: String,
+    pub symbol_locations: Vec<u8>,
+    pub unique_hash: String,
+    pub symbols: String,
+}
+
+impl From<LexicalDocument> for ContentDocument {
+    fn from(document: LexicalDocument) -> Self {
+        ContentDocument {
+            repo_name: document.repo_name,
+            repo_ref: document.repo_ref,
+            relative_path: document.relative_path,
+            lang: (!document.lang.is_empty()).then_some(document.lang),
+            line_end_indices: document.line_end_indices,
+            content: document.content,
+            symbol_locations: document.symbol_locations,
+            symbols: document.symbols,
+        }
+    }
+}
+
+/// A page of the documents matching a query.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SearchPage {
+    pub documents: Vec<LexicalDocument>,
+    // The offset of the next page, None on the last one.
+    pub next_offset: Option<usize>,
+}
+
+/// Stores and searches the documents of the indexed repos, one index per repo.
+#[async_trait]
+pub trait LexicalIndex: Send + Sync {
+    /// Creates the index if it doesn't exist yet. Existing indexes are kept as they are.
+    async fn ensure_index(&self, index_id: &str) -> Result<()>;
+
+    /// Adds the documents to the index, they are searchable once the call returns.
+    async fn ingest(&self, index_id: &str, documents: &[LexicalDocument]) -> Result<()>;
+
+    /// Deletes the documents matching the query.
+    async fn delete(&self, index_id: &str, query: &str) -> Result<()>;
+
+    /// Deletes the index and all its documents. Deleting a missing index is not an error.
+    async fn delete_index(&self, index_id: &str) -> Result<()>;
+
+    /// Returns up to `max_hits` documents matching the query, best first.
+    async fn search(
+        &self,
+        index_id: &str,
+        query: &str,
+        max_hits: usize,
+    ) -> Result<Vec<LexicalDocument>> {
+        Ok(self
+            .search_page(index_id, query, 0, max_hits, &[])
+            .await?
+            .documents)
+    }
+
+    /// Returns up to `max_hits` documents matching the query, best first, after skipping the
+    /// first `offset` ones. Documents scoring the same keep the order they are stored in, so the
+    /// pages of an index that isn't written to don't overlap.
+    ///
+    /// Only the `fields` of the documents are kept, all of them if `fields` is empty. The
+    /// selection is applied to the documents once they are read: Quickwit still sends the whole
+    /// hits, so it saves memory and the work of the callers rather than transfer.
+    async fn search_page(
+        &self,
+        index_id: &str,
+        query: &str,
+        offset: usize,
+        max_hits: usize,
+        fields: &[&str],
+    ) -> Result<SearchPage>;
+}
+
+/// The lexical index backend to use and its settings.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(tag = "backend", rename_all = "snake_case")]
+pub enum LexicalIndexConfig {
+    // A Quickwit server, at its base url.
+    Quickwit { url: String },
+    // Tantivy indexes stored under a local directory, one sub-directory per index.
+    Tantivy { path: String },
+}
+
+impl LexicalIndexConfig {
+    /// Reads the backend from `LEXICAL_BACKEND` (`quickwit` or `tantivy`, defaults to `quickwit`).
+    ///
+    /// Quickwit is reached at `quickwit_url`, the Tantivy indexes are stored under `TANTIVY_PATH`.
+    pub fn from_env(quickwit_url: &str) -> Result<Self> {
+        let backend = env::var("LEXICAL_BACKEND").unwrap_or_else(|_| "quickwit".to_string());
+
+        let config = match backend.to_ascii_lowercase().as_str() {
+            "quickwit" => LexicalIndexConfig::Quickwit {
+                url: quickwit_url.to_string(),
+            },
+            "tantivy" => LexicalIndexConfig::Tantivy {
+                path: env::var("TANTIVY_PATH").unwrap_or_else(|_| DEFAULT_TANTIVY_PATH.to_string()),
+            },
+            other => bail!("unknown LEXICAL_BACKEND `{}`", other),
+        };
+
+        Ok(config)
+    }
+
+    /// Connects to the backend.
+    pub fn build(&self) -> Result<Arc<dyn LexicalIndex>> {
+        let index: Arc<dyn LexicalIndex> = match self {
+            LexicalIndexConfig::Quickwit { url } => Arc::new(QuickwitIndex::new(url)),
+            LexicalIndexConfig::Tantivy { path } => Arc::new(TantivyIndex::new(path)?),
+        };
+        log::info!("Using the {:?} lexical index", self);
+        Ok(index)
+    }
+}
+
+/// Query clause restricting the results to the documents of a repo. Repo names are matched as
+/// phrases, so names sharing a prefix, e.g. `nezuko` and `nezuko-ui`, both match and the
+/// results have to be checked against the exact name.
+pub fn repo_clause(repo_name: &str) -> String {
+    format!("repo_name:\"{}\"", repo_name.replace('"', "\\\""))
+}
+
+/// Query clause restricting the results to the documents indexed from a branch or tag.
+pub fn branch_clause(branch: &str) -> String {
+    format!("branches:\"{}\"", branch.replace('"', "\\\""))
+}
+
+// Parses a stored document, dropping the fields that weren't selected.
+fn parse_document(mut document: Value, fields: &[&str]) -> Result<LexicalDocument> {
+    if !fields.is_empty() {
+        if let Value::Object(map) = &mut document {
+            map.retain(|key, _| fields.contains(&key.as_str()));
+        }
+    }
+    Ok(serde_json::from_value(document)?)
+}
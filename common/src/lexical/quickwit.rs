@@ -0,0 +1,222 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use log::{debug, error, info};
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use tokio::time::{sleep, Duration};
+
+use super::{parse_document, LexicalDocument, LexicalIndex, SearchPage};
+
+// Index config the repo indexes are created with, `index_id` is replaced by the id of each index.
+const INDEX_CONFIG: &str = include_str!("index-config.yaml");
+
+/// Documents stored on a Quickwit server through its REST API.
+pub struct QuickwitIndex {
+    url: String,
+    client: Client,
+}
+
+#[derive(Debug, Serialize)]
+struct SearchRequest<'a> {
+    query: &'a str,
+    start_offset: usize,
+    max_hits: usize,
+}
+
+// Quickwit returns whole documents, the fields that weren't selected are dropped as the hits
+// are parsed.
+#[derive(Debug, Deserialize)]
+struct SearchResponse {
+    num_hits: usize,
+    hits: Vec<serde_json::Value>,
+}
+
+impl QuickwitIndex {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_string(),
+            client: Client::new(),
+        }
+    }
+
+    async fn post(&self, url: &str, content_type: &str, body: String) -> Result<String> {
+        debug!("Making POST request to quickwit {}", url);
+        let response = self
+            .client
+            .post(url)
+            .header("Content-Type", content_type)
+            .body(body)
+            .send()
+            .await?;
+
+        match response.status() {
+            StatusCode::OK | StatusCode::CREATED | StatusCode::ACCEPTED => {
+                debug!("Status from quickwit: {}", response.status());
+                Ok(response.text().await?)
+            }
+            status => {
+                let error_message = format!("Error response from quickwit: {}", status);
+                error!(
+                    "{}: {}",
+                    error_message,
+                    response.text().await.unwrap_or_default()
+                );
+                Err(anyhow!(error_message))
+            }
+        }
+    }
+}
+
+// Points the index config at the given index id.
+fn replace_index_id_in_yaml(mut yaml_content: String, new_index_id: &str) -> String {
+    let old_index_id_pattern = "index_id: ";
+    if let Some(start) = yaml_content.find(old_index_id_pattern) {
+        let end = yaml_content[start..]
+            .find('\n')
+            .unwrap_or(yaml_content.len() - start);
+        yaml_content.replace_range(
+            start + old_index_id_pattern.len()..start + end,
+            new_index_id,
+        );
+    }
+    yaml_content
+}
+
+#[async_trait]
+impl LexicalIndex for QuickwitIndex {
+    async fn ensure_index(&self, index_id: &str) -> Result<()> {
+        let describe_url = format!("{}/api/v1/indexes/{}/describe", self.url, index_id);
+        let describe_response = self.client.get(&describe_url).send().await?;
+
+        match describe_response.status() {
+            StatusCode::NOT_FOUND => {
+                let create_index_url = format!("{}/api/v1/indexes", self.url);
+                info!(
+                    "Index not found, creating new index at URL: {}",
+                    create_index_url
+                );
+                let index_config = replace_index_id_in_yaml(INDEX_CONFIG.to_string(), index_id);
+                self.post(&create_index_url, "application/yaml", index_config)
+                    .await
+                    .map_err(|e| anyhow!("Error creating index {}: {}", index_id, e))?;
+
+                // give quickwit time to start the indexing pipeline of the new index.
+                sleep(Duration::from_secs(10)).await;
+                Ok(())
+            }
+            StatusCode::OK => {
+                // the index holds the documents of the other refs of the repo, keep it as is.
+                info!("Index found, keeping existing documents: {}", index_id);
+                Ok(())
+            }
+            status => Err(anyhow!(
+                "Unexpected status code {} received from describe URL",
+                status
+            )),
+        }
+    }
+
+    async fn ingest(&self, index_id: &str, documents: &[LexicalDocument]) -> Result<()> {
+        if documents.is_empty() {
+            return Ok(());
+        }
+
+        let url = format!("{}/api/v1/{}/ingest?commit=force", self.url, index_id);
+        let batch_data = documents
+            .iter()
+            .map(serde_json::to_string)
+            .collect::<Result<Vec<_>, _>>()?
+            .join("\n");
+        self.post(&url, "application/json", batch_data).await?;
+        Ok(())
+    }
+
+    // Quickwit applies delete tasks asynchronously, so the documents can still be returned for a
+    // short while. Only documents ingested before the task was created are deleted.
+    async fn delete(&self, index_id: &str, query: &str) -> Result<()> {
+        let url = format!("{}/api/v1/{}/delete-tasks", self.url, index_id);
+        let body = serde_json::json!({ "query": query }).to_string();
+        debug!("Creating delete task on {} for {}", index_id, query);
+        self.post(&url, "application/json", body).await?;
+        Ok(())
+    }
+
+    async fn delete_index(&self, index_id: &str) -> Result<()> {
+        let url = format!("{}/api/v1/indexes/{}", self.url, index_id);
+        let response = self.client.delete(&url).send().await?;
+
+        match response.status() {
+            StatusCode::NOT_FOUND => {
+                info!("Index {} doesn't exist, nothing to delete", index_id);
+                Ok(())
+            }
+            status if status.is_success() => {
+                info!("Deleted index {}", index_id);
+                Ok(())
+            }
+            status => Err(anyhow!(
+                "Failed to delete index {}: {} {}",
+                index_id,
+                status,
+                response.text().await.unwrap_or_default()
+            )),
+        }
+    }
+
+    async fn search_page(
+        &self,
+        index_id: &str,
+        query: &str,
+        offset: usize,
+        max_hits: usize,
+        fields: &[&str],
+    ) -> Result<SearchPage> {
+        let url = format!("{}/api/v1/{}/search", self.url, index_id);
+        let body = serde_json::to_string(&SearchRequest {
+            query,
+            start_offset: offset,
+            max_hits,
+        })?;
+        let response_text = self.post(&url, "application/json", body).await?;
+
+        let response: SearchResponse = serde_json::from_str(&response_text)
+            .map_err(|e| anyhow!("Failed to parse the quickwit response: {}", e))?;
+        debug!(
+            "Quickwit returned {} of {} hits for {} from {}",
+            response.hits.len(),
+            response.num_hits,
+            query,
+            offset
+        );
+
+        let documents = response
+            .hits
+            .into_iter()
+            .map(|hit| parse_document(hit, fields))
+            .collect::<Result<Vec<_>>>()
+            .map_err(|e| anyhow!("Failed to parse a quickwit hit: {}", e))?;
+        let next_offset = offset + documents.len();
+        Ok(SearchPage {
+            next_offset: (!documents.is_empty() && next_offset < response.num_hits)
+                .then_some(next_offset),
+            documents,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replace_index_id_in_yaml() {
+        let config = replace_index_id_in_yaml(INDEX_CONFIG.to_string(), "v2-nezuko-abc");
+        assert!(config.contains("index_id: v2-nezuko-abc\n"));
+        assert!(!config.contains("ctags-test-1"));
+
+        assert_eq!(
+            replace_index_id_in_yaml("index_id: old".to_string(), "new"),
+            "index_id: new"
+        );
+    }
+}
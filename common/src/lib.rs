@@ -3,11 +3,15 @@ use std::ops::Range;
 
 
 pub mod ast;
+pub mod embedding;
 pub mod hasher;
+pub mod lexical;
 pub mod llm_gateway;
 pub mod models;
 pub mod prompts;
+pub mod schema;
 pub mod service_interaction;
+pub mod vector;
 pub mod ai_gateway;
 
 pub mod task_graph;
@@ -36,6 +36,17 @@ pub struct CodeSpanRequest {
     pub id: Option<String>,
 }
 
+// Sent by ingestion when a ref of a repo was indexed, or when the repo was deleted, so that the
+// search results cached for the repo are dropped.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+pub struct IndexEvent {
+    pub repo_name: String,
+    // The indexed ref, None if the repo was deleted.
+    pub branch: Option<String>,
+    // The commit the ref was indexed at, None for the sources that don't match a commit.
+    pub commit: Option<String>,
+}
+
 #[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
 pub struct CodeUnderstandRequest {
     pub query: String,
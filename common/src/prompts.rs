@@ -7,7 +7,7 @@ pub fn functions(add_proc: bool) -> serde_json::Value {
         [
             {
                 "name": "code",
-                "description":  "Search the contents of files in a codebase semantically. Results will not necessarily match search terms exactly, but should be related.",
+                "description":  "Search the contents of files in a codebase semantically, documentation sections included. Results will not necessarily match search terms exactly, but should be related.",
                 "parameters": {
                     "type": "object",
                     "properties": {
@@ -21,7 +21,7 @@ pub fn functions(add_proc: bool) -> serde_json::Value {
             },
             {
                 "name": "path",
-                "description": "Search the pathnames in a codebase. Use when you want to find a specific file or directory. Results may not be exact matches, but will be similar by some edit-distance.",
+                "description": "Search the pathnames in a codebase. Use when you want to find a specific file or directory. Results may not be exact matches, but will be similar by some edit-distance, and recently changed files are listed first. The paths of the documentation files related to the query are listed as well.",
                 "parameters": {
                     "type": "object",
                     "properties": {
@@ -33,6 +33,20 @@ pub fn functions(add_proc: bool) -> serde_json::Value {
                     "required": ["query"]
                 }
             },
+            {
+                "name": "history",
+                "description": "Search the commit messages of a codebase semantically, along with the paths each commit changed. Use when you want to know why, when or by whom something was changed.",
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "query": {
+                            "type": "string",
+                            "description": "The query with which to search the commits. This should consist of keywords that might appear in a commit message or a changed path, e.g. 'retry backoff', 'auth middleware'."
+                        }
+                    },
+                    "required": ["query"]
+                }
+            },
             {
                 "name": "none",
                 "description": "Call this to answer the user. Call this only when you have enough information to answer the user's query.",
@@ -107,6 +121,7 @@ pub fn system<'a>(paths: impl IntoIterator<Item = &'a str>) -> String {
 - If the user is referring to, or asking for, information that is in your history, call functions.none
 - If after attempting to gather information you are still unsure how to answer the query, call functions.none
 - If the query is a greeting, or not a question or an instruction call functions.none
+- If the user asks why, when or by whom something was changed, call functions.history
 - When calling functions.code or functions.path, your query should consist of keywords. E.g. if the user says 'What does contextmanager do?', your query should be 'contextmanager'. If the user says 'How is contextmanager used in app', your query should be 'contextmanager app'. If the user says 'What is in the src directory', your query should be 'src'
 - If functions.code or functions.path did not return any relevant information, call them again with a SIGNIFICANTLY different query. The terms in the new query should not overlap with terms in your old one
 - If the output of a function is empty, try calling the function again with DIFFERENT arguments OR try calling a different function
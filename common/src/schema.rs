@@ -0,0 +1,402 @@
+//! Versioning of the index schema: the payload fields of the chunk and symbol collections and
+//! the document mapping of the lexical indexes.
+//!
+//! The version each index was written with is recorded in the `SCHEMA_COLLECTION` of the vector
+//! store, so the indexer and the search services can tell whether they understand an index.
+//! Indexes created before versions were recorded have no record and are at version 1.
+//!
+//! Versions:
+//! 1. chunks, symbol occurrences and documents without the refs they were indexed from.
+//! 2. `branches` on the chunks and documents, a `branch` per symbol occurrence.
+//! 3. `scope_path` on the chunks.
+//! 4. the last commit, author, commit date and churn of the files on the documents.
+//! 5. the refs of the symbol occurrences under `branches`, like the chunks and documents, rather
+//!    than `branch`.
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::hasher::generate_chunks_collection_name;
+use crate::vector::{PointPayload, PointSelector, VectorFilter, VectorPoint, VectorStore};
+
+/// The schema written by the indexer.
+pub const SCHEMA_VERSION: u32 = 5;
+/// The oldest schema the search services can read. Version 1 indexes have no refs and the symbols
+/// of the versions before 5 record theirs under another key, so the branch filters of the
+/// searches would drop all their results.
+pub const MIN_READABLE_SCHEMA_VERSION: u32 = 5;
+/// The version of the indexes that have no record.
+pub const LEGACY_SCHEMA_VERSION: u32 = 1;
+
+/// Holds one point per index with its schema version. The vectors are unused.
+pub const SCHEMA_COLLECTION: &str = "nezuko-schema";
+const SCROLL_PAGE_SIZE: u32 = 256;
+
+/// What an index holds.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum IndexKind {
+    Chunks,
+    Symbols,
+    Documents,
+    // The sections of the Markdown files.
+    Docs,
+    // The messages of the commits and the paths they touched.
+    Commits,
+}
+
+/// The schema version an index was written with.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct SchemaRecord {
+    // Name of the collection or id of the lexical index.
+    pub index: String,
+    pub kind: IndexKind,
+    pub repo_name: String,
+    pub schema_version: u32,
+}
+
+impl SchemaRecord {
+    pub fn new(index: &str, kind: IndexKind, repo_name: &str, schema_version: u32) -> Self {
+        Self {
+            index: index.to_string(),
+            kind,
+            repo_name: repo_name.to_string(),
+            schema_version,
+        }
+    }
+
+    /// Whether the search services can read the index.
+    pub fn is_readable(&self) -> bool {
+        (MIN_READABLE_SCHEMA_VERSION..=SCHEMA_VERSION).contains(&self.schema_version)
+    }
+}
+
+// Point ids have to be UUIDs for Qdrant, derive one from the index name.
+fn record_id(index: &str) -> String {
+    Uuid::from_bytes(md5::compute(index).0).to_string()
+}
+
+fn parse_record(payload: PointPayload) -> Option<SchemaRecord> {
+    serde_json::from_value(Value::Object(payload.into_iter().collect())).ok()
+}
+
+/// Returns the schema version recorded for the index, None if there is no record.
+pub async fn read_schema_version(
+    vector_store: &dyn VectorStore,
+    index: &str,
+) -> Result<Option<u32>> {
+    if vector_store
+        .collection_dimension(SCHEMA_COLLECTION)
+        .await?
+        .is_none()
+    {
+        return Ok(None);
+    }
+
+    let (points, _) = vector_store
+        .scroll(
+            SCHEMA_COLLECTION,
+            &VectorFilter::must("index", index),
+            None,
+            1,
+        )
+        .await?;
+    Ok(points
+        .into_iter()
+        .filter_map(|point| parse_record(point.payload))
+        .map(|record| record.schema_version)
+        .next())
+}
+
+/// Records the schema version of an index, replacing the previous record.
+pub async fn write_schema_version(
+    vector_store: &dyn VectorStore,
+    record: SchemaRecord,
+) -> Result<()> {
+    if vector_store
+        .collection_dimension(SCHEMA_COLLECTION)
+        .await?
+        .is_none()
+    {
+        vector_store
+            .create_collection(SCHEMA_COLLECTION, 1, &[])
+            .await?;
+    }
+
+    let payload = match serde_json::to_value(&record)? {
+        Value::Object(fields) => fields.into_iter().collect(),
+        _ => bail!("schema record isn't an object"),
+    };
+    vector_store
+        .upsert(
+            SCHEMA_COLLECTION,
+            vec![VectorPoint {
+                id: record_id(&record.index),
+                vector: vec![1.0],
+                payload,
+            }],
+        )
+        .await
+}
+
+/// Returns the records of all the indexes.
+pub async fn list_schema_records(vector_store: &dyn VectorStore) -> Result<Vec<SchemaRecord>> {
+    if vector_store
+        .collection_dimension(SCHEMA_COLLECTION)
+        .await?
+        .is_none()
+    {
+        return Ok(Vec::new());
+    }
+
+    let mut records = Vec::new();
+    let mut offset = None;
+    loop {
+        let (points, next) = vector_store
+            .scroll(
+                SCHEMA_COLLECTION,
+                &VectorFilter::default(),
+                offset,
+                SCROLL_PAGE_SIZE,
+            )
+            .await?;
+        records.extend(
+            points
+                .into_iter()
+                .filter_map(|point| parse_record(point.payload)),
+        );
+        match next {
+            Some(next) => offset = Some(next),
+            None => break,
+        }
+    }
+    Ok(records)
+}
+
+/// Removes the records of the indexes of a repo.
+pub async fn delete_schema_records(vector_store: &dyn VectorStore, repo_name: &str) -> Result<()> {
+    if vector_store
+        .collection_dimension(SCHEMA_COLLECTION)
+        .await?
+        .is_none()
+    {
+        return Ok(());
+    }
+
+    vector_store
+        .delete(
+            SCHEMA_COLLECTION,
+            &PointSelector::Filter(VectorFilter::must("repo_name", repo_name)),
+        )
+        .await
+}
+
+// Guesses the schema of a chunk collection written before versions were recorded from the
+// fields of one of its points. Empty collections have nothing to migrate.
+async fn detect_schema_version(vector_store: &dyn VectorStore, collection: &str) -> Result<u32> {
+    let (points, _) = vector_store
+        .scroll(collection, &VectorFilter::default(), None, 1)
+        .await?;
+    let Some(point) = points.into_iter().next() else {
+        return Ok(SCHEMA_VERSION);
+    };
+
+    Ok(if point.payload.contains_key("scope_path") {
+        3
+    } else if point.payload.contains_key("branches") {
+        2
+    } else {
+        LEGACY_SCHEMA_VERSION
+    })
+}
+
+/// The schema the indexes of the repo are at, None if the repo has no chunk collection. The
+/// chunk collections without a record are detected from their points.
+pub async fn repository_schema_version(
+    vector_store: &dyn VectorStore,
+    repo_name: &str,
+) -> Result<Option<u32>> {
+    let collection_name_chunks = generate_chunks_collection_name(repo_name);
+    if vector_store
+        .collection_dimension(&collection_name_chunks)
+        .await?
+        .is_none()
+    {
+        return Ok(None);
+    }
+
+    match read_schema_version(vector_store, &collection_name_chunks).await? {
+        Some(version) => Ok(Some(version)),
+        None => Ok(Some(
+            detect_schema_version(vector_store, &collection_name_chunks).await?,
+        )),
+    }
+}
+
+/// The error of `check_repository` when the indexes of the repo are at a schema the search
+/// services can't read. The other errors are the vector store's.
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+#[error(
+    "{repo_name} is indexed with schema {schema_version} but this service reads schemas {} to {}, migrate it with `ingestion migrate {repo_name}` or upgrade this service",
+    MIN_READABLE_SCHEMA_VERSION,
+    SCHEMA_VERSION
+)]
+pub struct IncompatibleSchema {
+    pub repo_name: String,
+    pub schema_version: u32,
+}
+
+/// Fails with `IncompatibleSchema` if the search services can't read the indexes of the repo,
+/// legacy ones included.
+pub async fn check_repository(vector_store: &dyn VectorStore, repo_name: &str) -> Result<()> {
+    match repository_schema_version(vector_store, repo_name).await? {
+        Some(version) if !(MIN_READABLE_SCHEMA_VERSION..=SCHEMA_VERSION).contains(&version) => {
+            Err(IncompatibleSchema {
+                repo_name: repo_name.to_string(),
+                schema_version: version,
+            }
+            .into())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Logs the recorded indexes the search services can't read and returns them. The searches of
+/// these repos are refused by `check_repository`, which also catches the legacy indexes that
+/// have no record.
+pub async fn check_compatibility(vector_store: &dyn VectorStore) -> Result<Vec<SchemaRecord>> {
+    let records = list_schema_records(vector_store).await?;
+    let incompatible = records
+        .iter()
+        .filter(|record| !record.is_readable())
+        .cloned()
+        .collect::<Vec<_>>();
+
+    for record in &incompatible {
+        log::warn!(
+            "{} ({:?} of {}) is at schema {}, this service reads schemas {} to {}; its searches are refused until it is migrated with `ingestion migrate`",
+            record.index,
+            record.kind,
+            record.repo_name,
+            record.schema_version,
+            MIN_READABLE_SCHEMA_VERSION,
+            SCHEMA_VERSION
+        );
+    }
+    log::info!(
+        "{} of {} recorded indexes are compatible with schema {}",
+        records.len() - incompatible.len(),
+        records.len(),
+        SCHEMA_VERSION
+    );
+    Ok(incompatible)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vector::LocalVectorStore;
+
+    #[tokio::test]
+    async fn test_schema_records() {
+        let dir = std::env::temp_dir().join(format!("schema-{}", Uuid::new_v4()));
+        let store = LocalVectorStore::new(dir.to_str().unwrap()).unwrap();
+
+        assert_eq!(
+            read_schema_version(&store, "repo-documents").await.unwrap(),
+            None
+        );
+        assert!(check_compatibility(&store).await.unwrap().is_empty());
+
+        let record = SchemaRecord::new("repo-documents", IndexKind::Chunks, "v2/owner/repo", 2);
+        write_schema_version(&store, record).await.unwrap();
+        assert_eq!(
+            read_schema_version(&store, "repo-documents").await.unwrap(),
+            Some(2)
+        );
+        assert_eq!(check_compatibility(&store).await.unwrap().len(), 1);
+
+        let record = SchemaRecord::new(
+            "repo-documents",
+            IndexKind::Chunks,
+            "v2/owner/repo",
+            SCHEMA_VERSION,
+        );
+        write_schema_version(&store, record).await.unwrap();
+        assert!(check_compatibility(&store).await.unwrap().is_empty());
+
+        // a newer schema replaces the record, one that can't be read is reported.
+        let record = SchemaRecord::new(
+            "repo-documents",
+            IndexKind::Chunks,
+            "v2/owner/repo",
+            SCHEMA_VERSION + 1,
+        );
+        write_schema_version(&store, record).await.unwrap();
+        assert_eq!(list_schema_records(&store).await.unwrap().len(), 1);
+        assert_eq!(check_compatibility(&store).await.unwrap().len(), 1);
+
+        delete_schema_records(&store, "v2/owner/repo")
+            .await
+            .unwrap();
+        assert!(list_schema_records(&store).await.unwrap().is_empty());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn test_check_repository() {
+        let dir = std::env::temp_dir().join(format!("schema-{}", Uuid::new_v4()));
+        let store = LocalVectorStore::new(dir.to_str().unwrap()).unwrap();
+        let collection = generate_chunks_collection_name("v1/owner/legacy");
+
+        // repos without indexes have nothing to refuse.
+        check_repository(&store, "v1/owner/legacy").await.unwrap();
+
+        // a chunk collection with neither a record nor refs is a legacy one, no record lists it.
+        store.create_collection(&collection, 1, &[]).await.unwrap();
+        store
+            .upsert(
+                &collection,
+                vec![VectorPoint {
+                    id: Uuid::new_v4().to_string(),
+                    vector: vec![1.0],
+                    payload: [("path".to_string(), Value::from("src/lib.rs"))]
+                        .into_iter()
+                        .collect(),
+                }],
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            repository_schema_version(&store, "v1/owner/legacy")
+                .await
+                .unwrap(),
+            Some(LEGACY_SCHEMA_VERSION)
+        );
+        assert!(check_compatibility(&store).await.unwrap().is_empty());
+        let error = check_repository(&store, "v1/owner/legacy")
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<IncompatibleSchema>(),
+            Some(&IncompatibleSchema {
+                repo_name: "v1/owner/legacy".to_string(),
+                schema_version: LEGACY_SCHEMA_VERSION,
+            })
+        );
+
+        let record = SchemaRecord::new(
+            &collection,
+            IndexKind::Chunks,
+            "v1/owner/legacy",
+            SCHEMA_VERSION,
+        );
+        write_schema_version(&store, record).await.unwrap();
+        check_repository(&store, "v1/owner/legacy").await.unwrap();
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
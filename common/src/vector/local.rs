@@ -0,0 +1,525 @@
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+
+use super::{
+    PointPayload, PointSelector, ScoredPoint, SearchRequest, StoredPoint, VectorFilter,
+    VectorPoint, VectorStore,
+};
+use crate::embedding::Embedding;
+
+const LOG_EXTENSION: &str = "jsonl";
+// Number of points written per entry when a log is compacted.
+const COMPACTION_BATCH_SIZE: usize = 1000;
+
+/// Collections kept in memory and searched exhaustively, for single-machine deployments and tests.
+///
+/// Each collection is persisted as an append-only log of its updates under `root`, replayed when
+/// the collection is first used. Logs that grew much larger than the collection they describe are
+/// compacted on load.
+pub struct LocalVectorStore {
+    root: PathBuf,
+    collections: Mutex<HashMap<String, Arc<RwLock<Collection>>>>,
+}
+
+#[derive(Default)]
+struct Collection {
+    dimension: usize,
+    // Ordered by id so that scrolling is stable.
+    points: BTreeMap<String, StoredVector>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct StoredVector {
+    // Normalized on insert, so that the cosine similarity is a dot product.
+    vector: Embedding,
+    payload: PointPayload,
+}
+
+// An update of a collection. Selectors are resolved to ids before being logged, so that replaying
+// the log gives the same result.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum LogEntry {
+    Create {
+        dimension: usize,
+    },
+    Upsert {
+        points: Vec<VectorPoint>,
+    },
+    SetPayload {
+        ids: Vec<String>,
+        payload: PointPayload,
+    },
+    OverwritePayload {
+        ids: Vec<String>,
+        payload: PointPayload,
+    },
+    Delete {
+        ids: Vec<String>,
+    },
+}
+
+fn normalize(mut vector: Embedding) -> Embedding {
+    let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|x| *x /= norm);
+    }
+    vector
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b.iter()).map(|(x, y)| x * y).sum()
+}
+
+impl Collection {
+    fn apply(&mut self, entry: LogEntry) {
+        match entry {
+            LogEntry::Create { dimension } => self.dimension = dimension,
+            LogEntry::Upsert { points } => {
+                for point in points {
+                    self.points.insert(
+                        point.id,
+                        StoredVector {
+                            vector: normalize(point.vector),
+                            payload: point.payload,
+                        },
+                    );
+                }
+            }
+            LogEntry::SetPayload { ids, payload } => {
+                for id in ids {
+                    if let Some(point) = self.points.get_mut(&id) {
+                        point.payload.extend(payload.clone());
+                    }
+                }
+            }
+            LogEntry::OverwritePayload { ids, payload } => {
+                for id in ids {
+                    if let Some(point) = self.points.get_mut(&id) {
+                        point.payload = payload.clone();
+                    }
+                }
+            }
+            LogEntry::Delete { ids } => {
+                for id in ids {
+                    self.points.remove(&id);
+                }
+            }
+        }
+    }
+
+    fn select(&self, selector: &PointSelector) -> Vec<String> {
+        match selector {
+            PointSelector::Ids(ids) => ids
+                .iter()
+                .filter(|id| self.points.contains_key(*id))
+                .cloned()
+                .collect(),
+            PointSelector::Filter(filter) => self
+                .points
+                .iter()
+                .filter(|(_, point)| filter.matches(&point.payload))
+                .map(|(id, _)| id.clone())
+                .collect(),
+        }
+    }
+
+    // The entries rebuilding the collection from scratch.
+    fn snapshot(&self) -> Vec<LogEntry> {
+        let points = self
+            .points
+            .iter()
+            .map(|(id, point)| VectorPoint {
+                id: id.clone(),
+                vector: point.vector.clone(),
+                payload: point.payload.clone(),
+            })
+            .collect::<Vec<_>>();
+
+        std::iter::once(LogEntry::Create {
+            dimension: self.dimension,
+        })
+        .chain(
+            points
+                .chunks(COMPACTION_BATCH_SIZE)
+                .map(|points| LogEntry::Upsert {
+                    points: points.to_vec(),
+                }),
+        )
+        .collect()
+    }
+}
+
+impl LocalVectorStore {
+    pub fn new(root: &str) -> Result<Self> {
+        let root = PathBuf::from(root);
+        fs::create_dir_all(&root)?;
+        info!(
+            "Storing the local vector collections under {}",
+            root.display()
+        );
+        Ok(Self {
+            root,
+            collections: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn log_path(&self, collection: &str) -> PathBuf {
+        self.root.join(format!("{}.{}", collection, LOG_EXTENSION))
+    }
+
+    // Returns the collection, loading it from its log on first use. None if it doesn't exist.
+    fn collection(&self, name: &str) -> Result<Option<Arc<RwLock<Collection>>>> {
+        let mut collections = self.collections.lock().unwrap();
+        if let Some(collection) = collections.get(name) {
+            return Ok(Some(collection.clone()));
+        }
+
+        let path = self.log_path(name);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let mut collection = Collection::default();
+        let mut entries = 0;
+        for line in BufReader::new(File::open(&path)?).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry = serde_json::from_str(&line)
+                .with_context(|| format!("corrupted vector log {}", path.display()))?;
+            collection.apply(entry);
+            entries += 1;
+        }
+
+        let snapshot = collection.snapshot();
+        if entries > 2 * snapshot.len() {
+            info!(
+                "Compacting {} from {} to {} entries",
+                path.display(),
+                entries,
+                snapshot.len()
+            );
+            write_log(&path, &snapshot)?;
+        }
+
+        let collection = Arc::new(RwLock::new(collection));
+        collections.insert(name.to_string(), collection.clone());
+        Ok(Some(collection))
+    }
+
+    fn existing(&self, name: &str) -> Result<Arc<RwLock<Collection>>> {
+        self.collection(name)?
+            .ok_or_else(|| anyhow!("Collection {} doesn't exist", name))
+    }
+
+    // Logs the update then applies it, the write lock is held so that the log keeps the order
+    // the updates were applied in.
+    fn update(
+        &self,
+        name: &str,
+        entry: impl FnOnce(&Collection) -> Result<LogEntry>,
+    ) -> Result<()> {
+        let collection = self.existing(name)?;
+        let mut collection = collection.write().unwrap();
+        let entry = entry(&*collection)?;
+        append_log(&self.log_path(name), &entry)?;
+        collection.apply(entry);
+        Ok(())
+    }
+}
+
+fn append_log(path: &Path, entry: &LogEntry) -> Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", serde_json::to_string(entry)?)?;
+    Ok(())
+}
+
+// Replaces the log, through a temporary file so that a crash leaves either version in place.
+fn write_log(path: &Path, entries: &[LogEntry]) -> Result<()> {
+    let tmp_path = path.with_extension("tmp");
+    {
+        let mut file = File::create(&tmp_path)?;
+        for entry in entries {
+            writeln!(file, "{}", serde_json::to_string(entry)?)?;
+        }
+        file.sync_all()?;
+    }
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+#[async_trait]
+impl VectorStore for LocalVectorStore {
+    async fn collection_dimension(&self, collection: &str) -> Result<Option<usize>> {
+        Ok(self
+            .collection(collection)?
+            .map(|collection| collection.read().unwrap().dimension))
+    }
+
+    async fn create_collection(
+        &self,
+        collection: &str,
+        dimension: usize,
+        _indexed_fields: &[String],
+    ) -> Result<()> {
+        if self.collection(collection)?.is_some() {
+            bail!("Collection {} already exists", collection);
+        }
+
+        let mut collections = self.collections.lock().unwrap();
+        write_log(
+            &self.log_path(collection),
+            &[LogEntry::Create { dimension }],
+        )?;
+        collections.insert(
+            collection.to_string(),
+            Arc::new(RwLock::new(Collection {
+                dimension,
+                ..Default::default()
+            })),
+        );
+        Ok(())
+    }
+
+    async fn upsert(&self, collection: &str, points: Vec<VectorPoint>) -> Result<()> {
+        self.update(collection, |collection| {
+            if let Some(point) = points
+                .iter()
+                .find(|p| p.vector.len() != collection.dimension)
+            {
+                bail!(
+                    "point {} has {} dimensions, the collection {}",
+                    point.id,
+                    point.vector.len(),
+                    collection.dimension
+                );
+            }
+            Ok(LogEntry::Upsert { points })
+        })
+    }
+
+    async fn search(&self, collection: &str, request: SearchRequest) -> Result<Vec<ScoredPoint>> {
+        let collection = self.existing(collection)?;
+        let collection = collection.read().unwrap();
+        let query = normalize(request.vector);
+
+        let mut scored = collection
+            .points
+            .iter()
+            .filter(|(_, point)| {
+                request
+                    .filter
+                    .as_ref()
+                    .map_or(true, |filter| filter.matches(&point.payload))
+            })
+            .map(|(id, point)| (dot(&query, &point.vector), id, point))
+            .filter(|(score, _, _)| request.score_threshold.map_or(true, |t| *score >= t))
+            .collect::<Vec<_>>();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        Ok(scored
+            .into_iter()
+            .skip(request.offset as usize)
+            .take(request.limit as usize)
+            .map(|(score, id, point)| ScoredPoint {
+                id: id.clone(),
+                score,
+                vector: request.with_vectors.then(|| point.vector.clone()),
+                payload: point.payload.clone(),
+            })
+            .collect())
+    }
+
+    async fn scroll(
+        &self,
+        collection: &str,
+        filter: &VectorFilter,
+        offset: Option<String>,
+        limit: u32,
+    ) -> Result<(Vec<StoredPoint>, Option<String>)> {
+        let collection = self.existing(collection)?;
+        let collection = collection.read().unwrap();
+
+        let mut matching = collection
+            .points
+            .range(offset.unwrap_or_default()..)
+            .filter(|(_, point)| filter.matches(&point.payload));
+        let points = matching
+            .by_ref()
+            .take(limit as usize)
+            .map(|(id, point)| StoredPoint {
+                id: id.clone(),
+                payload: point.payload.clone(),
+            })
+            .collect();
+        let next_offset = matching.next().map(|(id, _)| id.clone());
+        Ok((points, next_offset))
+    }
+
+    async fn set_payload(
+        &self,
+        collection: &str,
+        selector: &PointSelector,
+        payload: PointPayload,
+    ) -> Result<()> {
+        self.update(collection, |collection| {
+            Ok(LogEntry::SetPayload {
+                ids: collection.select(selector),
+                payload,
+            })
+        })
+    }
+
+    async fn overwrite_payload(
+        &self,
+        collection: &str,
+        selector: &PointSelector,
+        payload: PointPayload,
+    ) -> Result<()> {
+        self.update(collection, |collection| {
+            Ok(LogEntry::OverwritePayload {
+                ids: collection.select(selector),
+                payload,
+            })
+        })
+    }
+
+    async fn delete(&self, collection: &str, selector: &PointSelector) -> Result<()> {
+        self.update(collection, |collection| {
+            Ok(LogEntry::Delete {
+                ids: collection.select(selector),
+            })
+        })
+    }
+
+    async fn delete_collection(&self, collection: &str) -> Result<()> {
+        let mut collections = self.collections.lock().unwrap();
+        collections.remove(collection);
+        let path = self.log_path(collection);
+        if path.exists() {
+            fs::remove_file(&path)?;
+            info!("Deleted the vector log {}", path.display());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn point(id: &str, vector: Embedding, path: &str, branches: &[&str]) -> VectorPoint {
+        VectorPoint {
+            id: id.to_string(),
+            vector,
+            payload: PointPayload::from([
+                ("relative_path".to_string(), json!(path)),
+                ("branches".to_string(), json!(branches)),
+            ]),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_local_vector_store() {
+        let root = std::env::temp_dir().join(format!("vectors-{}", uuid::Uuid::new_v4()));
+        let root_str = root.to_str().unwrap();
+        let store = LocalVectorStore::new(root_str).unwrap();
+
+        assert_eq!(store.collection_dimension("chunks").await.unwrap(), None);
+        store.create_collection("chunks", 2, &[]).await.unwrap();
+        assert!(store.create_collection("chunks", 2, &[]).await.is_err());
+        assert_eq!(store.collection_dimension("chunks").await.unwrap(), Some(2));
+
+        store
+            .upsert(
+                "chunks",
+                vec![
+                    point("a", vec![1.0, 0.0], "src/a.rs", &["main"]),
+                    point("b", vec![0.0, 2.0], "src/b.rs", &["main", "dev"]),
+                    point("c", vec![1.0, 1.0], "src/c.rs", &["dev"]),
+                ],
+            )
+            .await
+            .unwrap();
+        assert!(store
+            .upsert("chunks", vec![point("d", vec![1.0], "src/d.rs", &[])])
+            .await
+            .is_err());
+
+        let request = SearchRequest {
+            vector: vec![2.0, 0.1],
+            limit: 10,
+            ..Default::default()
+        };
+        let hits = store.search("chunks", request.clone()).await.unwrap();
+        let ids = hits.iter().map(|hit| hit.id.as_str()).collect::<Vec<_>>();
+        assert_eq!(ids, vec!["a", "c", "b"]);
+        assert!(hits[0].vector.is_none());
+
+        let on_dev = store
+            .search(
+                "chunks",
+                SearchRequest {
+                    filter: Some(VectorFilter::must("branches", "dev")),
+                    score_threshold: Some(0.5),
+                    with_vectors: true,
+                    ..request.clone()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(on_dev.len(), 1);
+        assert_eq!(on_dev[0].id, "c");
+        assert_eq!(on_dev[0].vector.as_ref().unwrap().len(), 2);
+
+        let (page, next) = store
+            .scroll("chunks", &VectorFilter::default(), None, 2)
+            .await
+            .unwrap();
+        assert_eq!(page.len(), 2);
+        assert_eq!(next.as_deref(), Some("c"));
+
+        store
+            .set_payload(
+                "chunks",
+                &PointSelector::Filter(VectorFilter::must("relative_path", "src/b.rs")),
+                PointPayload::from([("branches".to_string(), json!(["dev"]))]),
+            )
+            .await
+            .unwrap();
+        store
+            .delete("chunks", &PointSelector::Ids(vec!["a".to_string()]))
+            .await
+            .unwrap();
+
+        // a new store replays the log of the collection.
+        let reopened = LocalVectorStore::new(root_str).unwrap();
+        let (points, _) = reopened
+            .scroll("chunks", &VectorFilter::must("branches", "dev"), None, 10)
+            .await
+            .unwrap();
+        let ids = points
+            .iter()
+            .map(|point| point.id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec!["b", "c"]);
+        assert_eq!(points[0].payload["relative_path"], json!("src/b.rs"));
+        assert_eq!(reopened.search("chunks", request).await.unwrap().len(), 2);
+
+        reopened.delete_collection("chunks").await.unwrap();
+        assert_eq!(reopened.collection_dimension("chunks").await.unwrap(), None);
+        reopened.delete_collection("chunks").await.unwrap();
+
+        let _ = fs::remove_dir_all(root);
+    }
+}
@@ -0,0 +1,273 @@
+//! Vector store backends shared by the indexer and the search services.
+//!
+//! Payloads are JSON objects. Filters match keyword values the way Qdrant does: a condition on a
+//! list field matches if any of its elements is equal to the value.
+
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::env;
+use std::sync::Arc;
+
+use crate::embedding::Embedding;
+
+mod local;
+mod qdrant;
+
+pub use local::LocalVectorStore;
+pub use qdrant::QdrantStore;
+
+const DEFAULT_LOCAL_PATH: &str = "./vectors";
+
+pub type PointPayload = HashMap<String, Value>;
+
+/// A point to insert, replacing any point with the same id.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct VectorPoint {
+    pub id: String,
+    pub vector: Embedding,
+    pub payload: PointPayload,
+}
+
+/// A point returned by a search, best first.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScoredPoint {
+    pub id: String,
+    pub score: f32,
+    // Only set when the search asked for the vectors.
+    pub vector: Option<Embedding>,
+    pub payload: PointPayload,
+}
+
+/// A point returned while scrolling through a collection.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StoredPoint {
+    pub id: String,
+    pub payload: PointPayload,
+}
+
+/// A keyword condition on a payload field.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldMatch {
+    pub key: String,
+    pub value: String,
+}
+
+/// Selects the points whose payload matches all the `must` conditions and, if there are any, at
+/// least one of the `should` conditions.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VectorFilter {
+    pub must: Vec<FieldMatch>,
+    pub should: Vec<FieldMatch>,
+}
+
+impl VectorFilter {
+    /// Matches the points whose `key` equals `value`.
+    pub fn must(key: &str, value: &str) -> Self {
+        Self {
+            must: vec![FieldMatch::new(key, value)],
+            ..Default::default()
+        }
+    }
+
+    /// Matches the points whose `key` equals one of the values.
+    pub fn any_of<'a>(key: &str, values: impl IntoIterator<Item = &'a String>) -> Self {
+        Self {
+            should: values
+                .into_iter()
+                .map(|value| FieldMatch::new(key, value))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    pub fn matches(&self, payload: &PointPayload) -> bool {
+        self.must.iter().all(|condition| condition.matches(payload))
+            && (self.should.is_empty()
+                || self
+                    .should
+                    .iter()
+                    .any(|condition| condition.matches(payload)))
+    }
+}
+
+impl FieldMatch {
+    pub fn new(key: &str, value: &str) -> Self {
+        Self {
+            key: key.to_string(),
+            value: value.to_string(),
+        }
+    }
+
+    fn matches(&self, payload: &PointPayload) -> bool {
+        match payload.get(&self.key) {
+            Some(Value::String(value)) => *value == self.value,
+            Some(Value::Array(values)) => values
+                .iter()
+                .any(|value| value.as_str() == Some(self.value.as_str())),
+            _ => false,
+        }
+    }
+}
+
+/// The points an update or delete applies to.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PointSelector {
+    Ids(Vec<String>),
+    Filter(VectorFilter),
+}
+
+/// A nearest neighbour query.
+#[derive(Clone, Debug, Default)]
+pub struct SearchRequest {
+    pub vector: Embedding,
+    pub filter: Option<VectorFilter>,
+    pub limit: u64,
+    pub offset: u64,
+    // Points scoring below the threshold are left out.
+    pub score_threshold: Option<f32>,
+    pub with_vectors: bool,
+}
+
+/// Stores the embedded chunks and symbols in collections of cosine-compared vectors.
+#[async_trait]
+pub trait VectorStore: Send + Sync {
+    /// Size of the vectors of the collection, None if it doesn't exist.
+    async fn collection_dimension(&self, collection: &str) -> Result<Option<usize>>;
+
+    /// Creates the collection. `indexed_fields` are the payload fields filters are run on.
+    async fn create_collection(
+        &self,
+        collection: &str,
+        dimension: usize,
+        indexed_fields: &[String],
+    ) -> Result<()>;
+
+    async fn upsert(&self, collection: &str, points: Vec<VectorPoint>) -> Result<()>;
+
+    async fn search(&self, collection: &str, request: SearchRequest) -> Result<Vec<ScoredPoint>>;
+
+    /// Returns a page of the points matching the filter, starting at `offset`, along with the
+    /// offset of the next page.
+    async fn scroll(
+        &self,
+        collection: &str,
+        filter: &VectorFilter,
+        offset: Option<String>,
+        limit: u32,
+    ) -> Result<(Vec<StoredPoint>, Option<String>)>;
+
+    /// Sets the given payload fields, keeping the other ones.
+    async fn set_payload(
+        &self,
+        collection: &str,
+        selector: &PointSelector,
+        payload: PointPayload,
+    ) -> Result<()>;
+
+    /// Replaces the whole payload.
+    async fn overwrite_payload(
+        &self,
+        collection: &str,
+        selector: &PointSelector,
+        payload: PointPayload,
+    ) -> Result<()>;
+
+    async fn delete(&self, collection: &str, selector: &PointSelector) -> Result<()>;
+
+    /// Drops the collection and all its points. Deleting a missing collection is not an error.
+    async fn delete_collection(&self, collection: &str) -> Result<()>;
+}
+
+/// The vector store backend to use and its settings.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(tag = "backend", rename_all = "snake_case")]
+pub enum VectorStoreConfig {
+    // A Qdrant server, at its gRPC url.
+    Qdrant {
+        url: String,
+        #[serde(default)]
+        api_key: Option<String>,
+    },
+    // In-process collections persisted under a local directory.
+    Local {
+        path: String,
+    },
+}
+
+impl VectorStoreConfig {
+    /// Reads the backend from `VECTOR_BACKEND` (`qdrant` or `local`, defaults to `qdrant`).
+    ///
+    /// Qdrant is reached at `qdrant_url`, the local collections are stored under `VECTOR_STORE_PATH`.
+    pub fn from_env(qdrant_url: &str, qdrant_api_key: Option<String>) -> Result<Self> {
+        let backend = env::var("VECTOR_BACKEND").unwrap_or_else(|_| "qdrant".to_string());
+
+        let config = match backend.to_ascii_lowercase().as_str() {
+            "qdrant" => VectorStoreConfig::Qdrant {
+                url: qdrant_url.to_string(),
+                api_key: qdrant_api_key,
+            },
+            "local" => VectorStoreConfig::Local {
+                path: env::var("VECTOR_STORE_PATH")
+                    .unwrap_or_else(|_| DEFAULT_LOCAL_PATH.to_string()),
+            },
+            other => bail!("unknown VECTOR_BACKEND `{}`", other),
+        };
+
+        Ok(config)
+    }
+
+    /// Connects to the backend or loads the local collections.
+    pub fn build(&self) -> Result<Arc<dyn VectorStore>> {
+        let store: Arc<dyn VectorStore> = match self {
+            VectorStoreConfig::Qdrant { url, api_key } => {
+                Arc::new(QdrantStore::new(url, api_key.clone())?)
+            }
+            VectorStoreConfig::Local { path } => Arc::new(LocalVectorStore::new(path)?),
+        };
+        // the api key is left out of the logs.
+        match self {
+            VectorStoreConfig::Qdrant { url, .. } => {
+                log::info!("Using the Qdrant store at {}", url)
+            }
+            VectorStoreConfig::Local { path } => {
+                log::info!("Using the local vector store at {}", path)
+            }
+        }
+        Ok(store)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_vector_filter() {
+        let payload = PointPayload::from([
+            ("repo_name".to_string(), json!("nezuko")),
+            ("branches".to_string(), json!(["main", "dev"])),
+            ("start_line".to_string(), json!(3)),
+        ]);
+
+        assert!(VectorFilter::default().matches(&payload));
+        assert!(VectorFilter::must("repo_name", "nezuko").matches(&payload));
+        assert!(!VectorFilter::must("repo_name", "bloop").matches(&payload));
+        // list fields match any of their elements.
+        assert!(VectorFilter::must("branches", "dev").matches(&payload));
+        // only strings are matched.
+        assert!(!VectorFilter::must("start_line", "3").matches(&payload));
+        assert!(!VectorFilter::must("missing", "x").matches(&payload));
+
+        let branches = ["feature".to_string(), "main".to_string()];
+        assert!(VectorFilter::any_of("branches", branches.iter()).matches(&payload));
+        let filter = VectorFilter {
+            must: vec![FieldMatch::new("repo_name", "bloop")],
+            ..VectorFilter::any_of("branches", branches.iter())
+        };
+        assert!(!filter.matches(&payload));
+    }
+}
@@ -0,0 +1,347 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use qdrant_client::prelude::{Payload as QdrantPayload, QdrantClient};
+use qdrant_client::qdrant::{
+    point_id::PointIdOptions, points_selector::PointsSelectorOneOf, r#match::MatchValue,
+    value::Kind, vectors::VectorsOptions, vectors_config, with_payload_selector,
+    with_vectors_selector, Condition, CreateCollection, Distance, FieldCondition, FieldType,
+    Filter, ListValue, Match, PointId, PointStruct, PointsIdsList, PointsSelector, ScrollPoints,
+    SearchPoints, Struct, Value as QdrantValue, VectorParams, Vectors, VectorsConfig,
+    WithPayloadSelector, WithVectorsSelector,
+};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::time::Duration;
+
+use super::{
+    FieldMatch, PointPayload, PointSelector, ScoredPoint, SearchRequest, StoredPoint, VectorFilter,
+    VectorPoint, VectorStore,
+};
+
+// Number of points sent to Qdrant per upsert request.
+const UPSERT_BATCH_SIZE: usize = 64;
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Collections stored on a Qdrant server.
+pub struct QdrantStore {
+    client: QdrantClient,
+}
+
+impl QdrantStore {
+    pub fn new(url: &str, api_key: Option<String>) -> Result<Self> {
+        let client = QdrantClient::from_url(url)
+            .with_timeout(TIMEOUT)
+            .with_connect_timeout(TIMEOUT)
+            .with_api_key(api_key)
+            .build()?;
+        Ok(Self { client })
+    }
+}
+
+fn keyword_condition(condition: &FieldMatch) -> Condition {
+    FieldCondition {
+        key: condition.key.clone(),
+        r#match: Some(Match {
+            match_value: MatchValue::Keyword(condition.value.clone()).into(),
+        }),
+        ..Default::default()
+    }
+    .into()
+}
+
+fn to_qdrant_filter(filter: &VectorFilter) -> Filter {
+    Filter {
+        must: filter.must.iter().map(keyword_condition).collect(),
+        should: filter.should.iter().map(keyword_condition).collect(),
+        ..Default::default()
+    }
+}
+
+fn to_qdrant_selector(selector: &PointSelector) -> PointsSelector {
+    let selector = match selector {
+        PointSelector::Ids(ids) => PointsSelectorOneOf::Points(PointsIdsList {
+            ids: ids.iter().map(|id| PointId::from(id.clone())).collect(),
+        }),
+        PointSelector::Filter(filter) => PointsSelectorOneOf::Filter(to_qdrant_filter(filter)),
+    };
+    PointsSelector {
+        points_selector_one_of: Some(selector),
+    }
+}
+
+fn to_qdrant_value(value: Value) -> QdrantValue {
+    let kind = match value {
+        Value::Null => Kind::NullValue(0),
+        Value::Bool(value) => Kind::BoolValue(value),
+        Value::Number(number) => match number.as_i64() {
+            Some(integer) => Kind::IntegerValue(integer),
+            None => Kind::DoubleValue(number.as_f64().unwrap_or_default()),
+        },
+        Value::String(value) => Kind::StringValue(value),
+        Value::Array(values) => Kind::ListValue(ListValue {
+            values: values.into_iter().map(to_qdrant_value).collect(),
+        }),
+        Value::Object(fields) => Kind::StructValue(Struct {
+            fields: fields
+                .into_iter()
+                .map(|(key, value)| (key, to_qdrant_value(value)))
+                .collect(),
+        }),
+    };
+    QdrantValue { kind: Some(kind) }
+}
+
+fn from_qdrant_value(value: QdrantValue) -> Value {
+    match value.kind {
+        Some(Kind::NullValue(_)) | None => Value::Null,
+        Some(Kind::BoolValue(value)) => Value::Bool(value),
+        Some(Kind::IntegerValue(value)) => Value::Number(value.into()),
+        Some(Kind::DoubleValue(value)) => serde_json::Number::from_f64(value)
+            .map(Value::Number)
+            .unwrap_or(Value::Null),
+        Some(Kind::StringValue(value)) => Value::String(value),
+        Some(Kind::ListValue(list)) => {
+            Value::Array(list.values.into_iter().map(from_qdrant_value).collect())
+        }
+        Some(Kind::StructValue(object)) => Value::Object(
+            object
+                .fields
+                .into_iter()
+                .map(|(key, value)| (key, from_qdrant_value(value)))
+                .collect(),
+        ),
+    }
+}
+
+fn to_qdrant_payload(payload: PointPayload) -> HashMap<String, QdrantValue> {
+    payload
+        .into_iter()
+        .map(|(key, value)| (key, to_qdrant_value(value)))
+        .collect()
+}
+
+fn from_qdrant_payload(payload: HashMap<String, QdrantValue>) -> PointPayload {
+    payload
+        .into_iter()
+        .map(|(key, value)| (key, from_qdrant_value(value)))
+        .collect()
+}
+
+fn point_id(id: Option<PointId>) -> Result<String> {
+    match id.and_then(|id| id.point_id_options) {
+        Some(PointIdOptions::Uuid(id)) => Ok(id),
+        Some(PointIdOptions::Num(id)) => Ok(id.to_string()),
+        None => Err(anyhow!("point without an id")),
+    }
+}
+
+#[async_trait]
+impl VectorStore for QdrantStore {
+    async fn collection_dimension(&self, collection: &str) -> Result<Option<usize>> {
+        if !self.client.has_collection(collection).await? {
+            return Ok(None);
+        }
+
+        let info = self.client.collection_info(collection).await?;
+        Ok(info
+            .result
+            .and_then(|info| info.config)
+            .and_then(|config| config.params)
+            .and_then(|params| params.vectors_config)
+            .and_then(|vectors| vectors.config)
+            .and_then(|config| match config {
+                vectors_config::Config::Params(params) => Some(params.size as usize),
+                _ => None,
+            }))
+    }
+
+    async fn create_collection(
+        &self,
+        collection: &str,
+        dimension: usize,
+        indexed_fields: &[String],
+    ) -> Result<()> {
+        self.client
+            .create_collection(&CreateCollection {
+                collection_name: collection.to_string(),
+                vectors_config: Some(VectorsConfig {
+                    config: Some(vectors_config::Config::Params(VectorParams {
+                        size: dimension as u64,
+                        distance: Distance::Cosine.into(),
+                        ..Default::default()
+                    })),
+                }),
+                ..Default::default()
+            })
+            .await?;
+
+        for field in indexed_fields {
+            self.client
+                .create_field_index(collection, field, FieldType::Text, None, None)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn upsert(&self, collection: &str, points: Vec<VectorPoint>) -> Result<()> {
+        let points = points
+            .into_iter()
+            .map(|point| PointStruct {
+                id: Some(PointId::from(point.id)),
+                vectors: Some(point.vector.into()),
+                payload: to_qdrant_payload(point.payload),
+            })
+            .collect::<Vec<_>>();
+        self.client
+            .upsert_points_batch(collection, points, None, UPSERT_BATCH_SIZE)
+            .await?;
+        Ok(())
+    }
+
+    async fn search(&self, collection: &str, request: SearchRequest) -> Result<Vec<ScoredPoint>> {
+        let response = self
+            .client
+            .search_points(&SearchPoints {
+                collection_name: collection.to_string(),
+                vector: request.vector,
+                filter: request.filter.as_ref().map(to_qdrant_filter),
+                limit: request.limit,
+                offset: Some(request.offset),
+                score_threshold: request.score_threshold,
+                with_payload: Some(WithPayloadSelector {
+                    selector_options: Some(with_payload_selector::SelectorOptions::Enable(true)),
+                }),
+                with_vectors: Some(WithVectorsSelector {
+                    selector_options: Some(with_vectors_selector::SelectorOptions::Enable(
+                        request.with_vectors,
+                    )),
+                }),
+                ..Default::default()
+            })
+            .await?;
+
+        response
+            .result
+            .into_iter()
+            .map(|point| {
+                let vector = match point.vectors {
+                    Some(Vectors {
+                        vectors_options: Some(VectorsOptions::Vector(vector)),
+                    }) => Some(vector.data),
+                    _ => None,
+                };
+                Ok(ScoredPoint {
+                    id: point_id(point.id)?,
+                    score: point.score,
+                    vector,
+                    payload: from_qdrant_payload(point.payload),
+                })
+            })
+            .collect()
+    }
+
+    async fn scroll(
+        &self,
+        collection: &str,
+        filter: &VectorFilter,
+        offset: Option<String>,
+        limit: u32,
+    ) -> Result<(Vec<StoredPoint>, Option<String>)> {
+        let response = self
+            .client
+            .scroll(&ScrollPoints {
+                collection_name: collection.to_string(),
+                filter: Some(to_qdrant_filter(filter)),
+                offset: offset.map(PointId::from),
+                limit: Some(limit),
+                with_payload: Some(WithPayloadSelector {
+                    selector_options: Some(with_payload_selector::SelectorOptions::Enable(true)),
+                }),
+                ..Default::default()
+            })
+            .await?;
+
+        let points = response
+            .result
+            .into_iter()
+            .map(|point| {
+                Ok(StoredPoint {
+                    id: point_id(point.id)?,
+                    payload: from_qdrant_payload(point.payload),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let next_offset = response
+            .next_page_offset
+            .map(|id| point_id(Some(id)))
+            .transpose()?;
+        Ok((points, next_offset))
+    }
+
+    async fn set_payload(
+        &self,
+        collection: &str,
+        selector: &PointSelector,
+        payload: PointPayload,
+    ) -> Result<()> {
+        self.client
+            .set_payload(
+                collection,
+                &to_qdrant_selector(selector),
+                QdrantPayload::new_from_hashmap(to_qdrant_payload(payload)),
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn overwrite_payload(
+        &self,
+        collection: &str,
+        selector: &PointSelector,
+        payload: PointPayload,
+    ) -> Result<()> {
+        self.client
+            .overwrite_payload(
+                collection,
+                &to_qdrant_selector(selector),
+                QdrantPayload::new_from_hashmap(to_qdrant_payload(payload)),
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn delete(&self, collection: &str, selector: &PointSelector) -> Result<()> {
+        self.client
+            .delete_points(collection, &to_qdrant_selector(selector), None)
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_collection(&self, collection: &str) -> Result<()> {
+        if self.client.has_collection(collection).await? {
+            self.client.delete_collection(collection).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_qdrant_value_round_trip() {
+        let value = json!({
+            "relative_path": ["src/main.rs", "src/lib.rs"],
+            "start_byte": [0, 42],
+            "is_global": [true, false],
+            "score": 0.5,
+            "scope": {"label": "impl Agent"},
+            "missing": null,
+        });
+        assert_eq!(from_qdrant_value(to_qdrant_value(value.clone())), value);
+    }
+}
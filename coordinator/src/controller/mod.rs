@@ -1 +1,2 @@
+pub mod registry;
 pub mod suggest;
\ No newline at end of file
@@ -0,0 +1,25 @@
+use anyhow::{anyhow, Result};
+use reqwest::{StatusCode, Url};
+
+use crate::CONFIG;
+
+/// Looks the repo up in the registry of the ingestion service.
+pub async fn is_indexed_repo(repo_name: &str) -> Result<bool> {
+    let mut url = Url::parse(&CONFIG.ingestion_url)?;
+    // each part of the name is a path segment, so that they get percent-encoded on their own.
+    url.path_segments_mut()
+        .map_err(|_| anyhow!("Invalid ingestion url {}", CONFIG.ingestion_url))?
+        .pop_if_empty()
+        .push("repos")
+        .extend(repo_name.split('/'));
+
+    let response = reqwest::get(url).await?;
+    match response.status() {
+        StatusCode::OK => Ok(true),
+        StatusCode::NOT_FOUND => Ok(false),
+        status => Err(anyhow!(
+            "Unexpected response from the repo registry: {}",
+            status
+        )),
+    }
+}
@@ -1,3 +1,4 @@
+use crate::controller::registry::is_indexed_repo;
 use crate::llm_ops::summarize::{
     generate_single_task_summarization_, generate_summarized_answer_for_task,
 };
@@ -27,6 +28,27 @@ use crate::{models::SuggestRequest, CONFIG};
 pub async fn handle_suggest_wrapper(
     request: SuggestRequest,
 ) -> Result<impl warp::Reply, Infallible> {
+    // queries against repos that were never indexed can't be answered, reject them early.
+    match is_indexed_repo(&request.repo_name).await {
+        Ok(true) => {}
+        Ok(false) => {
+            let error_message = format!("Repo {} is not indexed", request.repo_name);
+            log::error!("{}", error_message);
+            return Ok(warp::reply::with_status(
+                warp::reply::json(&error_message),
+                StatusCode::NOT_FOUND,
+            ));
+        }
+        Err(e) => {
+            log::error!("Failed to look up repo {}: {}", request.repo_name, e);
+            let error_message = format!("Failed to look up repo {}: {}", request.repo_name, e);
+            return Ok(warp::reply::with_status(
+                warp::reply::json(&error_message),
+                StatusCode::BAD_GATEWAY,
+            ));
+        }
+    }
+
     match handle_suggest_core(request).await {
         Ok(response) => Ok(warp::reply::with_status(
             warp::reply::json(&response),
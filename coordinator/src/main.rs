@@ -25,6 +25,8 @@ pub struct Configuration {
     context_generator_url: String,
     code_understanding_url: String,
     code_modifier_url: String,
+    // The ingestion service, whose repo registry tells which repos can be queried.
+    ingestion_url: String,
     redis_url: String,
     openai_url: String,
     openai_api_key: String,
@@ -53,6 +55,8 @@ impl Configuration {
                 .unwrap_or_else(|_| "http://127.0.0.1:3000".to_string()),
             code_modifier_url: env::var("CODE_MODIFIER_URL")
                 .unwrap_or_else(|_| "http://127.0.0.1:3000".to_string()),
+            ingestion_url: env::var("INGESTION_URL")
+                .unwrap_or_else(|_| "http://127.0.0.1:3001".to_string()),
             redis_url: env::var("REDIS_URL")
                 .unwrap_or_else(|_| "redis://127.0.0.1:6379".to_string()),
             openai_url: env::var("OPENAI_URL")
@@ -1,15 +1,127 @@
 use std::path::PathBuf;
+use std::time::Duration;
 
-use clap::{App, Arg};
+use clap::{App, Arg, ArgMatches};
+use common::embedding::EmbedderConfig;
+use common::lexical::LexicalIndexConfig;
+use common::vector::VectorStoreConfig;
+use ingestion::migration::migrate_repository;
+use ingestion::registry::list_repos;
 use ingestion::state::{update_process_state, CodeIndexingTaskStatus};
-use ingestion::{Config, Indexer};
-use log::info;
+use ingestion::watch::watch_repository;
+use ingestion::{ChunkingStrategy, Config, Indexer, RepoSource};
+use log::{error, info};
+
+fn values_of(matches: &ArgMatches, name: &str) -> Vec<String> {
+    matches
+        .values_of(name)
+        .map(|values| values.map(ToOwned::to_owned).collect())
+        .unwrap_or_default()
+}
+
+// Reads the embedding, lexical index and vector store backends from the environment.
+fn backends(
+    qdrant_url: &str,
+    quickwit_url: &str,
+    qdrant_api_key: &str,
+) -> Option<(EmbedderConfig, LexicalIndexConfig, VectorStoreConfig)> {
+    let embedder = match EmbedderConfig::from_env() {
+        Ok(embedder) => embedder,
+        Err(e) => {
+            error!("Invalid embedding configuration: {:?}", e);
+            return None;
+        }
+    };
+    info!("Embedder: {:?}", embedder);
+
+    let lexical_index = match LexicalIndexConfig::from_env(quickwit_url) {
+        Ok(lexical_index) => lexical_index,
+        Err(e) => {
+            error!("Invalid lexical index configuration: {:?}", e);
+            return None;
+        }
+    };
+    info!("Lexical index: {:?}", lexical_index);
+
+    let vector_store = match Config::vector_store_from_env(qdrant_url, qdrant_api_key) {
+        Ok(vector_store) => vector_store,
+        Err(e) => {
+            error!("Invalid vector store configuration: {:?}", e);
+            return None;
+        }
+    };
+
+    Some((embedder, lexical_index, vector_store))
+}
+
+// Brings the indexes of a repo, or of every repo of the registry, to the current schema.
+async fn migrate(matches: &ArgMatches) {
+    let qdrant_url = matches.value_of("qdrant_url").unwrap();
+    let quickwit_url = matches.value_of("quickwit_url").unwrap();
+    let qdrant_api_key = matches.value_of("qdrant_api_key").unwrap();
+    let dry_run = matches.is_present("dry_run");
+
+    let Some((embedder, lexical_index, vector_store)) =
+        backends(qdrant_url, quickwit_url, qdrant_api_key)
+    else {
+        return;
+    };
+
+    let repo_names = match matches.value_of("repo_name") {
+        Some(repo_name) => vec![repo_name.to_string()],
+        None => list_repos()
+            .into_iter()
+            .map(|repo| repo.repo_name)
+            .collect(),
+    };
+
+    for repo_name in repo_names {
+        // the rebuilds take the repo path, refs and settings from the registry.
+        let base = Config::new(
+            repo_name.clone(),
+            String::new(),
+            qdrant_url.to_string(),
+            quickwit_url.to_string(),
+            qdrant_api_key.to_string(),
+            String::new(),
+            String::new(),
+            false,
+        )
+        .with_embedder(embedder.clone())
+        .with_lexical_index(lexical_index.clone())
+        .with_vector_store(vector_store.clone());
+
+        match migrate_repository(&repo_name, &base, dry_run).await {
+            Ok(report) => info!(
+                "{}",
+                serde_json::to_string_pretty(&report).unwrap_or_default()
+            ),
+            Err(e) => error!("Failed to migrate {}: {:?}", repo_name, e),
+        }
+    }
+}
 
 pub async fn execute() {
     let matches = App::new("Ingestion Service")
         .version("0.1")
         .author("superspace <team@superspace.so>")
         .about("Handles custom repo configuration")
+        .subcommand_negates_reqs(true)
+        .subcommand(
+            App::new("migrate")
+                .about("Migrates indexes written with an older schema, rebuilding them if needed")
+                .arg(
+                    Arg::new("repo_name")
+                        .help("The repository to migrate, every repository of the registry if omitted")
+                        .index(1),
+                )
+                .arg(
+                    Arg::new("dry_run")
+                        .long("dry-run")
+                        .help("Only report the migrations that would run")
+                        .takes_value(false),
+                ),
+        )
         .arg(
             Arg::new("repo_name")
                 .help("The name of the repository")
@@ -28,7 +140,8 @@ pub async fn execute() {
                 .help("The URL of the Qdrant service")
                 .takes_value(true)
                 .env("QDRANT_URL")
-                .default_value("http://localhost:6334"),
+                .default_value("http://localhost:6334")
+                .global(true),
         )
         .arg(
             Arg::new("quickwit_url")
@@ -36,7 +149,8 @@ pub async fn execute() {
                 .help("The URL of the Quickwit service")
                 .takes_value(true)
                 .env("QUICKWIT_URL")
-                .default_value("http://localhost:7280"),
+                .default_value("http://localhost:7280")
+                .global(true),
         )
         .arg(
             Arg::new("qdrant_api_key")
@@ -44,7 +158,8 @@ pub async fn execute() {
                 .help("The API key for Qdrant")
                 .takes_value(true)
                 .env("QDRANT_API_KEY")
-                .default_value("default_api_key"),
+                .default_value("default_api_key")
+                .global(true),
         )
         .arg(
             Arg::new("branch")
@@ -60,8 +175,68 @@ pub async fn execute() {
                 .takes_value(true)
                 .default_value("v2"),
         )
+        .arg(
+            Arg::new("incremental")
+                .long("incremental")
+                .help("Only re-index the files changed since the last indexed commit")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::new("include")
+                .long("include")
+                .help("Only index the paths matching this glob, can be repeated")
+                .takes_value(true)
+                .multiple_occurrences(true),
+        )
+        .arg(
+            Arg::new("exclude")
+                .long("exclude")
+                .help("Skip the paths matching this glob, can be repeated")
+                .takes_value(true)
+                .multiple_occurrences(true),
+        )
+        .arg(
+            Arg::new("chunking")
+                .long("chunking")
+                .help("How the files are split into chunks")
+                .takes_value(true)
+                .possible_values(["tokens", "lines", "syntax"])
+                .default_value("tokens"),
+        )
+        .arg(
+            Arg::new("source")
+                .long("source")
+                .help("Where the files are read from, the repo path points to the archive for `archive`")
+                .takes_value(true)
+                .possible_values(["git", "working_tree", "directory", "archive"])
+                .default_value("git"),
+        )
+        .arg(
+            Arg::new("index_commits")
+                .long("index-commits")
+                .help("Also index the commit messages of the branch")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::new("watch")
+                .long("watch")
+                .help("Keep running and re-index the files as they change, the git source is watched as its working tree")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::new("debounce_ms")
+                .long("debounce-ms")
+                .help("With --watch, how long the files have to stay untouched before their changes are indexed")
+                .takes_value(true)
+                .default_value("500"),
+        )
         .get_matches();
 
+    if let Some(matches) = matches.subcommand_matches("migrate") {
+        migrate(matches).await;
+        return;
+    }
+
     let repo_name = matches.value_of("repo_name").unwrap();
     let disk_path_str = matches.value_of("repo_path").unwrap();
     let qdrant_url = matches.value_of("qdrant_url").unwrap();
@@ -69,6 +244,20 @@ pub async fn execute() {
     let qdrant_api_key = matches.value_of("qdrant_api_key").unwrap();
     let branch = matches.value_of("branch").unwrap();
     let version = matches.value_of("version").unwrap();
+    let incremental = matches.is_present("incremental");
+    let include = values_of(&matches, "include");
+    let exclude = values_of(&matches, "exclude");
+    let chunking = ChunkingStrategy::try_from(matches.value_of("chunking").unwrap()).unwrap();
+    let source = RepoSource::try_from(matches.value_of("source").unwrap()).unwrap();
+    let index_commits = matches.is_present("index_commits");
+    let watch = matches.is_present("watch");
+    let debounce = match matches.value_of("debounce_ms").unwrap().parse() {
+        Ok(debounce_ms) => Duration::from_millis(debounce_ms),
+        Err(e) => {
+            error!("Invalid --debounce-ms: {}", e);
+            return;
+        }
+    };
 
     info!("Repo name: {}", repo_name);
     info!("Repo path: {}", disk_path_str);
@@ -77,6 +266,19 @@ pub async fn execute() {
     info!("Qdrant API key: {}", qdrant_api_key);
     info!("Branch: {}", branch);
     info!("Version: {}", version);
+    info!("Incremental: {}", incremental);
+    info!("Include: {:?}", include);
+    info!("Exclude: {:?}", exclude);
+    info!("Chunking: {:?}", chunking);
+    info!("Source: {:?}", source);
+    info!("Index commits: {}", index_commits);
+    info!("Watch: {}", watch);
+
+    let Some((embedder, lexical_index, vector_store)) =
+        backends(qdrant_url, quickwit_url, qdrant_api_key)
+    else {
+        return;
+    };
 
     // Instantiate an Indexer.
     let indexer = Indexer;
@@ -92,7 +294,22 @@ pub async fn execute() {
         qdrant_api_key.to_string(),
         branch.to_string(),
         version.to_string(),
-    );
+        incremental,
+    )
+    .with_path_filters(include, exclude)
+    .with_embedder(embedder)
+    .with_chunking(chunking)
+    .with_source(source)
+    .with_commit_history(index_commits)
+    .with_lexical_index(lexical_index)
+    .with_vector_store(vector_store);
+
+    if watch {
+        if let Err(e) = watch_repository(config, debounce).await {
+            error!("Failed to watch {}: {:?}", repo_name, e);
+        }
+        return;
+    }
 
     let task_id = uuid::Uuid::new_v4().to_string();
     update_process_state(&task_id, 0, CodeIndexingTaskStatus::Queued);
@@ -0,0 +1,290 @@
+use anyhow::Result;
+use common::lexical::{branch_clause, LexicalIndex};
+use common::models::IndexEvent;
+use common::schema;
+use common::vector::VectorStore;
+use git2::Repository as GitRepository;
+use log::{error, info, warn};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use crate::hash::compute_hashes;
+use crate::index_processor::{delete_documents, generate_quikwit_index_name};
+use crate::source::{read_snapshot, RepoSource};
+use crate::{hooks, incremental, registry, semantic_index, Indexer};
+
+// Maximum number of documents of a ref looked at by a single GC pass.
+const GC_MAX_DOCUMENTS: usize = 10_000;
+
+/// The indexes removed by `delete_repository`.
+#[derive(Debug, Clone, Serialize)]
+pub struct DeletedRepository {
+    pub repo_name: String,
+    pub collections: Vec<String>,
+    pub lexical_index: String,
+    // The refs whose indexed commit was forgotten.
+    pub refs: Vec<String>,
+}
+
+/// The stale entries a GC pass removed from one indexed ref.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RefGarbage {
+    pub repo_ref: String,
+    pub commit: String,
+    pub stale_chunks: usize,
+    pub stale_doc_sections: usize,
+    pub stale_symbol_paths: usize,
+    pub stale_documents: usize,
+}
+
+/// The outcome of a GC pass over the refs of a repo.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct GarbageReport {
+    pub repo_name: String,
+    pub refs: Vec<RefGarbage>,
+}
+
+// The hashes and paths of the files of a ref at its indexed commit.
+#[derive(Default)]
+struct LiveFiles {
+    paths: HashSet<String>,
+    semantic_hashes: HashSet<String>,
+    tantivy_hashes: HashSet<String>,
+}
+
+/// Drops the chunk, symbol, docs and commits collections and the lexical index of a repo, and forgets the
+/// commits its refs were indexed at so that indexing it again starts from scratch. The repo is
+/// removed from the registry as well.
+pub async fn delete_repository(
+    repo_name: &str,
+    vector_store: &dyn VectorStore,
+    lexical_index: &dyn LexicalIndex,
+) -> Result<DeletedRepository> {
+    let deleted = delete_indexes(repo_name, vector_store, lexical_index).await?;
+    registry::forget_repo(repo_name)?;
+    hooks::notify(IndexEvent {
+        repo_name: repo_name.to_string(),
+        branch: None,
+        commit: None,
+    })
+    .await;
+
+    info!("Deleted repository {}", repo_name);
+    Ok(deleted)
+}
+
+/// Like `delete_repository`, but keeps the registry record of the repo, so that its refs can be
+/// indexed again with the same settings.
+pub(crate) async fn delete_indexes(
+    repo_name: &str,
+    vector_store: &dyn VectorStore,
+    lexical_index: &dyn LexicalIndex,
+) -> Result<DeletedRepository> {
+    let (collection_name_chunks, collection_name_symbols) = Indexer::collection_names(repo_name);
+    let collections = vec![
+        collection_name_chunks,
+        collection_name_symbols,
+        Indexer::docs_collection_name(repo_name),
+        Indexer::commits_collection_name(repo_name),
+    ];
+    let index_id = generate_quikwit_index_name(repo_name);
+
+    for collection_name in &collections {
+        vector_store.delete_collection(collection_name).await?;
+        info!("Deleted collection {}", collection_name);
+    }
+    lexical_index.delete_index(&index_id).await?;
+
+    let refs = incremental::indexed_commits(repo_name)
+        .into_iter()
+        .map(|(repo_ref, _)| repo_ref)
+        .collect();
+    incremental::forget_repository(repo_name)?;
+    schema::delete_schema_records(vector_store, repo_name).await?;
+
+    Ok(DeletedRepository {
+        repo_name: repo_name.to_string(),
+        collections,
+        lexical_index: index_id,
+        refs,
+    })
+}
+
+/// Removes the chunks, doc sections, symbol occurrences and documents recorded on the indexed refs of a repo
+/// that no longer match a file of the commit the ref was last indexed at.
+///
+/// Only the refs indexed from the git source are collected, the other sources don't record the
+/// commit their files were read at.
+pub async fn collect_garbage(
+    repo_name: &str,
+    disk_path: &Path,
+    vector_store: &dyn VectorStore,
+    lexical_index: &dyn LexicalIndex,
+) -> Result<GarbageReport> {
+    let (collection_name_chunks, collection_name_symbols) = Indexer::collection_names(repo_name);
+    let collection_name_docs = Indexer::docs_collection_name(repo_name);
+    let mut report = GarbageReport {
+        repo_name: repo_name.to_string(),
+        ..Default::default()
+    };
+
+    for (repo_ref, commit) in incremental::indexed_commits(repo_name) {
+        info!(
+            "Collecting garbage of {}@{} at {}",
+            repo_name, repo_ref, commit
+        );
+        let live = live_files(disk_path, &repo_ref, &commit)?;
+        let mut garbage = RefGarbage {
+            repo_ref: repo_ref.clone(),
+            commit,
+            ..Default::default()
+        };
+
+        garbage.stale_chunks =
+            collect_chunks(vector_store, &collection_name_chunks, &repo_ref, &live).await?;
+        garbage.stale_doc_sections =
+            collect_chunks(vector_store, &collection_name_docs, &repo_ref, &live).await?;
+
+        let stale_paths = semantic_index::symbol_paths_on_branch(
+            vector_store,
+            &collection_name_symbols,
+            &repo_ref,
+        )
+        .await?
+        .into_iter()
+        .filter(|path| !live.paths.contains(path))
+        .collect::<HashSet<_>>();
+        if !stale_paths.is_empty() {
+            semantic_index::prune_symbol_occurrences(
+                vector_store,
+                &collection_name_symbols,
+                &stale_paths,
+                &repo_ref,
+            )
+            .await?;
+        }
+        garbage.stale_symbol_paths = stale_paths.len();
+
+        garbage.stale_documents =
+            collect_documents(repo_name, &repo_ref, &live, lexical_index).await?;
+
+        info!(
+            "Collected garbage of {}@{}: {:?}",
+            repo_name, repo_ref, garbage
+        );
+        // the search results cached for the ref may point at the removed entries.
+        let collected = garbage.stale_chunks
+            + garbage.stale_doc_sections
+            + garbage.stale_symbol_paths
+            + garbage.stale_documents;
+        if collected > 0 {
+            hooks::notify(IndexEvent {
+                repo_name: repo_name.to_string(),
+                branch: Some(repo_ref.clone()),
+                commit: Some(garbage.commit.clone()),
+            })
+            .await;
+        }
+        report.refs.push(garbage);
+    }
+
+    Ok(report)
+}
+
+// Releases the chunks of the ref whose content hash doesn't match a live file, from the chunk or
+// the docs collection. Returns the number of stale file versions.
+async fn collect_chunks(
+    vector_store: &dyn VectorStore,
+    collection_name: &str,
+    repo_ref: &str,
+    live: &LiveFiles,
+) -> Result<usize> {
+    // repos indexed before the docs collection existed don't have one.
+    if vector_store
+        .collection_dimension(collection_name)
+        .await?
+        .is_none()
+    {
+        return Ok(0);
+    }
+
+    let stale_hashes =
+        semantic_index::chunk_hashes_on_branch(vector_store, collection_name, repo_ref)
+            .await?
+            .into_iter()
+            .filter(|hash| !live.semantic_hashes.contains(hash))
+            .collect::<Vec<_>>();
+    if !stale_hashes.is_empty() {
+        semantic_index::release_chunks(vector_store, collection_name, &stale_hashes, repo_ref)
+            .await?;
+    }
+    Ok(stale_hashes.len())
+}
+
+// Deletes the documents of the ref whose unique hash doesn't match a live file. Repos indexed
+// without a lexical index (v3) have nothing to collect.
+async fn collect_documents(
+    repo_name: &str,
+    repo_ref: &str,
+    live: &LiveFiles,
+    lexical_index: &dyn LexicalIndex,
+) -> Result<usize> {
+    let index_id = generate_quikwit_index_name(repo_name);
+    let documents = match lexical_index
+        .search(&index_id, &branch_clause(repo_ref), GC_MAX_DOCUMENTS)
+        .await
+    {
+        Ok(documents) => documents,
+        Err(e) => {
+            error!("Skipping the documents of {}: {:?}", index_id, e);
+            return Ok(0);
+        }
+    };
+    if documents.len() == GC_MAX_DOCUMENTS {
+        warn!(
+            "{}@{} has more than {} documents, only the first ones are collected",
+            repo_name, repo_ref, GC_MAX_DOCUMENTS
+        );
+    }
+
+    let stale_hashes = documents
+        .into_iter()
+        .filter(|document| !document.is_directory)
+        .map(|document| document.unique_hash)
+        .filter(|hash| !live.tantivy_hashes.contains(hash))
+        .collect::<Vec<_>>();
+    if !stale_hashes.is_empty() {
+        delete_documents(lexical_index, repo_name, &stale_hashes).await?;
+    }
+    Ok(stale_hashes.len())
+}
+
+// Reads the files of the ref at its indexed commit and hashes them the way `traverse` does.
+// The path filters of the runs aren't recorded, so all the files passing the ignore rules are
+// considered live; that keeps more entries than needed rather than dropping indexed ones.
+fn live_files(disk_path: &Path, repo_ref: &str, commit: &str) -> Result<LiveFiles> {
+    let git_repo = GitRepository::open(disk_path)?;
+    let snapshot = read_snapshot(
+        RepoSource::Git,
+        disk_path,
+        Some(&git_repo),
+        commit,
+        &[],
+        &[],
+    )?;
+
+    let mut live = LiveFiles::default();
+    for file in snapshot.files {
+        let buffer = std::str::from_utf8(&file.content).unwrap_or("");
+        let relative_path = PathBuf::from(&file.path)
+            .strip_prefix(disk_path)
+            .map(ToOwned::to_owned)
+            .unwrap_or(PathBuf::from(&file.path));
+        let (semantic_hash, tantivy_hash) = compute_hashes(relative_path, buffer, repo_ref);
+        live.semantic_hashes.insert(semantic_hash);
+        live.tantivy_hashes.insert(tantivy_hash);
+        live.paths.insert(file.path);
+    }
+    Ok(live)
+}
@@ -0,0 +1,181 @@
+use anyhow::Result;
+use git2::{DiffOptions, Oid, Repository as GitRepository, Sort};
+use log::{debug, info};
+use std::collections::HashMap;
+
+/// Number of commits walked back from the indexed commit. Files last modified before them get no
+/// history, which keeps the walk bounded on repos with a long history.
+pub const HISTORY_MAX_COMMITS: usize = 1000;
+
+/// The history of a file as of the indexed commit.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FileHistory {
+    pub last_commit: String,
+    pub author: String,
+    // Commit time of the last commit, in seconds since the epoch.
+    pub date: i64,
+    // Number of walked commits that modified the file.
+    pub churn: u64,
+}
+
+/// A commit reachable from the indexed commit, with the paths it touched.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommitRecord {
+    pub commit: String,
+    pub author: String,
+    pub date: i64,
+    pub message: String,
+    // Paths added, modified or deleted by the commit, compared to its first parent.
+    pub paths: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct History {
+    // By path relative to the repo root.
+    pub files: HashMap<String, FileHistory>,
+    // Newest first.
+    pub commits: Vec<CommitRecord>,
+}
+
+impl History {
+    pub fn file(&self, path: &str) -> Option<&FileHistory> {
+        self.files.get(path)
+    }
+}
+
+/// Walks the history of `head_commit`, newest first, and records for each path the last commit
+/// that modified it and how many walked commits did. Merge commits are diffed against their first
+/// parent, so the changes brought by a merged branch are attributed to the branch's own commits.
+pub fn read_history(
+    git_repo: &GitRepository,
+    head_commit: &str,
+    max_commits: usize,
+) -> Result<History> {
+    let mut revwalk = git_repo.revwalk()?;
+    revwalk.push(Oid::from_str(head_commit)?)?;
+    revwalk.set_sorting(Sort::TIME)?;
+
+    let mut history = History::default();
+    for oid in revwalk.take(max_commits) {
+        let commit = git_repo.find_commit(oid?)?;
+        let tree = commit.tree()?;
+        let parent_tree = match commit.parents().next() {
+            Some(parent) => Some(parent.tree()?),
+            None => None,
+        };
+
+        let diff = git_repo.diff_tree_to_tree(
+            parent_tree.as_ref(),
+            Some(&tree),
+            Some(DiffOptions::new().ignore_submodules(true)),
+        )?;
+        let paths = diff
+            .deltas()
+            .filter_map(|delta| delta.new_file().path().or_else(|| delta.old_file().path()))
+            .filter_map(|path| path.to_str().map(str::to_string))
+            .collect::<Vec<_>>();
+
+        let record = CommitRecord {
+            commit: commit.id().to_string(),
+            author: commit.author().name().unwrap_or_default().to_string(),
+            date: commit.time().seconds(),
+            message: commit.message().unwrap_or_default().trim().to_string(),
+            paths,
+        };
+
+        // the walk goes back in time, the first commit seen for a path is its last one.
+        for path in &record.paths {
+            let file = history
+                .files
+                .entry(path.clone())
+                .or_insert_with(|| FileHistory {
+                    last_commit: record.commit.clone(),
+                    author: record.author.clone(),
+                    date: record.date,
+                    churn: 0,
+                });
+            file.churn += 1;
+        }
+        debug!(
+            "Commit {} touched {} paths",
+            record.commit,
+            record.paths.len()
+        );
+        history.commits.push(record);
+    }
+
+    info!(
+        "Read the history of {} files from {} commits",
+        history.files.len(),
+        history.commits.len()
+    );
+    Ok(history)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::commit_files_by;
+
+    #[test]
+    fn test_read_history() {
+        let dir = std::env::temp_dir().join(format!("ingestion-history-{}", uuid::Uuid::new_v4()));
+        let repo = GitRepository::init(&dir).unwrap();
+
+        let first = commit_files_by(
+            &repo,
+            &[("a.rs", "fn a() {}\n"), ("b.rs", "fn b() {}\n")],
+            "alice",
+            1_000,
+            "Add a and b\n",
+            None,
+        );
+        let second = commit_files_by(
+            &repo,
+            &[("a.rs", "fn a() { 1 }\n"), ("b.rs", "fn b() {}\n")],
+            "bob",
+            2_000,
+            "Fix a",
+            Some(first),
+        );
+        let third = commit_files_by(
+            &repo,
+            &[("a.rs", "fn a() { 2 }\n"), ("c.rs", "fn c() {}\n")],
+            "carol",
+            3_000,
+            "Replace b with c",
+            Some(second),
+        );
+
+        let history = read_history(&repo, &third.to_string(), HISTORY_MAX_COMMITS).unwrap();
+        assert_eq!(
+            history
+                .commits
+                .iter()
+                .map(|commit| commit.message.as_str())
+                .collect::<Vec<_>>(),
+            vec!["Replace b with c", "Fix a", "Add a and b"]
+        );
+        assert_eq!(history.commits[0].paths, vec!["a.rs", "b.rs", "c.rs"]);
+
+        assert_eq!(
+            history.file("a.rs"),
+            Some(&FileHistory {
+                last_commit: third.to_string(),
+                author: "carol".to_string(),
+                date: 3_000,
+                churn: 3,
+            })
+        );
+        assert_eq!(history.file("b.rs").unwrap().churn, 2);
+        assert_eq!(history.file("c.rs").unwrap().author, "carol");
+
+        // older commits are left out past the limit.
+        let history = read_history(&repo, &third.to_string(), 2).unwrap();
+        assert_eq!(history.commits.len(), 2);
+        assert_eq!(history.file("a.rs").unwrap().churn, 2);
+        assert_eq!(history.file("b.rs").unwrap().last_commit, third.to_string());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
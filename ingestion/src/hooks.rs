@@ -0,0 +1,84 @@
+use common::models::IndexEvent;
+use log::{debug, error, warn};
+use once_cell::sync::Lazy;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::Client;
+use std::env;
+use std::time::Duration;
+
+// Time given to a hook to answer, indexing doesn't wait on a slow service any longer.
+const HOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+static CLIENT: Lazy<Client> = Lazy::new(|| {
+    Client::builder()
+        .timeout(HOOK_TIMEOUT)
+        .build()
+        .unwrap_or_default()
+});
+
+// The URLs notified of the index events, from the comma separated INDEX_HOOK_URLS, e.g.
+// `http://localhost:3003/cache/invalidate` for the cache of code-search.
+fn hook_urls() -> Vec<String> {
+    env::var("INDEX_HOOK_URLS")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|url| !url.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Warns when no index hook is set: the services caching search results, like code-search,
+/// then only drop them once they expire.
+pub fn warn_if_unset() {
+    if hook_urls().is_empty() {
+        warn!("INDEX_HOOK_URLS is not set, the search caches aren't invalidated by indexing and only expire with their TTL");
+    }
+}
+
+/// Posts the event to the index hooks. A failing hook is logged, it never fails the indexing run
+/// or the deletion that raised the event.
+pub async fn notify(event: IndexEvent) {
+    let body = match serde_json::to_string(&event) {
+        Ok(body) => body,
+        Err(e) => {
+            error!("Failed to serialize {:?}: {:?}", event, e);
+            return;
+        }
+    };
+    for url in hook_urls() {
+        let response = CLIENT
+            .post(&url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(body.clone())
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        match response {
+            Ok(_) => debug!("Notified {} of {:?}", url, event),
+            Err(e) => error!("Failed to notify {} of {:?}: {:?}", url, event, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hook_urls() {
+        env::set_var(
+            "INDEX_HOOK_URLS",
+            " http://localhost:3003/cache/invalidate, ,http://search:3003/cache/invalidate",
+        );
+        assert_eq!(
+            hook_urls(),
+            vec![
+                "http://localhost:3003/cache/invalidate",
+                "http://search:3003/cache/invalidate"
+            ]
+        );
+        env::remove_var("INDEX_HOOK_URLS");
+        assert!(hook_urls().is_empty());
+    }
+}
@@ -0,0 +1,339 @@
+use anyhow::Result;
+use git2::{ObjectType, Repository as GitRepository, Tree, TreeWalkMode, TreeWalkResult};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::index_filter::index_filter;
+
+pub(crate) const GITIGNORE_FILE: &str = ".gitignore";
+pub(crate) const NEZUKOIGNORE_FILE: &str = ".nezukoignore";
+
+/// Why a path is left out of the index.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum SkipReason {
+    // Rejected by the built-in blacklist of extensions and vendored directories.
+    BuiltinFilter,
+    // Matched by the `exclude` globs of the request.
+    ExcludeGlob { pattern: String },
+    // Matched by a pattern of the repo's `.nezukoignore`.
+    Nezukoignore { pattern: String },
+    // Matched by a pattern of a `.gitignore` file of the repo.
+    Gitignore { file: String, pattern: String },
+    // Not matched by any of the `include` globs of the request.
+    NotIncluded,
+    // Larger than `MAX_FILE_LEN`.
+    TooLarge { size: usize },
+    // The language of the file could not be detected.
+    UnknownLanguage,
+}
+
+/// A path left out of the index, along with the reason it was skipped.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct SkippedPath {
+    pub path: String,
+    pub is_directory: bool,
+    #[serde(flatten)]
+    pub reason: SkipReason,
+}
+
+/// An entry visited by `walk_tree`.
+pub enum WalkEntry {
+    Dir(String),
+    File { path: String, id: git2::Oid },
+    Skipped(SkippedPath),
+}
+
+/// The rules deciding which paths of a repository are indexed, in order of precedence:
+/// the built-in filter, the request's exclude globs, `.nezukoignore`, `.gitignore` files
+/// and finally the request's include globs.
+///
+/// Negated patterns in `.nezukoignore` re-include paths ignored by a `.gitignore`.
+pub struct IgnoreRules {
+    exclude: Vec<(String, GlobSet)>,
+    include: Option<GlobSet>,
+    nezukoignore: Option<Gitignore>,
+    // One matcher per `.gitignore` file, rooted at its directory, ordered from the shallowest.
+    gitignores: Vec<(String, Gitignore)>,
+}
+
+impl IgnoreRules {
+    /// Builds the rules for the given tree. `.gitignore` files are read from the tree, the
+    /// `.nezukoignore` file from the tree or, if it isn't committed, from the repo on disk.
+    pub fn from_tree(
+        git_repo: &GitRepository,
+        tree: &Tree,
+        disk_path: &Path,
+        include: &[String],
+        exclude: &[String],
+    ) -> Result<Self> {
+        let mut gitignores = Vec::new();
+        let mut nezukoignore = None;
+
+        tree.walk(TreeWalkMode::PreOrder, |root, entry| {
+            let name = entry.name().unwrap_or_default();
+            if entry.kind() != Some(ObjectType::Blob)
+                || (name != GITIGNORE_FILE && !(root.is_empty() && name == NEZUKOIGNORE_FILE))
+            {
+                return TreeWalkResult::Ok;
+            }
+
+            let content = match git_repo.find_blob(entry.id()) {
+                Ok(blob) => String::from_utf8_lossy(blob.content()).to_string(),
+                Err(e) => {
+                    warn!("Could not read {}{}: {}", root, name, e);
+                    return TreeWalkResult::Ok;
+                }
+            };
+
+            if name == NEZUKOIGNORE_FILE {
+                nezukoignore = Some(content);
+            } else {
+                gitignores.push((root.to_string(), content));
+            }
+            TreeWalkResult::Ok
+        })?;
+
+        let nezukoignore =
+            nezukoignore.or_else(|| fs::read_to_string(disk_path.join(NEZUKOIGNORE_FILE)).ok());
+
+        Self::new(&gitignores, nezukoignore.as_deref(), include, exclude)
+    }
+
+    /// Builds the rules from the content of the ignore files. `gitignores` holds the directory
+    /// of each `.gitignore` file (empty for the root, otherwise ending with a `/`) and its content.
+    pub fn new(
+        gitignores: &[(String, String)],
+        nezukoignore: Option<&str>,
+        include: &[String],
+        exclude: &[String],
+    ) -> Result<Self> {
+        let exclude = exclude
+            .iter()
+            .map(|pattern| Ok((pattern.clone(), glob_set(std::slice::from_ref(pattern))?)))
+            .collect::<Result<Vec<_>>>()?;
+
+        let include = if include.is_empty() {
+            None
+        } else {
+            Some(glob_set(include)?)
+        };
+
+        let nezukoignore = nezukoignore
+            .map(|content| ignore_matcher("", NEZUKOIGNORE_FILE, content))
+            .transpose()?;
+
+        let mut gitignores = gitignores
+            .iter()
+            .map(|(dir, content)| {
+                let file = format!("{}{}", dir, GITIGNORE_FILE);
+                Ok((file, ignore_matcher(dir, GITIGNORE_FILE, content)?))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        gitignores.sort_by_key(|(file, _)| file.matches('/').count());
+
+        Ok(Self {
+            exclude,
+            include,
+            nezukoignore,
+            gitignores,
+        })
+    }
+
+    /// Checks whether a path relative to the repo root should be indexed.
+    pub fn check(&self, path: &str, is_dir: bool) -> Result<(), SkipReason> {
+        if !index_filter(&path) {
+            return Err(SkipReason::BuiltinFilter);
+        }
+
+        if let Some((pattern, _)) = self.exclude.iter().find(|(_, glob)| glob.is_match(path)) {
+            return Err(SkipReason::ExcludeGlob {
+                pattern: pattern.clone(),
+            });
+        }
+
+        let mut whitelisted = false;
+        if let Some(nezukoignore) = &self.nezukoignore {
+            match nezukoignore.matched_path_or_any_parents(path, is_dir) {
+                Match::Ignore(glob) => {
+                    return Err(SkipReason::Nezukoignore {
+                        pattern: glob.original().to_string(),
+                    })
+                }
+                Match::Whitelist(_) => whitelisted = true,
+                Match::None => {}
+            }
+        }
+
+        if !whitelisted {
+            // the deepest .gitignore with a match decides, like git does.
+            let matched = self
+                .gitignores
+                .iter()
+                .filter(|(file, _)| path.starts_with(file.trim_end_matches(GITIGNORE_FILE)))
+                .filter_map(|(file, gitignore)| {
+                    match gitignore.matched_path_or_any_parents(path, is_dir) {
+                        Match::None => None,
+                        matched => Some((file, matched)),
+                    }
+                })
+                .last();
+
+            if let Some((file, Match::Ignore(glob))) = matched {
+                return Err(SkipReason::Gitignore {
+                    file: file.clone(),
+                    pattern: glob.original().to_string(),
+                });
+            }
+        }
+
+        // directories are always walked, their files may still match the include globs.
+        if let Some(include) = &self.include {
+            if !is_dir && !include.is_match(path) {
+                return Err(SkipReason::NotIncluded);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn glob_set(patterns: &[String]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(Glob::new(pattern)?);
+    }
+    Ok(builder.build()?)
+}
+
+fn ignore_matcher(dir: &str, file_name: &str, content: &str) -> Result<Gitignore> {
+    let mut builder = GitignoreBuilder::new(dir);
+    let source = PathBuf::from(format!("{}{}", dir, file_name));
+    for line in content.lines() {
+        if let Err(e) = builder.add_line(Some(source.clone()), line) {
+            warn!("Ignoring invalid pattern in {:?}: {}", source, e);
+        }
+    }
+    Ok(builder.build()?)
+}
+
+/// Walks the tree and reports every directory and file that passes the rules, along with the
+/// paths that are skipped. The content of skipped directories isn't visited.
+pub fn walk_tree(tree: &Tree, rules: &IgnoreRules, mut visit: impl FnMut(WalkEntry)) -> Result<()> {
+    tree.walk(TreeWalkMode::PreOrder, |root, entry| {
+        let Some(name) = entry.name() else {
+            return TreeWalkResult::Ok;
+        };
+        let path = format!("{}{}", root, name);
+
+        let is_dir = match entry.kind() {
+            Some(ObjectType::Tree) => true,
+            Some(ObjectType::Blob) => false,
+            // submodules and other entries are never indexed.
+            _ => return TreeWalkResult::Ok,
+        };
+
+        if let Err(reason) = rules.check(&path, is_dir) {
+            debug!("Skipping {}: {:?}", path, reason);
+            visit(WalkEntry::Skipped(SkippedPath {
+                path,
+                is_directory: is_dir,
+                reason,
+            }));
+            return if is_dir {
+                TreeWalkResult::Skip
+            } else {
+                TreeWalkResult::Ok
+            };
+        }
+
+        if is_dir {
+            visit(WalkEntry::Dir(path));
+        } else {
+            visit(WalkEntry::File {
+                path,
+                id: entry.id(),
+            });
+        }
+        TreeWalkResult::Ok
+    })?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ignore_rules() {
+        let gitignores = vec![
+            (String::new(), "build/\n*.tmp\n".to_string()),
+            ("docs/".to_string(), "generated.rs\n".to_string()),
+        ];
+        let rules = IgnoreRules::new(
+            &gitignores,
+            Some("scratch/\n!keep.tmp\n"),
+            &["src/**".to_string(), "docs/**".to_string()],
+            &["src/legacy/**".to_string()],
+        )
+        .unwrap();
+
+        let test_cases = [
+            ("src/main.rs", false, Ok(())),
+            ("src", true, Ok(())),
+            ("tests/main.rs", false, Err(SkipReason::NotIncluded)),
+            ("src/logo.png", false, Err(SkipReason::BuiltinFilter)),
+            (
+                "src/legacy/old.rs",
+                false,
+                Err(SkipReason::ExcludeGlob {
+                    pattern: "src/legacy/**".to_string(),
+                }),
+            ),
+            (
+                "scratch",
+                true,
+                Err(SkipReason::Nezukoignore {
+                    pattern: "scratch/".to_string(),
+                }),
+            ),
+            (
+                "src/build/out.rs",
+                false,
+                Err(SkipReason::Gitignore {
+                    file: ".gitignore".to_string(),
+                    pattern: "build/".to_string(),
+                }),
+            ),
+            (
+                "src/cache.tmp",
+                false,
+                Err(SkipReason::Gitignore {
+                    file: ".gitignore".to_string(),
+                    pattern: "*.tmp".to_string(),
+                }),
+            ),
+            // whitelisted by .nezukoignore even though .gitignore ignores it.
+            ("src/keep.tmp", false, Ok(())),
+            (
+                "docs/generated.rs",
+                false,
+                Err(SkipReason::Gitignore {
+                    file: "docs/.gitignore".to_string(),
+                    pattern: "generated.rs".to_string(),
+                }),
+            ),
+            ("src/generated.rs", false, Ok(())),
+        ];
+
+        for (path, is_dir, expected) in test_cases {
+            assert_eq!(rules.check(path, is_dir), expected, "path: {}", path);
+        }
+    }
+}
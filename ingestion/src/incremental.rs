@@ -0,0 +1,290 @@
+use anyhow::{anyhow, Result};
+use git2::{Delta, DiffFindOptions, Oid, Repository as GitRepository};
+use log::{debug, info};
+use once_cell::sync::Lazy;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::hash::compute_hashes;
+use crate::index_filter::index_filter;
+use crate::util::state_dir;
+
+const INDEXED_COMMITS_FILE: &str = "indexed_commits.json";
+
+// Serializes reads and writes of the indexed commits file between concurrent indexing runs.
+static INDEXED_COMMITS_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+/// The set of changes between the last indexed commit and the new head of a branch.
+#[derive(Debug, Default, Clone)]
+pub struct ChangeSet {
+    pub base_commit: String,
+    pub head_commit: String,
+    /// Paths that were added or modified (including rename targets) and need to be re-indexed.
+    pub changed: HashSet<String>,
+    /// Previous versions of files that were deleted, modified or renamed away.
+    /// Their vectors and documents have to be removed before the new versions are pushed.
+    pub stale: Vec<StaleFile>,
+}
+
+impl ChangeSet {
+    pub fn is_empty(&self) -> bool {
+        self.changed.is_empty() && self.stale.is_empty()
+    }
+
+    /// Paths whose previously indexed data is no longer valid.
+    pub fn stale_paths(&self) -> HashSet<String> {
+        self.stale.iter().map(|file| file.path.clone()).collect()
+    }
+}
+
+/// A file version that was indexed from the base commit, identified by the same hashes
+/// `compute_hashes` produced when it was indexed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StaleFile {
+    pub path: String,
+    pub semantic_hash: String,
+    pub tantivy_hash: String,
+}
+
+// Resolves the commit a branch or tag currently points to. Branches take precedence over tags
+// with the same name, anything else is resolved as a git revision (e.g. a commit sha).
+pub fn resolve_ref(git_repo: &GitRepository, repo_ref: &str) -> Result<String> {
+    let candidates = [
+        format!("refs/heads/{}", repo_ref),
+        format!("refs/tags/{}", repo_ref),
+    ];
+
+    let commit = candidates
+        .iter()
+        .find_map(|name| git_repo.find_reference(name).ok())
+        .map(|reference| reference.peel_to_commit())
+        .unwrap_or_else(|| git_repo.revparse_single(repo_ref)?.peel_to_commit())
+        .map_err(|e| anyhow!("Could not resolve {} to a commit: {}", repo_ref, e))?;
+
+    Ok(commit.id().to_string())
+}
+
+/// Diffs two commits of the repository and returns the files that have to be re-indexed
+/// along with the stale versions that have to be removed from the indexes.
+///
+/// Renames are detected so that the old path is cleaned up and the new one indexed.
+/// Paths that are rejected by `index_filter` are ignored on both sides.
+pub fn diff_commits(
+    git_repo: &GitRepository,
+    base_commit: &str,
+    head_commit: &str,
+    branch: &str,
+) -> Result<ChangeSet> {
+    let base_tree = git_repo.find_commit(Oid::from_str(base_commit)?)?.tree()?;
+    let head_tree = git_repo.find_commit(Oid::from_str(head_commit)?)?.tree()?;
+
+    let mut diff = git_repo.diff_tree_to_tree(Some(&base_tree), Some(&head_tree), None)?;
+    let mut find_options = DiffFindOptions::new();
+    find_options.renames(true);
+    diff.find_similar(Some(&mut find_options))?;
+
+    let mut change_set = ChangeSet {
+        base_commit: base_commit.to_string(),
+        head_commit: head_commit.to_string(),
+        ..Default::default()
+    };
+
+    for delta in diff.deltas() {
+        let old_path = delta.old_file().path().map(path_to_string);
+        let new_path = delta.new_file().path().map(path_to_string);
+
+        let (stale, changed) = match delta.status() {
+            Delta::Added | Delta::Copied => (None, new_path),
+            Delta::Deleted => (old_path, None),
+            Delta::Modified | Delta::Renamed | Delta::Typechange => (old_path, new_path),
+            status => {
+                debug!("Ignoring diff entry with status {:?}", status);
+                (None, None)
+            }
+        };
+
+        if let Some(path) = stale.filter(|path| index_filter(path)) {
+            if let Some(stale_file) = stale_file(git_repo, delta.old_file().id(), &path, branch) {
+                change_set.stale.push(stale_file);
+            }
+        }
+
+        if let Some(path) = changed.filter(|path| index_filter(path)) {
+            change_set.changed.insert(path);
+        }
+    }
+
+    info!(
+        "Diff {}..{}: {} files to re-index, {} stale files to remove",
+        base_commit,
+        head_commit,
+        change_set.changed.len(),
+        change_set.stale.len()
+    );
+
+    Ok(change_set)
+}
+
+// Recomputes the hashes the old version of the file was indexed with.
+fn stale_file(
+    git_repo: &GitRepository,
+    blob_id: Oid,
+    path: &str,
+    branch: &str,
+) -> Option<StaleFile> {
+    let blob = git_repo.find_blob(blob_id).ok()?;
+    let buffer = std::str::from_utf8(blob.content()).unwrap_or("");
+    let (semantic_hash, tantivy_hash) = compute_hashes(PathBuf::from(path), buffer, branch);
+
+    Some(StaleFile {
+        path: path.to_string(),
+        semantic_hash,
+        tantivy_hash,
+    })
+}
+
+fn path_to_string(path: &Path) -> String {
+    path.to_string_lossy().to_string()
+}
+
+fn commit_key(repo_name: &str, branch: &str) -> String {
+    format!("{}@{}", repo_name, branch)
+}
+
+fn indexed_commits_path() -> PathBuf {
+    state_dir().join(INDEXED_COMMITS_FILE)
+}
+
+fn read_indexed_commits(path: &Path) -> HashMap<String, String> {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+// Returns the commit the repo/branch pair was last successfully indexed at, if any.
+pub fn last_indexed_commit(repo_name: &str, branch: &str) -> Option<String> {
+    let _guard = INDEXED_COMMITS_LOCK.lock().unwrap();
+    read_indexed_commits(&indexed_commits_path()).remove(&commit_key(repo_name, branch))
+}
+
+// Records the commit the repo/branch pair has just been indexed at.
+pub fn record_indexed_commit(repo_name: &str, branch: &str, commit: &str) -> Result<()> {
+    update_indexed_commits(|commits| {
+        commits.insert(commit_key(repo_name, branch), commit.to_string());
+    })?;
+    info!(
+        "Recorded indexed commit {} for {}",
+        commit,
+        commit_key(repo_name, branch)
+    );
+    Ok(())
+}
+
+// Forgets the commit the repo/branch pair was indexed at, so that the next incremental run
+// indexes everything.
+pub fn forget_indexed_commit(repo_name: &str, branch: &str) -> Result<()> {
+    update_indexed_commits(|commits| {
+        commits.remove(&commit_key(repo_name, branch));
+    })
+}
+
+// Returns the refs of the repo that were indexed, along with the commit each was indexed at.
+pub fn indexed_commits(repo_name: &str) -> Vec<(String, String)> {
+    let _guard = INDEXED_COMMITS_LOCK.lock().unwrap();
+    let prefix = commit_key(repo_name, "");
+    let mut commits = read_indexed_commits(&indexed_commits_path())
+        .into_iter()
+        .filter_map(|(key, commit)| Some((key.strip_prefix(&prefix)?.to_string(), commit)))
+        .collect::<Vec<_>>();
+    commits.sort();
+    commits
+}
+
+// Forgets the commits of all the refs of the repo.
+pub fn forget_repository(repo_name: &str) -> Result<()> {
+    let prefix = commit_key(repo_name, "");
+    update_indexed_commits(|commits| commits.retain(|key, _| !key.starts_with(&prefix)))
+}
+
+fn update_indexed_commits(update: impl FnOnce(&mut HashMap<String, String>)) -> Result<()> {
+    let _guard = INDEXED_COMMITS_LOCK.lock().unwrap();
+    let path = indexed_commits_path();
+    let mut commits = read_indexed_commits(&path);
+    update(&mut commits);
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, serde_json::to_string_pretty(&commits)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::commit_files;
+
+    #[test]
+    fn test_diff_commits() {
+        let dir = std::env::temp_dir().join(format!("ingestion-diff-{}", uuid::Uuid::new_v4()));
+        let repo = GitRepository::init(&dir).unwrap();
+
+        let base = commit_files(
+            &repo,
+            &[
+                ("kept.rs", "fn kept() {}\n"),
+                ("modified.rs", "fn before() {}\n"),
+                ("deleted.rs", "fn deleted() {}\n"),
+                ("old_name.rs", "fn renamed() { let a = 1; let b = 2; }\n"),
+            ],
+            None,
+        );
+        let head = commit_files(
+            &repo,
+            &[
+                ("kept.rs", "fn kept() {}\n"),
+                ("modified.rs", "fn after() {}\n"),
+                ("added.rs", "fn added() {}\n"),
+                ("new_name.rs", "fn renamed() { let a = 1; let b = 2; }\n"),
+            ],
+            Some(base),
+        );
+
+        // branches, tags and revisions all resolve to commits.
+        let base_object = repo.find_object(base, None).unwrap();
+        repo.tag_lightweight("v1", &base_object, false).unwrap();
+        assert_eq!(resolve_ref(&repo, "main").unwrap(), head.to_string());
+        assert_eq!(resolve_ref(&repo, "v1").unwrap(), base.to_string());
+        assert_eq!(resolve_ref(&repo, "main~1").unwrap(), base.to_string());
+
+        let change_set = diff_commits(&repo, &base.to_string(), &head.to_string(), "main").unwrap();
+
+        let expected_changed: HashSet<String> = ["modified.rs", "added.rs", "new_name.rs"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let expected_stale: HashSet<String> = ["modified.rs", "deleted.rs", "old_name.rs"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        assert_eq!(change_set.changed, expected_changed);
+        assert_eq!(change_set.stale_paths(), expected_stale);
+
+        // stale entries carry the hashes the old content was indexed with.
+        let modified = change_set
+            .stale
+            .iter()
+            .find(|file| file.path == "modified.rs")
+            .unwrap();
+        let (semantic_hash, tantivy_hash) =
+            compute_hashes(PathBuf::from("modified.rs"), "fn before() {}\n", "main");
+        assert_eq!(modified.semantic_hash, semantic_hash);
+        assert_eq!(modified.tantivy_hash, tantivy_hash);
+
+        let _ = fs::remove_dir_all(dir);
+    }
+}
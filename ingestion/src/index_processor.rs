@@ -1,38 +1,16 @@
 use anyhow::{anyhow, Result};
-use futures::stream::StreamExt;
+use common::lexical::{branch_clause, LexicalDocument, LexicalIndex};
+use futures::stream::{self, StreamExt};
 use itertools::Itertools;
 use md5::compute;
-use ort::error;
-use std::process;
 
-use std::error::Error;
-use std::fs::{self};
+use crate::progress::ProgressReporter;
+use log::{error, info};
 
-use crate::FileFields;
-use log::{debug, error, info};
-use std::env;
-use std::path::Path;
-
-use crate::generate_index_schema;
-
-use tokio::time::{sleep, Duration};
-
-use reqwest::{self, StatusCode};
-
-fn get_config_path() -> Option<String> {
-    // Get the current executable path
-    let exe_path = env::current_exe().ok()?;
-
-    // Get the directory containing the executable
-    let exe_dir = exe_path.parent()?;
-
-    // Append "index-config.yaml" to the executable directory path
-    let config_path = exe_dir.join("index-config.yaml");
-
-    // Convert the path to a String if possible
-    log::debug!("Config path: {:?}", config_path);
-    config_path.to_str().map(|s| s.to_owned())
-}
+// Number of documents sent to the lexical index per ingest call.
+const INGEST_BATCH_SIZE: usize = 50;
+// Number of ingest calls in flight at once.
+const INGEST_CONCURRENCY: usize = 4;
 
 // Input is repo name in format v2/owner_name/repo_name.
 // We generate hash of namespace using md5 and prefix it with the repo name extracted from namespace.
@@ -46,189 +24,169 @@ pub fn generate_quikwit_index_name(namespace: &str) -> String {
     return index_name;
 }
 
-pub async fn process_entries(all_entries: Vec<FileFields>, repo_name: &str, quickwit_url: &str) {
-    let config = include_str!("../index-config.yaml");
-    let new_index_id = generate_quikwit_index_name(repo_name);
-    info!(
-        "Quickwit index_id: {} for repo: {}",
-        new_index_id, repo_name
-    );
-    let index_config =
-        generate_index_schema::replace_index_id_in_yaml(config.to_string(), &new_index_id).unwrap();
-
-    debug!("Sending first yaml to server...");
-    let response = send_yaml_to_server(&index_config, quickwit_url, &new_index_id).await;
-    match response {
-        Ok(_) => info!("Successfully sent yaml to server"),
-        Err(e) => error!("Failed to send yaml to server: {:?}", e),
+// The index of a repo holds the documents of all its indexed branches and tags. When `replace_ref` is
+// set, the documents previously indexed for that ref are deleted before the new ones are sent.
+// Incremental runs don't set it since their stale documents are removed file by file.
+pub async fn process_entries(
+    lexical_index: &dyn LexicalIndex,
+    all_entries: Vec<LexicalDocument>,
+    repo_name: &str,
+    replace_ref: Option<&str>,
+    progress: &mut ProgressReporter,
+) -> Result<()> {
+    let index_id = generate_quikwit_index_name(repo_name);
+    info!("Lexical index_id: {} for repo: {}", index_id, repo_name);
+
+    lexical_index.ensure_index(&index_id).await?;
+
+    if let Some(repo_ref) = replace_ref {
+        if let Err(e) = lexical_index
+            .delete(&index_id, &branch_clause(repo_ref))
+            .await
+        {
+            error!("Failed to delete the documents of {}: {:?}", repo_ref, e);
+        }
     }
 
-    let chunk_size = 3;
-    let mut futures = vec![];
-    let all_entries_clone = all_entries.clone();
-
-    for chunk in all_entries_clone.chunks(chunk_size) {
-        let chunk = chunk.to_owned();
-        let quickwit_url = quickwit_url.to_string();
-        let new_index_id = new_index_id.clone();
-        let repo_name = repo_name.to_string();
-
-        // Each chunk is processed in its own async block, ensuring thread safety
-        let handle = tokio::spawn(async move {
-            let url = format!(
-                "{}/api/v1/{}/ingest?commit=force",
-                quickwit_url, new_index_id
-            );
-            let json_data_vec: Vec<String> = chunk
-                .iter()
-                .map(|record| serde_json::to_string(record).unwrap())
-                .collect();
-
-            let batch_data = json_data_vec.join("\n");
-
-            match send_content_to_server(&batch_data, &url).await {
-                Ok(response) => {
-                    debug!("Successfully sent data: {:?}", response);
-                }
-                Err(e) => {
-                    error!("Repo ID: {}", repo_name);
-                    error!("Failed to send data to quickwit, ending process: {:?}", e);
-                    // Consider how to handle errors in async context; using process::exit here might not be ideal.
-                }
-            }
-        });
-        futures.push(handle);
+    let mut ingested = stream::iter(all_entries.chunks(INGEST_BATCH_SIZE))
+        .map(|chunk| async {
+            let result = lexical_index.ingest(&index_id, chunk).await;
+            (chunk.len(), result)
+        })
+        .buffer_unordered(INGEST_CONCURRENCY);
+
+    // a failed batch doesn't stop the others, the run fails once they are all sent so that its
+    // commit isn't recorded and the next run sends the documents again.
+    let mut failed = 0;
+    while let Some((chunk_len, result)) = ingested.next().await {
+        if let Err(e) = result {
+            error!("Repo ID: {}", repo_name);
+            error!("Failed to send data to the lexical index: {:?}", e);
+            failed += chunk_len;
+        }
+        progress.advance(chunk_len);
     }
 
-    // Await all futures to complete and handle their results.
-    for future in futures.into_iter() {
-        match future.await {
-            Ok(_) => debug!("Chunk processed successfully"),
-            Err(e) => error!("Error processing chunk: {:?}", e),
-        }
+    if failed > 0 {
+        return Err(anyhow!(
+            "{} of {} documents could not be sent to index {}",
+            failed,
+            all_entries.len(),
+            index_id
+        ));
     }
+    Ok(())
 }
 
-async fn send_yaml_to_server(
-    config_yaml: &str,
-    quickwit_url: &str,
-    new_index_id: &str,
-) -> anyhow::Result<()> {
-    debug!("Reading YAML content...");
-
-    let create_index_url = quickwit_url.to_owned() + "/api/v1/indexes";
-    let describe_url = quickwit_url.to_owned() + "/api/v1/indexes/" + new_index_id + "/describe";
-    let clear_url = quickwit_url.to_owned() + "/api/v1/indexes/" + new_index_id + "/clear";
-
-    debug!("Making GET request to describe URL: {}", &describe_url);
-    let client = reqwest::Client::new();
-    let describe_response = client.get(&describe_url).send().await?;
-
-    match describe_response.status() {
-        StatusCode::NOT_FOUND => {
-            // Index does not exist, proceed to create
-            // add info log
-            info!(
-                "Index not found, creating new index at URL: {}",
-                &create_index_url
-            );
-
-            let create_response = client
-                .post(&create_index_url)
-                .header("Content-Type", "application/yaml")
-                .body(config_yaml.to_string())
-                .send()
-                .await?;
-
-            if create_response.status() != StatusCode::OK {
-                error!("Error creating index");
-                return Err(anyhow::anyhow!("Error creating index"));
-            }
-        }
-        StatusCode::OK => {
-            // Index exists, proceed to clear
-            info!("Index found, clearing index at URL: {}", &clear_url);
-            debug!("Index found, clearing index at URL: {}", &clear_url);
-            let clear_response = client.put(&clear_url).send().await?;
-
-            if clear_response.status() != StatusCode::OK {
-                error!("Error clearing index");
-                return Err(anyhow::anyhow!("Error clearing index"));
-            }
-        }
-        _ => {
-            // Handle other unexpected statuses
-            error!("Unexpected status code received from describe URL");
-            return Err(anyhow::anyhow!(
-                "Unexpected status code received from describe URL"
-            ));
-        }
+// Number of unique hashes OR-ed together in a single delete query.
+const DELETE_BATCH_SIZE: usize = 50;
+
+// Deletes the documents with the given unique hashes from the repo's lexical index.
+pub async fn delete_documents(
+    lexical_index: &dyn LexicalIndex,
+    repo_name: &str,
+    unique_hashes: &[String],
+) -> Result<()> {
+    let index_id = generate_quikwit_index_name(repo_name);
+
+    for hashes in unique_hashes.chunks(DELETE_BATCH_SIZE) {
+        let query = hashes
+            .iter()
+            .map(|hash| format!("unique_hash:{}", hash))
+            .join(" OR ");
+        lexical_index.delete(&index_id, &query).await?;
     }
 
-    // Add a delay of 10 seconds here
-    sleep(Duration::from_secs(10)).await;
-
-    let response_text = describe_response.text().await?;
     info!(
-        "Response for describing/configuring quickwit: {}",
-        response_text
+        "Requested deletion of {} documents from lexical index {}",
+        unique_hashes.len(),
+        index_id
     );
-
     Ok(())
 }
 
-async fn send_json_to_server(json_path: &str, url: &str) -> Result<(), anyhow::Error> {
-    debug!("Reading JSON file...");
-
-    // Read the JSON file content
-    let json_content = fs::read_to_string(json_path)?;
-
-    debug!("Making POST request...\n");
-    debug!("{}", json_content);
-
-    // Make the POST request
-    let client = reqwest::Client::new();
-    let response = client
-        .post(url)
-        .header("Content-Type", "application/json")
-        .body(json_content)
-        .send()
-        .await?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use common::lexical::SearchPage;
+    use std::sync::Mutex;
+
+    // Keeps the documents it is sent, except the ones of the batches containing `fail_path`.
+    struct FailingIndex {
+        fail_path: String,
+        ingested: Mutex<Vec<String>>,
+    }
 
-    // Print the response status and text
-    info!("Status Json from quickit: {}", response.status());
-    debug!("Response Json: {}", response.text().await?);
+    #[async_trait]
+    impl LexicalIndex for FailingIndex {
+        async fn ensure_index(&self, _index_id: &str) -> Result<()> {
+            Ok(())
+        }
 
-    fs::remove_file(json_path)?;
+        async fn ingest(&self, _index_id: &str, documents: &[LexicalDocument]) -> Result<()> {
+            if documents
+                .iter()
+                .any(|document| document.relative_path == self.fail_path)
+            {
+                return Err(anyhow!("ingest failed"));
+            }
+            self.ingested
+                .lock()
+                .unwrap()
+                .extend(documents.iter().map(|document| document.relative_path.clone()));
+            Ok(())
+        }
 
-    Ok(())
-}
+        async fn delete(&self, _index_id: &str, _query: &str) -> Result<()> {
+            Ok(())
+        }
 
-async fn send_content_to_server(content: &str, url: &str) -> Result<()> {
-    // Read the JSON file content
-    let json_content = content.to_string();
-
-    debug!("Making POST request to quickwit...\n to {}", url);
-
-    // Make the POST request
-    let client = reqwest::Client::new();
-    let response = client
-        .post(url)
-        .header("Content-Type", "application/json")
-        .body(json_content)
-        .send()
-        .await?;
-
-    match response.status() {
-        StatusCode::OK | StatusCode::CREATED | StatusCode::ACCEPTED => {
-            info!("Status from quickwit: {}", response.status());
-            debug!("Response from quickwit: {}", response.text().await?);
+        async fn delete_index(&self, _index_id: &str) -> Result<()> {
             Ok(())
         }
-        _ => {
-            let error_message = format!("Error response from quickwit: {}", response.status());
-            error!("{}", &error_message);
-            Err(anyhow::anyhow!(error_message))
+
+        async fn search_page(
+            &self,
+            _index_id: &str,
+            _query: &str,
+            _offset: usize,
+            _max_hits: usize,
+            _fields: &[&str],
+        ) -> Result<SearchPage> {
+            Ok(SearchPage::default())
         }
     }
+
+    #[tokio::test]
+    async fn test_process_entries_failed_batch() {
+        let documents = (0..INGEST_BATCH_SIZE * 3)
+            .map(|i| LexicalDocument {
+                relative_path: format!("src/{}.rs", i),
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+        let index = FailingIndex {
+            fail_path: "src/0.rs".to_string(),
+            ingested: Mutex::new(Vec::new()),
+        };
+
+        let result = process_entries(
+            &index,
+            documents,
+            "v2/owner/repo",
+            None,
+            &mut ProgressReporter::disabled(),
+        )
+        .await;
+
+        let error = result.unwrap_err().to_string();
+        assert!(error.starts_with(&format!(
+            "{} of {} documents",
+            INGEST_BATCH_SIZE,
+            INGEST_BATCH_SIZE * 3
+        )));
+        // the other batches are still sent.
+        assert_eq!(index.ingested.lock().unwrap().len(), INGEST_BATCH_SIZE * 2);
+    }
 }
@@ -0,0 +1,318 @@
+use common::embedding::EmbedderConfig;
+use common::lexical::LexicalIndexConfig;
+use common::vector::VectorStoreConfig;
+use futures::future::{AbortHandle, Abortable};
+use log::{debug, error, info, warn};
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+use crate::state::{
+    cancel_process, get_process_state, list_process_states, queue_job, record_process_failure,
+    start_process_attempt, try_start_process, update_process_state, CodeIndexingTaskStatus,
+};
+use crate::watch::stop_watch;
+use crate::{ChunkingStrategy, Config, Indexer, RepoSource};
+
+// Delay before a failed job is queued again, multiplied by the number of attempts so far.
+const RETRY_BACKOFF: Duration = Duration::from_secs(30);
+// Delay between the checks of a job waiting for the other runs of its repo.
+const WAIT_INTERVAL: Duration = Duration::from_millis(500);
+
+/// The parameters of an indexing run, persisted with the job so it can be retried or resumed.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct IndexingJob {
+    pub repo_name: String,
+    pub repo_path: String,
+    pub branch: String,
+    pub version: String,
+    #[serde(default)]
+    pub incremental: bool,
+    #[serde(default)]
+    pub include: Vec<String>,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    #[serde(default)]
+    pub chunking: ChunkingStrategy,
+    #[serde(default)]
+    pub source: RepoSource,
+    #[serde(default)]
+    pub index_commits: bool,
+}
+
+impl IndexingJob {
+    /// The config of an indexing run of the job on the backends of the worker pool.
+    pub fn config(&self, queue_config: &JobQueueConfig) -> Config {
+        Config::new(
+            self.repo_name.clone(),
+            self.repo_path.clone(),
+            queue_config.qdrant_url.clone(),
+            queue_config.quickwit_url.clone(),
+            queue_config.qdrant_api_key.clone(),
+            self.branch.clone(),
+            self.version.clone(),
+            self.incremental,
+        )
+        .with_path_filters(self.include.clone(), self.exclude.clone())
+        .with_embedder(queue_config.embedder.clone())
+        .with_chunking(self.chunking)
+        .with_source(self.source)
+        .with_commit_history(self.index_commits)
+        .with_lexical_index(queue_config.lexical_index.clone())
+        .with_vector_store(queue_config.vector_store.clone())
+    }
+}
+
+/// Settings shared by all the jobs run by the worker pool.
+#[derive(Clone, Debug)]
+pub struct JobQueueConfig {
+    pub qdrant_url: String,
+    pub quickwit_url: String,
+    pub qdrant_api_key: String,
+    // Number of indexing runs allowed to execute concurrently.
+    pub workers: usize,
+    // Number of attempts a job gets before it is marked as failed.
+    pub max_attempts: u32,
+    pub embedder: EmbedderConfig,
+    pub lexical_index: LexicalIndexConfig,
+    pub vector_store: VectorStoreConfig,
+}
+
+struct JobQueue {
+    config: JobQueueConfig,
+    sender: UnboundedSender<String>,
+    // Abort handles of the running jobs, by task id.
+    running: Mutex<HashMap<String, AbortHandle>>,
+}
+
+static JOB_QUEUE: OnceCell<Arc<JobQueue>> = OnceCell::new();
+
+/// Starts the worker pool and queues the jobs left over from a previous run.
+pub fn start_job_queue(config: JobQueueConfig) {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    let workers = config.workers.max(1);
+    let queue = Arc::new(JobQueue {
+        config,
+        sender,
+        running: Mutex::new(HashMap::new()),
+    });
+
+    if JOB_QUEUE.set(queue.clone()).is_err() {
+        warn!("Job queue already started");
+        return;
+    }
+
+    let receiver = Arc::new(tokio::sync::Mutex::new(receiver));
+    for worker_id in 0..workers {
+        tokio::spawn(run_worker(worker_id, queue.clone(), receiver.clone()));
+    }
+    info!("Started job queue with {} workers", workers);
+
+    // Resume the jobs that were queued or running when the service stopped, oldest first.
+    let mut pending = list_process_states()
+        .into_iter()
+        .filter(|(_, state)| {
+            state.task_status == CodeIndexingTaskStatus::Queued && state.job.is_some()
+        })
+        .collect::<Vec<_>>();
+    pending.sort_by_key(|(_, state)| state.created_at);
+    for (task_id, _) in pending {
+        info!("Resuming queued job {}", task_id);
+        let _ = queue.sender.send(task_id);
+    }
+}
+
+/// Queues a new indexing job and returns its task id.
+pub fn submit_job(job: IndexingJob) -> anyhow::Result<String> {
+    let queue = JOB_QUEUE
+        .get()
+        .ok_or_else(|| anyhow::anyhow!("Job queue is not started"))?;
+
+    let task_id = uuid::Uuid::new_v4().to_string();
+    queue_job(&task_id, &job);
+    queue.sender.send(task_id.clone())?;
+    Ok(task_id)
+}
+
+/// The settings of the running worker pool, used to reach the same backends outside of a job.
+pub fn queue_config() -> anyhow::Result<JobQueueConfig> {
+    JOB_QUEUE
+        .get()
+        .map(|queue| queue.config.clone())
+        .ok_or_else(|| anyhow::anyhow!("Job queue is not started"))
+}
+
+/// Cancels a queued or running job. Running jobs are aborted at their next await point, so the
+/// data indexed so far is kept. Returns false if there is no unfinished job with this id.
+pub fn cancel_job(task_id: &str) -> bool {
+    if !cancel_process(task_id) {
+        return false;
+    }
+
+    if let Some(queue) = JOB_QUEUE.get() {
+        if let Some(handle) = queue.running.lock().unwrap().remove(task_id) {
+            info!("Aborting running job {}", task_id);
+            handle.abort();
+        }
+    }
+    true
+}
+
+/// Stops everything writing to the indexes of the repo: its watcher, and its queued and running
+/// processes, cancelled like with `cancel_job`. Returns the ids of the cancelled processes.
+pub fn stop_repo_indexing(repo_name: &str) -> Vec<String> {
+    if stop_watch(repo_name).is_some() {
+        info!("Stopped watching {}", repo_name);
+    }
+    list_process_states()
+        .into_iter()
+        .filter(|(_, state)| state.repo_name == repo_name && !state.task_status.is_finished())
+        .map(|(task_id, _)| task_id)
+        .filter(|task_id| cancel_job(task_id))
+        .collect()
+}
+
+async fn run_worker(
+    worker_id: usize,
+    queue: Arc<JobQueue>,
+    receiver: Arc<tokio::sync::Mutex<UnboundedReceiver<String>>>,
+) {
+    loop {
+        let task_id = match receiver.lock().await.recv().await {
+            Some(task_id) => task_id,
+            None => break,
+        };
+
+        // the job may have been cancelled while it was waiting in the queue.
+        let Some(job) = get_process_state(&task_id)
+            .filter(|state| state.task_status == CodeIndexingTaskStatus::Queued)
+            .and_then(|state| state.job)
+        else {
+            continue;
+        };
+
+        info!("Worker {} picked up job {}", worker_id, task_id);
+        run_job(&queue, &task_id, job).await;
+    }
+}
+
+async fn run_job(queue: &Arc<JobQueue>, task_id: &str, job: IndexingJob) {
+    let attempt = start_process_attempt(task_id).unwrap_or(1);
+
+    let config = job.config(&queue.config);
+
+    let (abort_handle, abort_registration) = AbortHandle::new_pair();
+    queue
+        .running
+        .lock()
+        .unwrap()
+        .insert(task_id.to_string(), abort_handle);
+
+    // the runs of a watcher and of the other jobs of the repo write to the same indexes.
+    let indexing = async {
+        while let Some(running) = try_start_process(task_id, &job.repo_name) {
+            debug!("Job {} waits for {} to index {}", task_id, running, job.repo_name);
+            tokio::time::sleep(WAIT_INTERVAL).await;
+        }
+        Indexer
+            .index_repository(
+                PathBuf::from(&job.repo_path),
+                job.repo_name.clone(),
+                config,
+                &job.branch,
+                &job.version,
+                task_id.to_string(),
+            )
+            .await
+    };
+    let result = Abortable::new(indexing, abort_registration).await;
+
+    queue.running.lock().unwrap().remove(task_id);
+
+    match result {
+        Ok(Ok(())) => info!("Job {} completed", task_id),
+        Ok(Err(e)) => {
+            let retry = attempt < queue.config.max_attempts;
+            error!(
+                "Job {} failed on attempt {}/{}: {:?}",
+                task_id, attempt, queue.config.max_attempts, e
+            );
+            record_process_failure(task_id, &format!("{:#}", e), retry);
+
+            if retry {
+                let sender = queue.sender.clone();
+                let task_id = task_id.to_string();
+                tokio::spawn(async move {
+                    tokio::time::sleep(RETRY_BACKOFF * attempt).await;
+                    let _ = sender.send(task_id);
+                });
+            }
+        }
+        Err(_aborted) => {
+            info!("Job {} was cancelled", task_id);
+            update_process_state(task_id, 0, CodeIndexingTaskStatus::Cancelled);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{active_process, try_claim_repo};
+    use crate::watch::{list_watches, start_watch, DEFAULT_DEBOUNCE};
+
+    #[tokio::test]
+    async fn test_stop_repo_indexing() {
+        let dir = std::env::temp_dir().join(format!("ingestion-jobs-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::env::set_var("INGESTION_STATE_DIR", dir.join("state"));
+        let repo_name = format!("v2/owner/{}", uuid::Uuid::new_v4());
+        let repo_path = dir.to_string_lossy().to_string();
+
+        let job = IndexingJob {
+            repo_name: repo_name.clone(),
+            repo_path: repo_path.clone(),
+            branch: "main".to_string(),
+            version: "v2".to_string(),
+            incremental: false,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            chunking: ChunkingStrategy::default(),
+            source: RepoSource::WorkingTree,
+            index_commits: false,
+        };
+        let queued = uuid::Uuid::new_v4().to_string();
+        queue_job(&queued, &job);
+        let running = uuid::Uuid::new_v4().to_string();
+        assert_eq!(try_claim_repo(&running, &repo_name, &repo_path), None);
+        // the watcher task doesn't run before the test yields.
+        let config = Config::new(
+            repo_name.clone(),
+            repo_path.clone(),
+            String::new(),
+            String::new(),
+            String::new(),
+            "main".to_string(),
+            "v2".to_string(),
+            false,
+        )
+        .with_source(RepoSource::WorkingTree);
+        start_watch(config, DEFAULT_DEBOUNCE).unwrap();
+
+        let mut cancelled = stop_repo_indexing(&repo_name);
+        cancelled.sort();
+        let mut expected = vec![queued, running];
+        expected.sort();
+        assert_eq!(cancelled, expected);
+        assert_eq!(active_process(&repo_name), None);
+        assert!(list_watches()
+            .iter()
+            .all(|watched| watched.repo_name != repo_name));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
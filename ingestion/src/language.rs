@@ -0,0 +1,280 @@
+use common::ast::language_support::{Language, TSLanguage, ALL_LANGUAGES};
+use hyperpolyglot::detect_buffer;
+use std::io::Cursor;
+use std::path::Path;
+
+// Number of bytes looked at to tell binary files apart.
+const BINARY_SNIFF_LEN: usize = 8 * 1024;
+// Vim and Emacs only look for modelines in the first and last few lines.
+const MODELINE_LINES: usize = 5;
+
+// A format without a tree-sitter grammar. Its files are still indexed for lexical and semantic
+// search, just without scope graphs.
+struct TextLanguage {
+    language_id: &'static str,
+    file_extensions: &'static [&'static str],
+    // Files recognized by their whole name, or by `<name>.<suffix>` like `Dockerfile.dev`.
+    file_names: &'static [&'static str],
+}
+
+const TEXT_LANGUAGES: &[TextLanguage] = &[
+    TextLanguage {
+        language_id: "Markdown",
+        file_extensions: &["md", "markdown", "mdx"],
+        file_names: &[],
+    },
+    TextLanguage {
+        language_id: "YAML",
+        file_extensions: &["yml", "yaml"],
+        file_names: &[],
+    },
+    TextLanguage {
+        language_id: "TOML",
+        file_extensions: &["toml"],
+        file_names: &["Cargo.lock", "Pipfile"],
+    },
+    TextLanguage {
+        language_id: "JSON",
+        file_extensions: &["json"],
+        file_names: &[],
+    },
+    TextLanguage {
+        language_id: "Dockerfile",
+        file_extensions: &["dockerfile"],
+        file_names: &["Dockerfile", "Containerfile"],
+    },
+    TextLanguage {
+        language_id: "Makefile",
+        file_extensions: &["mk"],
+        file_names: &["Makefile", "GNUmakefile", "makefile"],
+    },
+    TextLanguage {
+        language_id: "Shell",
+        file_extensions: &["sh", "bash", "zsh"],
+        file_names: &[],
+    },
+    TextLanguage {
+        language_id: "SQL",
+        file_extensions: &["sql"],
+        file_names: &[],
+    },
+    TextLanguage {
+        language_id: "INI",
+        file_extensions: &["ini", "cfg"],
+        file_names: &[],
+    },
+    TextLanguage {
+        language_id: "Text",
+        file_extensions: &["txt", "rst"],
+        file_names: &["LICENSE", "CODEOWNERS"],
+    },
+];
+
+// Names used by shebangs and modelines that aren't a language id.
+const LANGUAGE_ALIASES: &[(&str, &str)] = &[
+    ("py", "Python"),
+    ("node", "JavaScript"),
+    ("nodejs", "JavaScript"),
+    ("js", "JavaScript"),
+    ("deno", "TypeScript"),
+    ("ts-node", "TypeScript"),
+    ("ts", "TypeScript"),
+    ("rb", "Ruby"),
+    ("rscript", "R"),
+    ("cs", "C#"),
+    ("csharp", "C#"),
+    ("cpp", "C++"),
+    ("golang", "Go"),
+    ("sh", "Shell"),
+    ("bash", "Shell"),
+    ("zsh", "Shell"),
+    ("dash", "Shell"),
+    ("ksh", "Shell"),
+    ("md", "Markdown"),
+    ("yml", "YAML"),
+    ("make", "Makefile"),
+    ("conf", "INI"),
+];
+
+/// Detects the language of a file, None for binary files and the ones that can't be classified.
+///
+/// An explicit modeline wins, then the tree-sitter languages and the text formats are matched on
+/// the file name, then the interpreter of the shebang. Linguist's heuristics classify the rest.
+pub fn detect_language(path: &Path, buf: &[u8]) -> Option<&'static str> {
+    if is_binary(buf) {
+        return None;
+    }
+
+    let head = String::from_utf8_lossy(&buf[..buf.len().min(BINARY_SNIFF_LEN)]);
+    modeline_language(&head)
+        .or_else(|| language_from_path(path))
+        .or_else(|| shebang_language(&head))
+        .or_else(|| {
+            detect_buffer(path, |_| Ok(Cursor::new(buf)))
+                .ok()
+                .flatten()
+                .map(|detection| detection.language())
+        })
+}
+
+fn is_binary(buf: &[u8]) -> bool {
+    buf[..buf.len().min(BINARY_SNIFF_LEN)].contains(&0)
+}
+
+fn language_from_path(path: &Path) -> Option<&'static str> {
+    let file_name = path.file_name()?.to_str()?;
+    let extension = path.extension().and_then(|ext| ext.to_str());
+
+    if let Some(extension) = extension {
+        if let Language::Supported(config) = TSLanguage::from_extension(extension) {
+            return config.language_ids.first().copied();
+        }
+    }
+
+    TEXT_LANGUAGES
+        .iter()
+        .find(|language| {
+            language.file_names.iter().any(|name| {
+                file_name == *name
+                    || file_name
+                        .strip_prefix(name)
+                        .map_or(false, |rest| rest.starts_with('.'))
+            }) || extension.map_or(false, |extension| {
+                language
+                    .file_extensions
+                    .iter()
+                    .any(|ext| ext.eq_ignore_ascii_case(extension))
+            })
+        })
+        .map(|language| language.language_id)
+}
+
+// Resolves the name of a language as written in a shebang or a modeline.
+fn language_from_name(name: &str) -> Option<&'static str> {
+    let name = name.trim().to_ascii_lowercase();
+    ALL_LANGUAGES
+        .iter()
+        .flat_map(|config| config.language_ids.iter().copied())
+        .chain(TEXT_LANGUAGES.iter().map(|language| language.language_id))
+        .find(|id| id.to_ascii_lowercase() == name)
+        .or_else(|| {
+            LANGUAGE_ALIASES
+                .iter()
+                .find(|(alias, _)| *alias == name)
+                .map(|(_, id)| *id)
+        })
+}
+
+// `#!/usr/bin/python3`, `#!/usr/bin/env -S node --flag`, ...
+fn shebang_language(head: &str) -> Option<&'static str> {
+    let line = head.lines().next()?.strip_prefix("#!")?;
+    let mut words = line.split_whitespace();
+    let mut interpreter = words.next()?.rsplit('/').next()?;
+    if interpreter == "env" {
+        interpreter = words.find(|word| !word.starts_with('-'))?;
+    }
+    // python3.11 -> python
+    let interpreter = interpreter.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    language_from_name(interpreter)
+}
+
+// `vim: set ft=python:`, `vi: filetype=ruby`, `-*- mode: yaml -*-` or `-*- python -*-`.
+fn modeline_language(head: &str) -> Option<&'static str> {
+    let lines = head.lines().collect::<Vec<_>>();
+    let first = lines.iter().take(MODELINE_LINES);
+    let last = lines.iter().rev().take(MODELINE_LINES);
+    first
+        .chain(last)
+        .find_map(|line| vim_modeline(line).or_else(|| emacs_modeline(line)))
+        .and_then(language_from_name)
+}
+
+fn vim_modeline(line: &str) -> Option<&str> {
+    let start = ["vim:", "vi:", "ex:"]
+        .iter()
+        .find_map(|marker| line.find(marker).map(|i| i + marker.len()))?;
+    line[start..]
+        .split(|c: char| c == ':' || c.is_whitespace())
+        .find_map(|option| {
+            option
+                .strip_prefix("ft=")
+                .or_else(|| option.strip_prefix("filetype="))
+                .or_else(|| option.strip_prefix("syntax="))
+        })
+}
+
+fn emacs_modeline(line: &str) -> Option<&str> {
+    let start = line.find("-*-")? + 3;
+    let end = start + line[start..].find("-*-")?;
+    let variables = line[start..end].trim();
+    if !variables.contains(':') {
+        return Some(variables);
+    }
+    variables.split(';').find_map(|variable| {
+        let (key, value) = variable.split_once(':')?;
+        key.trim()
+            .eq_ignore_ascii_case("mode")
+            .then_some(value.trim())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detect(path: &str, content: &str) -> Option<&'static str> {
+        detect_language(Path::new(path), content.as_bytes())
+    }
+
+    #[test]
+    fn test_detect_language() {
+        // tree-sitter languages by extension.
+        assert_eq!(detect("src/main.rs", "fn main() {}\n"), Some("Rust"));
+        assert_eq!(detect("app/index.tsx", ""), Some("TypeScript"));
+        assert_eq!(detect("analysis/plot.R", ""), Some("R"));
+
+        // text formats by extension or file name.
+        assert_eq!(detect("docs/README.md", "# Title\n"), Some("Markdown"));
+        assert_eq!(
+            detect(".github/workflows/ci.yml", "on: push\n"),
+            Some("YAML")
+        );
+        assert_eq!(detect("Cargo.toml", "[package]\n"), Some("TOML"));
+        assert_eq!(
+            detect("docker/Dockerfile", "FROM rust\n"),
+            Some("Dockerfile")
+        );
+        assert_eq!(detect("Dockerfile.dev", "FROM rust\n"), Some("Dockerfile"));
+
+        // shebangs.
+        assert_eq!(
+            detect("bin/tool", "#!/usr/bin/env python3.11\n"),
+            Some("Python")
+        );
+        assert_eq!(
+            detect("bin/serve", "#!/usr/bin/env -S node --inspect\n"),
+            Some("JavaScript")
+        );
+        assert_eq!(
+            detect("scripts/setup", "#!/bin/bash\nset -e\n"),
+            Some("Shell")
+        );
+
+        // modelines override the file name.
+        assert_eq!(
+            detect("conf/app.conf", "# vim: set ft=yaml:\nkey: value\n"),
+            Some("YAML")
+        );
+        assert_eq!(
+            detect("build", "# -*- mode: python; indent-tabs-mode: nil -*-\n"),
+            Some("Python")
+        );
+        assert_eq!(detect("notes", "-*- markdown -*-\n"), Some("Markdown"));
+
+        // binary files are never indexed.
+        assert_eq!(
+            detect_language(Path::new("logo.md"), b"\x89PNG\r\n\x1a\n\0\0"),
+            None
+        );
+    }
+}
@@ -1,40 +1,60 @@
-use anyhow::anyhow;
+use anyhow::{anyhow, Context};
+use common::ast::ast_graph::NamedScope;
 use common::ast::symbol::{SymbolKey, SymbolLocations, SymbolValue};
 use common::ast::CodeFileAST;
 use serde::Serialize;
 use std::collections::HashMap;
 use std::error::Error;
-use std::path::PathBuf;
-use std::sync::{Arc, Mutex};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
 use std::time::{Duration, Instant};
 use std::{any, fmt};
 use tokio;
 
 use log::{debug, error, info};
 
-mod generate_index_schema;
+pub mod gc;
 mod hash;
+mod history;
+pub mod hooks;
+pub mod ignore_rules;
+mod incremental;
 mod index_filter;
 mod index_processor;
+pub mod jobs;
+mod language;
+pub mod migration;
+pub mod progress;
+pub mod registry;
 mod semantic_index;
+pub mod source;
 mod stack_graph;
 pub mod state;
+#[cfg(test)]
+mod test_util;
 mod util;
+pub mod watch;
 
 extern crate git2;
 
+use crate::progress::{IndexingPhase, ProgressReporter};
+use crate::registry::IndexStats;
+pub use crate::semantic_index::ChunkingStrategy;
 use crate::semantic_index::{SemanticError, SemanticIndex};
 use crate::state::{update_process_state, CodeIndexingTaskStatus};
+use common::embedding::{Embedder, EmbedderConfig};
+use common::lexical::{LexicalDocument, LexicalIndex, LexicalIndexConfig};
+use common::models::IndexEvent;
+use common::vector::{VectorStore, VectorStoreConfig};
 use hash::compute_hashes;
-use index_filter::index_filter;
+use history::{CommitRecord, History};
+use ignore_rules::{IgnoreRules, SkipReason, SkippedPath};
+use incremental::ChangeSet;
+pub use source::RepoSource;
+use source::SourceFile;
 
 use git2::{ObjectType, Repository as GitRepository};
 use md5::compute;
-use qdrant_client::prelude::QdrantClient;
-use qdrant_client::qdrant::CollectionOperationResponse;
-use qdrant_client::qdrant::{
-    vectors_config, CreateCollection, Distance, FieldType, VectorParams, VectorsConfig,
-};
 use std::collections::HashSet;
 
 // Enum to represent the file type
@@ -48,9 +68,13 @@ enum FileType {
 pub const AVG_LINE_LEN: u64 = 30;
 pub const MAX_LINE_COUNT: u64 = 20000;
 pub const MAX_FILE_LEN: u64 = AVG_LINE_LEN * MAX_LINE_COUNT;
+// Number of parsed files whose symbols are committed together, which bounds the symbols held in
+// memory during a run.
+const SYMBOL_BATCH_FILES: usize = 256;
+// Files of this language are split into sections and go to the docs collection.
+const DOCS_LANGUAGE: &str = "Markdown";
 // const COLLECTION_NAME: &str = "documents";
 // const COLLECTION_NAME_SYMBOLS: &str = "documents_symbol";
-const EMBEDDING_DIM: usize = 384;
 // const BRANCH_REF_STR: &str = "refs/heads/{}";
 // data structure to represent a repository  file or directory or other.
 #[derive(Clone)]
@@ -88,22 +112,6 @@ pub struct CodeFile {
     pub language: String,
 }
 
-#[derive(Debug, Clone, Serialize)]
-pub struct FileFields {
-    repo_name: String,
-    repo_disk_path: String,
-    repo_ref: String,
-    relative_path: String,
-    last_commit: String,
-    lang: String,
-    is_directory: bool,
-    avg_line_length: f64,
-    line_end_indices: Vec<u8>,
-    content: String,
-    symbol_locations: Vec<u8>,
-    unique_hash: String,
-    symbols: String,
-}
 // Implement the Display trait for FileType.
 // This allows us to print out the file type in a human-readable format.
 impl fmt::Display for FileType {
@@ -135,15 +143,35 @@ type Result<T> = anyhow::Result<T, anyhow::Error>;
 pub struct Repository {
     disk_path: PathBuf,
     repo_name: String,
-    git_repo: Arc<Mutex<GitRepository>>,
+    // Only opened for the git sources.
+    git_repo: Option<Arc<Mutex<GitRepository>>>,
     file_entries: HashMap<String, EntryData>, // The file_entries HashMap
     repo_entries: Vec<RepoEntry>,             // The repo_entries Vec
-    qdrant_client_code_chunk: Option<QdrantClient>,
-    qdrant_client_symbol: Option<QdrantClient>,
+    // The vector store backend, built from `config.vector_store` at the start of an indexing run.
+    vector_store: Option<Arc<dyn VectorStore>>,
+    // The embedding backend, loaded from `config.embedder` at the start of an indexing run.
+    embedder: Option<Arc<dyn Embedder>>,
+    // The lexical index backend, built from `config.lexical_index` at the start of an indexing run.
+    lexical_index: Option<Arc<dyn LexicalIndex>>,
     semantic_payloads: Vec<SemanticPayload>,
-    symbol_meta_payload: HashMap<SymbolKey, Vec<SymbolValue>>,
+    symbol_batch: SymbolBatch,
+    // Number of symbol occurrences committed by the run.
+    symbol_count: usize,
     config: Config,
+    // The branch or tag being indexed and the commit it resolved to.
     branch: String,
+    head_commit: String,
+    // The history of the ref up to `head_commit`, empty for the sources without git history.
+    history: History,
+    progress: ProgressReporter,
+}
+
+// The symbols of the files parsed since the last symbol commit.
+#[derive(Default)]
+struct SymbolBatch {
+    symbols: HashMap<SymbolKey, Vec<SymbolValue>>,
+    // The files of the batch, with or without symbols.
+    paths: HashSet<String>,
 }
 
 pub struct SemanticPayload {
@@ -151,6 +179,8 @@ pub struct SemanticPayload {
     buffer: String,
     semantic_hash: String,
     language: String,
+    // The named scopes of the file, only collected for the syntax chunking strategy.
+    scopes: Vec<NamedScope>,
 }
 
 #[derive(Clone)]
@@ -232,48 +262,15 @@ impl From<git2::Error> for RepositoryError {
     }
 }
 
-struct GitContent {
-    path: String,
-    content: Vec<u8>,
-}
-
 impl Repository {
-    pub fn collection_config(collection_name: String) -> CreateCollection {
-        CreateCollection {
-            collection_name: collection_name,
-            vectors_config: Some(VectorsConfig {
-                config: Some(vectors_config::Config::Params(VectorParams {
-                    size: EMBEDDING_DIM as u64,
-                    distance: Distance::Cosine.into(),
-                    ..Default::default()
-                })),
-            }),
-            ..Default::default()
-        }
-    }
-
-    async fn make_client(&self) -> Result<QdrantClient> {
-        // Assuming YourErrorType is the type of error returned by QdrantClient build process
-        let client = if self.config.qdrant_url.contains("localhost") {
-            QdrantClient::from_url("http://localhost:6334").build()?
-        } else {
-            QdrantClient::from_url(&self.config.qdrant_url)
-                // using an env variable for the API KEY, for example
-                .with_api_key(self.config.qdrant_api_key.as_str())
-                .build()?
-        };
-
-        Ok(client)
-    }
-
-    // Note: Changed from &self to no self argument.
-    async fn init_qdrant_client(
+    // The collection is created with the dimension of the embedder, an existing collection has to match it.
+    async fn init_collection(
         &self,
-        _qdrant_url: &str,
         collection_name: &str,
-        indexes: Vec<String>,
-    ) -> Result<QdrantClient> {
-        let qdrant = self.make_client().await?;
+        indexes: &[String],
+        dimension: usize,
+    ) -> Result<()> {
+        let vector_store = self.vector_store()?;
 
         info!("Creating collection {}", collection_name);
 
@@ -281,45 +278,44 @@ impl Repository {
         let max_retries = 7;
 
         for attempt in 1..=max_retries {
-            match qdrant.has_collection(collection_name).await {
-                Ok(false) => {
+            match vector_store.collection_dimension(collection_name).await {
+                Ok(None) => {
                     info!("Collection {} does not exist, creating it", collection_name);
-                    match qdrant
-                        .create_collection(&Repository::collection_config(
-                            collection_name.to_string(),
-                        ))
+                    match vector_store
+                        .create_collection(collection_name, dimension, indexes)
                         .await
                     {
-                        Ok(CollectionOperationResponse { result, time: _ }) => {
-                            assert!(result);
-                            break; // Break out of the loop if successful
-                        }
+                        Ok(()) => break, // Break out of the loop if successful
                         Err(e) => {
                             error!("Error creating collection: {:?}", e);
                             if attempt == max_retries {
-                                return Err(anyhow!(SemanticError::QdrantInitializationError));
+                                return Err(anyhow!(SemanticError::VectorStoreInitializationError));
                             }
                             tokio::time::sleep(Duration::from_secs(20)).await;
                         }
                     }
                 }
-                Ok(true) => break, // Collection already exists
+                Ok(Some(size)) if size != dimension => {
+                    return Err(anyhow!(
+                        "Collection {} holds {}-dimensional vectors but the {:?} embedder produces {} dimensions, delete the collection to re-index with this model",
+                        collection_name,
+                        size,
+                        self.config.embedder,
+                        dimension
+                    ));
+                }
+                // Collection already exists
+                Ok(Some(_)) => break,
                 Err(e) => {
                     error!("Error checking if collection exists: {:?}", e);
                     if attempt == max_retries {
-                        return Err(anyhow!(SemanticError::QdrantInitializationError));
+                        return Err(anyhow!(SemanticError::VectorStoreInitializationError));
                     }
                     tokio::time::sleep(Duration::from_secs(30)).await;
                 }
             }
         }
-        //iterate through the indexes and create field indexes
-        for index in indexes.iter() {
-            let _result = qdrant
-                .create_field_index(collection_name, index, FieldType::Text, None, None)
-                .await?;
-        }
-        Ok(qdrant)
+        Ok(())
     }
 
     // Note: Changed from &mut self to no self argument, and modified the return type.
@@ -338,85 +334,473 @@ impl Repository {
         // let _qdrant_url = env::var("QDRANT_URL").map_err(|e| e.to_string())?;
 
         // let indexes_symbols = vec!["repo_name".to_string(), "symbol".to_string()];
-        let git_repo = GitRepository::open(&disk_path)?;
-        let qdrant_client_chunks = None;
-        //Some(self.init_qdrant_client(&qdrant_url, COLLECTION_NAME, indexes_chunk).await?);
-        let qdrant_client_symbols = None;
-        //Some(
-        //self.init_qdrant_client(&qdrant_url, COLLECTION_NAME_SYMBOLS, indexes_symbols)
-        // .await?,
-        // );
+        let git_repo = if config.source.uses_git() {
+            Some(Arc::new(Mutex::new(GitRepository::open(&disk_path)?)))
+        } else {
+            None
+        };
 
         Ok(Self {
             disk_path,
             repo_name,
-            git_repo: Arc::new(Mutex::new(git_repo)),
+            git_repo,
             file_entries: HashMap::new(),
             repo_entries: Vec::new(),
-            qdrant_client_code_chunk: qdrant_client_chunks,
-            qdrant_client_symbol: qdrant_client_symbols,
+            vector_store: None,
+            embedder: None,
+            lexical_index: None,
             semantic_payloads: Vec::new(),
-            symbol_meta_payload: HashMap::new(),
+            symbol_batch: SymbolBatch::default(),
+            symbol_count: 0,
             config: config.clone(),
             branch: branch.to_string(),
+            head_commit: String::new(),
+            history: History::default(),
+            progress: ProgressReporter::disabled(),
         })
     }
 
-    fn collect_git_entries(&mut self, branch: &str) -> Result<Vec<GitContent>> {
-        info!("Collecting git entries for branch: {}", branch);
-        let mut file_blobs: Vec<GitContent> = Vec::new();
-        let git_repo = self.git_repo.lock().unwrap();
+    // The git repository of the git sources.
+    fn git_repo(&self) -> Result<MutexGuard<'_, GitRepository>> {
+        self.git_repo
+            .as_ref()
+            .map(|git_repo| git_repo.lock().unwrap())
+            .ok_or_else(|| anyhow!("The {:?} source has no git repository", self.config.source))
+    }
 
-        let branch_ref_str = format!("refs/heads/{}", branch);
-        let head_ref = git_repo.find_reference(&branch_ref_str)?;
-        let head_commit = git_repo.find_commit(head_ref.target().unwrap())?;
-        let tree = head_commit.tree()?;
+    // The vector store backend of the run.
+    fn vector_store(&self) -> Result<&Arc<dyn VectorStore>> {
+        self.vector_store
+            .as_ref()
+            .ok_or_else(|| anyhow!("The vector store is not initialized"))
+    }
 
-        tree.walk(git2::TreeWalkMode::PreOrder, |root, entry| {
-            if let Some(name) = entry.name() {
-                let path = format!("{}{}", root, name);
+    // The lexical index backend of the run.
+    fn lexical_index(&self) -> Result<&Arc<dyn LexicalIndex>> {
+        self.lexical_index
+            .as_ref()
+            .ok_or_else(|| anyhow!("The lexical index is not initialized"))
+    }
 
-                if !index_filter(&path) {
-                    debug!("Skipping file: {}", path);
-                    return git2::TreeWalkResult::Ok;
-                }
+    // Builds the backends of the run from the config and creates the collections of the repo.
+    async fn init_backends(&mut self) -> Result<()> {
+        let indexes_chunk = vec![
+            "repo_name".to_string(),
+            "content_hash".to_string(),
+            "relative_path".to_string(),
+        ];
+        let indexes_symbols = vec!["repo_name".to_string(), "symbol".to_string()];
 
-                let file_type = match entry.kind().unwrap() {
-                    git2::ObjectType::Tree => FileType::Dir,
-                    git2::ObjectType::Blob => FileType::File,
-                    _ => FileType::Other,
-                };
+        let (collection_name_chunks, collection_name_symbols) =
+            Indexer::collection_names(&self.repo_name);
+
+        let embedder = self.config.embedder.build().await?;
+        let dimension = embedder.dimension();
+        self.embedder = Some(embedder);
+        self.lexical_index = Some(self.config.lexical_index.build()?);
+        self.vector_store = Some(self.config.vector_store.build()?);
+        migration::check_schema(self.vector_store()?.as_ref(), &self.repo_name).await?;
+
+        self.init_collection(&collection_name_chunks, &indexes_chunk, dimension)
+            .await?;
+        self.init_collection(&collection_name_symbols, &indexes_symbols, dimension)
+            .await?;
+        self.init_collection(
+            &Indexer::docs_collection_name(&self.repo_name),
+            &indexes_chunk,
+            dimension,
+        )
+        .await?;
+        if self.config.index_commits {
+            let indexes_commits = vec!["repo_name".to_string(), "commit".to_string()];
+            self.init_collection(
+                &Indexer::commits_collection_name(&self.repo_name),
+                &indexes_commits,
+                dimension,
+            )
+            .await?;
+        }
 
-                let git_id = entry.id();
+        info!("done creating collections");
+        Ok(())
+    }
 
-                match file_type {
-                    FileType::Dir => {
-                        self.repo_entries.push(RepoEntry::Dir(CodeDir { path }));
-                    }
-                    FileType::File => {
-                        if let Ok(blob) = git_repo.find_blob(git_id) {
-                            let content_buffer = blob.content().to_vec();
-                            let path_buf = PathBuf::from(&path);
-
-                            file_blobs.push(GitContent {
-                                path: path.clone(),
-                                content: content_buffer,
-                            });
-                        }
-                    }
-                    FileType::Other => {
-                        self.repo_entries.push(RepoEntry::Other);
-                    }
+    // Reads the files of the configured source that pass the ignore rules.
+    fn collect_entries(&mut self, commit: &str) -> Result<Vec<SourceFile>> {
+        info!(
+            "Collecting {:?} entries for {} at {}",
+            self.config.source, self.branch, commit
+        );
+        let git_repo = self
+            .git_repo
+            .as_ref()
+            .map(|git_repo| git_repo.lock().unwrap());
+        let snapshot = source::read_snapshot(
+            self.config.source,
+            &self.disk_path,
+            git_repo.as_deref(),
+            commit,
+            &self.config.include,
+            &self.config.exclude,
+        )?;
+        drop(git_repo);
+
+        self.repo_entries.extend(
+            snapshot
+                .dirs
+                .into_iter()
+                .map(|path| RepoEntry::Dir(CodeDir { path })),
+        );
+
+        Ok(snapshot.files)
+    }
+
+    // Chunks, embeds and commits the collected semantic payloads: Markdown files are split into
+    // sections committed to the docs collection, the other files to the chunk collection. With
+    // `config.index_commits`, the commits of the history not indexed yet go to the commits
+    // collection.
+    async fn commit_semantic_payloads(
+        &mut self,
+        index: &SemanticIndex,
+        repo_name: &str,
+        collection_name_chunks: &str,
+        collection_name_docs: &str,
+    ) -> Result<()> {
+        let semantic_payloads = std::mem::take(&mut self.semantic_payloads);
+        let (docs, code): (Vec<_>, Vec<_>) = semantic_payloads
+            .iter()
+            .partition(|payload| payload.language == DOCS_LANGUAGE);
+        let vector_store = self.vector_store()?.clone();
+
+        let changed_code = self
+            .share_unchanged(vector_store.as_ref(), collection_name_chunks, code)
+            .await?;
+        let mut chunks = Vec::new();
+        for payload in changed_code.iter() {
+            debug!("Chunking {}", payload.path);
+            chunks.extend(index.chunk_file(
+                &payload.buffer,
+                repo_name,
+                &payload.path,
+                &payload.semantic_hash,
+                &payload.language,
+                &payload.scopes,
+            ));
+        }
+
+        let changed_docs = self
+            .share_unchanged(vector_store.as_ref(), collection_name_docs, docs)
+            .await?;
+        let mut sections = Vec::new();
+        for payload in changed_docs.iter() {
+            debug!("Splitting {} into sections", payload.path);
+            sections.extend(index.chunk_markdown(
+                &payload.buffer,
+                repo_name,
+                &payload.path,
+                &payload.semantic_hash,
+            ));
+        }
+        info!(
+            "Committing {} chunks for {} files and {} sections for {} docs, {} unchanged files skipped",
+            chunks.len(),
+            changed_code.len(),
+            sections.len(),
+            changed_docs.len(),
+            semantic_payloads.len() - changed_code.len() - changed_docs.len()
+        );
+
+        let collection_name_commits = Indexer::commits_collection_name(repo_name);
+        let history = std::mem::take(&mut self.history);
+        let commits = if self.config.index_commits {
+            self.share_indexed_commits(vector_store.as_ref(), &collection_name_commits, &history)
+                .await?
+        } else {
+            Vec::new()
+        };
+
+        // progress is counted in chunks, files can be split into very different numbers of them.
+        self.progress.start_phase(
+            IndexingPhase::ChunkEmbedding,
+            Some(chunks.len() + sections.len() + commits.len()),
+        );
+        if !chunks.is_empty() {
+            index
+                .commit_chunks(chunks, vector_store.as_ref(), &mut self.progress)
+                .await?;
+        }
+        if !sections.is_empty() {
+            index
+                .commit_docs(sections, vector_store.as_ref(), &mut self.progress)
+                .await?;
+        }
+        if !commits.is_empty() {
+            index
+                .commit_history(
+                    &collection_name_commits,
+                    repo_name,
+                    commits,
+                    vector_store.as_ref(),
+                    &mut self.progress,
+                )
+                .await?;
+        }
+        self.progress.finish_phase();
+        Ok(())
+    }
+
+    // Returns the commits of the history that aren't in the commits collection yet. The ones
+    // indexed from another ref get this ref added to their branches.
+    async fn share_indexed_commits<'h>(
+        &self,
+        vector_store: &dyn VectorStore,
+        collection_name: &str,
+        history: &'h History,
+    ) -> Result<Vec<&'h CommitRecord>> {
+        let ids = history
+            .commits
+            .iter()
+            .map(|commit| commit.commit.clone())
+            .collect::<Vec<_>>();
+        let existing = semantic_index::commit_branches(vector_store, collection_name, &ids).await?;
+        let (indexed, new): (Vec<_>, Vec<_>) = history
+            .commits
+            .iter()
+            .partition(|commit| existing.contains_key(&commit.commit));
+
+        for commit in indexed {
+            let mut branches = existing[&commit.commit].clone();
+            if branches.insert(self.branch.clone()) {
+                semantic_index::set_commit_branches(
+                    vector_store,
+                    collection_name,
+                    &commit.commit,
+                    &branches,
+                )
+                .await?;
+            }
+        }
+        info!(
+            "{} commits to index, {} already indexed",
+            new.len(),
+            history.commits.len() - new.len()
+        );
+        Ok(new)
+    }
+
+    // Returns the payloads whose semantic hash has no chunks in the collection yet. The chunks of
+    // the other ones are reused; if they were indexed from another branch, this branch is added
+    // to them.
+    async fn share_unchanged<'p>(
+        &self,
+        vector_store: &dyn VectorStore,
+        collection_name: &str,
+        payloads: Vec<&'p SemanticPayload>,
+    ) -> Result<Vec<&'p SemanticPayload>> {
+        let content_hashes = payloads
+            .iter()
+            .map(|payload| payload.semantic_hash.clone())
+            .collect::<Vec<_>>();
+        let existing =
+            semantic_index::chunk_branches(vector_store, collection_name, &content_hashes).await?;
+        let (unchanged, changed): (Vec<_>, Vec<_>) = payloads
+            .into_iter()
+            .partition(|payload| existing.contains_key(&payload.semantic_hash));
+
+        for payload in unchanged {
+            let mut branches = existing[&payload.semantic_hash].clone();
+            if branches.insert(self.branch.clone()) {
+                semantic_index::set_chunk_branches(
+                    vector_store,
+                    collection_name,
+                    &payload.semantic_hash,
+                    &branches,
+                )
+                .await?;
+            }
+        }
+        Ok(changed)
+    }
+
+    // Collects only the files that changed since the last indexed commit. The change sets of the
+    // sources read from disk list paths already checked against the ignore rules, their files are
+    // read from disk.
+    fn collect_changed_entries(&mut self, change_set: &ChangeSet) -> Result<Vec<SourceFile>> {
+        if self.config.source != RepoSource::Git {
+            let file_blobs = source::read_files(&self.disk_path, &change_set.changed);
+            info!("Changed file entries read from disk: {}", file_blobs.len());
+            return Ok(file_blobs);
+        }
+
+        info!(
+            "Collecting changed entries between {} and {}",
+            change_set.base_commit, change_set.head_commit
+        );
+        let mut file_blobs: Vec<SourceFile> = Vec::new();
+        let git_repo = self.git_repo()?;
+
+        let head_commit = git_repo.find_commit(git2::Oid::from_str(&change_set.head_commit)?)?;
+        let tree = head_commit.tree()?;
+        let rules = IgnoreRules::from_tree(
+            &git_repo,
+            &tree,
+            &self.disk_path,
+            &self.config.include,
+            &self.config.exclude,
+        )?;
+
+        for path in change_set.changed.iter() {
+            if let Err(reason) = rules.check(path, false) {
+                debug!("Skipping changed file {}: {:?}", path, reason);
+                continue;
+            }
+
+            let entry = match tree.get_path(std::path::Path::new(path)) {
+                Ok(entry) => entry,
+                Err(e) => {
+                    error!("Changed file {} not found in head tree: {}", path, e);
+                    continue;
                 }
+            };
+
+            if entry.kind() != Some(ObjectType::Blob) {
+                continue;
             }
-            git2::TreeWalkResult::Ok
-        })?;
 
-        info!("Git file entries: {}", file_blobs.len());
+            if let Ok(blob) = git_repo.find_blob(entry.id()) {
+                file_blobs.push(SourceFile {
+                    path: path.clone(),
+                    content: blob.content().to_vec(),
+                });
+            }
+        }
+
+        info!("Changed git file entries: {}", file_blobs.len());
 
         Ok(file_blobs)
     }
 
+    // Removes the chunks, doc sections, symbol occurrences and quickwit documents of the stale
+    // file versions.
+    async fn remove_stale_entries(
+        &self,
+        change_set: &ChangeSet,
+        collection_name_chunks: &str,
+        collection_name_symbols: &str,
+        version: &str,
+    ) -> Result<()> {
+        if change_set.stale.is_empty() {
+            return Ok(());
+        }
+
+        info!(
+            "Removing {} stale files from the indexes",
+            change_set.stale.len()
+        );
+
+        let semantic_hashes = change_set
+            .stale
+            .iter()
+            .map(|file| file.semantic_hash.clone())
+            .collect::<Vec<_>>();
+        let collection_name_docs = Indexer::docs_collection_name(&self.repo_name);
+        for collection_name in [collection_name_chunks, collection_name_docs.as_str()] {
+            semantic_index::release_chunks(
+                self.vector_store()?.as_ref(),
+                collection_name,
+                &semantic_hashes,
+                &self.branch,
+            )
+            .await?;
+        }
+
+        semantic_index::prune_symbol_occurrences(
+            self.vector_store()?.as_ref(),
+            collection_name_symbols,
+            &change_set.stale_paths(),
+            &self.branch,
+        )
+        .await?;
+
+        if version != "v3" {
+            let tantivy_hashes = change_set
+                .stale
+                .iter()
+                .map(|file| file.tantivy_hash.clone())
+                .collect::<Vec<_>>();
+            index_processor::delete_documents(
+                self.lexical_index()?,
+                &self.repo_name,
+                &tantivy_hashes,
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    // Commits the symbols of the current batch. A full run first drops the occurrences its files
+    // had on the ref, so that indexing the ref again replaces them instead of adding duplicates;
+    // incremental runs pruned the stale files already. Only the last batch reports its progress,
+    // the other ones are committed while parsing.
+    async fn commit_symbols(
+        &mut self,
+        index: &SemanticIndex,
+        collection_name_symbols: &str,
+        replace_ref: bool,
+        last: bool,
+    ) -> Result<()> {
+        let batch = std::mem::take(&mut self.symbol_batch);
+        let vector_store = self.vector_store()?.clone();
+        if replace_ref && !batch.paths.is_empty() {
+            semantic_index::prune_symbol_occurrences(
+                vector_store.as_ref(),
+                collection_name_symbols,
+                &batch.paths,
+                &self.branch,
+            )
+            .await?;
+        }
+
+        let occurrences = batch.symbols.values().map(Vec::len).sum::<usize>();
+        let mut batch_progress = ProgressReporter::disabled();
+        let progress = if last {
+            self.progress
+                .start_phase(IndexingPhase::SymbolEmbedding, Some(batch.symbols.len()));
+            &mut self.progress
+        } else {
+            &mut batch_progress
+        };
+        let metrics = index
+            .commit_symbol_metadata(&batch.symbols, vector_store.as_ref(), progress)
+            .await?;
+        if last {
+            self.progress.finish_phase();
+        }
+
+        self.symbol_count += occurrences;
+        debug!(
+            "Committed {} symbols of {} files, {} points embedded",
+            batch.symbols.len(),
+            batch.paths.len(),
+            metrics.points_upserted
+        );
+        Ok(())
+    }
+
+    // Counts the files and symbols processed by the last `traverse`.
+    fn index_stats(&self) -> IndexStats {
+        let mut stats = IndexStats::default();
+        for entry in &self.repo_entries {
+            if let RepoEntry::File(file) = entry {
+                stats.file_count += 1;
+                *stats.languages.entry(file.language.clone()).or_default() += 1;
+            }
+        }
+        stats.symbol_count = self.symbol_count;
+        stats
+    }
+
     pub async fn traverse(
         &mut self,
         repo_name: &str,
@@ -424,6 +808,7 @@ impl Repository {
         collection_name_chunks: String,
         collection_name_symbols: String,
         version: String,
+        change_set: Option<&ChangeSet>,
     ) -> Result<()> {
         info!("Traversing repo: {}", repo_name);
 
@@ -433,19 +818,56 @@ impl Repository {
         // Find the reference to the default branch
 
         // Create a Vec to store all the RepoEntry::File entries
-        let mut all_entries: Vec<FileFields> = Vec::new();
+        let mut all_entries: Vec<LexicalDocument> = Vec::new();
 
         #[cfg(feature = "stack_graph")]
-        // Suppoerted files for stack graph construction
-        let mut supported_files: HashSet<PathBuf> = HashSet::new();
+        // Files to build stack graphs for, by language.
+        let mut supported_files: HashMap<String, HashSet<PathBuf>> = HashMap::new();
 
         // Walk through the tree, visiting each entry in a pre-order traversal
         let counter = 0;
 
-        let branch = self.branch.clone();
-        let file_blobs = self.collect_git_entries(&branch)?;
+        let head_commit = self.head_commit.clone();
+        self.progress.start_phase(IndexingPhase::GitWalk, None);
+        let file_blobs = match change_set {
+            // Incremental run: drop what was indexed for the old versions, then only walk the changed files.
+            Some(change_set) => {
+                self.remove_stale_entries(
+                    change_set,
+                    &collection_name_chunks,
+                    &collection_name_symbols,
+                    &version,
+                )
+                .await?;
+                self.collect_changed_entries(change_set)?
+            }
+            None => self.collect_entries(&head_commit)?,
+        };
+        self.progress.set_total(file_blobs.len());
+        self.progress.finish_phase();
+
+        let embedder = self
+            .embedder
+            .clone()
+            .ok_or_else(|| anyhow!("The embedder is not initialized"))?;
+        let collection_name_docs = Indexer::docs_collection_name(repo_name);
+        let index = SemanticIndex::new(
+            &counter,
+            &collection_name_chunks,
+            &collection_name_symbols,
+            &collection_name_docs,
+            &self.branch,
+            embedder,
+            self.config.embedder.tokenizer_path(),
+        )?
+        .with_chunking(self.config.chunking);
+        let replace_ref = change_set.is_none();
+
+        self.progress
+            .start_phase(IndexingPhase::Parsing, Some(file_blobs.len()));
 
         for file_blob in file_blobs {
+            self.progress.advance(1);
             let path = file_blob.path.clone();
             let path_buf = PathBuf::from(&path);
             let content_buffer: &[u8] = &file_blob.content;
@@ -469,12 +891,14 @@ impl Repository {
                 .map(ToOwned::to_owned)
                 .unwrap_or(PathBuf::from(&path));
 
-            // Compute the semantic and tantivy hashes for the file. NOTE: "main" is hardcoded.
+            // Compute the semantic and tantivy hashes for the file.
+            // The tantivy hash includes the ref, so every indexed ref gets its own quickwit document.
             let (semantic_hash, tantivy_hash) =
                 compute_hashes(relative_path.clone(), &buffer, &self.branch);
 
-            // Detect the programming language of the file.
-            let language = util::detect_language(&path_buf, content_buffer.clone())
+            // Detect the language of the file. Text formats without a tree-sitter grammar, like
+            // Markdown or YAML, are indexed without a scope graph.
+            let language = language::detect_language(&path_buf, content_buffer)
                 .map(|s| s.to_string())
                 .unwrap_or("Unknown".to_string());
 
@@ -488,10 +912,13 @@ impl Repository {
 
             #[cfg(feature = "stack_graph")]
             // Add supported files to the `supported_files` HashSet to build stack-graph representation of the files later.
-            if ["Java", "Python", "Typescript", "Javascript"].contains(&language.as_str()) {
+            if stack_graph::graph::supports_language(&language) {
                 match fs::canonicalize(std::path::Path::new(&disk_path.join(&path_buf))) {
                     Ok(absolute_path) => {
-                        supported_files.insert(absolute_path);
+                        supported_files
+                            .entry(language.clone())
+                            .or_default()
+                            .insert(absolute_path);
                     }
                     Err(e) => {
                         // Handle the error, e.g., by logging or ignoring
@@ -512,6 +939,14 @@ impl Repository {
                 }
             };
 
+            // The named scopes align the chunks to functions and classes with the syntax strategy.
+            let scopes = match symbol_locations.scope_graph() {
+                Some(graph) if self.config.chunking == ChunkingStrategy::Syntax => {
+                    graph.named_scopes(content_buffer)
+                }
+                _ => Vec::new(),
+            };
+
             // Extract symbols from the syntax-aware representation.
             let symbols = symbol_locations
                 .list()
@@ -524,34 +959,39 @@ impl Repository {
 
             // Collect and aggregate metadata for each symbol in the file.
             // This is to utilize the symbols during code search and perform ranking.
-            self.symbol_meta_payload = symbol_locations
-                .list_metadata(content_buffer.clone(), repo_name, &language, &path)
-                .into_iter()
-                .fold(self.symbol_meta_payload.clone(), |mut meta_map, meta| {
-                    let meta_key = SymbolKey {
-                        symbol: meta.symbol_type.clone(),
-                        repo_name: meta.repo_name.clone(),
-                    };
-
-                    let meta_value = SymbolValue {
-                        symbol_type: meta.symbol.clone(),
-                        language_id: meta.language_id.clone(),
-                        relative_path: meta.relative_path.clone(),
-                        start_byte: meta.range.start.byte.clone(),
-                        end_byte: meta.range.end.byte.clone(),
-                        is_global: meta.is_global.clone(),
-                        node_kind: meta.node_kind.clone(),
-                    };
-
-                    meta_map
-                        .entry(meta_key)
-                        .or_insert_with(Vec::new)
-                        .push(meta_value);
-
-                    meta_map
-                });
+            for meta in
+                symbol_locations.list_metadata(content_buffer.clone(), repo_name, &language, &path)
+            {
+                let meta_key = SymbolKey {
+                    symbol: meta.symbol_type.clone(),
+                    repo_name: meta.repo_name.clone(),
+                };
+
+                let meta_value = SymbolValue {
+                    symbol_type: meta.symbol.clone(),
+                    language_id: meta.language_id.clone(),
+                    relative_path: meta.relative_path.clone(),
+                    start_byte: meta.range.start.byte.clone(),
+                    end_byte: meta.range.end.byte.clone(),
+                    is_global: meta.is_global.clone(),
+                    node_kind: meta.node_kind.clone(),
+                };
 
-            // debug!("Symbol Meta Payload: {:?}", self.symbol_meta_payload);
+                self.symbol_batch
+                    .symbols
+                    .entry(meta_key)
+                    .or_default()
+                    .push(meta_value);
+            }
+            self.symbol_batch.paths.insert(path.clone());
+
+            // a failed batch fails the run, so that its commit isn't recorded as indexed and the
+            // next run indexes the missing symbols.
+            if self.symbol_batch.paths.len() >= SYMBOL_BATCH_FILES {
+                self.commit_symbols(&index, &collection_name_symbols, replace_ref, false)
+                    .await
+                    .context("Failed to commit a batch of symbols")?;
+            }
 
             // Ensure the content ends with a newline.
             if !buffer.ends_with('\n') {
@@ -579,22 +1019,30 @@ impl Repository {
                     buffer: buffer.clone(),
                     semantic_hash: semantic_hash.clone(),
                     language: language.clone(),
+                    scopes,
                 });
             } else {
                 error!("Path is not valid UTF-8");
                 continue;
             }
 
+            // Files last modified before the walked history have no history.
+            let file_history = self.history.file(&path).cloned().unwrap_or_default();
+
             // Create a struct to store various fields about the file.
-            let file_fields = FileFields {
+            let file_fields = LexicalDocument {
                 repo_name: repo_name.to_string(),
                 // use the disk path of the repo.
                 repo_disk_path: disk_path.to_str().unwrap().to_owned()
                     + repo_name.to_string().as_str(),
-                repo_ref: String::new(),
+                repo_ref: repo_name.to_string(),
+                branches: vec![self.branch.clone()],
                 lang: language.clone(),
                 relative_path: path.clone(),
-                last_commit: String::new(),
+                last_commit: file_history.last_commit,
+                last_author: file_history.author,
+                last_commit_date: file_history.date,
+                churn: file_history.churn,
                 is_directory: false,
                 avg_line_length: lines_avg,
                 line_end_indices: line_end_indices.clone(),
@@ -619,7 +1067,12 @@ impl Repository {
 
         #[cfg(feature = "stack_graph")]
         // Creating the stack graph for the supported files
-        let _ = stack_graph::graph::index_files(supported_files.into_iter().collect(), "Python");
+        for (language, files) in supported_files {
+            if let Err(e) = stack_graph::graph::index_files(files.into_iter().collect(), &language)
+            {
+                error!("Failed to build the {} stack graphs: {:?}", language, e);
+            }
+        }
 
         //stopping the logging time for qdrant indexing
         let duration_processsing = start_processing.elapsed();
@@ -627,23 +1080,16 @@ impl Repository {
 
         //starting the logging time for qdrant indexing
         let start_qdrant = Instant::now();
-        let mut index =
-            SemanticIndex::new(&counter, &collection_name_chunks, &collection_name_symbols);
-        // send self.symbolMetaPayload to commit_symbol_metadata function to commit the metadata.
+        // the symbols of the last files parsed, the other batches were committed while parsing.
         debug!("Before commiting symbol meta payload");
-        let result = index
-            .commit_symbol_metadata(&self.symbol_meta_payload, &self.qdrant_client_symbol)
-            .await;
+        self.commit_symbols(&index, &collection_name_symbols, replace_ref, true)
+            .await
+            .context("Failed to commit the last batch of symbols")?;
         debug!("After commiting symbol meta payload");
-
-        if let Err(e) = result {
-            error!("Error: {:?}", e);
-        } else {
-            info!(
-                "Successfully committed symbol metadata: {:?}",
-                result.unwrap()
-            );
-        }
+        info!(
+            "Successfully committed metadata of {} symbols",
+            self.symbol_count
+        );
         //stopping the logging time for qdrant indexing
         let duration_qdrant = start_qdrant.elapsed();
         info!(
@@ -651,6 +1097,23 @@ impl Repository {
             duration_qdrant
         );
 
+        //starting the logging time for chunk indexing
+        let start_chunks = Instant::now();
+        self.commit_semantic_payloads(
+            &index,
+            repo_name,
+            &collection_name_chunks,
+            &collection_name_docs,
+        )
+        .await
+        .context("Failed to commit the code chunks")?;
+        //stopping the logging time for chunk indexing
+        let duration_chunks = start_chunks.elapsed();
+        info!(
+            "Time elapsed in commiting code chunks is: {:?}",
+            duration_chunks
+        );
+
         //starting the logging time for quickwit indexing
         let start_quickwit = Instant::now();
         info!("The current version is {}", version);
@@ -658,8 +1121,18 @@ impl Repository {
         match version {
             v if v != String::from("v3") => {
                 // index to quickwit
-                index_processor::process_entries(all_entries, repo_name, &self.config.quickwit_url)
-                    .await;
+                self.progress
+                    .start_phase(IndexingPhase::QuickwitUpload, Some(all_entries.len()));
+                let lexical_index = self.lexical_index()?.clone();
+                index_processor::process_entries(
+                    lexical_index.as_ref(),
+                    all_entries,
+                    repo_name,
+                    change_set.is_none().then_some(self.branch.as_str()),
+                    &mut self.progress,
+                )
+                .await?;
+                self.progress.finish_phase();
             }
             _ => {
                 info!(
@@ -681,6 +1154,15 @@ impl Repository {
 // Define a structure to represent an Indexer.
 pub struct Indexer;
 
+/// The paths of a branch that an indexing run would process, and the ones it would skip.
+#[derive(Debug, Clone, Serialize)]
+pub struct DryRunReport {
+    pub branch: String,
+    pub commit: String,
+    pub indexed: Vec<String>,
+    pub skipped: Vec<SkippedPath>,
+}
+
 impl Indexer {
     pub async fn index_repository(
         &self,
@@ -693,6 +1175,15 @@ impl Indexer {
     ) -> Result<()> {
         info!("Indexing repository: {}", repo_name);
         update_process_state(&task_id, 0, CodeIndexingTaskStatus::Running);
+        let source = config.source;
+        // only the commits of the git source can be diffed.
+        let incremental = config.incremental && source == RepoSource::Git;
+        if config.incremental && !incremental {
+            info!(
+                "Incremental indexing isn't supported for the {:?} source, indexing everything",
+                source
+            );
+        }
         // Create a new Repository instance using the `new` method.
         let mut repo = Repository::new(
             disk_path.clone(),
@@ -702,43 +1193,63 @@ impl Indexer {
         )
         .await?;
 
-        let indexes_chunk = vec![
-            "repo_name".to_string(),
-            "content_hash".to_string(),
-            "relative_path".to_string(),
-        ];
-        let indexes_symbols = vec!["repo_name".to_string(), "symbol".to_string()];
-
-        let collection_name_chunks =
-            format!("{}-documents", Self::generate_qdrant_index_name(&repo_name));
-        let collection_name_symbols = format!(
-            "{}-documents-symbols",
-            Self::generate_qdrant_index_name(&repo_name)
-        );
+        let head_commit = if source.uses_git() {
+            incremental::resolve_ref(&repo.git_repo()?, branch)?
+        } else {
+            String::new()
+        };
+        repo.head_commit = head_commit.clone();
 
-        info!("Sending data to qdrant for collection");
+        // For incremental runs, diff the branch head against the last indexed commit.
+        // Without a previous run (or if the diff fails) we fall back to indexing everything.
+        repo.progress = ProgressReporter::new(&task_id);
 
-        repo.qdrant_client_code_chunk = Some(
-            repo.init_qdrant_client(
-                &repo.config.qdrant_url,
-                &collection_name_chunks,
-                indexes_chunk,
-            )
-            .await?,
-        );
+        let change_set = match incremental::last_indexed_commit(&repo_name, branch) {
+            Some(last_commit) if incremental && last_commit == head_commit => {
+                info!(
+                    "{}@{} is already indexed at {}, nothing to do",
+                    repo_name, branch, head_commit
+                );
+                update_process_state(&task_id, 100, CodeIndexingTaskStatus::Completed);
+                return Ok(());
+            }
+            Some(last_commit) if incremental => {
+                let diff = incremental::diff_commits(
+                    &repo.git_repo()?,
+                    &last_commit,
+                    &head_commit,
+                    branch,
+                );
+                match diff {
+                    Ok(change_set) => Some(change_set),
+                    Err(e) => {
+                        error!(
+                            "Failed to diff {} against {}, re-indexing everything: {:?}",
+                            last_commit, head_commit, e
+                        );
+                        None
+                    }
+                }
+            }
+            _ => None,
+        };
 
-        info!("Sending data to qdrant for collection symbols");
+        if source.uses_git() {
+            // the history only adds metadata, the files are still indexed without it.
+            let history = history::read_history(
+                &repo.git_repo()?,
+                &head_commit,
+                history::HISTORY_MAX_COMMITS,
+            );
+            match history {
+                Ok(history) => repo.history = history,
+                Err(e) => error!("Failed to read the history of {}: {:?}", branch, e),
+            }
+        }
 
-        repo.qdrant_client_symbol = Some(
-            repo.init_qdrant_client(
-                &repo.config.qdrant_url,
-                &collection_name_symbols,
-                indexes_symbols,
-            )
-            .await?,
-        );
+        let (collection_name_chunks, collection_name_symbols) = Self::collection_names(&repo_name);
+        repo.init_backends().await?;
 
-        info!("done creating clients");
         // Call the traverse method to list the files in the repository.
         repo.traverse(
             &repo_name.clone(),
@@ -746,13 +1257,190 @@ impl Indexer {
             collection_name_chunks.clone(),
             collection_name_symbols.clone(),
             version.to_string(),
+            change_set.as_ref(),
+        )
+        .await?;
+
+        // the other sources don't match a commit, the next incremental run has to start over.
+        let recorded = match source {
+            RepoSource::Git => incremental::record_indexed_commit(&repo_name, branch, &head_commit),
+            _ => incremental::forget_indexed_commit(&repo_name, branch),
+        };
+        if let Err(e) = recorded {
+            error!("Failed to record indexed commit: {:?}", e);
+        }
+
+        if let Err(e) =
+            migration::record_schema(repo.vector_store()?.as_ref(), &repo_name, version != "v3")
+                .await
+        {
+            error!("Failed to record the schema of {}: {:?}", repo_name, e);
+        }
+
+        // incremental runs only processed the changed files, the registry keeps the full counts.
+        let stats = change_set.is_none().then(|| repo.index_stats());
+        let commit = source.uses_git().then_some(head_commit);
+        if let Err(e) = registry::record_indexing_run(&repo.config, commit.clone(), stats) {
+            error!(
+                "Failed to record {} in the repo registry: {:?}",
+                repo_name, e
+            );
+        }
+        hooks::notify(IndexEvent {
+            repo_name: repo_name.clone(),
+            branch: Some(branch.to_string()),
+            commit,
+        })
+        .await;
+
+        update_process_state(&task_id, 100, CodeIndexingTaskStatus::Completed);
+        Ok(())
+    }
+
+    // Indexes the files of a change set computed by the caller, see `watch`. The registry keeps
+    // the counts of the last full run and the history isn't read again, the documents of the
+    // changed files get no last commit until the next full run.
+    pub(crate) async fn index_changes(
+        &self,
+        disk_path: PathBuf,
+        repo_name: String,
+        config: Config,
+        change_set: &ChangeSet,
+        task_id: String,
+    ) -> Result<()> {
+        info!(
+            "Indexing {} changed and {} stale files of {}",
+            change_set.changed.len(),
+            change_set.stale.len(),
+            repo_name
+        );
+        update_process_state(&task_id, 0, CodeIndexingTaskStatus::Running);
+        let source = config.source;
+        let branch = config.branch.clone();
+        let version = config.version.clone();
+        let mut repo =
+            Repository::new(disk_path.clone(), repo_name.clone(), config, branch.clone()).await?;
+        if source.uses_git() {
+            repo.head_commit = incremental::resolve_ref(&repo.git_repo()?, &branch)?;
+        }
+        repo.progress = ProgressReporter::new(&task_id);
+
+        let (collection_name_chunks, collection_name_symbols) = Self::collection_names(&repo_name);
+        repo.init_backends().await?;
+        repo.traverse(
+            &repo_name,
+            disk_path,
+            collection_name_chunks,
+            collection_name_symbols,
+            version.clone(),
+            Some(change_set),
         )
         .await?;
 
+        if let Err(e) =
+            migration::record_schema(repo.vector_store()?.as_ref(), &repo_name, version != "v3")
+                .await
+        {
+            error!("Failed to record the schema of {}: {:?}", repo_name, e);
+        }
+        let commit = source.uses_git().then(|| repo.head_commit.clone());
+        if let Err(e) = registry::record_indexing_run(&repo.config, commit.clone(), None) {
+            error!(
+                "Failed to record {} in the repo registry: {:?}",
+                repo_name, e
+            );
+        }
+        hooks::notify(IndexEvent {
+            repo_name,
+            branch: Some(branch),
+            commit,
+        })
+        .await;
+
         update_process_state(&task_id, 100, CodeIndexingTaskStatus::Completed);
         Ok(())
     }
 
+    // Applies the ignore rules, size limit and language detection of an indexing run to the branch
+    // without indexing anything.
+    pub fn dry_run(&self, disk_path: PathBuf, config: &Config) -> Result<DryRunReport> {
+        let git_repo = if config.source.uses_git() {
+            Some(GitRepository::open(&disk_path)?)
+        } else {
+            None
+        };
+        let commit = match &git_repo {
+            Some(git_repo) => incremental::resolve_ref(git_repo, &config.branch)?,
+            None => String::new(),
+        };
+        let snapshot = source::read_snapshot(
+            config.source,
+            &disk_path,
+            git_repo.as_ref(),
+            &commit,
+            &config.include,
+            &config.exclude,
+        )?;
+
+        let mut indexed = Vec::new();
+        let mut skipped = snapshot.skipped;
+        for file in snapshot.files {
+            let reason = if file.content.len() > MAX_FILE_LEN as usize {
+                Some(SkipReason::TooLarge {
+                    size: file.content.len(),
+                })
+            } else if language::detect_language(Path::new(&file.path), &file.content).is_none() {
+                Some(SkipReason::UnknownLanguage)
+            } else {
+                None
+            };
+
+            match reason {
+                Some(reason) => skipped.push(SkippedPath {
+                    path: file.path,
+                    is_directory: false,
+                    reason,
+                }),
+                None => indexed.push(file.path),
+            }
+        }
+
+        info!(
+            "Dry run for {}@{}: {} files indexed, {} paths skipped",
+            config.repo_name,
+            config.branch,
+            indexed.len(),
+            skipped.len()
+        );
+
+        Ok(DryRunReport {
+            branch: config.branch.clone(),
+            commit,
+            indexed,
+            skipped,
+        })
+    }
+
+    // The names of the chunk and symbol collections of a repo.
+    pub fn collection_names(repo_name: &str) -> (String, String) {
+        let index_name = Self::generate_qdrant_index_name(repo_name);
+        (
+            format!("{}-documents", index_name),
+            format!("{}-documents-symbols", index_name),
+        )
+    }
+
+    // The name of the collection holding the Markdown sections of a repo, the search services
+    // derive it the same way.
+    pub fn docs_collection_name(repo_name: &str) -> String {
+        common::hasher::generate_docs_collection_name(repo_name)
+    }
+
+    // The name of the collection holding the commit messages of a repo.
+    pub fn commits_collection_name(repo_name: &str) -> String {
+        common::hasher::generate_commits_collection_name(repo_name)
+    }
+
     pub fn generate_qdrant_index_name(namespace: &str) -> String {
         let repo_name = namespace.split("/").last().unwrap();
         let version = namespace.split("/").nth(0).unwrap();
@@ -773,6 +1461,23 @@ pub struct Config {
     pub qdrant_api_key: String,
     pub branch: String,
     pub version: String,
+    // Only re-index the files that changed since the last indexed commit of the branch.
+    pub incremental: bool,
+    // Globs restricting the indexed paths, on top of the repo's ignore files.
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    // The model used to embed the chunks and symbols, it also sets the dimension of the collections.
+    pub embedder: EmbedderConfig,
+    // How the files are split into chunks before being embedded.
+    pub chunking: ChunkingStrategy,
+    // Where the files are read from, `repo_path` points to the archive for the archive source.
+    pub source: RepoSource,
+    // Where the file documents are stored, Quickwit at `quickwit_url` by default.
+    pub lexical_index: LexicalIndexConfig,
+    // Where the chunk and symbol vectors are stored, Qdrant at `qdrant_url` by default.
+    pub vector_store: VectorStoreConfig,
+    // Also embed the commit messages of the ref's history in the commits collection of the repo.
+    pub index_commits: bool,
 }
 
 impl Config {
@@ -784,7 +1489,13 @@ impl Config {
         qdrant_api_key: String,
         branch: String,
         version: String,
+        incremental: bool,
     ) -> Self {
+        let lexical_index = LexicalIndexConfig::Quickwit {
+            url: quickwit_url.clone(),
+        };
+        let (url, api_key) = Self::qdrant_connection(&qdrant_url, &qdrant_api_key);
+        let vector_store = VectorStoreConfig::Qdrant { url, api_key };
         Config {
             repo_name,
             repo_path,
@@ -793,6 +1504,76 @@ impl Config {
             qdrant_api_key,
             branch,
             version,
+            incremental,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            embedder: EmbedderConfig::default(),
+            chunking: ChunkingStrategy::default(),
+            source: RepoSource::default(),
+            lexical_index,
+            vector_store,
+            index_commits: false,
         }
     }
+
+    // A local Qdrant is reached on its gRPC port, without authentication.
+    fn qdrant_connection(qdrant_url: &str, qdrant_api_key: &str) -> (String, Option<String>) {
+        if qdrant_url.contains("localhost") {
+            ("http://localhost:6334".to_string(), None)
+        } else {
+            (qdrant_url.to_string(), Some(qdrant_api_key.to_string()))
+        }
+    }
+
+    // Reads the vector store backend from the environment, see `VectorStoreConfig::from_env`.
+    pub fn vector_store_from_env(
+        qdrant_url: &str,
+        qdrant_api_key: &str,
+    ) -> Result<VectorStoreConfig> {
+        let (url, api_key) = Self::qdrant_connection(qdrant_url, qdrant_api_key);
+        VectorStoreConfig::from_env(&url, api_key)
+    }
+
+    // Sets the include and exclude globs applied to the paths of the repo.
+    pub fn with_path_filters(mut self, include: Vec<String>, exclude: Vec<String>) -> Self {
+        self.include = include;
+        self.exclude = exclude;
+        self
+    }
+
+    // Sets the embedding backend used by the run.
+    pub fn with_embedder(mut self, embedder: EmbedderConfig) -> Self {
+        self.embedder = embedder;
+        self
+    }
+
+    // Sets the chunking strategy of the run.
+    pub fn with_chunking(mut self, chunking: ChunkingStrategy) -> Self {
+        self.chunking = chunking;
+        self
+    }
+
+    // Sets where the files of the repo are read from.
+    pub fn with_source(mut self, source: RepoSource) -> Self {
+        self.source = source;
+        self
+    }
+
+    // Sets the lexical index backend the file documents are stored in.
+    pub fn with_lexical_index(mut self, lexical_index: LexicalIndexConfig) -> Self {
+        self.lexical_index = lexical_index;
+        self
+    }
+
+    // Sets the vector store backend the chunks and symbols are stored in.
+    pub fn with_vector_store(mut self, vector_store: VectorStoreConfig) -> Self {
+        self.vector_store = vector_store;
+        self
+    }
+
+    // Sets whether the commit messages are indexed.
+    pub fn with_commit_history(mut self, index_commits: bool) -> Self {
+        self.index_commits = index_commits;
+        self
+    }
 }
@@ -16,6 +16,9 @@ async fn main() {
     {
         log::info!("CLI feature not enabled. Running in API mode...");
 
+        ingestion::hooks::warn_if_unset();
+        ingestion::jobs::start_job_queue(server::job_queue_config());
+
         let ingestion_routes = server::routes::ingestion();
         warp::serve(ingestion_routes)
             .run(([0, 0, 0, 0], 3001))
@@ -0,0 +1,404 @@
+use anyhow::{anyhow, bail, Result};
+use common::models::IndexEvent;
+use common::schema::{
+    self, repository_schema_version, IndexKind, SchemaRecord, LEGACY_SCHEMA_VERSION, SCHEMA_VERSION,
+};
+use common::vector::{PointPayload, PointSelector, VectorFilter, VectorStore};
+use log::{error, info};
+use serde::Serialize;
+use serde_json::Value;
+use std::path::PathBuf;
+
+use crate::index_processor::generate_quikwit_index_name;
+use crate::state::{queue_process, record_process_failure};
+use crate::{gc, hooks, registry, Config, Indexer};
+
+// Number of points whose payload is rewritten per request.
+const REWRITE_BATCH_SIZE: usize = 64;
+const SCROLL_PAGE_SIZE: u32 = 256;
+
+/// How the indexes written before a schema change are brought up to date.
+#[derive(Clone, Copy, Debug, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Strategy {
+    /// The payloads are rewritten in place.
+    Rewrite,
+    /// The indexes are dropped and every ref recorded in the registry is indexed again.
+    Rebuild,
+}
+
+/// A schema change, from `from` to the next version.
+#[derive(Clone, Copy, Debug, Serialize, PartialEq)]
+pub struct Migration {
+    pub from: u32,
+    pub description: &'static str,
+    pub strategy: Strategy,
+}
+
+// One entry per schema version, see `common::schema` for what each version holds.
+const MIGRATIONS: [Migration; 4] = [
+    Migration {
+        from: 1,
+        description: "record the refs of the chunks, symbol occurrences and documents",
+        strategy: Strategy::Rebuild,
+    },
+    Migration {
+        from: 2,
+        description: "add an empty scope path to the chunks",
+        strategy: Strategy::Rewrite,
+    },
+    // the lexical mappings can't gain fields in place.
+    Migration {
+        from: 3,
+        description: "record the last commit, author, date and churn of the documents",
+        strategy: Strategy::Rebuild,
+    },
+    Migration {
+        from: 4,
+        description: "record the refs of the symbol occurrences under `branches`",
+        strategy: Strategy::Rewrite,
+    },
+];
+
+/// What `migrate_repository` did, or would do on a dry run.
+#[derive(Clone, Debug, Serialize)]
+pub struct MigrationReport {
+    pub repo_name: String,
+    pub from: u32,
+    pub to: u32,
+    pub migrations: Vec<Migration>,
+    // Whether the indexes were rebuilt rather than rewritten.
+    pub rebuild: bool,
+    pub dry_run: bool,
+}
+
+// The migrations taking an index from `from` to the current schema. A single rebuild step
+// makes the whole migration a rebuild, which writes the current schema right away.
+fn plan(from: u32) -> Result<Vec<Migration>> {
+    if from > SCHEMA_VERSION {
+        bail!(
+            "schema {} is newer than the supported schema {}, upgrade the indexer",
+            from,
+            SCHEMA_VERSION
+        );
+    }
+    Ok(MIGRATIONS
+        .iter()
+        .filter(|migration| migration.from >= from)
+        .copied()
+        .collect())
+}
+
+// Refuses to add to indexes written with another schema, mixing them would leave points the
+// migrations can't tell apart.
+pub(crate) async fn check_schema(vector_store: &dyn VectorStore, repo_name: &str) -> Result<()> {
+    match repository_schema_version(vector_store, repo_name).await? {
+        Some(version) if version != SCHEMA_VERSION => Err(anyhow!(
+            "{} is indexed with schema {} but this indexer writes schema {}, run `ingestion migrate {}` first",
+            repo_name,
+            version,
+            SCHEMA_VERSION,
+            repo_name
+        )),
+        _ => Ok(()),
+    }
+}
+
+// Records the current schema for the indexes of the repo. Repos indexed without a lexical index
+// (v3) only have collections, and only the repos indexed with their commits have a commits
+// collection.
+pub(crate) async fn record_schema(
+    vector_store: &dyn VectorStore,
+    repo_name: &str,
+    with_documents: bool,
+) -> Result<()> {
+    let (collection_name_chunks, collection_name_symbols) = Indexer::collection_names(repo_name);
+    let mut records = vec![
+        SchemaRecord::new(
+            &collection_name_chunks,
+            IndexKind::Chunks,
+            repo_name,
+            SCHEMA_VERSION,
+        ),
+        SchemaRecord::new(
+            &collection_name_symbols,
+            IndexKind::Symbols,
+            repo_name,
+            SCHEMA_VERSION,
+        ),
+        SchemaRecord::new(
+            &Indexer::docs_collection_name(repo_name),
+            IndexKind::Docs,
+            repo_name,
+            SCHEMA_VERSION,
+        ),
+    ];
+    let collection_name_commits = Indexer::commits_collection_name(repo_name);
+    if vector_store
+        .collection_dimension(&collection_name_commits)
+        .await?
+        .is_some()
+    {
+        records.push(SchemaRecord::new(
+            &collection_name_commits,
+            IndexKind::Commits,
+            repo_name,
+            SCHEMA_VERSION,
+        ));
+    }
+    if with_documents {
+        records.push(SchemaRecord::new(
+            &generate_quikwit_index_name(repo_name),
+            IndexKind::Documents,
+            repo_name,
+            SCHEMA_VERSION,
+        ));
+    }
+
+    for record in records {
+        schema::write_schema_version(vector_store, record).await?;
+    }
+    Ok(())
+}
+
+/// Brings the indexes of the repo to the current schema. `base` provides the backends and the
+/// embedder; a rebuild re-indexes the refs with the settings recorded in the registry.
+pub async fn migrate_repository(
+    repo_name: &str,
+    base: &Config,
+    dry_run: bool,
+) -> Result<MigrationReport> {
+    let vector_store = base.vector_store.build()?;
+    let from = match repository_schema_version(vector_store.as_ref(), repo_name).await? {
+        Some(from) => from,
+        // a registered repo without indexes is one whose rebuild failed, it is rebuilt again.
+        None if registry::get_repo(repo_name).is_some() => {
+            info!("{} has no indexes left, resuming its rebuild", repo_name);
+            LEGACY_SCHEMA_VERSION
+        }
+        None => bail!("{} is not indexed", repo_name),
+    };
+    let migrations = plan(from)?;
+    let report = MigrationReport {
+        repo_name: repo_name.to_string(),
+        from,
+        to: SCHEMA_VERSION,
+        rebuild: migrations
+            .iter()
+            .any(|migration| migration.strategy == Strategy::Rebuild),
+        migrations,
+        dry_run,
+    };
+    if dry_run || report.migrations.is_empty() {
+        return Ok(report);
+    }
+
+    info!(
+        "Migrating {} from schema {} to {}",
+        repo_name, from, SCHEMA_VERSION
+    );
+    if report.rebuild {
+        rebuild(repo_name, base).await?;
+        return Ok(report);
+    }
+
+    let (collection_name_chunks, collection_name_symbols) = Indexer::collection_names(repo_name);
+    for migration in &report.migrations {
+        info!("Schema {}: {}", migration.from, migration.description);
+        match migration.from {
+            2 => add_scope_paths(vector_store.as_ref(), &collection_name_chunks).await?,
+            4 => rename_symbol_branches(vector_store.as_ref(), &collection_name_symbols).await?,
+            from => bail!("no rewrite from schema {}", from),
+        }
+    }
+    let with_documents = registry::get_repo(repo_name).map_or(true, |repo| repo.version != "v3");
+    record_schema(vector_store.as_ref(), repo_name, with_documents).await?;
+
+    info!("Migrated {} to schema {}", repo_name, SCHEMA_VERSION);
+    Ok(report)
+}
+
+// Drops the indexes of the repo and indexes its refs again, which records the current schema.
+// The registry record is kept throughout: if a ref fails, the partial indexes are dropped again
+// so that the next migration starts the rebuild over from the record.
+async fn rebuild(repo_name: &str, base: &Config) -> Result<()> {
+    let record = registry::get_repo(repo_name).ok_or_else(|| {
+        anyhow!(
+            "{} isn't in the repo registry, delete it and index it again by hand",
+            repo_name
+        )
+    })?;
+
+    let vector_store = base.vector_store.build()?;
+    let lexical_index = base.lexical_index.build()?;
+    gc::delete_indexes(repo_name, vector_store.as_ref(), lexical_index.as_ref()).await?;
+    // the searches of the dropped indexes are stale until the refs are indexed again.
+    hooks::notify(IndexEvent {
+        repo_name: repo_name.to_string(),
+        branch: None,
+        commit: None,
+    })
+    .await;
+
+    for (branch, repo_ref) in record.refs {
+        info!("Re-indexing {}@{}", repo_name, branch);
+        let config = Config {
+            repo_name: repo_name.to_string(),
+            repo_path: record.repo_path.clone(),
+            branch: branch.clone(),
+            version: record.version.clone(),
+            incremental: false,
+            include: repo_ref.include,
+            exclude: repo_ref.exclude,
+            chunking: record.chunking,
+            source: repo_ref.source,
+            index_commits: repo_ref.index_commits,
+            ..base.clone()
+        };
+
+        // listed along with the jobs, so that they wait for the rebuild.
+        let task_id = uuid::Uuid::new_v4().to_string();
+        queue_process(&task_id, repo_name, &record.repo_path);
+        let indexed = Indexer
+            .index_repository(
+                PathBuf::from(&record.repo_path),
+                repo_name.to_string(),
+                config,
+                &branch,
+                &record.version,
+                task_id.clone(),
+            )
+            .await;
+        if let Err(e) = indexed {
+            record_process_failure(&task_id, &format!("{:#}", e), false);
+            let cleanup =
+                gc::delete_indexes(repo_name, vector_store.as_ref(), lexical_index.as_ref()).await;
+            if let Err(cleanup) = cleanup {
+                error!(
+                    "Failed to drop the partial indexes of {}: {:?}",
+                    repo_name, cleanup
+                );
+            }
+            return Err(e.context(format!(
+                "Failed to re-index {}@{}, run the migration again to rebuild it",
+                repo_name, branch
+            )));
+        }
+    }
+    Ok(())
+}
+
+// Schema 3: the chunks carry the labels of their enclosing scopes, empty unless chunked by syntax.
+async fn add_scope_paths(vector_store: &dyn VectorStore, collection: &str) -> Result<()> {
+    let mut ids = Vec::new();
+    let mut offset = None;
+    loop {
+        let (points, next) = vector_store
+            .scroll(
+                collection,
+                &VectorFilter::default(),
+                offset,
+                SCROLL_PAGE_SIZE,
+            )
+            .await?;
+        ids.extend(
+            points
+                .into_iter()
+                .filter(|point| !point.payload.contains_key("scope_path"))
+                .map(|point| point.id),
+        );
+        match next {
+            Some(next) => offset = Some(next),
+            None => break,
+        }
+    }
+
+    for batch in ids.chunks(REWRITE_BATCH_SIZE) {
+        vector_store
+            .set_payload(
+                collection,
+                &PointSelector::Ids(batch.to_vec()),
+                PointPayload::from([("scope_path".to_string(), Value::from(""))]),
+            )
+            .await?;
+    }
+    info!(
+        "Added scope paths to {} chunks of {}",
+        ids.len(),
+        collection
+    );
+    Ok(())
+}
+
+// Schema 5: the symbols record the refs of their occurrences under the key of the chunks.
+async fn rename_symbol_branches(vector_store: &dyn VectorStore, collection: &str) -> Result<()> {
+    let mut points = Vec::new();
+    let mut offset = None;
+    loop {
+        let (page, next) = vector_store
+            .scroll(
+                collection,
+                &VectorFilter::default(),
+                offset,
+                SCROLL_PAGE_SIZE,
+            )
+            .await?;
+        points.extend(
+            page.into_iter()
+                .filter(|point| point.payload.contains_key("branch")),
+        );
+        match next {
+            Some(next) => offset = Some(next),
+            None => break,
+        }
+    }
+
+    // the payloads differ, each point is overwritten on its own.
+    for point in &points {
+        let mut payload = point.payload.clone();
+        if let Some(branches) = payload.remove("branch") {
+            payload.insert("branches".to_string(), branches);
+        }
+        vector_store
+            .overwrite_payload(collection, &PointSelector::Ids(vec![point.id.clone()]), payload)
+            .await?;
+    }
+    info!(
+        "Renamed the branches of {} symbols of {}",
+        points.len(),
+        collection
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan() {
+        assert!(plan(SCHEMA_VERSION).unwrap().is_empty());
+        assert!(plan(SCHEMA_VERSION + 1).is_err());
+
+        let steps = plan(4).unwrap();
+        assert_eq!(steps.len(), 1);
+        assert_eq!(steps[0].strategy, Strategy::Rewrite);
+
+        let steps = plan(3).unwrap();
+        assert_eq!(steps.len(), 2);
+        assert_eq!(steps[0].strategy, Strategy::Rebuild);
+
+        let steps = plan(2).unwrap();
+        assert_eq!(steps.len(), 3);
+        assert_eq!(steps[0].strategy, Strategy::Rewrite);
+
+        let steps = plan(LEGACY_SCHEMA_VERSION).unwrap();
+        assert_eq!(
+            steps.len(),
+            (SCHEMA_VERSION - LEGACY_SCHEMA_VERSION) as usize
+        );
+        assert_eq!(steps[0].strategy, Strategy::Rebuild);
+        // every schema change has a migration.
+        assert_eq!(MIGRATIONS.last().unwrap().from + 1, SCHEMA_VERSION);
+    }
+}
@@ -0,0 +1,229 @@
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+
+use crate::state::{update_process_phase, CodeIndexingTaskStatus, ProcessState};
+
+// Minimum delay between two progress updates of the same phase.
+const UPDATE_INTERVAL: Duration = Duration::from_millis(250);
+// Number of updates buffered for slow subscribers before they start lagging.
+const CHANNEL_CAPACITY: usize = 1024;
+
+static PROGRESS_CHANNEL: Lazy<broadcast::Sender<ProgressUpdate>> =
+    Lazy::new(|| broadcast::channel(CHANNEL_CAPACITY).0);
+
+/// The phases of an indexing run, in the order they are executed.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum IndexingPhase {
+    GitWalk,
+    Parsing,
+    SymbolEmbedding,
+    ChunkEmbedding,
+    QuickwitUpload,
+}
+
+impl IndexingPhase {
+    const ALL: [IndexingPhase; 5] = [
+        IndexingPhase::GitWalk,
+        IndexingPhase::Parsing,
+        IndexingPhase::SymbolEmbedding,
+        IndexingPhase::ChunkEmbedding,
+        IndexingPhase::QuickwitUpload,
+    ];
+
+    // Share of the overall progress, in percent, a phase accounts for.
+    // Roughly follows how long each phase takes on a typical repository.
+    fn weight(&self) -> u32 {
+        match self {
+            IndexingPhase::GitWalk => 5,
+            IndexingPhase::Parsing => 25,
+            IndexingPhase::SymbolEmbedding => 20,
+            IndexingPhase::ChunkEmbedding => 40,
+            IndexingPhase::QuickwitUpload => 10,
+        }
+    }
+
+    // Overall progress reached once all the phases before this one are done.
+    fn offset(&self) -> u32 {
+        Self::ALL
+            .iter()
+            .take_while(|phase| *phase != self)
+            .map(IndexingPhase::weight)
+            .sum()
+    }
+}
+
+/// Progress of the phase an indexing run is currently in.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct PhaseProgress {
+    pub phase: IndexingPhase,
+    pub processed: usize,
+    // None while the number of items is not known yet, e.g. during the git walk.
+    pub total: Option<usize>,
+    // Estimated seconds left in the current phase.
+    pub eta_secs: Option<u64>,
+}
+
+/// Update sent to the subscribers of the progress stream.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ProgressUpdate {
+    pub task_id: String,
+    pub task_status: CodeIndexingTaskStatus,
+    pub progress: u32,
+    pub phase: Option<PhaseProgress>,
+    pub error: Option<String>,
+}
+
+impl ProgressUpdate {
+    pub fn new(task_id: &str, state: &ProcessState) -> Self {
+        Self {
+            task_id: task_id.to_string(),
+            task_status: state.task_status.clone(),
+            progress: state.progress,
+            phase: state.phase.clone(),
+            error: state.error.clone(),
+        }
+    }
+}
+
+// Broadcasts a progress update to the subscribers, if any.
+pub(crate) fn publish(update: ProgressUpdate) {
+    let _ = PROGRESS_CHANNEL.send(update);
+}
+
+/// Subscribes to the progress updates of all the indexing runs.
+pub fn subscribe() -> broadcast::Receiver<ProgressUpdate> {
+    PROGRESS_CHANNEL.subscribe()
+}
+
+/// Tracks the progress of an indexing run through its phases and reports it to the process state.
+pub struct ProgressReporter {
+    task_id: Option<String>,
+    phase: IndexingPhase,
+    processed: usize,
+    total: Option<usize>,
+    phase_started: Instant,
+    last_update: Option<Instant>,
+}
+
+impl ProgressReporter {
+    pub fn new(task_id: &str) -> Self {
+        Self {
+            task_id: Some(task_id.to_string()),
+            ..Self::disabled()
+        }
+    }
+
+    // A reporter that doesn't report anything, for runs without a task.
+    pub fn disabled() -> Self {
+        Self {
+            task_id: None,
+            phase: IndexingPhase::GitWalk,
+            processed: 0,
+            total: None,
+            phase_started: Instant::now(),
+            last_update: None,
+        }
+    }
+
+    pub fn start_phase(&mut self, phase: IndexingPhase, total: Option<usize>) {
+        self.phase = phase;
+        self.processed = 0;
+        self.total = total;
+        self.phase_started = Instant::now();
+        self.report(true);
+    }
+
+    pub fn set_total(&mut self, total: usize) {
+        self.total = Some(total);
+        self.report(true);
+    }
+
+    pub fn advance(&mut self, processed: usize) {
+        self.processed += processed;
+        self.report(false);
+    }
+
+    pub fn finish_phase(&mut self) {
+        if let Some(total) = self.total {
+            self.processed = self.processed.max(total);
+        }
+        self.report(true);
+    }
+
+    fn report(&mut self, force: bool) {
+        let Some(task_id) = &self.task_id else {
+            return;
+        };
+
+        let now = Instant::now();
+        let throttled = self
+            .last_update
+            .map_or(false, |last| now.duration_since(last) < UPDATE_INTERVAL);
+        if throttled && !force {
+            return;
+        }
+        self.last_update = Some(now);
+
+        update_process_phase(task_id, self.overall_progress(), self.phase_progress());
+    }
+
+    fn fraction(&self) -> f64 {
+        match self.total {
+            Some(0) => 1.0,
+            Some(total) => (self.processed as f64 / total as f64).min(1.0),
+            None => 0.0,
+        }
+    }
+
+    // Overall progress in percent. Stays below 100 until the run is marked as completed.
+    fn overall_progress(&self) -> u32 {
+        let progress = self.phase.offset() as f64 + self.phase.weight() as f64 * self.fraction();
+        (progress as u32).min(99)
+    }
+
+    fn phase_progress(&self) -> PhaseProgress {
+        PhaseProgress {
+            phase: self.phase,
+            processed: self.processed,
+            total: self.total,
+            eta_secs: self.eta(),
+        }
+    }
+
+    // Extrapolates the time left in the phase from the rate observed so far.
+    fn eta(&self) -> Option<u64> {
+        let total = self.total?;
+        if self.processed == 0 {
+            return None;
+        }
+        let elapsed = self.phase_started.elapsed().as_secs_f64();
+        let remaining = total.saturating_sub(self.processed) as f64;
+        Some((elapsed / self.processed as f64 * remaining).round() as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overall_progress() {
+        let mut reporter = ProgressReporter::disabled();
+        assert_eq!(reporter.overall_progress(), 0);
+
+        reporter.start_phase(IndexingPhase::Parsing, Some(10));
+        assert_eq!(reporter.overall_progress(), 5);
+        reporter.advance(5);
+        assert_eq!(reporter.overall_progress(), 17);
+
+        reporter.start_phase(IndexingPhase::ChunkEmbedding, Some(0));
+        assert_eq!(reporter.overall_progress(), 90);
+
+        reporter.start_phase(IndexingPhase::QuickwitUpload, Some(4));
+        reporter.finish_phase();
+        assert_eq!(reporter.overall_progress(), 99);
+    }
+}
@@ -0,0 +1,211 @@
+use anyhow::Result;
+use log::info;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::state::now;
+use crate::util::state_dir;
+use crate::{ChunkingStrategy, Config, RepoSource};
+
+const REGISTRY_FILE: &str = "repos.json";
+
+// Serializes reads and writes of the registry file between concurrent indexing runs.
+static REGISTRY_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+/// What was indexed for a repo, recorded at the end of each successful indexing run.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct RepoRecord {
+    pub repo_name: String,
+    pub repo_path: String,
+    // The index version the repo was last indexed with, e.g. "v2".
+    pub version: String,
+    // The model the chunks and symbols were embedded with, see `EmbedderConfig::model_name`.
+    pub embedding_model: String,
+    pub chunking: ChunkingStrategy,
+    pub refs: BTreeMap<String, RefRecord>,
+    // Unix timestamps in seconds.
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+/// The state of an indexed ref.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct RefRecord {
+    pub source: RepoSource,
+    // The path filters of the last run, reused when the ref has to be indexed again.
+    #[serde(default)]
+    pub include: Vec<String>,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    // Whether the commit messages of the ref were indexed by the last run.
+    #[serde(default)]
+    pub index_commits: bool,
+    // The commit the ref was last indexed at. Only the git sources match a commit.
+    pub commit: Option<String>,
+    // Counts of the last full run of the ref. Incremental runs only re-read the changed files,
+    // so they move `commit` but leave the counts, and `stats_commit`, as they were.
+    pub stats: IndexStats,
+    pub stats_commit: Option<String>,
+    // Unix timestamp in seconds.
+    pub indexed_at: u64,
+}
+
+/// Counts of the files and symbols indexed by a run.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct IndexStats {
+    pub file_count: usize,
+    pub symbol_count: usize,
+    // Number of files by detected language.
+    pub languages: BTreeMap<String, usize>,
+}
+
+fn registry_path() -> PathBuf {
+    state_dir().join(REGISTRY_FILE)
+}
+
+fn read_registry(path: &Path) -> BTreeMap<String, RepoRecord> {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn update_registry(update: impl FnOnce(&mut BTreeMap<String, RepoRecord>)) -> Result<()> {
+    let _guard = REGISTRY_LOCK.lock().unwrap();
+    let path = registry_path();
+    let mut registry = read_registry(&path);
+    update(&mut registry);
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, serde_json::to_string_pretty(&registry)?)?;
+    Ok(())
+}
+
+// Updates the record of the repo with a run of `config.branch`. `stats` is None for incremental
+// runs, which keep the counts of the previous full run.
+fn apply_run(
+    registry: &mut BTreeMap<String, RepoRecord>,
+    config: &Config,
+    commit: Option<String>,
+    stats: Option<IndexStats>,
+    timestamp: u64,
+) {
+    let record = registry
+        .entry(config.repo_name.clone())
+        .or_insert_with(|| RepoRecord {
+            repo_name: config.repo_name.clone(),
+            created_at: timestamp,
+            ..Default::default()
+        });
+    record.repo_path = config.repo_path.clone();
+    record.version = config.version.clone();
+    record.embedding_model = config.embedder.model_name();
+    record.chunking = config.chunking;
+    record.updated_at = timestamp;
+
+    let repo_ref = record.refs.entry(config.branch.clone()).or_default();
+    repo_ref.source = config.source;
+    repo_ref.include = config.include.clone();
+    repo_ref.exclude = config.exclude.clone();
+    repo_ref.index_commits = config.index_commits;
+    repo_ref.indexed_at = timestamp;
+    if let Some(stats) = stats {
+        repo_ref.stats = stats;
+        repo_ref.stats_commit = commit.clone();
+    }
+    repo_ref.commit = commit;
+}
+
+/// Records a successful indexing run of `config.branch`, see `apply_run`.
+pub fn record_indexing_run(
+    config: &Config,
+    commit: Option<String>,
+    stats: Option<IndexStats>,
+) -> Result<()> {
+    update_registry(|registry| apply_run(registry, config, commit, stats, now()))?;
+    info!(
+        "Recorded {}@{} in the repo registry",
+        config.repo_name, config.branch
+    );
+    Ok(())
+}
+
+/// The indexed repos, by name.
+pub fn list_repos() -> Vec<RepoRecord> {
+    let _guard = REGISTRY_LOCK.lock().unwrap();
+    read_registry(&registry_path()).into_values().collect()
+}
+
+pub fn get_repo(repo_name: &str) -> Option<RepoRecord> {
+    let _guard = REGISTRY_LOCK.lock().unwrap();
+    read_registry(&registry_path()).remove(repo_name)
+}
+
+/// Removes the repo from the registry. Returns false if it wasn't registered.
+pub fn forget_repo(repo_name: &str) -> Result<bool> {
+    let mut removed = false;
+    update_registry(|registry| removed = registry.remove(repo_name).is_some())?;
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_run() {
+        let config = Config::new(
+            "v2/owner/repo".to_string(),
+            "/tmp/repo".to_string(),
+            "http://localhost:6333".to_string(),
+            "http://localhost:7280".to_string(),
+            String::new(),
+            "main".to_string(),
+            "v2".to_string(),
+            false,
+        );
+        let stats = IndexStats {
+            file_count: 2,
+            symbol_count: 10,
+            languages: BTreeMap::from([("Rust".to_string(), 1), ("Python".to_string(), 1)]),
+        };
+        let mut registry = BTreeMap::new();
+
+        apply_run(
+            &mut registry,
+            &config,
+            Some("a".to_string()),
+            Some(stats.clone()),
+            1,
+        );
+        // an incremental run moves the commit but keeps the counts of the full run.
+        apply_run(&mut registry, &config, Some("b".to_string()), None, 2);
+
+        let record = &registry["v2/owner/repo"];
+        assert_eq!(record.created_at, 1);
+        assert_eq!(record.updated_at, 2);
+        assert_eq!(record.embedding_model, config.embedder.model_name());
+        let main = &record.refs["main"];
+        assert_eq!(main.commit.as_deref(), Some("b"));
+        assert_eq!(main.stats, stats);
+        assert_eq!(main.stats_commit.as_deref(), Some("a"));
+        assert_eq!(main.indexed_at, 2);
+
+        let dev = Config {
+            branch: "dev".to_string(),
+            source: RepoSource::Directory,
+            ..config
+        };
+        apply_run(&mut registry, &dev, None, Some(IndexStats::default()), 3);
+        let record = &registry["v2/owner/repo"];
+        assert_eq!(record.refs.len(), 2);
+        assert_eq!(record.refs["dev"].commit, None);
+        assert_eq!(record.refs["main"].commit.as_deref(), Some("b"));
+    }
+}
@@ -1,49 +1,54 @@
 extern crate tokenizers;
-use std::env;
-use std::error::Error;
 use std::ops::Range;
 use std::sync::Arc;
 extern crate tracing;
 
 use anyhow::anyhow;
+use common::ast::ast_graph::NamedScope;
 use common::ast::symbol::{SymbolKey, SymbolValue};
 use common::ast::text_range::{Point, TextRange};
+use common::embedding::Embedder;
+use common::vector::{
+    PointPayload, PointSelector, StoredPoint, VectorFilter, VectorPoint, VectorStore,
+};
+use futures::stream::{self, StreamExt, TryStreamExt};
 use tracing::{debug, error, trace, warn};
 mod chunking;
+mod markdown;
+mod pipeline;
 mod vector_payload;
 
-use chunking::{add_token_range, Chunk, DEDUCT_SPECIAL_TOKENS};
-use ndarray::Axis;
-use ort::tensor::OrtOwnedTensor;
-use ort::value::Value;
-use ort::{
-    session::SessionBuilder, Environment, ExecutionProvider, GraphOptimizationLevel, LoggingLevel,
-};
+pub use chunking::ChunkingStrategy;
+use chunking::{add_token_range, plan_scope_chunks, point, Chunk, DEDUCT_SPECIAL_TOKENS};
+pub use pipeline::EmbeddingMetrics;
 
-use qdrant_client::prelude::QdrantClient;
-use qdrant_client::qdrant::{PointId, PointStruct};
-use std::collections::HashMap;
-use std::fmt;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
 use thiserror::Error;
 use uuid::Uuid;
-use vector_payload::{Embedding, Payload, SymbolPayload};
+use vector_payload::{CommitPayload, DocPayload, Payload, SymbolPayload};
+
+use crate::history::CommitRecord;
+use crate::progress::ProgressReporter;
 
 pub struct SemanticIndex {
     tokenizer: tokenizers::Tokenizer,
     overlap: chunking::OverlapStrategy,
-    session: ort::Session,
-    qdrantPayload: Vec<PointStruct>,
-    qdrantSymbolPayload: Vec<PointStruct>,
+    chunking: ChunkingStrategy,
+    embedder: Arc<dyn Embedder>,
     counter: usize,
     collection_name: String,
     collection_name_symbols: String,
+    collection_name_docs: String,
+    // The branch or tag being indexed, recorded on the chunks and symbol occurrences.
+    branch: String,
 }
 // use crate::{COLLECTION_NAME, COLLECTION_NAME_SYMBOLS};
 #[derive(Error, Debug)]
 pub enum SemanticError {
-    /// Represents failure to initialize Qdrant client
-    #[error("Qdrant initialization failed. Is Qdrant running on `qdrant-url`?")]
-    QdrantInitializationError,
+    /// Represents failure to initialize the vector store collections
+    #[error("Vector store initialization failed. Is Qdrant running on `qdrant-url`?")]
+    VectorStoreInitializationError,
 
     #[error("ONNX runtime error")]
     OnnxRuntimeError {
@@ -58,358 +63,453 @@ pub enum SemanticError {
     },
 }
 
-#[derive(Debug)]
-pub enum CommitError {
-    QdrantError,
-    NoQdrantClient, // Add other error variants as needed
-}
-
-impl fmt::Display for CommitError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
-            CommitError::QdrantError => write!(f, "Error with Qdrant"),
-            CommitError::NoQdrantClient => write!(f, "No Qdrant client available"),
-            // ... match other variants and write an appropriate message
-        }
-    }
-}
-
-impl Error for CommitError {
-    // This trait can often remain empty; it's mainly here to ensure compatibility
-    // with the broader Error trait.
-}
-
-fn get_bin_path() -> Option<String> {
-    // Get the current executable path
-    let exe_path = env::current_exe().ok()?;
-
-    // Get the directory containing the executable
-    let exe_dir = exe_path.parent()?.parent()?.parent()?;
-
-    log::debug!("Bin path: {:?}", exe_dir);
-    // Convert the path to a String if possible
-    exe_dir.to_str().map(|s| s.to_owned())
-}
-
 impl SemanticIndex {
     pub fn new(
         counter: &usize,
         collection_name_chunks: &String,
         collection_name_symbols: &String,
-    ) -> Self {
-        let threads: i16 = 1;
-        let env = Environment::builder()
-            .with_name("Encode")
-            .with_log_level(LoggingLevel::Warning)
-            .with_execution_providers([ExecutionProvider::CPU(Default::default())])
-            .with_telemetry(false)
-            .build()
-            .unwrap();
-
-        let environment = Arc::new(env);
-
-        let current_path = get_bin_path().unwrap();
-        let tokenizers_path = "./model/tokenizer.json";
-        let model_path = "./model/model.onnx";
-
-        Self {
-            // initialize the tokenizer with ./model/tokenizer.json and turn off padding and truncation
-            tokenizer: tokenizers::Tokenizer::from_file(tokenizers_path)
-                .unwrap()
-                .into(),
+        collection_name_docs: &String,
+        branch: &str,
+        embedder: Arc<dyn Embedder>,
+        tokenizer_path: &str,
+    ) -> anyhow::Result<Self> {
+        // the tokenizer of the embedding model sizes the chunks, padding and truncation must be off.
+        let tokenizer = tokenizers::Tokenizer::from_file(tokenizer_path)
+            .map_err(|e| anyhow!("Failed to load tokenizer {}: {}", tokenizer_path, e))?;
 
+        Ok(Self {
+            tokenizer,
             overlap: chunking::OverlapStrategy::default(),
-
-            session: SessionBuilder::new(&environment)
-                .unwrap()
-                .with_optimization_level(GraphOptimizationLevel::Level3)
-                .unwrap()
-                .with_intra_threads(threads)
-                .unwrap()
-                .with_model_from_file(model_path)
-                .unwrap()
-                .into(),
-            qdrantPayload: Vec::new(),
-            qdrantSymbolPayload: Vec::new(),
+            chunking: ChunkingStrategy::default(),
+            embedder,
             counter: *counter,
             collection_name: (*collection_name_chunks).clone(),
             collection_name_symbols: (*collection_name_symbols).clone(),
-        }
+            collection_name_docs: (*collection_name_docs).clone(),
+            branch: branch.to_string(),
+        })
     }
 
     pub fn overlap_strategy(&self) -> chunking::OverlapStrategy {
         self.overlap
     }
 
-    pub fn embed(&self, sequence: &str) -> anyhow::Result<Embedding> {
-        let tokenizer_output = self.tokenizer.encode(sequence, true).unwrap();
-
-        let input_ids = tokenizer_output.get_ids();
-        let attention_mask = tokenizer_output.get_attention_mask();
-        let token_type_ids = tokenizer_output.get_type_ids();
-        let length = input_ids.len();
-        trace!("embedding {} tokens {:?}", length, sequence);
-
-        let inputs_ids_array = ndarray::Array::from_shape_vec(
-            (1, length),
-            input_ids.iter().map(|&x| x as i64).collect(),
-        )?;
-
-        let attention_mask_array = ndarray::Array::from_shape_vec(
-            (1, length),
-            attention_mask.iter().map(|&x| x as i64).collect(),
-        )?;
-
-        let token_type_ids_array = ndarray::Array::from_shape_vec(
-            (1, length),
-            token_type_ids.iter().map(|&x| x as i64).collect(),
-        )?;
-
-        let outputs = self.session.run(vec![
-            Value::from_array(
-                self.session.allocator(),
-                &ndarray::CowArray::from(inputs_ids_array).into_dyn(),
-            )
-            .unwrap(),
-            Value::from_array(
-                self.session.allocator(),
-                &ndarray::CowArray::from(attention_mask_array).into_dyn(),
-            )
-            .unwrap(),
-            Value::from_array(
-                self.session.allocator(),
-                &ndarray::CowArray::from(token_type_ids_array).into_dyn(),
-            )
-            .unwrap(),
-        ])?;
-
-        let output_tensor: OrtOwnedTensor<f32, _> = outputs[0].try_extract().unwrap();
-        let sequence_embedding = &*output_tensor.view();
-        let pooled = sequence_embedding.mean_axis(Axis(1)).unwrap();
-        Ok(pooled.to_owned().as_slice().unwrap().to_vec())
+    // Sets how the files are split into chunks.
+    pub fn with_chunking(mut self, chunking: ChunkingStrategy) -> Self {
+        self.chunking = chunking;
+        self
     }
 
-    pub async fn tokenize_and_commit<'a>(
-        &mut self,
-        buffer: &'a str,
-        repo_name: &'a str,
+    // Splits a file into chunks and builds their payloads, ready to be embedded by `commit_chunks`.
+    // `scopes` are the named scopes of the file, used by the syntax strategy.
+    pub fn chunk_file(
+        &self,
+        buffer: &str,
+        repo_name: &str,
         path: &str,
         semantic_hash: &str,
         lang_str: &str,
-        qdrant_client: &Option<QdrantClient>,
-    ) -> Result<(), anyhow::Error> {
-        // Tokenize
-        let chunks = self.tokenize_chunk(
-            buffer,
-            repo_name,
-            path,
-            semantic_hash,
-            50..256,
-            qdrant_client,
-        );
+        scopes: &[NamedScope],
+    ) -> Vec<Payload> {
+        let chunks = match self.chunking {
+            ChunkingStrategy::Syntax if !scopes.is_empty() => {
+                self.syntax_chunks(buffer, repo_name, path, semantic_hash, scopes, 50..256)
+            }
+            ChunkingStrategy::Lines => Self::by_lines(buffer, chunking::LINES_PER_CHUNK)
+                .into_iter()
+                .map(|chunk| (chunk, String::new()))
+                .collect(),
+            ChunkingStrategy::Tokens | ChunkingStrategy::Syntax => self
+                .tokenize_chunk(buffer, repo_name, path, semantic_hash, 50..256)
+                .into_iter()
+                .map(|chunk| (chunk, String::new()))
+                .collect(),
+        };
 
-        // Commit
-        self.commit_chunks(
-            chunks,
-            repo_name,
-            path,
-            semantic_hash,
-            lang_str,
-            qdrant_client,
-        )
-        .await
+        chunks
+            .into_iter()
+            .map(|(chunk, scope_path)| Payload {
+                repo_name: repo_name.to_owned(),
+                repo_ref: repo_name.to_owned(),
+                branches: vec![self.branch.clone()],
+                relative_path: path.to_owned(),
+                content_hash: semantic_hash.to_string(),
+                text: chunk.data.to_owned(),
+                lang: lang_str.to_ascii_lowercase(),
+                start_line: chunk.range.start.line as u64,
+                end_line: chunk.range.end.line as u64,
+                start_byte: chunk.range.start.byte as u64,
+                end_byte: chunk.range.end.byte as u64,
+                scope_path,
+                ..Default::default()
+            })
+            .collect()
     }
 
-    // takes the hash map containing the symbol metadata and commits it to the qdrant database.
-    // the key of the hash map where the key primarily contains
-    pub async fn commit_symbol_metadata(
-        &mut self,
-        symbol_meta_hash_map: &HashMap<SymbolKey, Vec<SymbolValue>>,
-        qdrant_client: &Option<QdrantClient>,
-    ) -> Result<String, anyhow::Error> {
-        //let mut temp_payloads = Vec::new();
-
-        debug!("Inside commiting symbol meta payload");
-
-        let embedder = |c: &str| {
-            debug!("generating embedding");
-            self.embed(c)
+    // Splits a Markdown file into its heading-scoped sections, ready to be embedded by
+    // `commit_docs` along with the text they are embedded from: the heading path, then the
+    // section. Sections too large for a chunk are split into token windows.
+    pub fn chunk_markdown(
+        &self,
+        buffer: &str,
+        repo_name: &str,
+        path: &str,
+        semantic_hash: &str,
+    ) -> Vec<(String, DocPayload)> {
+        let Some(max_tokens) = self.max_chunk_tokens(repo_name, path, 256) else {
+            return Vec::new();
         };
 
-        // iterate through the symbolMeta hashmap and create SymbolPayload from the symbolMeta hashmap.
+        markdown::sections(buffer)
+            .into_iter()
+            .flat_map(|section| {
+                let heading_path = section.heading_path.join(markdown::HEADING_SEPARATOR);
+                let (start, end) = (section.range.start, section.range.end);
+                let text = &buffer[start..end];
+                let token_count = self
+                    .tokenizer
+                    .encode(format!("{}\n\n{}", heading_path, text), false)
+                    .map_or(usize::MAX, |encoding| encoding.get_ids().len());
 
-        let mut symbol_meta_payload: Vec<PointStruct> = symbol_meta_hash_map
-            .iter()
-            .map(|(key, values)| {
-                // iterate the values and create the vectors containing relative paths, start_bytes, end_bytes, and is_global.
-                // is_global is a vector of bools which signifies whether the symbol is declared in the root scope or not.
-                // relative_paths is a vector of strings which signifies the relative path of the file in which the symbol is declared.
-                // start_bytes and end_bytes are vectors of i64 which signifies the start and end bytes of the symbol in the file.
-                let (
-                    start_bytes,
-                    end_bytes,
-                    relative_paths,
-                    is_global_vec,
-                    language_ids,
-                    symbol_types,
-                    node_kinds,
-                ) = values.iter().fold(
-                    (
-                        Vec::new(),
-                        Vec::new(),
-                        Vec::new(),
-                        Vec::new(),
-                        Vec::new(),
-                        Vec::new(),
-                        Vec::new(),
-                    ),
-                    |(
-                        mut start_acc,
-                        mut end_acc,
-                        mut paths_acc,
-                        mut global_acc,
-                        mut language_ids,
-                        mut symbol_types,
-                        mut node_kinds,
-                    ),
-                     value| {
-                        start_acc.push(value.start_byte as i64);
-                        end_acc.push(value.end_byte as i64);
-                        paths_acc.push(value.relative_path.clone());
-                        global_acc.push(value.is_global);
-                        language_ids.push(value.language_id.clone());
-                        symbol_types.push(value.symbol_type.clone());
-                        node_kinds.push(value.node_kind.clone());
-
-                        (
-                            start_acc,
-                            end_acc,
-                            paths_acc,
-                            global_acc,
-                            language_ids,
-                            symbol_types,
-                            node_kinds,
-                        )
-                    },
-                );
-
-                // create the SymbolPayload from the key and the vectors created above.
-                // this format is required by qdrant.
-                let symbol_qdrant_meta = SymbolPayload {
-                    lang_ids: language_ids,
-                    repo_name: key.repo_name.clone(),
-                    symbol: key.symbol.clone(),
-                    symbol_types: symbol_types,
-                    is_globals: is_global_vec,
-                    start_bytes: start_bytes,
-                    end_bytes: end_bytes,
-                    relative_paths: relative_paths,
-                    node_kinds: node_kinds,
-                    ..Default::default()
+                let chunks = if token_count <= max_tokens {
+                    vec![Chunk::new(
+                        text,
+                        point(buffer, start, 0, 0),
+                        point(buffer, end, 0, 0),
+                    )]
+                } else {
+                    self.tokenize_chunk(text, repo_name, path, semantic_hash, 0..256)
+                        .into_iter()
+                        .map(|chunk| {
+                            // positions are relative to the section, make them relative to the file.
+                            Chunk::new(
+                                chunk.data,
+                                point(buffer, start + chunk.range.start.byte, 0, 0),
+                                point(buffer, start + chunk.range.end.byte, 0, 0),
+                            )
+                        })
+                        .collect()
                 };
 
-                let id = Uuid::new_v4();
-                debug!("id: {}", id);
-                // we find the embedding vector using the symbol from the ast.
-                return PointStruct {
-                    id: Some(PointId::from(id.to_string())),
-                    vectors: Some(embedder(&key.symbol).unwrap().into()),
-                    payload: symbol_qdrant_meta.convert_to__qdrant_fields(),
-                };
+                chunks
+                    .into_iter()
+                    .map(|chunk| {
+                        let embedded = if heading_path.is_empty() {
+                            chunk.data.to_owned()
+                        } else {
+                            format!("{}\n\n{}", heading_path, chunk.data)
+                        };
+                        let payload = DocPayload {
+                            repo_name: repo_name.to_owned(),
+                            relative_path: path.to_owned(),
+                            content_hash: semantic_hash.to_string(),
+                            heading_path: heading_path.clone(),
+                            code_languages: section.code_languages.clone(),
+                            text: chunk.data.to_owned(),
+                            start_line: chunk.range.start.line as u64,
+                            end_line: chunk.range.end.line as u64,
+                            start_byte: chunk.range.start.byte as u64,
+                            end_byte: chunk.range.end.byte as u64,
+                            branches: vec![self.branch.clone()],
+                        };
+                        (embedded, payload)
+                    })
+                    .collect::<Vec<_>>()
             })
-            .collect();
+            .collect()
+    }
 
-        debug!("finished iterating on the chuks and creating temp payload");
+    // Chunks aligned to the named scopes of the file, each along with its scope path. Scopes that
+    // don't fit in the token budget are split into token windows.
+    fn syntax_chunks<'s>(
+        &self,
+        src: &'s str,
+        repo_name: &'s str,
+        file: &str,
+        semantic_hash: &str,
+        scopes: &[NamedScope],
+        token_bounds: Range<usize>,
+    ) -> Vec<(Chunk<'s>, String)> {
+        let Some(max_tokens) = self.max_chunk_tokens(repo_name, file, token_bounds.end) else {
+            return Vec::new();
+        };
+        let Ok(encoding) = self.tokenizer.encode(src, false) else {
+            error!("Could not encode \"{}\"", file);
+            return Vec::new();
+        };
 
-        debug!("length of the payload: {}", symbol_meta_payload.len());
+        let token_starts = encoding
+            .get_offsets()
+            .iter()
+            .map(|&(start, _)| start)
+            .collect::<Vec<_>>();
+        let token_count = |range: Range<usize>| {
+            token_starts.partition_point(|&start| start < range.end)
+                - token_starts.partition_point(|&start| start < range.start)
+        };
 
-        // commit the data to Qdrant.
-        let new: Vec<_> = std::mem::take(symbol_meta_payload.as_mut());
+        plan_scope_chunks(src, scopes, max_tokens, token_count)
+            .into_iter()
+            .flat_map(|planned| {
+                let (start, end) = (planned.range.start, planned.range.end);
+                let chunks = if planned.oversized {
+                    self.tokenize_chunk(
+                        &src[start..end],
+                        repo_name,
+                        file,
+                        semantic_hash,
+                        token_bounds.clone(),
+                    )
+                    .into_iter()
+                    .map(|chunk| {
+                        // positions are relative to the scope, make them relative to the file.
+                        Chunk::new(
+                            chunk.data,
+                            point(src, start + chunk.range.start.byte, 0, 0),
+                            point(src, start + chunk.range.end.byte, 0, 0),
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                } else {
+                    vec![Chunk::new(
+                        &src[start..end],
+                        point(src, start, 0, 0),
+                        point(src, end, 0, 0),
+                    )]
+                };
+                let scope_path = planned.scope_path;
+                chunks
+                    .into_iter()
+                    .map(move |chunk| (chunk, scope_path.clone()))
+            })
+            .collect()
+    }
 
-        // qdrant doesn't like empty payloads.
-        if let Some(ref client) = qdrant_client {
-            // qdrant doesn't like empty payloads.
-            if !new.is_empty() {
-                debug!("Just before committing to the qdrant database.");
-                client
-                    .upsert_points_batch(&self.collection_name_symbols, new, None, 10)
-                    .await
-                    .map_err(|_| anyhow!(CommitError::QdrantError))?;
+    // The number of tokens left for the content of a chunk once the repo and file name are
+    // accounted for, `None` if the budget is too small.
+    fn max_chunk_tokens(&self, repo_name: &str, file: &str, max_tokens: usize) -> Option<usize> {
+        let repo_plus_file = repo_name.to_owned() + "\t" + file + "\n";
+        let repo_tokens = match self.tokenizer.encode(repo_plus_file, true) {
+            Ok(encoding) => encoding.get_ids().len(),
+            Err(e) => {
+                error!("failure during encoding repo + file {:?}", e);
+                return None;
             }
-            debug!("finished committing symbol to qdrant");
-        } else {
-            // Handle the case where qdrant_client is None if necessary
-            return Err(anyhow!(CommitError::NoQdrantClient));
+        };
+
+        if max_tokens <= DEDUCT_SPECIAL_TOKENS + repo_tokens {
+            error!("too few tokens");
+            return None;
         }
+        Some(max_tokens - DEDUCT_SPECIAL_TOKENS - repo_tokens)
+    }
 
-        Ok("Completed".to_string())
+    // Embeds the chunk payloads in concurrent batches and upserts them to the vector store as they are embedded.
+    pub async fn commit_chunks(
+        &self,
+        payloads: Vec<Payload>,
+        vector_store: &dyn VectorStore,
+        progress: &mut ProgressReporter,
+    ) -> Result<EmbeddingMetrics, anyhow::Error> {
+        pipeline::embed_and_upsert(
+            &self.embedder,
+            vector_store,
+            &self.collection_name,
+            "chunks",
+            payloads,
+            |payload| payload.text.as_str(),
+            |payload, embedding| VectorPoint {
+                id: Uuid::new_v4().to_string(),
+                vector: embedding,
+                payload: payload.into_point_payload(),
+            },
+            |count| progress.advance(count),
+        )
+        .await
     }
 
-    pub async fn commit_chunks<'s>(
-        &mut self,
-        chunks: Vec<Chunk<'_>>,
-        repo_name: &'s str,
-        relative_path: &str,
-        semanticHash: &str,
-        lang_str: &str,
-        qdrant_client: &Option<QdrantClient>,
-    ) -> Result<(), anyhow::Error> {
-        let mut temp_payloads = Vec::new();
+    // Embeds the Markdown sections and upserts them to the docs collection, see `chunk_markdown`.
+    pub async fn commit_docs(
+        &self,
+        sections: Vec<(String, DocPayload)>,
+        vector_store: &dyn VectorStore,
+        progress: &mut ProgressReporter,
+    ) -> Result<EmbeddingMetrics, anyhow::Error> {
+        pipeline::embed_and_upsert(
+            &self.embedder,
+            vector_store,
+            &self.collection_name_docs,
+            "doc sections",
+            sections,
+            |(embedded, _)| embedded.as_str(),
+            |(_, payload), embedding| VectorPoint {
+                id: Uuid::new_v4().to_string(),
+                vector: embedding,
+                payload: payload.into_point_payload(),
+            },
+            |count| progress.advance(count),
+        )
+        .await
+    }
 
-        let embedder = |c: &str| {
-            debug!("generating embedding");
-            self.embed(c)
-        };
-        chunks.iter().for_each(|chunk| {
-            let _data = format!("{repo_name}\t{relative_path}\n{}", chunk.data,);
-            let payload = Payload {
-                repo_name: repo_name.to_owned(),
-                relative_path: relative_path.to_owned(),
-                content_hash: semanticHash.to_string(),
-                text: chunk.data.to_owned(),
-                lang: lang_str.to_ascii_lowercase(),
-                start_line: chunk.range.start.line as u64,
-                end_line: chunk.range.end.line as u64,
-                start_byte: chunk.range.start.byte as u64,
-                end_byte: chunk.range.end.byte as u64,
-                ..Default::default()
-            };
+    // Embeds the messages of the commits along with the paths they touched and upserts them to
+    // the commits collection. Points are keyed by repo and commit, so a commit indexed again
+    // replaces its point.
+    pub async fn commit_history(
+        &self,
+        collection_name: &str,
+        repo_name: &str,
+        commits: Vec<&CommitRecord>,
+        vector_store: &dyn VectorStore,
+        progress: &mut ProgressReporter,
+    ) -> Result<EmbeddingMetrics, anyhow::Error> {
+        let commits = commits
+            .into_iter()
+            .map(|commit| {
+                let mut embedded = commit.message.clone();
+                if !commit.paths.is_empty() {
+                    embedded.push_str("\n\n");
+                    embedded.push_str(
+                        &commit.paths[..commit.paths.len().min(COMMIT_EMBEDDED_PATHS)].join("\n"),
+                    );
+                }
+                let payload = CommitPayload {
+                    repo_name: repo_name.to_string(),
+                    commit: commit.commit.clone(),
+                    author: commit.author.clone(),
+                    date: commit.date,
+                    message: commit.message.clone(),
+                    paths: commit.paths.clone(),
+                    branches: vec![self.branch.clone()],
+                };
+                (embedded, payload)
+            })
+            .collect::<Vec<_>>();
 
-            let id = Uuid::new_v4();
-            let qdrant_payload = PointStruct {
-                id: Some(PointId::from(id.to_string())),
-                vectors: Some(embedder(chunk.data).unwrap().into()),
-                payload: payload.convert_to__qdrant_fields(),
-            };
+        pipeline::embed_and_upsert(
+            &self.embedder,
+            vector_store,
+            collection_name,
+            "commits",
+            commits,
+            |(embedded, _)| embedded.as_str(),
+            |(_, payload), embedding| VectorPoint {
+                id: payload.point_id(),
+                vector: embedding,
+                payload: payload.into_point_payload(),
+            },
+            |count| progress.advance(count),
+        )
+        .await
+    }
 
-            temp_payloads.push(qdrant_payload);
-        });
-        debug!("finished iterating on the chuks and creating temp payload");
+    // Commits the symbols of a batch of files, one point per symbol whose id is derived from its
+    // `SymbolKey`. The occurrences already indexed for a symbol, by other files, refs or batches,
+    // are merged into its point: a symbol that has its point only gets the payload rewritten,
+    // without being embedded again. Points written with random ids before are merged, then
+    // deleted. The occurrences of the files of the batch have to be pruned beforehand.
+    pub async fn commit_symbol_metadata(
+        &self,
+        symbol_meta_hash_map: &HashMap<SymbolKey, Vec<SymbolValue>>,
+        vector_store: &dyn VectorStore,
+        progress: &mut ProgressReporter,
+    ) -> Result<EmbeddingMetrics, anyhow::Error> {
+        debug!("Inside commiting symbol meta payload");
 
-        // self.qdrantPayload.extend(temp_payloads);
-        // print length of the payload
-        debug!("length of the payload: {}", temp_payloads.len());
+        let symbols = symbol_meta_hash_map
+            .keys()
+            .map(|key| key.symbol.clone())
+            .collect::<Vec<_>>();
+        let mut existing: HashMap<String, Vec<(String, SymbolPayload)>> = HashMap::new();
+        for batch in symbols.chunks(FILTER_BATCH_SIZE) {
+            let filter = VectorFilter::any_of("symbol", batch.iter());
+            for point in scroll_all(vector_store, &self.collection_name_symbols, &filter).await? {
+                if let Some(payload) = symbol_payload_from_point(&point.payload, &self.branch) {
+                    existing
+                        .entry(payload.symbol.clone())
+                        .or_default()
+                        .push((point.id, payload));
+                }
+            }
+        }
 
-        // commit the data to Qdrant.
-        let new: Vec<_> = std::mem::take(temp_payloads.as_mut());
+        // the symbol itself is embedded, so it is kept alongside its payload.
+        let mut to_embed: Vec<(String, SymbolPayload)> = Vec::new();
+        let mut to_rewrite: Vec<(String, SymbolPayload)> = Vec::new();
+        let mut to_delete: Vec<String> = Vec::new();
+        for (key, values) in symbol_meta_hash_map {
+            let payload = symbol_payload(key, values, &self.branch);
+            let id = payload.point_id();
+            let points = existing
+                .remove(&key.symbol)
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|(_, existing)| existing.repo_name == key.repo_name)
+                .collect::<Vec<_>>();
 
-        // qdrant doesn't like empty payloads.
-        if let Some(ref client) = qdrant_client {
-            // qdrant doesn't like empty payloads.
-            if !new.is_empty() {
-                debug!("Just before committing to the database.");
-                client
-                    .upsert_points_batch(&self.collection_name, new, None, 10)
-                    .await
-                    .map_err(|_| anyhow!(CommitError::QdrantError))?;
+            let has_point = points.iter().any(|(point_id, _)| *point_id == id);
+            to_delete.extend(
+                points
+                    .iter()
+                    .map(|(point_id, _)| point_id.clone())
+                    .filter(|point_id| *point_id != id),
+            );
+            let merged = merge_occurrences(points.into_iter().map(|(_, p)| p), payload);
+            if has_point {
+                to_rewrite.push((id, merged));
+            } else {
+                to_embed.push((key.symbol.clone(), merged));
             }
-            debug!("finished committing to qdrant");
-        } else {
-            // Handle the case where qdrant_client is None if necessary
-            return Err(anyhow!(CommitError::NoQdrantClient));
         }
 
-        Ok(())
+        debug!(
+            "{} symbols to embed, {} symbol payloads to rewrite",
+            to_embed.len(),
+            to_rewrite.len()
+        );
+
+        let rewritten = to_rewrite.len();
+        stream::iter(to_rewrite)
+            .map(|(id, payload)| async move {
+                vector_store
+                    .overwrite_payload(
+                        &self.collection_name_symbols,
+                        &PointSelector::Ids(vec![id]),
+                        payload.into_point_payload(),
+                    )
+                    .await
+            })
+            .buffer_unordered(PAYLOAD_REWRITE_CONCURRENCY)
+            .try_collect::<Vec<_>>()
+            .await?;
+        progress.advance(rewritten);
+
+        let metrics = pipeline::embed_and_upsert(
+            &self.embedder,
+            vector_store,
+            &self.collection_name_symbols,
+            "symbols",
+            to_embed,
+            |(symbol, _)| symbol.as_str(),
+            |(_, payload), embedding| VectorPoint {
+                id: payload.point_id(),
+                vector: embedding,
+                payload: payload.into_point_payload(),
+            },
+            |count| progress.advance(count),
+        )
+        .await?;
+
+        // only once their occurrences live in the merged points.
+        if !to_delete.is_empty() {
+            vector_store
+                .delete(
+                    &self.collection_name_symbols,
+                    &PointSelector::Ids(to_delete),
+                )
+                .await?;
+        }
+        Ok(metrics)
     }
 
     pub fn tokenize_chunk<'s>(
@@ -419,7 +519,6 @@ impl SemanticIndex {
         file: &str,
         _semanticHash: &str,
         token_bounds: Range<usize>,
-        _qdrant_client: &Option<QdrantClient>,
     ) -> Vec<Chunk<'s>> {
         if self.tokenizer.get_padding().is_some() || self.tokenizer.get_truncation().is_some() {
             error!(
@@ -444,21 +543,9 @@ impl SemanticIndex {
             return Vec::new();
         }
 
-        let repo_plus_file = repo_name.to_owned() + "\t" + file + "\n";
-        let repo_tokens = match self.tokenizer.encode(repo_plus_file, true) {
-            Ok(encoding) => encoding.get_ids().len(),
-            Err(e) => {
-                error!("failure during encoding repo + file {:?}", e);
-                return Vec::new();
-            }
-        };
-
-        if token_bounds.end <= DEDUCT_SPECIAL_TOKENS + repo_tokens {
-            error!("too few tokens");
+        let Some(max_tokens) = self.max_chunk_tokens(repo_name, file, token_bounds.end) else {
             return Vec::new();
-        }
-
-        let max_tokens = token_bounds.end - DEDUCT_SPECIAL_TOKENS - repo_tokens;
+        };
         let max_newline_tokens = max_tokens * 3 / 4; //TODO: make this configurable
         let max_boundary_tokens = max_tokens * 7 / 8; //TODO: make this configurable
         debug!("max tokens reduced to {max_tokens}");
@@ -569,3 +656,488 @@ impl SemanticIndex {
             .collect()
     }
 }
+
+// Number of touched paths embedded with a commit message, the payload keeps all of them.
+const COMMIT_EMBEDDED_PATHS: usize = 32;
+// Number of symbol payloads rewritten concurrently.
+const PAYLOAD_REWRITE_CONCURRENCY: usize = 8;
+// Number of values OR-ed together in a single filter.
+const FILTER_BATCH_SIZE: usize = 64;
+// Page size used while scrolling through the collections.
+const SCROLL_PAGE_SIZE: u32 = 256;
+
+// Returns all the points matching the filter, page by page.
+async fn scroll_all(
+    vector_store: &dyn VectorStore,
+    collection_name: &str,
+    filter: &VectorFilter,
+) -> Result<Vec<StoredPoint>, anyhow::Error> {
+    let mut points = Vec::new();
+    let mut offset = None;
+    loop {
+        let (page, next) = vector_store
+            .scroll(collection_name, filter, offset, SCROLL_PAGE_SIZE)
+            .await?;
+        points.extend(page);
+        match next {
+            Some(next) => offset = Some(next),
+            None => break,
+        }
+    }
+    Ok(points)
+}
+
+/// Returns the branches recorded on the chunks of each of the given content hashes that already
+/// have chunks in the collection. Files whose content did not change keep the same semantic hash,
+/// so their chunks don't need to be embedded again, on this branch or any other.
+///
+/// Chunks indexed before branches were recorded are returned with an empty set.
+pub async fn chunk_branches(
+    vector_store: &dyn VectorStore,
+    collection_name: &str,
+    content_hashes: &[String],
+) -> Result<HashMap<String, HashSet<String>>, anyhow::Error> {
+    branches_by_key(
+        vector_store,
+        collection_name,
+        "content_hash",
+        content_hashes,
+    )
+    .await
+}
+
+/// Returns the branches recorded on each of the given commits that are already in the commits
+/// collection. A commit reachable from several refs is only embedded once.
+pub async fn commit_branches(
+    vector_store: &dyn VectorStore,
+    collection_name: &str,
+    commits: &[String],
+) -> Result<HashMap<String, HashSet<String>>, anyhow::Error> {
+    branches_by_key(vector_store, collection_name, "commit", commits).await
+}
+
+// Collects the `branches` of the points whose `key` field is one of the values, by value.
+async fn branches_by_key(
+    vector_store: &dyn VectorStore,
+    collection_name: &str,
+    key: &str,
+    values: &[String],
+) -> Result<HashMap<String, HashSet<String>>, anyhow::Error> {
+    let mut existing: HashMap<String, HashSet<String>> = HashMap::new();
+    for values in values.chunks(FILTER_BATCH_SIZE) {
+        let filter = VectorFilter::any_of(key, values.iter());
+        for point in scroll_all(vector_store, collection_name, &filter).await? {
+            let Some(value) = point.payload.get(key).and_then(as_string) else {
+                continue;
+            };
+            existing.entry(value).or_default().extend(
+                list_values(&point.payload, "branches")
+                    .iter()
+                    .filter_map(as_string),
+            );
+        }
+    }
+
+    Ok(existing)
+}
+
+/// Returns the content hashes of the chunks recorded on the given branch.
+pub async fn chunk_hashes_on_branch(
+    vector_store: &dyn VectorStore,
+    collection_name: &str,
+    branch: &str,
+) -> Result<HashSet<String>, anyhow::Error> {
+    let filter = VectorFilter::must("branches", branch);
+    Ok(scroll_all(vector_store, collection_name, &filter)
+        .await?
+        .iter()
+        .filter_map(|point| point.payload.get("content_hash").and_then(as_string))
+        .collect())
+}
+
+/// Returns the paths of the symbol occurrences recorded on the given branch.
+pub async fn symbol_paths_on_branch(
+    vector_store: &dyn VectorStore,
+    collection_name: &str,
+    branch: &str,
+) -> Result<HashSet<String>, anyhow::Error> {
+    let filter = VectorFilter::must("branches", branch);
+    let mut paths = HashSet::new();
+    for point in scroll_all(vector_store, collection_name, &filter).await? {
+        let branches = list_values(&point.payload, "branches");
+        paths.extend(
+            list_values(&point.payload, "relative_path")
+                .iter()
+                .zip(branches.iter())
+                .filter(|(_, b)| b.as_str() == Some(branch))
+                .filter_map(|(path, _)| as_string(path)),
+        );
+    }
+    Ok(paths)
+}
+
+/// Sets the branches of every chunk produced from the file version with the given content hash.
+/// Used to share the chunks of a file between the branches it has the same content on.
+pub async fn set_chunk_branches(
+    vector_store: &dyn VectorStore,
+    collection_name: &str,
+    content_hash: &str,
+    branches: &HashSet<String>,
+) -> Result<(), anyhow::Error> {
+    let mut branches = branches.iter().cloned().collect::<Vec<_>>();
+    branches.sort();
+    vector_store
+        .set_payload(
+            collection_name,
+            &PointSelector::Filter(VectorFilter::must("content_hash", content_hash)),
+            PointPayload::from([("branches".to_string(), Value::from(branches))]),
+        )
+        .await
+}
+
+/// Sets the branches of an indexed commit.
+pub async fn set_commit_branches(
+    vector_store: &dyn VectorStore,
+    collection_name: &str,
+    commit: &str,
+    branches: &HashSet<String>,
+) -> Result<(), anyhow::Error> {
+    let mut branches = branches.iter().cloned().collect::<Vec<_>>();
+    branches.sort();
+    vector_store
+        .set_payload(
+            collection_name,
+            &PointSelector::Filter(VectorFilter::must("commit", commit)),
+            PointPayload::from([("branches".to_string(), Value::from(branches))]),
+        )
+        .await
+}
+
+/// Releases the chunks of the given file versions from a branch. Chunks are shared between the
+/// branches a file has the same content on, so they are only deleted once no branch is left;
+/// otherwise the branch is removed from their branches. Used to drop the chunks of modified and
+/// deleted files during incremental indexing.
+pub async fn release_chunks(
+    vector_store: &dyn VectorStore,
+    collection_name: &str,
+    content_hashes: &[String],
+    branch: &str,
+) -> Result<(), anyhow::Error> {
+    let existing = chunk_branches(vector_store, collection_name, content_hashes).await?;
+    let mut to_delete = Vec::new();
+    for (content_hash, mut branches) in existing {
+        branches.remove(branch);
+        if branches.is_empty() {
+            to_delete.push(content_hash);
+        } else {
+            set_chunk_branches(vector_store, collection_name, &content_hash, &branches).await?;
+        }
+    }
+
+    for hashes in to_delete.chunks(FILTER_BATCH_SIZE) {
+        let selector = PointSelector::Filter(VectorFilter::any_of("content_hash", hashes.iter()));
+        vector_store.delete(collection_name, &selector).await?;
+    }
+
+    debug!(
+        "Released chunks of {} stale files from {} in {}, {} deleted",
+        content_hashes.len(),
+        branch,
+        collection_name,
+        to_delete.len()
+    );
+    Ok(())
+}
+
+/// Removes the occurrences recorded for the given paths on a branch from the symbol collection.
+///
+/// Symbol points aggregate occurrences across files, so a point is only deleted once all of its
+/// occurrences are gone; otherwise its payload is rewritten without the stale occurrences.
+pub async fn prune_symbol_occurrences(
+    vector_store: &dyn VectorStore,
+    collection_name: &str,
+    paths: &HashSet<String>,
+    branch: &str,
+) -> Result<(), anyhow::Error> {
+    let paths_vec = paths.iter().cloned().collect::<Vec<_>>();
+    let mut points_to_delete: Vec<String> = Vec::new();
+    let mut points_to_rewrite: Vec<(String, SymbolPayload)> = Vec::new();
+
+    for batch in paths_vec.chunks(FILTER_BATCH_SIZE) {
+        let filter = VectorFilter::any_of("relative_path", batch.iter());
+        for point in scroll_all(vector_store, collection_name, &filter).await? {
+            // a point can show up in more than one batch if it references several stale paths.
+            if points_to_delete.contains(&point.id)
+                || points_to_rewrite
+                    .iter()
+                    .any(|(other, _)| other == &point.id)
+            {
+                continue;
+            }
+            match symbol_payload_without_paths(&point.payload, paths, branch) {
+                Some(payload) => points_to_rewrite.push((point.id, payload)),
+                None => points_to_delete.push(point.id),
+            }
+        }
+    }
+
+    if !points_to_delete.is_empty() {
+        vector_store
+            .delete(
+                collection_name,
+                &PointSelector::Ids(points_to_delete.clone()),
+            )
+            .await?;
+    }
+
+    for (id, payload) in points_to_rewrite.iter() {
+        vector_store
+            .overwrite_payload(
+                collection_name,
+                &PointSelector::Ids(vec![id.clone()]),
+                payload.clone().into_point_payload(),
+            )
+            .await?;
+    }
+
+    debug!(
+        "Pruned symbol occurrences for {} paths: {} points deleted, {} points rewritten",
+        paths.len(),
+        points_to_delete.len(),
+        points_to_rewrite.len()
+    );
+    Ok(())
+}
+
+// The payload of the occurrences of a symbol found in the files of a batch.
+fn symbol_payload(key: &SymbolKey, values: &[SymbolValue], branch: &str) -> SymbolPayload {
+    SymbolPayload {
+        repo_name: key.repo_name.clone(),
+        symbol: key.symbol.clone(),
+        symbol_types: values.iter().map(|v| v.symbol_type.clone()).collect(),
+        lang_ids: values.iter().map(|v| v.language_id.clone()).collect(),
+        is_globals: values.iter().map(|v| v.is_global).collect(),
+        start_bytes: values.iter().map(|v| v.start_byte as i64).collect(),
+        end_bytes: values.iter().map(|v| v.end_byte as i64).collect(),
+        relative_paths: values.iter().map(|v| v.relative_path.clone()).collect(),
+        node_kinds: values.iter().map(|v| v.node_kind.clone()).collect(),
+        branches: vec![branch.to_string(); values.len()],
+        ..Default::default()
+    }
+}
+
+// Appends the occurrences of the new payload to the ones of the existing points of the symbol.
+fn merge_occurrences(
+    existing: impl IntoIterator<Item = SymbolPayload>,
+    new: SymbolPayload,
+) -> SymbolPayload {
+    let mut merged = SymbolPayload {
+        repo_name: new.repo_name.clone(),
+        symbol: new.symbol.clone(),
+        ..Default::default()
+    };
+    for payload in existing.into_iter().chain(std::iter::once(new)) {
+        merged.symbol_types.extend(payload.symbol_types);
+        merged.lang_ids.extend(payload.lang_ids);
+        merged.is_globals.extend(payload.is_globals);
+        merged.start_bytes.extend(payload.start_bytes);
+        merged.end_bytes.extend(payload.end_bytes);
+        merged.relative_paths.extend(payload.relative_paths);
+        merged.node_kinds.extend(payload.node_kinds);
+        merged.branches.extend(payload.branches);
+    }
+    merged
+}
+
+// Reads all the occurrences of a symbol point, None if it has none. The occurrences without a
+// recorded ref are taken to be on `branch`, see `symbol_payload_without_paths`.
+fn symbol_payload_from_point(payload: &PointPayload, branch: &str) -> Option<SymbolPayload> {
+    symbol_payload_without_paths(payload, &HashSet::new(), branch)
+}
+
+// Rebuilds a symbol payload keeping only the occurrences outside of `paths` on `branch`.
+// Occurrences written before schema 5 have their ref under `branch` rather than `branches`, and
+// the ones indexed before refs were recorded have none: they are taken to be on `branch`, so that
+// the branches stay parallel to the other fields. Returns None when no occurrence is left.
+fn symbol_payload_without_paths(
+    payload: &PointPayload,
+    paths: &HashSet<String>,
+    branch: &str,
+) -> Option<SymbolPayload> {
+    let relative_paths = list_values(payload, "relative_path");
+    let lang_ids = list_values(payload, "lang");
+    let symbol_types = list_values(payload, "symbol_type");
+    let start_bytes = list_values(payload, "start_byte");
+    let end_bytes = list_values(payload, "end_byte");
+    let node_kinds = list_values(payload, "node_kind");
+    let is_globals = list_values(payload, "is_global");
+    let recorded_branches = list_values(payload, "branches");
+    let legacy_branches = list_values(payload, "branch");
+    let branches = (0..relative_paths.len())
+        .map(|i| {
+            recorded_branches
+                .get(i)
+                .or_else(|| legacy_branches.get(i))
+                .and_then(as_string)
+                .map_or_else(|| Value::from(branch), Value::from)
+        })
+        .collect::<Vec<_>>();
+
+    let keep = relative_paths
+        .iter()
+        .zip(branches.iter())
+        .map(|(path, occurrence_branch)| {
+            let stale_path = as_string(path).map_or(false, |p| paths.contains(&p));
+            !(stale_path && occurrence_branch.as_str() == Some(branch))
+        })
+        .collect::<Vec<_>>();
+
+    if !keep.iter().any(|&k| k) {
+        return None;
+    }
+
+    fn retain<T>(values: &[Value], keep: &[bool], convert: fn(&Value) -> Option<T>) -> Vec<T> {
+        values
+            .iter()
+            .zip(keep.iter())
+            .filter(|(_, &keep)| keep)
+            .filter_map(|(value, _)| convert(value))
+            .collect()
+    }
+
+    Some(SymbolPayload {
+        repo_name: payload
+            .get("repo_name")
+            .and_then(as_string)
+            .unwrap_or_default(),
+        symbol: payload
+            .get("symbol")
+            .and_then(as_string)
+            .unwrap_or_default(),
+        relative_paths: retain(&relative_paths, &keep, as_string),
+        lang_ids: retain(&lang_ids, &keep, as_string),
+        symbol_types: retain(&symbol_types, &keep, as_string),
+        start_bytes: retain(&start_bytes, &keep, Value::as_i64),
+        end_bytes: retain(&end_bytes, &keep, Value::as_i64),
+        node_kinds: retain(&node_kinds, &keep, as_string),
+        is_globals: retain(&is_globals, &keep, Value::as_bool),
+        branches: retain(&branches, &keep, as_string),
+        ..Default::default()
+    })
+}
+
+fn list_values(payload: &PointPayload, key: &str) -> Vec<Value> {
+    match payload.get(key) {
+        Some(Value::Array(values)) => values.clone(),
+        Some(value) => vec![value.clone()],
+        None => Vec::new(),
+    }
+}
+
+fn as_string(value: &Value) -> Option<String> {
+    value.as_str().map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn value(path: &str, start_byte: usize) -> SymbolValue {
+        SymbolValue {
+            symbol_type: "function".to_string(),
+            language_id: "rust".to_string(),
+            is_global: true,
+            relative_path: path.to_string(),
+            start_byte,
+            end_byte: start_byte + 4,
+            node_kind: "function_item".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_merge_occurrences() {
+        let key = SymbolKey {
+            symbol: "step".to_string(),
+            repo_name: "repo".to_string(),
+        };
+        let first = symbol_payload(&key, &[value("src/a.rs", 10)], "main");
+        let second = symbol_payload(&key, &[value("src/b.rs", 20), value("src/c.rs", 30)], "dev");
+        // the point of a symbol doesn't depend on the batch or ref it was found in.
+        assert_eq!(first.point_id(), second.point_id());
+        let other = SymbolKey {
+            symbol: "run".to_string(),
+            ..key.clone()
+        };
+        assert_ne!(
+            first.point_id(),
+            symbol_payload(&other, &[], "main").point_id()
+        );
+
+        let stored = symbol_payload_from_point(&first.into_point_payload(), "dev").unwrap();
+        let merged = merge_occurrences([stored], second);
+        assert_eq!(merged.symbol, "step");
+        assert_eq!(
+            merged.relative_paths,
+            vec!["src/a.rs", "src/b.rs", "src/c.rs"]
+        );
+        assert_eq!(merged.start_bytes, vec![10, 20, 30]);
+        assert_eq!(merged.end_bytes, vec![14, 24, 34]);
+        assert_eq!(merged.branches, vec!["main", "dev", "dev"]);
+        assert_eq!(merged.is_globals.len(), 3);
+    }
+
+    #[test]
+    fn test_legacy_symbol_payload() {
+        let payload = |branches: Option<(&str, Value)>| {
+            let mut payload = PointPayload::from([
+                ("repo_name".to_string(), Value::from("repo")),
+                ("symbol".to_string(), Value::from("step")),
+                ("relative_path".to_string(), json!(["src/a.rs", "src/b.rs"])),
+                ("start_byte".to_string(), json!([10, 20])),
+                ("end_byte".to_string(), json!([14, 24])),
+            ]);
+            payload.extend(branches.map(|(key, value)| (key.to_string(), value)));
+            payload
+        };
+
+        // schema 2 to 4 record the ref of the occurrences under `branch`.
+        let stored = payload(Some(("branch", json!(["main", "dev"]))));
+        let merged = merge_occurrences(
+            symbol_payload_from_point(&stored, "dev"),
+            symbol_payload(
+                &SymbolKey {
+                    symbol: "step".to_string(),
+                    repo_name: "repo".to_string(),
+                },
+                &[value("src/c.rs", 30)],
+                "dev",
+            ),
+        );
+        assert_eq!(merged.relative_paths, vec!["src/a.rs", "src/b.rs", "src/c.rs"]);
+        assert_eq!(merged.branches, vec!["main", "dev", "dev"]);
+
+        let pruned = symbol_payload_without_paths(
+            &stored,
+            &HashSet::from(["src/a.rs".to_string(), "src/b.rs".to_string()]),
+            "dev",
+        )
+        .unwrap();
+        assert_eq!(pruned.relative_paths, vec!["src/a.rs"]);
+        assert_eq!(pruned.branches, vec!["main"]);
+
+        // schema 1 doesn't record them, the occurrences are taken to be on the ref indexed.
+        let stored = payload(None);
+        let read = symbol_payload_from_point(&stored, "main").unwrap();
+        assert_eq!(read.branches, vec!["main", "main"]);
+        assert_eq!(read.start_bytes, vec![10, 20]);
+        let pruned = symbol_payload_without_paths(
+            &stored,
+            &HashSet::from(["src/a.rs".to_string()]),
+            "main",
+        )
+        .unwrap();
+        assert_eq!(pruned.relative_paths, vec!["src/b.rs"]);
+        assert_eq!(pruned.branches, vec!["main"]);
+    }
+}
@@ -1,5 +1,6 @@
 extern crate clap;
 use clap::builder::PossibleValue;
+use common::ast::ast_graph::NamedScope;
 use common::ast::text_range::Point;
 use common::ast::text_range::TextRange;
 // use range
@@ -147,6 +148,215 @@ impl Default for OverlapStrategy {
     }
 }
 
+/// How the files of a repository are split into chunks before being embedded.
+#[derive(Copy, Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChunkingStrategy {
+    /// Overlapping token windows, cut at newlines where possible.
+    #[default]
+    Tokens,
+    /// Fixed windows of `LINES_PER_CHUNK` lines.
+    Lines,
+    /// Chunks aligned to the functions, classes and impl blocks of the scope graph, falling back
+    /// to token windows for oversized scopes and files without a scope graph.
+    Syntax,
+}
+
+impl TryFrom<&'_ str> for ChunkingStrategy {
+    type Error = &'static str;
+
+    fn try_from(input: &str) -> Result<Self, &'static str> {
+        match input {
+            "tokens" => Ok(Self::Tokens),
+            "lines" => Ok(Self::Lines),
+            "syntax" => Ok(Self::Syntax),
+            _ => Err("chunking should be one of tokens, lines or syntax"),
+        }
+    }
+}
+
+/// Number of lines per chunk with `ChunkingStrategy::Lines`.
+pub const LINES_PER_CHUNK: usize = 20;
+
+/// Separator between the labels of the scope path of a chunk.
+pub const SCOPE_PATH_SEPARATOR: &str = " > ";
+
+/// A byte range of a file to be chunked, along with the path of the scopes enclosing it.
+#[derive(Debug, PartialEq)]
+pub struct ScopeChunk {
+    pub range: Range<usize>,
+    pub scope_path: String,
+    /// The range doesn't fit in the token budget and has to be split into token windows.
+    pub oversized: bool,
+}
+
+// A named scope and the indices of the named scopes directly nested in it.
+struct ScopeNode<'a> {
+    scope: &'a NamedScope,
+    children: Vec<usize>,
+}
+
+/// Splits a file along its named scopes so that every chunk holds whole functions, classes or
+/// impl blocks where they fit in `max_tokens`. Scopes that don't fit are split along their
+/// nested scopes, or flagged as oversized when they have none. The code between scopes is packed
+/// with its neighbours, and a chunk holding a single scope is attributed to it.
+///
+/// `scopes` must be ordered by their start, with enclosing scopes first, as returned by
+/// `ScopeGraph::named_scopes`.
+pub fn plan_scope_chunks(
+    src: &str,
+    scopes: &[NamedScope],
+    max_tokens: usize,
+    token_count: impl Fn(Range<usize>) -> usize,
+) -> Vec<ScopeChunk> {
+    let mut nodes: Vec<ScopeNode> = Vec::with_capacity(scopes.len());
+    let mut roots = Vec::new();
+    let mut stack: Vec<usize> = Vec::new();
+    for scope in scopes {
+        while let Some(&parent) = stack.last() {
+            if nodes[parent].scope.range.end.byte >= scope.range.end.byte {
+                break;
+            }
+            stack.pop();
+        }
+        let idx = nodes.len();
+        match stack.last() {
+            Some(&parent) => nodes[parent].children.push(idx),
+            None => roots.push(idx),
+        }
+        nodes.push(ScopeNode {
+            scope,
+            children: Vec::new(),
+        });
+        stack.push(idx);
+    }
+
+    let planner = ScopePlanner {
+        src,
+        nodes: &nodes,
+        max_tokens,
+        token_count: &token_count,
+    };
+    let mut chunks = Vec::new();
+    planner.plan(0..src.len(), &roots, &[], &mut chunks);
+    chunks
+}
+
+struct ScopePlanner<'a, F> {
+    src: &'a str,
+    nodes: &'a [ScopeNode<'a>],
+    max_tokens: usize,
+    token_count: &'a F,
+}
+
+impl<'a, F: Fn(Range<usize>) -> usize> ScopePlanner<'a, F> {
+    fn plan(
+        &self,
+        region: Range<usize>,
+        children: &[usize],
+        path: &[&str],
+        chunks: &mut Vec<ScopeChunk>,
+    ) {
+        if (self.token_count)(region.clone()) <= self.max_tokens || children.is_empty() {
+            self.push(region.clone(), path, None, chunks);
+            return;
+        }
+
+        // the region alternates between the code outside the scopes and the scopes themselves.
+        let mut segments = Vec::new();
+        let mut cursor = region.start;
+        for &child in children {
+            let range = self.nodes[child].scope.range;
+            let (start, end) = (range.start.byte.max(cursor), range.end.byte.min(region.end));
+            if start > cursor {
+                segments.push((cursor..start, None));
+            }
+            segments.push((start..end, Some(child)));
+            cursor = end;
+        }
+        if cursor < region.end {
+            segments.push((cursor..region.end, None));
+        }
+
+        // consecutive segments are packed together while they fit in the budget.
+        let mut packed: Option<(Range<usize>, Vec<usize>)> = None;
+        for (range, child) in segments {
+            if (self.token_count)(range.clone()) > self.max_tokens {
+                if let Some((packed_range, packed_children)) = packed.take() {
+                    self.push_packed(packed_range, &packed_children, path, chunks);
+                }
+                match child {
+                    Some(child) => {
+                        let node = &self.nodes[child];
+                        let mut child_path = path.to_vec();
+                        child_path.push(&node.scope.label);
+                        self.plan(range, &node.children, &child_path, chunks);
+                    }
+                    None => self.push(range, path, None, chunks),
+                }
+                continue;
+            }
+
+            packed = match packed.take() {
+                Some((packed_range, mut packed_children))
+                    if (self.token_count)(packed_range.start..range.end) <= self.max_tokens =>
+                {
+                    packed_children.extend(child);
+                    Some((packed_range.start..range.end, packed_children))
+                }
+                previous => {
+                    if let Some((packed_range, packed_children)) = previous {
+                        self.push_packed(packed_range, &packed_children, path, chunks);
+                    }
+                    Some((range, child.into_iter().collect()))
+                }
+            };
+        }
+        if let Some((packed_range, packed_children)) = packed {
+            self.push_packed(packed_range, &packed_children, path, chunks);
+        }
+    }
+
+    fn push_packed(
+        &self,
+        range: Range<usize>,
+        children: &[usize],
+        path: &[&str],
+        chunks: &mut Vec<ScopeChunk>,
+    ) {
+        let label = match children {
+            [child] => Some(self.nodes[*child].scope.label.as_str()),
+            _ => None,
+        };
+        self.push(range, path, label, chunks);
+    }
+
+    fn push(
+        &self,
+        range: Range<usize>,
+        path: &[&str],
+        label: Option<&str>,
+        chunks: &mut Vec<ScopeChunk>,
+    ) {
+        // the code between scopes is often just whitespace and closing braces.
+        if !self.src[range.clone()].chars().any(char::is_alphanumeric) {
+            return;
+        }
+        let oversized = (self.token_count)(range.clone()) > self.max_tokens;
+        let scope_path = path
+            .iter()
+            .copied()
+            .chain(label)
+            .collect::<Vec<_>>()
+            .join(SCOPE_PATH_SEPARATOR);
+        chunks.push(ScopeChunk {
+            range,
+            scope_path,
+            oversized,
+        });
+    }
+}
+
 /// This should take care of [CLS], [SEP] etc. which could be introduced during per-chunk tokenization
 pub const DEDUCT_SPECIAL_TOKENS: usize = 2;
 
@@ -208,3 +418,55 @@ pub fn point(src: &str, byte: usize, last_line: usize, last_byte: usize) -> Poin
     };
     Point { byte, column, line }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn named_scope(src: &str, label: &str, text: &str) -> NamedScope {
+        let start = src.find(text).unwrap();
+        let end = start + text.len();
+        NamedScope {
+            label: label.to_string(),
+            range: TextRange {
+                start: point(src, start, 0, 0),
+                end: point(src, end, 0, 0),
+            },
+        }
+    }
+
+    #[test]
+    fn test_plan_scope_chunks() {
+        let step = "fn step() {\n  a b c\n  }";
+        let reset = "fn reset() {\n  a b c d e f g h\n  }";
+        let agent = format!("impl Agent {{\n  {}\n  {}\n}}", step, reset);
+        let src = format!("use a;\n\n{}\n\nfn main() {{ a }}\n", agent);
+        let scopes = vec![
+            named_scope(&src, "impl Agent", &agent),
+            named_scope(&src, "fn step", step),
+            named_scope(&src, "fn reset", reset),
+            named_scope(&src, "fn main", "fn main() { a }"),
+        ];
+
+        // tokens are whitespace separated words.
+        let chunks = plan_scope_chunks(&src, &scopes, 10, |range| {
+            src[range].split_whitespace().count()
+        });
+        let chunks = chunks
+            .iter()
+            .map(|chunk| (chunk.scope_path.as_str(), chunk.oversized))
+            .collect::<Vec<_>>();
+
+        // the impl block doesn't fit and is split along its functions: its header is packed with
+        // `step`, `reset` has no nested scope to split along and the closing brace is dropped.
+        assert_eq!(
+            chunks,
+            vec![
+                ("", false),
+                ("impl Agent > fn step", false),
+                ("impl Agent > fn reset", true),
+                ("fn main", false),
+            ]
+        );
+    }
+}
@@ -0,0 +1,270 @@
+use std::ops::Range;
+
+// Separator of the headings in a heading path, the same as the one of the scope paths.
+pub const HEADING_SEPARATOR: &str = " > ";
+
+/// A part of a Markdown file running from a heading to the next heading of any level. The text
+/// before the first heading is a section with an empty heading path.
+#[derive(Debug, PartialEq)]
+pub struct Section {
+    // The headings enclosing the section, outermost first, ending with its own heading.
+    pub heading_path: Vec<String>,
+    // Bytes of the section in the file, its heading included.
+    pub range: Range<usize>,
+    // 0-based lines of the first and last lines of the section.
+    pub start_line: usize,
+    pub end_line: usize,
+    // Languages of the code fences of the section, in order of appearance and without duplicates.
+    pub code_languages: Vec<String>,
+}
+
+// An open code fence: its character and length, a closing fence has to be at least as long.
+struct Fence {
+    marker: char,
+    len: usize,
+}
+
+/// Splits a Markdown file into heading-scoped sections. Headings inside code fences and the front
+/// matter are ignored; sections with nothing but blank lines under their heading are dropped.
+pub fn sections(src: &str) -> Vec<Section> {
+    let mut sections = Vec::new();
+    // (level, text) of the headings enclosing the current line.
+    let mut headings: Vec<(usize, String)> = Vec::new();
+    let mut current = Section {
+        heading_path: Vec::new(),
+        range: 0..0,
+        start_line: 0,
+        end_line: 0,
+        code_languages: Vec::new(),
+    };
+    // Where the text under the heading of the current section starts.
+    let mut body_start = 0;
+    let mut fence: Option<Fence> = None;
+    // Start byte and text of the previous line if it can be the title of a setext heading.
+    let mut paragraph_line: Option<(usize, &str)> = None;
+    let mut in_front_matter = src.starts_with("---\n") || src.starts_with("---\r\n");
+
+    let mut offset = 0;
+    for (line_number, line) in src.split_inclusive('\n').enumerate() {
+        let start = offset;
+        offset += line.len();
+        let content = line.trim_end_matches(['\n', '\r']);
+
+        if in_front_matter {
+            if line_number > 0 && matches!(content.trim_end(), "---" | "...") {
+                in_front_matter = false;
+            }
+            continue;
+        }
+
+        if let Some(open) = &fence {
+            if closes_fence(content, open) {
+                fence = None;
+            }
+            continue;
+        }
+        if let Some((opened, language)) = opens_fence(content) {
+            fence = Some(opened);
+            if let Some(language) = language {
+                if !current.code_languages.contains(&language) {
+                    current.code_languages.push(language);
+                }
+            }
+            paragraph_line = None;
+            continue;
+        }
+
+        let heading = match (atx_heading(content), setext_level(content), paragraph_line) {
+            (Some((level, text)), _, _) => Some((level, text.to_string(), start, line_number)),
+            (None, Some(level), Some((title_start, title))) => Some((
+                level,
+                title.trim().to_string(),
+                title_start,
+                line_number - 1,
+            )),
+            _ => None,
+        };
+
+        match heading {
+            Some((level, text, heading_start, heading_line)) => {
+                current.range.end = heading_start;
+                push_section(&mut sections, src, current, body_start);
+                body_start = offset;
+
+                headings.retain(|(other, _)| *other < level);
+                headings.push((level, text));
+                current = Section {
+                    heading_path: headings.iter().map(|(_, text)| text.clone()).collect(),
+                    range: heading_start..heading_start,
+                    start_line: heading_line,
+                    end_line: line_number,
+                    code_languages: Vec::new(),
+                };
+                paragraph_line = None;
+            }
+            None => {
+                paragraph_line = (!content.trim().is_empty()).then_some((start, content));
+            }
+        }
+    }
+
+    current.range.end = src.len();
+    push_section(&mut sections, src, current, body_start);
+    sections
+}
+
+// Adds the section unless there is nothing but blank lines under its heading. Its last line is
+// the last one that isn't blank.
+fn push_section(sections: &mut Vec<Section>, src: &str, mut section: Section, body_start: usize) {
+    if src[body_start.max(section.range.start)..section.range.end]
+        .trim()
+        .is_empty()
+    {
+        return;
+    }
+    let text = src[section.range.clone()].trim_end();
+    section.end_line = section.start_line + text.matches('\n').count();
+    sections.push(section);
+}
+
+// Lines indented by more than 3 spaces are code blocks, not headings or fences.
+fn unindent(line: &str) -> Option<&str> {
+    let trimmed = line.trim_start_matches(' ');
+    (line.len() - trimmed.len() <= 3).then_some(trimmed)
+}
+
+// `## Title ##` -> (2, "Title")
+fn atx_heading(line: &str) -> Option<(usize, &str)> {
+    let line = unindent(line)?;
+    let level = line.chars().take_while(|&c| c == '#').count();
+    if !(1..=6).contains(&level) {
+        return None;
+    }
+    let rest = &line[level..];
+    if !rest.is_empty() && !rest.starts_with([' ', '\t']) {
+        return None;
+    }
+    let text = rest.trim();
+    // the closing sequence has to be separated from the text.
+    let unclosed = text.trim_end_matches('#');
+    let text = if unclosed.is_empty() || unclosed.ends_with([' ', '\t']) {
+        unclosed.trim_end()
+    } else {
+        text
+    };
+    Some((level, text))
+}
+
+// `===` underlines a level 1 heading and `---` a level 2 one.
+fn setext_level(line: &str) -> Option<usize> {
+    let line = unindent(line)?.trim_end();
+    match line.chars().next()? {
+        '=' if line.chars().all(|c| c == '=') => Some(1),
+        '-' if line.chars().all(|c| c == '-') => Some(2),
+        _ => None,
+    }
+}
+
+// "```rust ignore" -> the fence and Some("rust")
+fn opens_fence(line: &str) -> Option<(Fence, Option<String>)> {
+    let line = unindent(line)?;
+    let marker = line.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let len = line.chars().take_while(|&c| c == marker).count();
+    if len < 3 {
+        return None;
+    }
+    let info = line[len..].trim();
+    // backtick fences can't have backticks in their info string.
+    if marker == '`' && info.contains('`') {
+        return None;
+    }
+    let language = info
+        .split(|c: char| c.is_whitespace() || c == ',' || c == '{')
+        .next()
+        .filter(|language| !language.is_empty())
+        .map(str::to_ascii_lowercase);
+    Some((Fence { marker, len }, language))
+}
+
+fn closes_fence(line: &str, fence: &Fence) -> bool {
+    unindent(line).map_or(false, |line| {
+        let line = line.trim_end();
+        line.len() >= fence.len && line.chars().all(|c| c == fence.marker)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sections() {
+        let src = "\
+---
+title: Guide
+---
+Intro line.
+
+# Setup
+Install it:
+
+```bash
+# not a heading
+cargo install nezuko
+```
+
+## Linux ##
+```rust,ignore
+fn main() {}
+```
+~~~bash
+apt install
+~~~
+
+Usage
+-----
+Run it.
+
+# Empty
+
+";
+        let sections = sections(src);
+        let summary = sections
+            .iter()
+            .map(|section| {
+                (
+                    section.heading_path.join(HEADING_SEPARATOR),
+                    section.start_line,
+                    section.end_line,
+                    section.code_languages.clone(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                (String::new(), 0, 3, vec![]),
+                ("Setup".to_string(), 5, 11, vec!["bash".to_string()]),
+                (
+                    "Setup > Linux".to_string(),
+                    13,
+                    19,
+                    vec!["rust".to_string(), "bash".to_string()]
+                ),
+                ("Setup > Usage".to_string(), 21, 23, vec![]),
+            ]
+        );
+        assert!(src[sections[1].range.clone()].starts_with("# Setup\n"));
+        assert!(src[sections[3].range.clone()].starts_with("Usage\n-----\n"));
+    }
+
+    #[test]
+    fn test_headings() {
+        assert_eq!(atx_heading("### Title ###"), Some((3, "Title")));
+        assert_eq!(atx_heading("# C#"), Some((1, "C#")));
+        assert_eq!(atx_heading("#hashtag"), None);
+        assert_eq!(atx_heading("    # indented code"), None);
+        assert_eq!(setext_level("==="), Some(1));
+        assert_eq!(setext_level("- item"), None);
+    }
+}
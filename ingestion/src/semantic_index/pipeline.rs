@@ -0,0 +1,141 @@
+use anyhow::anyhow;
+use common::embedding::{Embedder, Embedding};
+use common::vector::{VectorPoint, VectorStore};
+use futures::stream::{self, StreamExt, TryStreamExt};
+use log::info;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+// Number of sequences sent to the embedder at once. The ONNX backend splits them further by token count.
+const EMBEDDING_BATCH_SIZE: usize = 64;
+// Number of batches embedded concurrently.
+const EMBEDDING_CONCURRENCY: usize = 4;
+// Number of embedded batches waiting to be upserted before the embedding side is paused.
+const UPSERT_QUEUE_CAPACITY: usize = 8;
+
+/// Throughput of an embedding run, logged once all the points are upserted.
+#[derive(Debug, Default)]
+pub struct EmbeddingMetrics {
+    pub sequences: usize,
+    pub batches: usize,
+    pub points_upserted: usize,
+    // Time spent waiting on the embedder and on the vector store. The two overlap, so they can add up to
+    // more than the elapsed time.
+    pub embedding_time: Duration,
+    pub upsert_time: Duration,
+    pub elapsed: Duration,
+}
+
+impl EmbeddingMetrics {
+    fn log(&self, label: &str) {
+        let seconds = self.elapsed.as_secs_f64().max(f64::EPSILON);
+        info!(
+            "Embedded {} {} in {} batches in {:.2?} ({:.1} per second), embedding {:.2?}, upserting {} points {:.2?}",
+            self.sequences,
+            label,
+            self.batches,
+            self.elapsed,
+            self.sequences as f64 / seconds,
+            self.embedding_time,
+            self.points_upserted,
+            self.upsert_time,
+        );
+    }
+}
+
+/// Embeds the text of the items in concurrent batches and upserts the resulting points.
+///
+/// Embedded batches go through a bounded channel to a consumer that upserts them to the store, so
+/// the upserts of a batch overlap with the embedding of the next ones while memory stays bounded.
+/// `on_progress` is called with the number of items of each embedded batch.
+pub async fn embed_and_upsert<T>(
+    embedder: &Arc<dyn Embedder>,
+    vector_store: &dyn VectorStore,
+    collection_name: &str,
+    label: &str,
+    items: Vec<T>,
+    text: impl Fn(&T) -> &str,
+    into_point: impl Fn(T, Embedding) -> VectorPoint,
+    mut on_progress: impl FnMut(usize),
+) -> anyhow::Result<EmbeddingMetrics> {
+    let started = Instant::now();
+    let mut metrics = EmbeddingMetrics::default();
+    let mut upsert_time = Duration::ZERO;
+    let mut points_upserted = 0;
+    let (sender, receiver) = mpsc::channel::<Vec<VectorPoint>>(UPSERT_QUEUE_CAPACITY);
+
+    let mut batches = Vec::new();
+    let mut items = items.into_iter().peekable();
+    while items.peek().is_some() {
+        batches.push(
+            items
+                .by_ref()
+                .take(EMBEDDING_BATCH_SIZE)
+                .collect::<Vec<_>>(),
+        );
+    }
+
+    let text = &text;
+    let produce = async {
+        // the sender is moved in so that the consumer stops once everything is sent or on error.
+        let sender = sender;
+        let mut embedded = stream::iter(batches)
+            .map(|batch| async move {
+                let sequences = batch.iter().map(|item| text(item)).collect::<Vec<_>>();
+                let embedding_started = Instant::now();
+                let embeddings = embedder.embed_batch(&sequences).await?;
+                if embeddings.len() != batch.len() {
+                    return Err(anyhow!(
+                        "expected {} embeddings, got {}",
+                        batch.len(),
+                        embeddings.len()
+                    ));
+                }
+                Ok::<_, anyhow::Error>((batch, embeddings, embedding_started.elapsed()))
+            })
+            .buffered(EMBEDDING_CONCURRENCY);
+
+        while let Some((batch, embeddings, embedding_time)) = embedded.try_next().await? {
+            metrics.sequences += batch.len();
+            metrics.batches += 1;
+            metrics.embedding_time += embedding_time;
+            on_progress(batch.len());
+
+            let points = batch
+                .into_iter()
+                .zip(embeddings)
+                .map(|(item, embedding)| into_point(item, embedding))
+                .collect::<Vec<_>>();
+            sender
+                .send(points)
+                .await
+                .map_err(|_| anyhow!("the upsert queue of {} was closed", collection_name))?;
+        }
+        Ok::<_, anyhow::Error>(())
+    };
+
+    let consume = async {
+        // likewise, dropping the receiver on error stops the producer.
+        let mut receiver = receiver;
+        while let Some(points) = receiver.recv().await {
+            let count = points.len();
+            let upsert_started = Instant::now();
+            vector_store.upsert(collection_name, points).await?;
+            upsert_time += upsert_started.elapsed();
+            points_upserted += count;
+        }
+        Ok::<_, anyhow::Error>(())
+    };
+
+    let (produced, consumed) = tokio::join!(produce, consume);
+    // an upsert failure closes the queue, report it rather than the send error it causes.
+    consumed?;
+    produced?;
+
+    metrics.upsert_time = upsert_time;
+    metrics.points_upserted = points_upserted;
+    metrics.elapsed = started.elapsed();
+    metrics.log(label);
+    Ok(metrics)
+}
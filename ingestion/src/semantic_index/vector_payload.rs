@@ -1,9 +1,10 @@
-use qdrant_client::prelude::Value;
-use std::collections::HashMap;
+use common::vector::PointPayload;
 
 pub type Embedding = Vec<f32>;
 
-// Payload format to write and deserialize data in and from qdrant.
+// Payload format to write and deserialize data in and from the vector store.
+// The fields of the payloads are part of the index schema: changing them means bumping
+// `common::schema::SCHEMA_VERSION` and adding a migration to `migration::MIGRATIONS`.
 #[derive(Default, Clone, Debug, serde::Deserialize, serde::Serialize)]
 pub struct SymbolPayload {
     pub repo_name: String,
@@ -16,6 +17,9 @@ pub struct SymbolPayload {
     pub end_bytes: Vec<i64>,
     pub relative_paths: Vec<String>,
     pub node_kinds: Vec<String>,
+    // branch or tag each occurrence was indexed from.
+    #[serde(default)]
+    pub branches: Vec<String>,
 
     #[serde(skip)]
     pub id: Option<String>,
@@ -26,8 +30,15 @@ pub struct SymbolPayload {
 }
 
 impl SymbolPayload {
-    pub fn convert_to__qdrant_fields(self) -> HashMap<String, Value> {
-        HashMap::from([
+    // Derived from the `SymbolKey` of the symbol, the repo and the symbol name, so that every
+    // run upserts the same point.
+    pub fn point_id(&self) -> String {
+        let digest = md5::compute(format!("{}#{}", self.repo_name, self.symbol));
+        uuid::Uuid::from_bytes(digest.0).to_string()
+    }
+
+    pub fn into_point_payload(self) -> PointPayload {
+        PointPayload::from([
             ("repo_name".into(), self.repo_name.into()),
             ("symbol".into(), self.symbol.into()),
             ("lang".into(), self.lang_ids.into()),
@@ -37,6 +48,7 @@ impl SymbolPayload {
             ("relative_path".into(), self.relative_paths.into()),
             ("node_kind".into(), self.node_kinds.into()),
             ("is_global".into(), self.is_globals.into()),
+            ("branches".into(), self.branches.into()),
         ])
     }
 }
@@ -54,6 +66,9 @@ pub struct Payload {
     pub start_byte: u64,
     pub end_byte: u64,
     pub branches: Vec<String>,
+    // Labels of the scopes enclosing the chunk, e.g. `impl Agent > fn step`. Empty unless the
+    // syntax chunking strategy is used.
+    pub scope_path: String,
 
     #[serde(skip)]
     pub id: Option<String>,
@@ -64,10 +79,11 @@ pub struct Payload {
 }
 
 impl Payload {
-    pub fn convert_to__qdrant_fields(self) -> HashMap<String, Value> {
-        HashMap::from([
+    pub fn into_point_payload(self) -> PointPayload {
+        PointPayload::from([
             ("lang".into(), self.lang.to_ascii_lowercase().into()),
             ("repo_name".into(), self.repo_name.into()),
+            ("repo_ref".into(), self.repo_ref.into()),
             ("relative_path".into(), self.relative_path.into()),
             ("content_hash".into(), self.content_hash.into()),
             ("snippet".into(), self.text.into()),
@@ -75,9 +91,81 @@ impl Payload {
             ("end_line".into(), self.end_line.to_string().into()),
             ("start_byte".into(), self.start_byte.to_string().into()),
             ("end_byte".into(), self.end_byte.to_string().into()),
+            ("branches".into(), self.branches.into()),
+            ("scope_path".into(), self.scope_path.into()),
         ])
     }
 }
+// A section of a Markdown file, stored in the docs collection of the repo.
+#[derive(Default, Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct DocPayload {
+    pub repo_name: String,
+    pub relative_path: String,
+    pub content_hash: String,
+    // The headings enclosing the section, e.g. `Setup > Linux`. Empty before the first heading.
+    pub heading_path: String,
+    // Languages of the code fences of the section.
+    pub code_languages: Vec<String>,
+    pub text: String,
+    pub start_line: u64,
+    pub end_line: u64,
+    pub start_byte: u64,
+    pub end_byte: u64,
+    pub branches: Vec<String>,
+}
+
+impl DocPayload {
+    pub fn into_point_payload(self) -> PointPayload {
+        PointPayload::from([
+            ("lang".into(), "markdown".into()),
+            ("repo_name".into(), self.repo_name.into()),
+            ("relative_path".into(), self.relative_path.into()),
+            ("content_hash".into(), self.content_hash.into()),
+            ("heading_path".into(), self.heading_path.into()),
+            ("code_languages".into(), self.code_languages.into()),
+            ("snippet".into(), self.text.into()),
+            ("start_line".into(), self.start_line.to_string().into()),
+            ("end_line".into(), self.end_line.to_string().into()),
+            ("start_byte".into(), self.start_byte.to_string().into()),
+            ("end_byte".into(), self.end_byte.to_string().into()),
+            ("branches".into(), self.branches.into()),
+        ])
+    }
+}
+
+// A commit of the repo and the paths it touched, stored in the commits collection.
+#[derive(Default, Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct CommitPayload {
+    pub repo_name: String,
+    pub commit: String,
+    pub author: String,
+    // Commit time in seconds since the epoch.
+    pub date: i64,
+    pub message: String,
+    pub paths: Vec<String>,
+    pub branches: Vec<String>,
+}
+
+impl CommitPayload {
+    // The same commit of a repo always maps to the same point.
+    pub fn point_id(&self) -> String {
+        let digest = md5::compute(format!("{}@{}", self.repo_name, self.commit));
+        uuid::Uuid::from_bytes(digest.0).to_string()
+    }
+
+    pub fn into_point_payload(self) -> PointPayload {
+        PointPayload::from([
+            ("repo_name".into(), self.repo_name.into()),
+            ("commit".into(), self.commit.into()),
+            ("author".into(), self.author.into()),
+            ("date".into(), self.date.into()),
+            ("message".into(), self.message.into()),
+            ("paths".into(), self.paths.into()),
+            ("branches".into(), self.branches.into()),
+        ])
+    }
+}
+
 impl PartialEq for Payload {
     fn eq(&self, other: &Self) -> bool {
         self.lang == other.lang
@@ -91,6 +179,7 @@ impl PartialEq for Payload {
             && self.start_byte == other.start_byte
             && self.end_byte == other.end_byte
             && self.branches == other.branches
+            && self.scope_path == other.scope_path
         // ignoring deserialized fields that will not exist on a newly
         // created payload
     }
@@ -1,7 +1,22 @@
-use super::models::{CodeIndexingRequest, CodeIndexingStatus};
-use ingestion::state::{get_process_state, queue_process, CodeIndexingTaskStatus};
-use ingestion::{Config, Indexer};
-use std::{convert::Infallible, env, path::PathBuf};
+use super::models::{
+    CodeIndexingRequest, CodeIndexingStatus, GarbageCollectionRequest, JobSummary, WatchRequest,
+};
+use futures::stream::{self, Stream, StreamExt};
+use ingestion::gc::{collect_garbage, delete_repository, DeletedRepository, GarbageReport};
+use ingestion::jobs::{cancel_job, queue_config, stop_repo_indexing, submit_job, IndexingJob};
+use ingestion::progress::{subscribe, ProgressUpdate};
+use ingestion::registry::{get_repo, list_repos};
+use ingestion::state::{
+    active_process, get_process_state, list_process_states, CodeIndexingTaskStatus,
+};
+use ingestion::watch::{list_watches, start_watch, stop_watch, WatchedRepo, DEFAULT_DEBOUNCE};
+use ingestion::{Config, DryRunReport, Indexer};
+use std::time::Duration;
+use std::{convert::Infallible, path::PathBuf};
+use tokio::sync::broadcast::error::RecvError;
+use warp::path::Tail;
+use warp::sse::Event;
+use warp::Reply;
 
 pub async fn handle_code_index_wrapper(
     request: CodeIndexingRequest,
@@ -33,66 +48,301 @@ async fn handle_code_index_core(
 ) -> Result<CodeIndexingStatus, anyhow::Error> {
     log::info!("Code indexing request received: {:?}", request);
 
-    let repo_name = request.repo_name.clone();
-    let disk_path_str = request.repo_path.clone();
-    let qdrant_url = env::var("QDRANT_URL").unwrap();
-    let quickwit_url = env::var("QUICKWIT_URL").unwrap();
-    let qdrant_api_key = env::var("QDRANT_API_KEY").unwrap();
-    let branch = request.branch.clone();
-    let version = request.version.clone();
-
-    log::info!("Repo name: {}", repo_name);
-    log::info!("Repo path: {}", disk_path_str);
-    log::info!("Qdrant URL: {}", qdrant_url);
-    log::info!("Quickwit URL: {}", quickwit_url);
-    log::info!("Qdrant API key: {}", qdrant_api_key);
-    log::info!("Branch: {}", branch);
-    log::info!("Version: {}", version);
-
-    // Instantiate an Indexer.
-    let indexer = Indexer;
-
-    let disk_path = PathBuf::from(disk_path_str.clone());
-    let config = Config::new(
-        repo_name.to_string(),
-        disk_path_str.to_string(),
-        qdrant_url.to_string(),
-        quickwit_url.to_string(),
-        qdrant_api_key.to_string(),
-        branch.to_string(),
-        version.to_string(),
-    );
-
-    let task_id = uuid::Uuid::new_v4().to_string();
-    queue_process(&task_id, &repo_name, &disk_path_str);
-
-    // Clone `task_id` for use in the asynchronous block
-    let task_id_for_async = task_id.clone();
+    log::info!("Repo name: {}", request.repo_name);
+    log::info!("Repo path: {}", request.repo_path);
+    log::info!("Branch: {}", request.branch);
+    log::info!("Version: {}", request.version);
+    log::info!("Incremental: {}", request.incremental);
+    log::info!("Include: {:?}", request.include);
+    log::info!("Exclude: {:?}", request.exclude);
+    log::info!("Chunking: {:?}", request.chunking);
+    log::info!("Source: {:?}", request.source);
+    log::info!("Index commits: {}", request.index_commits);
 
-    // Use the indexer to index the repository, passing the disk path.
-    tokio::spawn(async move {
-        let _ = indexer
-            .index_repository(
-                disk_path,
-                repo_name.to_string(),
-                config,
-                &branch,
-                &version,
-                task_id_for_async,
-            )
-            .await;
-    });
+    // The job is picked up by the worker pool once a worker is free.
+    let task_id = submit_job(IndexingJob {
+        repo_name: request.repo_name.clone(),
+        repo_path: request.repo_path.clone(),
+        branch: request.branch.clone(),
+        version: request.version.clone(),
+        incremental: request.incremental,
+        include: request.include.clone(),
+        exclude: request.exclude.clone(),
+        chunking: request.chunking,
+        source: request.source,
+        index_commits: request.index_commits,
+    })?;
 
-    log::info!("Code indexing completed successfully");
+    log::info!("Code indexing job {} queued", task_id);
 
     Ok(CodeIndexingStatus {
         repo_name: request.repo_name,
         repo_path: request.repo_path,
         task_id,
         task_status: CodeIndexingTaskStatus::Queued,
+        progress: 0,
+        phase: None,
     })
 }
 
+pub async fn handle_dry_run_wrapper(
+    request: CodeIndexingRequest,
+) -> Result<impl warp::Reply, Infallible> {
+    log::info!("Received dry run request: {:?}", request);
+
+    match handle_dry_run_core(request).await {
+        Ok(report) => Ok(warp::reply::with_status(
+            warp::reply::json(&report),
+            warp::http::StatusCode::OK,
+        )),
+        Err(e) => {
+            log::error!("Dry run failed: {}", e);
+            Ok(warp::reply::with_status(
+                warp::reply::json(&e.to_string()),
+                warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+            ))
+        }
+    }
+}
+
+// Reports which paths an indexing run with the same request would index, and why the others are skipped.
+async fn handle_dry_run_core(request: CodeIndexingRequest) -> Result<DryRunReport, anyhow::Error> {
+    let config = Config::new(
+        request.repo_name.clone(),
+        request.repo_path.clone(),
+        String::new(),
+        String::new(),
+        String::new(),
+        request.branch.clone(),
+        request.version.clone(),
+        request.incremental,
+    )
+    .with_path_filters(request.include, request.exclude)
+    .with_source(request.source);
+
+    let disk_path = PathBuf::from(&request.repo_path);
+    tokio::task::spawn_blocking(move || Indexer.dry_run(disk_path, &config)).await?
+}
+
+pub async fn handle_cancel_index_wrapper(task_id: String) -> Result<impl warp::Reply, Infallible> {
+    log::info!("Received cancel request for task_id: {}", task_id);
+
+    match handle_cancel_index_core(task_id).await {
+        Ok(status) => Ok(warp::reply::with_status(
+            warp::reply::json(&status),
+            warp::http::StatusCode::OK,
+        )),
+        Err(e) => Ok(warp::reply::with_status(
+            warp::reply::json(&e.to_string()),
+            warp::http::StatusCode::NOT_FOUND,
+        )),
+    }
+}
+
+pub async fn handle_cancel_index_core(
+    task_id: String,
+) -> Result<CodeIndexingStatus, anyhow::Error> {
+    if !cancel_job(&task_id) {
+        return Err(anyhow::anyhow!(
+            "No unfinished task found for id {}",
+            task_id
+        ));
+    }
+
+    handle_index_status_core(task_id).await
+}
+
+pub async fn handle_list_jobs_wrapper() -> Result<impl warp::Reply, Infallible> {
+    let jobs = list_process_states()
+        .into_iter()
+        .map(|(task_id, state)| JobSummary::new(task_id, state))
+        .collect::<Vec<_>>();
+
+    Ok(warp::reply::with_status(
+        warp::reply::json(&jobs),
+        warp::http::StatusCode::OK,
+    ))
+}
+
+pub async fn handle_list_repos_wrapper() -> Result<impl warp::Reply, Infallible> {
+    Ok(warp::reply::with_status(
+        warp::reply::json(&list_repos()),
+        warp::http::StatusCode::OK,
+    ))
+}
+
+pub async fn handle_get_repo_wrapper(tail: Tail) -> Result<impl warp::Reply, Infallible> {
+    let repo_name = repo_name_from_path(&tail);
+
+    match get_repo(&repo_name) {
+        Some(record) => Ok(warp::reply::with_status(
+            warp::reply::json(&record),
+            warp::http::StatusCode::OK,
+        )),
+        None => Ok(warp::reply::with_status(
+            warp::reply::json(&format!("Repo {} is not indexed", repo_name)),
+            warp::http::StatusCode::NOT_FOUND,
+        )),
+    }
+}
+
+// Repo names hold slashes, so they are read from the whole tail of the path, percent-decoded.
+fn repo_name_from_path(tail: &Tail) -> String {
+    percent_encoding::percent_decode_str(tail.as_str())
+        .decode_utf8_lossy()
+        .trim_matches('/')
+        .to_string()
+}
+
+pub async fn handle_delete_repository_wrapper(tail: Tail) -> Result<impl warp::Reply, Infallible> {
+    let repo_name = repo_name_from_path(&tail);
+    log::info!("Received delete request for repo: {}", repo_name);
+
+    if repo_name.is_empty() {
+        return Ok(warp::reply::with_status(
+            warp::reply::json(&"Missing repo name"),
+            warp::http::StatusCode::BAD_REQUEST,
+        ));
+    }
+    // a watcher or a job left running would write the indexes of the repo again.
+    let cancelled = stop_repo_indexing(&repo_name);
+    if !cancelled.is_empty() {
+        log::info!("Cancelled {:?} to delete {}", cancelled, repo_name);
+    }
+
+    match handle_delete_repository_core(repo_name).await {
+        Ok(deleted) => Ok(warp::reply::with_status(
+            warp::reply::json(&deleted),
+            warp::http::StatusCode::OK,
+        )),
+        Err(e) => {
+            log::error!("Repo deletion failed: {:?}", e);
+            Ok(warp::reply::with_status(
+                warp::reply::json(&e.to_string()),
+                warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+            ))
+        }
+    }
+}
+
+async fn handle_delete_repository_core(
+    repo_name: String,
+) -> Result<DeletedRepository, anyhow::Error> {
+    let config = queue_config()?;
+    let vector_store = config.vector_store.build()?;
+    let lexical_index = config.lexical_index.build()?;
+    delete_repository(&repo_name, vector_store.as_ref(), lexical_index.as_ref()).await
+}
+
+pub async fn handle_gc_wrapper(
+    request: GarbageCollectionRequest,
+) -> Result<impl warp::Reply, Infallible> {
+    log::info!("Received garbage collection request: {:?}", request);
+
+    // same as deleting, the job would write while the garbage is collected.
+    if let Some(task_id) = active_process(&request.repo_name) {
+        return Ok(warp::reply::with_status(
+            warp::reply::json(&format!(
+                "Repo {} is being indexed by {}",
+                request.repo_name, task_id
+            )),
+            warp::http::StatusCode::CONFLICT,
+        ));
+    }
+
+    match handle_gc_core(request).await {
+        Ok(report) => Ok(warp::reply::with_status(
+            warp::reply::json(&report),
+            warp::http::StatusCode::OK,
+        )),
+        Err(e) => {
+            log::error!("Garbage collection failed: {:?}", e);
+            Ok(warp::reply::with_status(
+                warp::reply::json(&e.to_string()),
+                warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+            ))
+        }
+    }
+}
+
+async fn handle_gc_core(request: GarbageCollectionRequest) -> Result<GarbageReport, anyhow::Error> {
+    let config = queue_config()?;
+    let vector_store = config.vector_store.build()?;
+    let lexical_index = config.lexical_index.build()?;
+    collect_garbage(
+        &request.repo_name,
+        &PathBuf::from(&request.repo_path),
+        vector_store.as_ref(),
+        lexical_index.as_ref(),
+    )
+    .await
+}
+
+pub async fn handle_start_watch_wrapper(
+    request: WatchRequest,
+) -> Result<impl warp::Reply, Infallible> {
+    log::info!("Received watch request: {:?}", request);
+
+    match handle_start_watch_core(request) {
+        Ok(watched) => Ok(warp::reply::with_status(
+            warp::reply::json(&watched),
+            warp::http::StatusCode::OK,
+        )),
+        Err(e) => {
+            log::error!("Failed to start watching: {:?}", e);
+            Ok(warp::reply::with_status(
+                warp::reply::json(&e.to_string()),
+                warp::http::StatusCode::BAD_REQUEST,
+            ))
+        }
+    }
+}
+
+// Watches the repo with the backends of the worker pool, the watcher runs until it is stopped.
+fn handle_start_watch_core(request: WatchRequest) -> Result<WatchedRepo, anyhow::Error> {
+    let index = request.index;
+    let job = IndexingJob {
+        repo_name: index.repo_name,
+        repo_path: index.repo_path,
+        branch: index.branch,
+        version: index.version,
+        incremental: false,
+        include: index.include,
+        exclude: index.exclude,
+        chunking: index.chunking,
+        source: index.source,
+        index_commits: index.index_commits,
+    };
+    let debounce = request
+        .debounce_ms
+        .map_or(DEFAULT_DEBOUNCE, Duration::from_millis);
+
+    start_watch(job.config(&queue_config()?), debounce)
+}
+
+pub async fn handle_list_watches_wrapper() -> Result<impl warp::Reply, Infallible> {
+    Ok(warp::reply::with_status(
+        warp::reply::json(&list_watches()),
+        warp::http::StatusCode::OK,
+    ))
+}
+
+pub async fn handle_stop_watch_wrapper(tail: Tail) -> Result<impl warp::Reply, Infallible> {
+    let repo_name = repo_name_from_path(&tail);
+    log::info!("Received stop watch request for repo: {}", repo_name);
+
+    match stop_watch(&repo_name) {
+        Some(watched) => Ok(warp::reply::with_status(
+            warp::reply::json(&watched),
+            warp::http::StatusCode::OK,
+        )),
+        None => Ok(warp::reply::with_status(
+            warp::reply::json(&format!("Repo {} is not watched", repo_name)),
+            warp::http::StatusCode::NOT_FOUND,
+        )),
+    }
+}
+
 pub async fn handle_index_status_wrapper(task_id: String) -> Result<impl warp::Reply, Infallible> {
     log::info!(
         "Received code indexing status request for task_id: {}",
@@ -120,7 +370,74 @@ pub async fn handle_index_status_core(
             repo_path: state.repo_path.clone(),
             task_id: task_id.clone(),
             task_status: state.task_status.clone(),
+            progress: state.progress,
+            phase: state.phase.clone(),
         }),
         None => return Err(anyhow::anyhow!("No task found for id {}", task_id)),
     }
 }
+
+pub async fn handle_index_status_stream(task_id: String) -> Result<impl warp::Reply, Infallible> {
+    log::info!("Received progress stream request for task_id: {}", task_id);
+
+    match handle_index_status_stream_core(task_id) {
+        Ok(events) => Ok(warp::sse::reply(warp::sse::keep_alive().stream(events)).into_response()),
+        Err(e) => Ok(warp::reply::with_status(
+            warp::reply::json(&e.to_string()),
+            warp::http::StatusCode::NOT_FOUND,
+        )
+        .into_response()),
+    }
+}
+
+// Sends the current state of the task first, then every update until the task finishes.
+fn handle_index_status_stream_core(
+    task_id: String,
+) -> Result<impl Stream<Item = Result<Event, Infallible>>, anyhow::Error> {
+    // subscribe before reading the current state so no update is missed in between.
+    let receiver = subscribe();
+    let state = get_process_state(&task_id)
+        .ok_or_else(|| anyhow::anyhow!("No task found for id {}", task_id))?;
+    let initial = ProgressUpdate::new(&task_id, &state);
+
+    let updates = stream::unfold(
+        (receiver, initial.task_status.is_finished()),
+        move |(mut receiver, finished)| {
+            let task_id = task_id.clone();
+            async move {
+                if finished {
+                    return None;
+                }
+                loop {
+                    match receiver.recv().await {
+                        Ok(update) if update.task_id == task_id => {
+                            let finished = update.task_status.is_finished();
+                            return Some((update, (receiver, finished)));
+                        }
+                        Ok(_) => continue,
+                        Err(RecvError::Lagged(skipped)) => {
+                            log::warn!(
+                                "Progress stream for {} skipped {} updates",
+                                task_id,
+                                skipped
+                            );
+                            continue;
+                        }
+                        Err(RecvError::Closed) => return None,
+                    }
+                }
+            }
+        },
+    );
+
+    Ok(stream::once(async { initial })
+        .chain(updates)
+        .map(|update| Ok(progress_event(&update))))
+}
+
+fn progress_event(update: &ProgressUpdate) -> Event {
+    Event::default()
+        .event("progress")
+        .json_data(update)
+        .unwrap_or_else(|_| Event::default().event("progress"))
+}
@@ -1,3 +1,32 @@
 pub mod controller;
 pub mod models;
 pub mod routes;
+
+use common::embedding::EmbedderConfig;
+use common::lexical::LexicalIndexConfig;
+use ingestion::jobs::JobQueueConfig;
+use ingestion::Config;
+use std::env;
+
+// Reads the worker pool settings from the environment.
+pub fn job_queue_config() -> JobQueueConfig {
+    let quickwit_url = env::var("QUICKWIT_URL").unwrap();
+    let qdrant_url = env::var("QDRANT_URL").unwrap();
+    let qdrant_api_key = env::var("QDRANT_API_KEY").unwrap();
+    JobQueueConfig {
+        lexical_index: LexicalIndexConfig::from_env(&quickwit_url).unwrap(),
+        vector_store: Config::vector_store_from_env(&qdrant_url, &qdrant_api_key).unwrap(),
+        qdrant_url,
+        quickwit_url,
+        qdrant_api_key,
+        workers: env::var("INGESTION_WORKERS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(2),
+        max_attempts: env::var("INGESTION_MAX_ATTEMPTS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(3),
+        embedder: EmbedderConfig::from_env().unwrap(),
+    }
+}
@@ -1,4 +1,6 @@
-use ingestion::state::CodeIndexingTaskStatus;
+use ingestion::progress::PhaseProgress;
+use ingestion::state::{CodeIndexingTaskStatus, ProcessState};
+use ingestion::{ChunkingStrategy, RepoSource};
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -11,6 +13,23 @@ pub struct CodeIndexingRequest {
     // TODO: Change the version to be a float instead of a string
     #[serde(default = "default_version")]
     pub version: String,
+    // Only re-index the files changed since the last indexed commit of the branch.
+    #[serde(default)]
+    pub incremental: bool,
+    // Globs restricting the indexed paths, on top of .gitignore and .nezukoignore.
+    #[serde(default)]
+    pub include: Vec<String>,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    // How the files are split into chunks: `tokens` (default), `lines` or `syntax`.
+    #[serde(default)]
+    pub chunking: ChunkingStrategy,
+    // Where the files are read from: `git` (default), `working_tree`, `directory` or `archive`.
+    #[serde(default)]
+    pub source: RepoSource,
+    // Also index the commit messages of the branch, searchable by the history tool of the agent.
+    #[serde(default)]
+    pub index_commits: bool,
 }
 
 fn default_repo_path() -> String {
@@ -25,10 +44,63 @@ fn default_version() -> String {
     String::from("v1")
 }
 
+// Body of `POST /watch`: the repo is indexed with the settings of `POST /index`, then watched.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct WatchRequest {
+    #[serde(flatten)]
+    pub index: CodeIndexingRequest,
+    // How long the files have to stay untouched before their changes are indexed.
+    #[serde(default)]
+    pub debounce_ms: Option<u64>,
+}
+
+// Body of `POST /gc`. The files of each indexed ref are read from the git repo at `repo_path`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct GarbageCollectionRequest {
+    pub repo_name: String,
+    pub repo_path: String,
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct CodeIndexingStatus {
     pub repo_name: String,
     pub repo_path: String,
     pub task_id: String,
     pub task_status: CodeIndexingTaskStatus,
+    pub progress: u32,
+    pub phase: Option<PhaseProgress>,
+}
+
+// Entry of the `GET /jobs` listing. Timestamps are unix seconds.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct JobSummary {
+    pub task_id: String,
+    pub repo_name: String,
+    pub repo_path: String,
+    pub branch: Option<String>,
+    pub task_status: CodeIndexingTaskStatus,
+    pub progress: u32,
+    pub attempts: u32,
+    pub error: Option<String>,
+    pub created_at: u64,
+    pub started_at: Option<u64>,
+    pub finished_at: Option<u64>,
+}
+
+impl JobSummary {
+    pub fn new(task_id: String, state: ProcessState) -> Self {
+        Self {
+            task_id,
+            branch: state.job.map(|job| job.branch),
+            repo_name: state.repo_name,
+            repo_path: state.repo_path,
+            task_status: state.task_status,
+            progress: state.progress,
+            attempts: state.attempts,
+            error: state.error,
+            created_at: state.created_at,
+            started_at: state.started_at,
+            finished_at: state.finished_at,
+        }
+    }
 }
@@ -1,14 +1,31 @@
 use warp::{self, http::Response, Filter};
 
-use super::{controller, models::CodeIndexingRequest};
+use super::{
+    controller,
+    models::{CodeIndexingRequest, GarbageCollectionRequest, WatchRequest},
+};
 
 pub fn ingestion() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
-    home_route().or(start_indexing()).or(status_route())
+    home_route()
+        .or(dry_run_indexing())
+        .or(start_indexing())
+        .or(cancel_indexing())
+        .or(status_stream_route())
+        .or(status_route())
+        .or(jobs_route())
+        .or(list_repos_route())
+        .or(repo_route())
+        .or(delete_repository())
+        .or(collect_garbage())
+        .or(start_watch())
+        .or(list_watches())
+        .or(stop_watch())
 }
 
 /// POST /index
 fn start_indexing() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
     warp::path("index")
+        .and(warp::path::end())
         .and(warp::post())
         .and(
             warp::body::content_length_limit(1024 * 16)
@@ -17,6 +34,111 @@ fn start_indexing() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rej
         .and_then(controller::handle_code_index_wrapper)
 }
 
+/// POST /index/dry-run
+/// Takes the same body as `POST /index` and lists the paths that would be indexed or skipped.
+fn dry_run_indexing() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("index" / "dry-run")
+        .and(warp::post())
+        .and(
+            warp::body::content_length_limit(1024 * 16)
+                .and(warp::body::json::<CodeIndexingRequest>()),
+        )
+        .and_then(controller::handle_dry_run_wrapper)
+}
+
+/// DELETE /index/:task_id
+fn cancel_indexing() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("index" / String)
+        .and(warp::delete())
+        .and_then(controller::handle_cancel_index_wrapper)
+}
+
+/// GET /jobs
+fn jobs_route() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path("jobs")
+        .and(warp::get())
+        .and(warp::path::end())
+        .and_then(controller::handle_list_jobs_wrapper)
+}
+
+/// GET /repos
+/// Lists the indexed repos recorded in the registry.
+fn list_repos_route() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path("repos")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and_then(controller::handle_list_repos_wrapper)
+}
+
+/// GET /repos/:name
+/// Like `DELETE /repos/:name`, the whole tail of the path is the repo name.
+fn repo_route() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path("repos")
+        .and(warp::path::tail())
+        .and(warp::get())
+        .and_then(controller::handle_get_repo_wrapper)
+}
+
+/// DELETE /repos/:name
+/// Repo names hold slashes (`v2/owner/repo`), so the whole tail of the path is the name; it can
+/// also be sent percent-encoded.
+fn delete_repository() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path("repos")
+        .and(warp::path::tail())
+        .and(warp::delete())
+        .and_then(controller::handle_delete_repository_wrapper)
+}
+
+/// POST /gc
+/// Removes the chunks, symbols and documents of the indexed refs of a repo that don't match a
+/// file anymore.
+fn collect_garbage() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path("gc")
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(
+            warp::body::content_length_limit(1024 * 16)
+                .and(warp::body::json::<GarbageCollectionRequest>()),
+        )
+        .and_then(controller::handle_gc_wrapper)
+}
+
+/// POST /watch
+/// Indexes the repo, then re-indexes its files as they change until `DELETE /watch/:name`.
+fn start_watch() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path("watch")
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(warp::body::content_length_limit(1024 * 16).and(warp::body::json::<WatchRequest>()))
+        .and_then(controller::handle_start_watch_wrapper)
+}
+
+/// GET /watch
+fn list_watches() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path("watch")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and_then(controller::handle_list_watches_wrapper)
+}
+
+/// DELETE /watch/:name
+/// Like `DELETE /repos/:name`, the whole tail of the path is the repo name.
+fn stop_watch() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path("watch")
+        .and(warp::path::tail())
+        .and(warp::delete())
+        .and_then(controller::handle_stop_watch_wrapper)
+}
+
+/// GET /status/:task_id/stream
+/// Streams the progress updates of the task as server-sent events until it finishes.
+fn status_stream_route() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone
+{
+    warp::path!("status" / String / "stream")
+        .and(warp::get())
+        .and_then(controller::handle_index_status_stream)
+}
+
 // GET /status/:task_id
 fn status_route() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
     warp::path("status")
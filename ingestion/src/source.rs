@@ -0,0 +1,562 @@
+use anyhow::{anyhow, Result};
+use flate2::read::GzDecoder;
+use git2::{Oid, Repository as GitRepository, Status, StatusOptions};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+use crate::ignore_rules::{
+    walk_tree, IgnoreRules, SkippedPath, WalkEntry, GITIGNORE_FILE, NEZUKOIGNORE_FILE,
+};
+
+/// Where the files of a repository are read from.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RepoSource {
+    /// The tree of the branch, tag or commit being indexed.
+    #[default]
+    Git,
+    /// The tree of the ref with the uncommitted changes of the working tree on top of it,
+    /// untracked files included.
+    WorkingTree,
+    /// Every file under the repo path, which doesn't have to be a git repository.
+    Directory,
+    /// A `.tar`, `.tar.gz` or `.tgz` archive at the repo path.
+    Archive,
+}
+
+impl RepoSource {
+    /// Whether the source is read from a git repository, and so has a ref to resolve.
+    pub fn uses_git(&self) -> bool {
+        matches!(self, Self::Git | Self::WorkingTree)
+    }
+}
+
+impl TryFrom<&'_ str> for RepoSource {
+    type Error = &'static str;
+
+    fn try_from(input: &str) -> Result<Self, &'static str> {
+        match input {
+            "git" => Ok(Self::Git),
+            "working_tree" => Ok(Self::WorkingTree),
+            "directory" => Ok(Self::Directory),
+            "archive" => Ok(Self::Archive),
+            _ => Err("source should be one of git, working_tree, directory or archive"),
+        }
+    }
+}
+
+/// A file read from a source, with its path relative to the root of the repo.
+pub struct SourceFile {
+    pub path: String,
+    pub content: Vec<u8>,
+}
+
+/// The files of a source that pass the ignore rules, along with the directories holding them
+/// and the paths that were skipped.
+#[derive(Default)]
+pub struct Snapshot {
+    pub files: Vec<SourceFile>,
+    pub dirs: Vec<String>,
+    pub skipped: Vec<SkippedPath>,
+}
+
+/// Reads the files of a repository from the given source. The git sources need the repository
+/// and the commit the indexed ref resolved to.
+pub fn read_snapshot(
+    source: RepoSource,
+    disk_path: &Path,
+    git_repo: Option<&GitRepository>,
+    commit: &str,
+    include: &[String],
+    exclude: &[String],
+) -> Result<Snapshot> {
+    let snapshot = match source {
+        RepoSource::Git | RepoSource::WorkingTree => {
+            let git_repo = git_repo
+                .ok_or_else(|| anyhow!("{} is not a git repository", disk_path.display()))?;
+            let snapshot = git_snapshot(git_repo, commit, disk_path, include, exclude)?;
+            if source == RepoSource::WorkingTree {
+                apply_working_tree(git_repo, commit, disk_path, snapshot, include, exclude)?
+            } else {
+                snapshot
+            }
+        }
+        RepoSource::Directory => directory_snapshot(disk_path, include, exclude)?,
+        RepoSource::Archive => filter_files(read_archive(disk_path)?, include, exclude)?,
+    };
+
+    info!(
+        "Read {} files from the {:?} source at {}, {} paths skipped",
+        snapshot.files.len(),
+        source,
+        disk_path.display(),
+        snapshot.skipped.len()
+    );
+    Ok(snapshot)
+}
+
+fn git_snapshot(
+    git_repo: &GitRepository,
+    commit: &str,
+    disk_path: &Path,
+    include: &[String],
+    exclude: &[String],
+) -> Result<Snapshot> {
+    let tree = git_repo.find_commit(Oid::from_str(commit)?)?.tree()?;
+    let rules = IgnoreRules::from_tree(git_repo, &tree, disk_path, include, exclude)?;
+
+    let mut snapshot = Snapshot::default();
+    walk_tree(&tree, &rules, |entry| match entry {
+        WalkEntry::Dir(path) => snapshot.dirs.push(path),
+        WalkEntry::File { path, id } => {
+            if let Ok(blob) = git_repo.find_blob(id) {
+                snapshot.files.push(SourceFile {
+                    path,
+                    content: blob.content().to_vec(),
+                });
+            }
+        }
+        WalkEntry::Skipped(skipped) => snapshot.skipped.push(skipped),
+    })?;
+
+    Ok(snapshot)
+}
+
+// Applies the uncommitted changes of the working tree to the snapshot of the commit: modified and
+// untracked files are read from disk and deleted ones dropped. The files ignored by git are left
+// out by git itself, the others go through the rules of the commit.
+fn apply_working_tree(
+    git_repo: &GitRepository,
+    commit: &str,
+    disk_path: &Path,
+    snapshot: Snapshot,
+    include: &[String],
+    exclude: &[String],
+) -> Result<Snapshot> {
+    let tree = git_repo.find_commit(Oid::from_str(commit)?)?.tree()?;
+    let rules = IgnoreRules::from_tree(git_repo, &tree, disk_path, include, exclude)?;
+
+    let mut options = StatusOptions::new();
+    options
+        .include_untracked(true)
+        .recurse_untracked_dirs(true)
+        .include_ignored(false);
+    let statuses = git_repo.statuses(Some(&mut options))?;
+
+    let Snapshot {
+        files,
+        mut dirs,
+        mut skipped,
+    } = snapshot;
+    let mut files = files
+        .into_iter()
+        .map(|file| (file.path, file.content))
+        .collect::<BTreeMap<_, _>>();
+
+    for entry in statuses.iter() {
+        let Some(path) = entry.path().map(ToOwned::to_owned) else {
+            continue;
+        };
+        let status = entry.status();
+        let full_path = disk_path.join(&path);
+
+        if status.intersects(Status::WT_DELETED | Status::INDEX_DELETED) && !full_path.is_file() {
+            debug!("Dropping deleted file {}", path);
+            files.remove(&path);
+            continue;
+        }
+        if !full_path.is_file() {
+            continue;
+        }
+
+        if let Err(reason) = rules.check(&path, false) {
+            skipped.push(SkippedPath {
+                path,
+                is_directory: false,
+                reason,
+            });
+            continue;
+        }
+
+        match fs::read(&full_path) {
+            Ok(content) => {
+                debug!("Reading uncommitted file {} ({:?})", path, status);
+                add_parent_dirs(&path, &mut dirs);
+                files.insert(path, content);
+            }
+            Err(e) => warn!("Could not read {}: {}", full_path.display(), e),
+        }
+    }
+
+    Ok(Snapshot {
+        files: files
+            .into_iter()
+            .map(|(path, content)| SourceFile { path, content })
+            .collect(),
+        dirs,
+        skipped,
+    })
+}
+
+/// The ignore rules of a source read from disk, to check paths one by one without reading the
+/// whole source. The working tree is checked against the rules of the commit, like the files
+/// `read_snapshot` reads from disk.
+pub fn disk_rules(
+    source: RepoSource,
+    disk_path: &Path,
+    git_repo: Option<&GitRepository>,
+    commit: &str,
+    include: &[String],
+    exclude: &[String],
+) -> Result<IgnoreRules> {
+    match source {
+        RepoSource::WorkingTree => {
+            let git_repo = git_repo
+                .ok_or_else(|| anyhow!("{} is not a git repository", disk_path.display()))?;
+            let tree = git_repo.find_commit(Oid::from_str(commit)?)?.tree()?;
+            IgnoreRules::from_tree(git_repo, &tree, disk_path, include, exclude)
+        }
+        RepoSource::Directory => directory_rules(disk_path, include, exclude),
+        _ => Err(anyhow!("The {:?} source isn't read from disk", source)),
+    }
+}
+
+/// Reads the given files from disk, the ones that can't be read are left out.
+pub fn read_files(disk_path: &Path, paths: &HashSet<String>) -> Vec<SourceFile> {
+    let mut paths = paths.iter().collect::<Vec<_>>();
+    paths.sort();
+
+    paths
+        .into_iter()
+        .filter_map(|path| match fs::read(disk_path.join(path)) {
+            Ok(content) => Some(SourceFile {
+                path: path.clone(),
+                content,
+            }),
+            Err(e) => {
+                warn!("Could not read {}: {}", path, e);
+                None
+            }
+        })
+        .collect()
+}
+
+// The files under a directory of the repo, or the whole repo for an empty path, that pass the
+// rules. Paths are relative to the root.
+pub(crate) fn files_under(root: &Path, dir: &str, rules: &IgnoreRules) -> Result<Vec<String>> {
+    let prefix = if dir.is_empty() {
+        String::new()
+    } else {
+        format!("{}/", dir)
+    };
+
+    let mut files = Vec::new();
+    visit_dir(root, &prefix, &mut |path, is_dir| {
+        if rules.check(path, is_dir).is_err() {
+            return false;
+        }
+        if !is_dir {
+            files.push(path.to_string());
+        }
+        true
+    })?;
+    Ok(files)
+}
+
+// Reads the directory in two passes: the ignore files first, so that ignored directories are
+// never read in the second one.
+fn directory_snapshot(root: &Path, include: &[String], exclude: &[String]) -> Result<Snapshot> {
+    let rules = directory_rules(root, include, exclude)?;
+
+    let mut snapshot = Snapshot::default();
+    visit_dir(root, "", &mut |path, is_dir| {
+        if let Err(reason) = rules.check(path, is_dir) {
+            debug!("Skipping {}: {:?}", path, reason);
+            snapshot.skipped.push(SkippedPath {
+                path: path.to_string(),
+                is_directory: is_dir,
+                reason,
+            });
+            return false;
+        }
+
+        if is_dir {
+            snapshot.dirs.push(path.to_string());
+            return true;
+        }
+        match fs::read(root.join(path)) {
+            Ok(content) => snapshot.files.push(SourceFile {
+                path: path.to_string(),
+                content,
+            }),
+            Err(e) => warn!("Could not read {}: {}", path, e),
+        }
+        true
+    })?;
+
+    Ok(snapshot)
+}
+
+// The rules of the ignore files found in the directory.
+fn directory_rules(root: &Path, include: &[String], exclude: &[String]) -> Result<IgnoreRules> {
+    if !root.is_dir() {
+        return Err(anyhow!("{} is not a directory", root.display()));
+    }
+
+    let mut gitignores = Vec::new();
+    let mut nezukoignore = None;
+    visit_dir(root, "", &mut |path, is_dir| {
+        if is_dir {
+            return true;
+        }
+        let (dir, name) = split_path(path);
+        if name == GITIGNORE_FILE || (dir.is_empty() && name == NEZUKOIGNORE_FILE) {
+            match fs::read_to_string(root.join(path)) {
+                Ok(content) if name == GITIGNORE_FILE => {
+                    gitignores.push((dir.to_string(), content))
+                }
+                Ok(content) => nezukoignore = Some(content),
+                Err(e) => warn!("Could not read {}: {}", path, e),
+            }
+        }
+        true
+    })?;
+    IgnoreRules::new(&gitignores, nezukoignore.as_deref(), include, exclude)
+}
+
+// Walks the directory in a stable order, calling `visit` with the path of each entry relative to
+// the root and whether it is a directory. Directories are only entered if `visit` returns true.
+// Symlinks and `.git` directories are never visited.
+fn visit_dir(root: &Path, prefix: &str, visit: &mut dyn FnMut(&str, bool) -> bool) -> Result<()> {
+    let mut entries = fs::read_dir(root.join(prefix))?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let file_type = entry.file_type()?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if file_type.is_symlink() || name == ".git" {
+            continue;
+        }
+
+        let path = format!("{}{}", prefix, name);
+        if file_type.is_dir() {
+            if visit(&path, true) {
+                visit_dir(root, &format!("{}/", path), visit)?;
+            }
+        } else if file_type.is_file() {
+            visit(&path, false);
+        }
+    }
+    Ok(())
+}
+
+// Reads the regular files of a tar archive, gzipped or not. Release tarballs usually hold a
+// single top-level directory, it is stripped so that paths are relative to the project root.
+fn read_archive(path: &Path) -> Result<Vec<SourceFile>> {
+    let name = path.to_string_lossy();
+    let file = fs::File::open(path)?;
+    let reader: Box<dyn Read> = if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        Box::new(GzDecoder::new(file))
+    } else if name.ends_with(".tar") {
+        Box::new(file)
+    } else {
+        return Err(anyhow!(
+            "Unsupported archive {}, expected a .tar, .tar.gz or .tgz file",
+            name
+        ));
+    };
+
+    let mut archive = tar::Archive::new(reader);
+    let mut files = Vec::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let path = entry.path()?.to_string_lossy().to_string();
+        let mut content = Vec::new();
+        entry.read_to_end(&mut content)?;
+        files.push(SourceFile {
+            path: path.trim_start_matches("./").to_string(),
+            content,
+        });
+    }
+
+    strip_common_root(&mut files);
+    Ok(files)
+}
+
+fn strip_common_root(files: &mut [SourceFile]) {
+    let Some(root) = files
+        .first()
+        .and_then(|file| file.path.split_once('/'))
+        .map(|(root, _)| format!("{}/", root))
+    else {
+        return;
+    };
+
+    if files.iter().all(|file| file.path.starts_with(&root)) {
+        for file in files.iter_mut() {
+            file.path = file.path[root.len()..].to_string();
+        }
+    }
+}
+
+// Applies the ignore rules found among the files themselves. A file is skipped along with its
+// directory when one of its parent directories is ignored.
+fn filter_files(
+    mut files: Vec<SourceFile>,
+    include: &[String],
+    exclude: &[String],
+) -> Result<Snapshot> {
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let mut gitignores = Vec::new();
+    let mut nezukoignore = None;
+    for file in files.iter() {
+        let (dir, name) = split_path(&file.path);
+        let content = || String::from_utf8_lossy(&file.content).to_string();
+        if name == GITIGNORE_FILE {
+            gitignores.push((dir.to_string(), content()));
+        } else if dir.is_empty() && name == NEZUKOIGNORE_FILE {
+            nezukoignore = Some(content());
+        }
+    }
+    let rules = IgnoreRules::new(&gitignores, nezukoignore.as_deref(), include, exclude)?;
+
+    let mut snapshot = Snapshot::default();
+    // whether each directory seen so far passes the rules.
+    let mut dirs: HashMap<String, bool> = HashMap::new();
+    'files: for file in files {
+        for dir in parent_dirs(&file.path) {
+            let accepted = match dirs.get(dir) {
+                Some(&accepted) => accepted,
+                None => {
+                    let check = rules.check(dir, true);
+                    let accepted = check.is_ok();
+                    match check {
+                        Ok(()) => snapshot.dirs.push(dir.to_string()),
+                        Err(reason) => snapshot.skipped.push(SkippedPath {
+                            path: dir.to_string(),
+                            is_directory: true,
+                            reason,
+                        }),
+                    }
+                    dirs.insert(dir.to_string(), accepted);
+                    accepted
+                }
+            };
+            if !accepted {
+                continue 'files;
+            }
+        }
+
+        match rules.check(&file.path, false) {
+            Ok(()) => snapshot.files.push(file),
+            Err(reason) => snapshot.skipped.push(SkippedPath {
+                path: file.path,
+                is_directory: false,
+                reason,
+            }),
+        }
+    }
+
+    Ok(snapshot)
+}
+
+// Splits a relative path into its directory, ending with a `/` unless empty, and its file name.
+fn split_path(path: &str) -> (&str, &str) {
+    match path.rfind('/') {
+        Some(idx) => (&path[..idx + 1], &path[idx + 1..]),
+        None => ("", path),
+    }
+}
+
+// The parent directories of a relative path, from the shallowest.
+fn parent_dirs(path: &str) -> impl Iterator<Item = &str> {
+    path.match_indices('/').map(move |(idx, _)| &path[..idx])
+}
+
+fn add_parent_dirs(path: &str, dirs: &mut Vec<String>) {
+    for dir in parent_dirs(path) {
+        if !dirs.iter().any(|known| known == dir) {
+            dirs.push(dir.to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ignore_rules::SkipReason;
+
+    fn file(path: &str, content: &str) -> SourceFile {
+        SourceFile {
+            path: path.to_string(),
+            content: content.as_bytes().to_vec(),
+        }
+    }
+
+    fn paths(files: &[SourceFile]) -> Vec<&str> {
+        files.iter().map(|file| file.path.as_str()).collect()
+    }
+
+    #[test]
+    fn test_filter_files() {
+        let mut files = vec![
+            file("project-1.0/.gitignore", "build/\n"),
+            file("project-1.0/src/main.rs", "fn main() {}\n"),
+            file("project-1.0/build/out.rs", "fn out() {}\n"),
+            file("project-1.0/build/nested/gen.rs", "fn gen() {}\n"),
+            file("project-1.0/logo.png", ""),
+        ];
+        strip_common_root(&mut files);
+
+        let snapshot = filter_files(files, &[], &[]).unwrap();
+
+        assert_eq!(paths(&snapshot.files), vec![".gitignore", "src/main.rs"]);
+        assert_eq!(snapshot.dirs, vec!["src".to_string()]);
+        // the ignored directory is reported once, its files aren't.
+        assert_eq!(
+            snapshot
+                .skipped
+                .iter()
+                .map(|skipped| (skipped.path.as_str(), &skipped.reason))
+                .collect::<Vec<_>>(),
+            vec![
+                (
+                    "build",
+                    &SkipReason::Gitignore {
+                        file: ".gitignore".to_string(),
+                        pattern: "build/".to_string(),
+                    }
+                ),
+                ("logo.png", &SkipReason::BuiltinFilter),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_directory_snapshot() {
+        let dir = std::env::temp_dir().join(format!("ingestion-source-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(dir.join("src/generated")).unwrap();
+        fs::create_dir_all(dir.join(".git")).unwrap();
+        fs::write(dir.join(".nezukoignore"), "generated/\n").unwrap();
+        fs::write(dir.join("src/lib.rs"), "pub fn lib() {}\n").unwrap();
+        fs::write(dir.join("src/generated/api.rs"), "pub fn api() {}\n").unwrap();
+        fs::write(dir.join(".git/HEAD"), "ref: refs/heads/main\n").unwrap();
+
+        let snapshot = directory_snapshot(&dir, &[], &["*.md".to_string()]).unwrap();
+
+        assert_eq!(paths(&snapshot.files), vec![".nezukoignore", "src/lib.rs"]);
+        assert_eq!(snapshot.dirs, vec!["src".to_string()]);
+        assert_eq!(snapshot.skipped.len(), 1);
+        assert_eq!(snapshot.skipped[0].path, "src/generated");
+
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -7,6 +7,14 @@ use tree_sitter_stack_graphs::{
 };
 use tree_sitter_stack_graphs_python::language_configuration;
 
+// The languages with a tree-sitter-stack-graphs definition, by the ids `detect_language` returns.
+const STACK_GRAPH_LANGUAGES: &[&str] = &["Python"];
+
+/// Whether stack graphs can be built for files of the language.
+pub fn supports_language(language: &str) -> bool {
+    STACK_GRAPH_LANGUAGES.contains(&language)
+}
+
 fn get_language_configurations(language: &str) -> Vec<LanguageConfiguration> {
     match language {
         "Python" => vec![language_configuration(&NoCancellation)],
@@ -2,22 +2,63 @@ use core::fmt;
 use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-#[derive(Clone)]
+use crate::jobs::IndexingJob;
+use crate::progress::{publish, PhaseProgress, ProgressUpdate};
+use crate::util::state_dir;
+
+const JOBS_FILE: &str = "jobs.json";
+// Number of finished processes kept in the history, the ones that finished first are dropped.
+const MAX_FINISHED_PROCESSES: usize = 500;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct ProcessState {
     pub repo_name: String,
     pub repo_path: String,
     pub progress: u32,
     pub task_status: CodeIndexingTaskStatus,
+    // The phase the run is in and its item counts, see `progress::ProgressReporter`.
+    #[serde(default)]
+    pub phase: Option<PhaseProgress>,
+    // The request the job was queued with, used to re-run it after a failure or a restart.
+    // Processes started outside of the job queue (e.g. from the CLI) don't have one.
+    #[serde(default)]
+    pub job: Option<IndexingJob>,
+    #[serde(default)]
+    pub attempts: u32,
+    #[serde(default)]
+    pub error: Option<String>,
+    // Unix timestamps in seconds.
+    #[serde(default)]
+    pub created_at: u64,
+    #[serde(default)]
+    pub started_at: Option<u64>,
+    #[serde(default)]
+    pub finished_at: Option<u64>,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
 pub enum CodeIndexingTaskStatus {
     Queued,
     Running,
     Completed,
     Failed,
+    Cancelled,
+}
+
+impl CodeIndexingTaskStatus {
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self,
+            CodeIndexingTaskStatus::Completed
+                | CodeIndexingTaskStatus::Failed
+                | CodeIndexingTaskStatus::Cancelled
+        )
+    }
 }
 
 impl fmt::Display for CodeIndexingTaskStatus {
@@ -27,33 +68,151 @@ impl fmt::Display for CodeIndexingTaskStatus {
             CodeIndexingTaskStatus::Running => write!(f, "Running"),
             CodeIndexingTaskStatus::Completed => write!(f, "Completed"),
             CodeIndexingTaskStatus::Failed => write!(f, "Failed"),
+            CodeIndexingTaskStatus::Cancelled => write!(f, "Cancelled"),
         }
     }
 }
 
 // Define the global state structure
-// TODO: Eventually rework this to use crossbeam instead of lazy_static to better manage the progress
-// updates in concurrent enviroments. Also look into storing these states in a database rather than in-memory.
+// The states are persisted to `jobs.json` in the state directory on every status change, so the job
+// history and the queued jobs survive a restart. The history keeps the last
+// `MAX_FINISHED_PROCESSES` finished processes.
 lazy_static! {
     static ref GLOBAL_STATE: Mutex<HashMap<String, Arc<Mutex<ProcessState>>>> =
-        Mutex::new(HashMap::new());
+        Mutex::new(load_states(&jobs_path()));
+}
+
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+fn jobs_path() -> PathBuf {
+    state_dir().join(JOBS_FILE)
+}
+
+// Loads the persisted states. Jobs that were running when the service stopped are queued again.
+fn load_states(path: &PathBuf) -> HashMap<String, Arc<Mutex<ProcessState>>> {
+    let states: HashMap<String, ProcessState> = fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default();
+
+    let mut states = states
+        .into_iter()
+        .map(|(id, mut state)| {
+            if state.task_status == CodeIndexingTaskStatus::Running {
+                log::info!("Re-queueing process {} interrupted by a restart", id);
+                state.task_status = CodeIndexingTaskStatus::Queued;
+                state.progress = 0;
+                state.phase = None;
+                state.started_at = None;
+            }
+            (id, Arc::new(Mutex::new(state)))
+        })
+        .collect();
+    prune_finished(&mut states, MAX_FINISHED_PROCESSES);
+    states
+}
+
+// Drops the finished processes beyond the `limit` most recently finished ones.
+fn prune_finished(states: &mut HashMap<String, Arc<Mutex<ProcessState>>>, limit: usize) {
+    let mut finished = states
+        .iter()
+        .filter_map(|(id, state)| {
+            let state = state.lock().unwrap();
+            state
+                .task_status
+                .is_finished()
+                .then(|| (state.finished_at.unwrap_or(state.created_at), id.clone()))
+        })
+        .collect::<Vec<_>>();
+    if finished.len() <= limit {
+        return;
+    }
+
+    finished.sort();
+    let pruned = finished.len() - limit;
+    for (_, id) in finished.into_iter().take(pruned) {
+        states.remove(&id);
+    }
+    log::debug!("Dropped {} finished processes from the history", pruned);
+}
+
+fn persist_states(global_state: &HashMap<String, Arc<Mutex<ProcessState>>>) {
+    let states = global_state
+        .iter()
+        .map(|(id, state)| (id.clone(), state.lock().unwrap().clone()))
+        .collect::<HashMap<_, _>>();
+
+    let path = jobs_path();
+    let result = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| {
+            let content = serde_json::to_string_pretty(&states)?;
+            fs::write(&path, content)
+        });
+
+    if let Err(e) = result {
+        log::error!("Failed to persist process states to {:?}: {}", path, e);
+    }
 }
 
 // Function to queue a new process
 pub fn queue_process(process_id: &str, repo_name: &str, repo_path: &str) {
-    log::info!("Queueing process {} for repo {}", process_id, repo_name);
-    let mut global_state = GLOBAL_STATE.lock().unwrap();
-    global_state.insert(
-        process_id.to_string(),
-        Arc::new(Mutex::new(ProcessState {
+    queue_process_state(
+        process_id,
+        ProcessState {
             repo_name: repo_name.to_string(),
             repo_path: repo_path.to_string(),
             progress: 0,
             task_status: CodeIndexingTaskStatus::Queued,
-        })),
+            phase: None,
+            job: None,
+            attempts: 0,
+            error: None,
+            created_at: now(),
+            started_at: None,
+            finished_at: None,
+        },
     );
 }
 
+// Function to queue a new process for an indexing job that can be retried and resumed.
+pub fn queue_job(process_id: &str, job: &IndexingJob) {
+    queue_process_state(
+        process_id,
+        ProcessState {
+            repo_name: job.repo_name.clone(),
+            repo_path: job.repo_path.clone(),
+            progress: 0,
+            task_status: CodeIndexingTaskStatus::Queued,
+            phase: None,
+            job: Some(job.clone()),
+            attempts: 0,
+            error: None,
+            created_at: now(),
+            started_at: None,
+            finished_at: None,
+        },
+    );
+}
+
+fn queue_process_state(process_id: &str, state: ProcessState) {
+    log::info!(
+        "Queueing process {} for repo {}",
+        process_id,
+        state.repo_name
+    );
+    let mut global_state = GLOBAL_STATE.lock().unwrap();
+    publish(ProgressUpdate::new(process_id, &state));
+    global_state.insert(process_id.to_string(), Arc::new(Mutex::new(state)));
+    persist_states(&global_state);
+}
+
 // Function to update a process state with progress and status
 pub fn update_process_state(process_id: &str, progress: u32, task_status: CodeIndexingTaskStatus) {
     log::info!(
@@ -62,12 +221,89 @@ pub fn update_process_state(process_id: &str, progress: u32, task_status: CodeIn
         progress,
         task_status
     );
+    let finished = task_status.is_finished();
     let mut global_state = GLOBAL_STATE.lock().unwrap();
     match global_state.get_mut(process_id) {
+        Some(state) => {
+            {
+                let mut state = state.lock().unwrap();
+                // a cancelled process stays cancelled, even if the indexing run reports back later.
+                if state.task_status == CodeIndexingTaskStatus::Cancelled {
+                    return;
+                }
+                state.progress = progress;
+                match task_status {
+                    CodeIndexingTaskStatus::Running if state.started_at.is_none() => {
+                        state.started_at = Some(now());
+                    }
+                    ref status if status.is_finished() => state.finished_at = Some(now()),
+                    _ => {}
+                }
+                state.task_status = task_status;
+                publish(ProgressUpdate::new(process_id, &state));
+            }
+            if finished {
+                prune_finished(&mut global_state, MAX_FINISHED_PROCESSES);
+            }
+            persist_states(&global_state);
+        }
+        None => {
+            log::error!("Process {} not found in global state", process_id);
+        }
+    }
+}
+
+// Function to update the progress of a running process within its current phase.
+// Phase updates are frequent, so they are only kept in memory and published to the subscribers.
+pub fn update_process_phase(process_id: &str, progress: u32, phase: PhaseProgress) {
+    log::debug!(
+        "Updating process phase for process id: {} with {}, {:?}",
+        process_id,
+        progress,
+        phase
+    );
+    let global_state = GLOBAL_STATE.lock().unwrap();
+    match global_state.get(process_id) {
         Some(state) => {
             let mut state = state.lock().unwrap();
+            if state.task_status != CodeIndexingTaskStatus::Running {
+                return;
+            }
             state.progress = progress;
-            state.task_status = task_status;
+            state.phase = Some(phase);
+            publish(ProgressUpdate::new(process_id, &state));
+        }
+        None => {
+            log::error!("Process {} not found in global state", process_id);
+        }
+    }
+}
+
+// Records a failed attempt of a process. The process is queued again when `retry` is set,
+// otherwise it is marked as failed.
+pub fn record_process_failure(process_id: &str, error: &str, retry: bool) {
+    let mut global_state = GLOBAL_STATE.lock().unwrap();
+    match global_state.get_mut(process_id) {
+        Some(state) => {
+            {
+                let mut state = state.lock().unwrap();
+                if state.task_status == CodeIndexingTaskStatus::Cancelled {
+                    return;
+                }
+                state.error = Some(error.to_string());
+                if retry {
+                    state.task_status = CodeIndexingTaskStatus::Queued;
+                    state.progress = 0;
+                    state.phase = None;
+                    state.started_at = None;
+                } else {
+                    state.task_status = CodeIndexingTaskStatus::Failed;
+                    state.finished_at = Some(now());
+                }
+                publish(ProgressUpdate::new(process_id, &state));
+            }
+            prune_finished(&mut global_state, MAX_FINISHED_PROCESSES);
+            persist_states(&global_state);
         }
         None => {
             log::error!("Process {} not found in global state", process_id);
@@ -75,6 +311,43 @@ pub fn update_process_state(process_id: &str, progress: u32, task_status: CodeIn
     }
 }
 
+// Marks the start of a new attempt and returns the attempt number.
+pub fn start_process_attempt(process_id: &str) -> Option<u32> {
+    let mut global_state = GLOBAL_STATE.lock().unwrap();
+    let attempts = {
+        let mut state = global_state.get_mut(process_id)?.lock().unwrap();
+        state.attempts += 1;
+        state.attempts
+    };
+    persist_states(&global_state);
+    Some(attempts)
+}
+
+// Marks a queued or running process as cancelled. Returns false if the process doesn't exist
+// or has already finished.
+pub fn cancel_process(process_id: &str) -> bool {
+    let mut global_state = GLOBAL_STATE.lock().unwrap();
+    let cancelled = match global_state.get_mut(process_id) {
+        Some(state) => {
+            let mut state = state.lock().unwrap();
+            if state.task_status.is_finished() {
+                false
+            } else {
+                state.task_status = CodeIndexingTaskStatus::Cancelled;
+                state.finished_at = Some(now());
+                publish(ProgressUpdate::new(process_id, &state));
+                true
+            }
+        }
+        None => false,
+    };
+    if cancelled {
+        prune_finished(&mut global_state, MAX_FINISHED_PROCESSES);
+        persist_states(&global_state);
+    }
+    cancelled
+}
+
 // Function to get the progress and status of a process
 pub fn get_process_state(process_id: &str) -> Option<ProcessState> {
     let global_state = GLOBAL_STATE.lock().unwrap();
@@ -85,3 +358,174 @@ pub fn get_process_state(process_id: &str) -> Option<ProcessState> {
         None
     }
 }
+
+/// The id of a queued or running process of the repo. The processes queued without a job, like
+/// the runs of a watcher, can't be resumed after a restart and only count while running.
+pub fn active_process(repo_name: &str) -> Option<String> {
+    list_process_states()
+        .into_iter()
+        .find(|(_, state)| is_active(state, repo_name))
+        .map(|(task_id, _)| task_id)
+}
+
+/// Registers a running process of the repo that isn't a job, like the run of a watcher, unless
+/// another process of the repo is active. Checked and registered at once, so that two processes
+/// don't both see the repo free. Returns the id of the active process if there is one.
+pub fn try_claim_repo(process_id: &str, repo_name: &str, repo_path: &str) -> Option<String> {
+    let mut global_state = GLOBAL_STATE.lock().unwrap();
+    if let Some((task_id, _)) = global_state
+        .iter()
+        .find(|(_, state)| is_active(&state.lock().unwrap(), repo_name))
+    {
+        return Some(task_id.clone());
+    }
+
+    let now = now();
+    let state = ProcessState {
+        repo_name: repo_name.to_string(),
+        repo_path: repo_path.to_string(),
+        progress: 0,
+        task_status: CodeIndexingTaskStatus::Running,
+        phase: None,
+        job: None,
+        attempts: 0,
+        error: None,
+        created_at: now,
+        started_at: Some(now),
+        finished_at: None,
+    };
+    log::info!("Claiming repo {} for process {}", repo_name, process_id);
+    publish(ProgressUpdate::new(process_id, &state));
+    global_state.insert(process_id.to_string(), Arc::new(Mutex::new(state)));
+    persist_states(&global_state);
+    None
+}
+
+/// Marks a queued job as running, unless another process of the repo is running. Returns the id
+/// of the running process if there is one. The other queued jobs of the repo don't count, the
+/// first one started runs first.
+pub fn try_start_process(process_id: &str, repo_name: &str) -> Option<String> {
+    let mut global_state = GLOBAL_STATE.lock().unwrap();
+    if let Some((task_id, _)) = global_state.iter().find(|(task_id, state)| {
+        let state = state.lock().unwrap();
+        *task_id != process_id
+            && state.repo_name == repo_name
+            && state.task_status == CodeIndexingTaskStatus::Running
+    }) {
+        return Some(task_id.clone());
+    }
+
+    if let Some(state) = global_state.get_mut(process_id) {
+        {
+            let mut state = state.lock().unwrap();
+            if state.task_status != CodeIndexingTaskStatus::Queued {
+                return None;
+            }
+            state.task_status = CodeIndexingTaskStatus::Running;
+            state.started_at = Some(now());
+            publish(ProgressUpdate::new(process_id, &state));
+        }
+        persist_states(&global_state);
+    }
+    None
+}
+
+fn is_active(state: &ProcessState, repo_name: &str) -> bool {
+    state.repo_name == repo_name
+        && match state.task_status {
+            CodeIndexingTaskStatus::Running => true,
+            CodeIndexingTaskStatus::Queued => state.job.is_some(),
+            _ => false,
+        }
+}
+
+// Lists all known processes, most recently created first.
+pub fn list_process_states() -> Vec<(String, ProcessState)> {
+    let global_state = GLOBAL_STATE.lock().unwrap();
+    let mut states = global_state
+        .iter()
+        .map(|(id, state)| (id.clone(), state.lock().unwrap().clone()))
+        .collect::<Vec<_>>();
+    states.sort_by(|(_, a), (_, b)| b.created_at.cmp(&a.created_at));
+    states
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_states_requeues_running_jobs() {
+        let path =
+            std::env::temp_dir().join(format!("ingestion-jobs-{}.json", uuid::Uuid::new_v4()));
+        let state = |task_status| ProcessState {
+            repo_name: "repo".to_string(),
+            repo_path: "/tmp/repo".to_string(),
+            progress: 50,
+            task_status,
+            phase: None,
+            job: None,
+            attempts: 1,
+            error: None,
+            created_at: 1,
+            started_at: Some(2),
+            finished_at: None,
+        };
+        let states = HashMap::from([
+            (
+                "running".to_string(),
+                state(CodeIndexingTaskStatus::Running),
+            ),
+            (
+                "completed".to_string(),
+                state(CodeIndexingTaskStatus::Completed),
+            ),
+        ]);
+        fs::write(&path, serde_json::to_string(&states).unwrap()).unwrap();
+
+        let loaded = load_states(&path);
+        let running = loaded["running"].lock().unwrap().clone();
+        let completed = loaded["completed"].lock().unwrap().clone();
+
+        assert_eq!(running.task_status, CodeIndexingTaskStatus::Queued);
+        assert_eq!(running.progress, 0);
+        assert_eq!(running.started_at, None);
+        assert_eq!(completed.task_status, CodeIndexingTaskStatus::Completed);
+        assert_eq!(completed.progress, 50);
+
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_prune_finished() {
+        let state = |task_status, finished_at| ProcessState {
+            repo_name: "repo".to_string(),
+            repo_path: "/tmp/repo".to_string(),
+            progress: 0,
+            task_status,
+            phase: None,
+            job: None,
+            attempts: 1,
+            error: None,
+            created_at: 1,
+            started_at: Some(2),
+            finished_at,
+        };
+        let mut states = [
+            ("old", state(CodeIndexingTaskStatus::Completed, Some(10))),
+            ("failed", state(CodeIndexingTaskStatus::Failed, Some(30))),
+            ("new", state(CodeIndexingTaskStatus::Cancelled, Some(20))),
+            ("running", state(CodeIndexingTaskStatus::Running, None)),
+            ("queued", state(CodeIndexingTaskStatus::Queued, None)),
+        ]
+        .into_iter()
+        .map(|(id, state)| (id.to_string(), Arc::new(Mutex::new(state))))
+        .collect::<HashMap<_, _>>();
+
+        prune_finished(&mut states, 2);
+        let mut ids = states.keys().map(String::as_str).collect::<Vec<_>>();
+        ids.sort();
+        // the unfinished processes are kept whatever the limit.
+        assert_eq!(ids, vec!["failed", "new", "queued", "running"]);
+    }
+}
@@ -0,0 +1,39 @@
+//! Fixtures shared by the tests of the modules reading git repositories.
+
+use git2::{Oid, Repository as GitRepository, Signature, Time};
+
+/// Commits the files on `refs/heads/main` of the repo, as the only files of the tree.
+pub fn commit_files(repo: &GitRepository, files: &[(&str, &str)], parent: Option<Oid>) -> Oid {
+    commit_files_by(repo, files, "test", 0, "commit", parent)
+}
+
+/// Like `commit_files`, by `author` at `time`, in seconds since the epoch.
+pub fn commit_files_by(
+    repo: &GitRepository,
+    files: &[(&str, &str)],
+    author: &str,
+    time: i64,
+    message: &str,
+    parent: Option<Oid>,
+) -> Oid {
+    let mut builder = repo.treebuilder(None).unwrap();
+    for (path, content) in files {
+        let blob = repo.blob(content.as_bytes()).unwrap();
+        builder.insert(path, blob, 0o100644).unwrap();
+    }
+    let tree = repo.find_tree(builder.write().unwrap()).unwrap();
+    let signature = Signature::new(author, "test@example.com", &Time::new(time, 0)).unwrap();
+    let parents = parent
+        .map(|oid| vec![repo.find_commit(oid).unwrap()])
+        .unwrap_or_default();
+    let parents = parents.iter().collect::<Vec<_>>();
+    repo.commit(
+        Some("refs/heads/main"),
+        &signature,
+        &signature,
+        message,
+        &tree,
+        &parents,
+    )
+    .unwrap()
+}
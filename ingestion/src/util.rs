@@ -1,10 +1,13 @@
-use hyperpolyglot::detect_buffer;
-use std::{io::Cursor, path::Path};
+use std::path::PathBuf;
 
-// Detects the language of the given file.
-pub fn detect_language(path: &Path, buf: &[u8]) -> Option<&'static str> {
-    detect_buffer(path, |_| Ok(Cursor::new(buf)))
-        .ok()
-        .flatten()
-        .map(|d| d.language())
+// Directory where ingestion keeps its local bookkeeping, e.g. the last indexed commit per branch.
+// Defaults to the platform's local data directory and can be overridden with INGESTION_STATE_DIR.
+pub fn state_dir() -> PathBuf {
+    std::env::var("INGESTION_STATE_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| {
+            dirs::data_local_dir()
+                .unwrap_or_else(|| PathBuf::from("."))
+                .join("nezuko")
+        })
 }
@@ -0,0 +1,507 @@
+use anyhow::{anyhow, bail, Result};
+use futures::future::{AbortHandle, Abortable};
+use git2::Repository as GitRepository;
+use log::{debug, error, info, warn};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+
+use crate::hash::compute_hashes;
+use crate::ignore_rules::{IgnoreRules, GITIGNORE_FILE, NEZUKOIGNORE_FILE};
+use crate::incremental::{self, ChangeSet, StaleFile};
+use crate::source::{self, RepoSource};
+use crate::state::{record_process_failure, try_claim_repo};
+use crate::{Config, Indexer};
+
+/// How long the files have to stay untouched before a batch of changes is indexed.
+pub const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(500);
+
+// The watchers started by `start_watch`, by repo name.
+static WATCHERS: Lazy<Mutex<HashMap<String, (WatchedRepo, AbortHandle)>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// A repo watched in the background by `start_watch`.
+#[derive(Clone, Debug, Serialize)]
+pub struct WatchedRepo {
+    pub id: String,
+    pub repo_name: String,
+    pub repo_path: String,
+    pub branch: String,
+    pub source: RepoSource,
+    pub debounce_ms: u64,
+}
+
+/// The source a watched repo is read from. Saving a file doesn't change the commits of the git
+/// source, so it is watched as its working tree; archives can't be watched.
+pub fn watch_source(source: RepoSource) -> Result<RepoSource> {
+    match source {
+        RepoSource::Git => Ok(RepoSource::WorkingTree),
+        RepoSource::Archive => Err(anyhow!("The archive source can't be watched")),
+        source => Ok(source),
+    }
+}
+
+// The hashes of the files as last indexed, and the rules deciding which files are indexed.
+struct WatchState {
+    root: PathBuf,
+    config: Config,
+    rules: IgnoreRules,
+    // By path relative to the root, computed the way `traverse` does.
+    files: HashMap<String, StaleFile>,
+}
+
+impl WatchState {
+    fn read(root: PathBuf, config: Config) -> Result<Self> {
+        let git_repo = open_git_repo(&root, &config)?;
+        let commit = resolve_commit(git_repo.as_ref(), &config)?;
+        let snapshot = source::read_snapshot(
+            config.source,
+            &root,
+            git_repo.as_ref(),
+            &commit,
+            &config.include,
+            &config.exclude,
+        )?;
+        let files = snapshot
+            .files
+            .iter()
+            .map(|file| {
+                let hashes = hash_file(&file.path, &file.content, &config.branch);
+                (file.path.clone(), hashes)
+            })
+            .collect();
+        let rules = source::disk_rules(
+            config.source,
+            &root,
+            git_repo.as_ref(),
+            &commit,
+            &config.include,
+            &config.exclude,
+        )?;
+
+        Ok(Self {
+            root,
+            config,
+            rules,
+            files,
+        })
+    }
+
+    fn reload_rules(&mut self) -> Result<()> {
+        let git_repo = open_git_repo(&self.root, &self.config)?;
+        let commit = resolve_commit(git_repo.as_ref(), &self.config)?;
+        self.rules = source::disk_rules(
+            self.config.source,
+            &self.root,
+            git_repo.as_ref(),
+            &commit,
+            &self.config.include,
+            &self.config.exclude,
+        )?;
+        Ok(())
+    }
+
+    // The change set of the paths touched since the last batch, an empty path standing for the
+    // whole repo. Files whose hashes match the indexed ones are left out, so that saving a file
+    // without changing it, or touching it, doesn't re-index it. Also returns the new hashes of
+    // the files, None for the removed ones, to apply once the change set is indexed.
+    fn changes(
+        &self,
+        touched: &HashSet<String>,
+    ) -> (ChangeSet, HashMap<String, Option<StaleFile>>) {
+        let mut candidates = BTreeSet::new();
+        for path in touched {
+            if self.root.join(path).is_dir() {
+                match source::files_under(&self.root, path, &self.rules) {
+                    Ok(files) => candidates.extend(files),
+                    Err(e) => warn!("Could not list the files under {}: {}", path, e),
+                }
+            } else {
+                candidates.insert(path.clone());
+            }
+            // the indexed files of a directory that was removed or renamed are gone too.
+            let prefix = if path.is_empty() {
+                String::new()
+            } else {
+                format!("{}/", path)
+            };
+            candidates.extend(
+                self.files
+                    .keys()
+                    .filter(|known| known.starts_with(&prefix))
+                    .cloned(),
+            );
+        }
+
+        let mut change_set = ChangeSet::default();
+        let mut updates = HashMap::new();
+        for path in candidates {
+            let indexed = self.files.get(&path);
+            let current = self.hash_current(&path);
+            if indexed == current.as_ref() {
+                continue;
+            }
+
+            debug!("{} changed on disk", path);
+            change_set.stale.extend(indexed.cloned());
+            if current.is_some() {
+                change_set.changed.insert(path.clone());
+            }
+            updates.insert(path, current);
+        }
+        (change_set, updates)
+    }
+
+    // The hashes of the file on disk, None if it is gone, ignored or can't be read.
+    fn hash_current(&self, path: &str) -> Option<StaleFile> {
+        self.rules.check(path, false).ok()?;
+        // like the snapshots, symlinks aren't followed.
+        let full_path = self.root.join(path);
+        if !fs::symlink_metadata(&full_path).ok()?.is_file() {
+            return None;
+        }
+        match fs::read(&full_path) {
+            Ok(content) => Some(hash_file(path, &content, &self.config.branch)),
+            Err(e) => {
+                warn!("Could not read {}: {}", path, e);
+                None
+            }
+        }
+    }
+
+    fn apply(&mut self, updates: HashMap<String, Option<StaleFile>>) {
+        for (path, hashes) in updates {
+            match hashes {
+                Some(hashes) => self.files.insert(path, hashes),
+                None => self.files.remove(&path),
+            };
+        }
+    }
+}
+
+fn open_git_repo(root: &Path, config: &Config) -> Result<Option<GitRepository>> {
+    if config.source.uses_git() {
+        Ok(Some(GitRepository::open(root)?))
+    } else {
+        Ok(None)
+    }
+}
+
+fn resolve_commit(git_repo: Option<&GitRepository>, config: &Config) -> Result<String> {
+    match git_repo {
+        Some(git_repo) => incremental::resolve_ref(git_repo, &config.branch),
+        None => Ok(String::new()),
+    }
+}
+
+fn hash_file(path: &str, content: &[u8], branch: &str) -> StaleFile {
+    let buffer = std::str::from_utf8(content).unwrap_or("");
+    let (semantic_hash, tantivy_hash) = compute_hashes(PathBuf::from(path), buffer, branch);
+    StaleFile {
+        path: path.to_string(),
+        semantic_hash,
+        tantivy_hash,
+    }
+}
+
+// The paths of an event relative to the root. Reads and the files of `.git` directories are left
+// out, git rewrites them on every command.
+fn event_paths(root: &Path, event: &Event) -> Vec<String> {
+    if matches!(event.kind, EventKind::Access(_)) {
+        return Vec::new();
+    }
+
+    event
+        .paths
+        .iter()
+        .filter_map(|path| path.strip_prefix(root).ok())
+        .filter(|path| {
+            !path
+                .components()
+                .any(|component| component.as_os_str() == ".git")
+        })
+        .filter_map(|path| path.to_str().map(str::to_string))
+        .collect()
+}
+
+fn is_ignore_file(path: &str) -> bool {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    name == GITIGNORE_FILE || name == NEZUKOIGNORE_FILE
+}
+
+// Waits for a touched path, then collects the following ones until none arrived for `debounce`.
+// Returns None once the watcher is gone.
+async fn next_batch(
+    events: &mut UnboundedReceiver<String>,
+    debounce: Duration,
+) -> Option<HashSet<String>> {
+    let mut batch = HashSet::from([events.recv().await?]);
+    while let Ok(Some(path)) = tokio::time::timeout(debounce, events.recv()).await {
+        batch.insert(path);
+    }
+    Some(batch)
+}
+
+// Records a run of the watcher, listed along with the jobs, once no other process of the repo is
+// queued or running: they write to the same indexes. Checked every `debounce`.
+async fn claim_run(config: &Config, debounce: Duration) -> String {
+    let task_id = uuid::Uuid::new_v4().to_string();
+    while let Some(active) = try_claim_repo(&task_id, &config.repo_name, &config.repo_path) {
+        debug!("Waiting for {} to index {}", active, config.repo_name);
+        tokio::time::sleep(debounce).await;
+    }
+    task_id
+}
+
+/// Indexes the repo, then watches its files and indexes the ones that change, until the task is
+/// aborted. Changes are batched until no file was touched for `debounce`, and only the files
+/// whose hashes differ from the indexed ones are indexed again.
+///
+/// The batches only index the changed files, see `Indexer::index_changes`; the documents they
+/// write get their history back on the next full run.
+pub async fn watch_repository(config: Config, debounce: Duration) -> Result<()> {
+    let source = watch_source(config.source)?;
+    if source != config.source {
+        info!(
+            "Watching the {:?} source of {} instead of {:?}",
+            source, config.repo_name, config.source
+        );
+    }
+    let config = Config {
+        source,
+        incremental: false,
+        ..config
+    };
+    let root = fs::canonicalize(&config.repo_path)?;
+
+    // the watcher starts before the first run, so the files saved in the meantime aren't missed.
+    let (sender, mut events) = mpsc::unbounded_channel();
+    let watched_root = root.clone();
+    let mut watcher =
+        notify::recommended_watcher(move |event: notify::Result<Event>| match event {
+            Ok(event) => {
+                for path in event_paths(&watched_root, &event) {
+                    let _ = sender.send(path);
+                }
+            }
+            Err(e) => error!("Watch error: {:?}", e),
+        })?;
+    watcher.watch(&root, RecursiveMode::Recursive)?;
+
+    let mut state = WatchState::read(root.clone(), config.clone())?;
+    let task_id = claim_run(&config, debounce).await;
+    let indexed = Indexer
+        .index_repository(
+            root.clone(),
+            config.repo_name.clone(),
+            config.clone(),
+            &config.branch,
+            &config.version,
+            task_id.clone(),
+        )
+        .await;
+    if let Err(e) = indexed {
+        record_process_failure(&task_id, &format!("{:#}", e), false);
+        return Err(e);
+    }
+    info!("Watching {} for changes", root.display());
+
+    // the touched paths not indexed yet, kept across batches when indexing them fails.
+    let mut pending = HashSet::new();
+    while let Some(batch) = next_batch(&mut events, debounce).await {
+        pending.extend(batch);
+
+        // other files may be ignored or indexed now, the whole repo is compared.
+        if pending.iter().any(|path| is_ignore_file(path)) {
+            match state.reload_rules() {
+                Ok(()) => {
+                    pending.insert(String::new());
+                }
+                Err(e) => error!("Failed to reload the ignore rules: {:?}", e),
+            }
+        }
+
+        let (change_set, updates) = state.changes(&pending);
+        if change_set.is_empty() {
+            debug!(
+                "{} touched paths left the indexed files unchanged",
+                pending.len()
+            );
+            pending.clear();
+            continue;
+        }
+
+        // the files saved while the run waits for the jobs of the repo are left to the next batch.
+        let task_id = claim_run(&config, debounce).await;
+        let indexed = Indexer
+            .index_changes(
+                root.clone(),
+                config.repo_name.clone(),
+                config.clone(),
+                &change_set,
+                task_id.clone(),
+            )
+            .await;
+        match indexed {
+            Ok(()) => {
+                state.apply(updates);
+                pending.clear();
+            }
+            Err(e) => {
+                error!(
+                    "Failed to index the changes of {}, retrying with the next batch: {:?}",
+                    config.repo_name, e
+                );
+                record_process_failure(&task_id, &format!("{:#}", e), false);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Starts watching the repo in the background, see `watch_repository`. A repo is watched once.
+pub fn start_watch(config: Config, debounce: Duration) -> Result<WatchedRepo> {
+    let mut watchers = WATCHERS.lock().unwrap();
+    if watchers.contains_key(&config.repo_name) {
+        bail!("{} is already watched", config.repo_name);
+    }
+
+    let watched = WatchedRepo {
+        id: uuid::Uuid::new_v4().to_string(),
+        repo_name: config.repo_name.clone(),
+        repo_path: config.repo_path.clone(),
+        branch: config.branch.clone(),
+        source: watch_source(config.source)?,
+        debounce_ms: debounce.as_millis() as u64,
+    };
+    let (abort_handle, abort_registration) = AbortHandle::new_pair();
+    watchers.insert(watched.repo_name.clone(), (watched.clone(), abort_handle));
+
+    let (id, repo_name) = (watched.id.clone(), watched.repo_name.clone());
+    tokio::spawn(async move {
+        match Abortable::new(watch_repository(config, debounce), abort_registration).await {
+            Ok(Err(e)) => error!("Watching {} failed: {:?}", repo_name, e),
+            _ => info!("Stopped watching {}", repo_name),
+        }
+        // a watcher stopped by `stop_watch` may already have been replaced.
+        let mut watchers = WATCHERS.lock().unwrap();
+        if matches!(watchers.get(&repo_name), Some((watched, _)) if watched.id == id) {
+            watchers.remove(&repo_name);
+        }
+    });
+
+    info!("Started watching {}", watched.repo_name);
+    Ok(watched)
+}
+
+/// Stops watching the repo. Returns None if it wasn't watched.
+pub fn stop_watch(repo_name: &str) -> Option<WatchedRepo> {
+    let (watched, abort_handle) = WATCHERS.lock().unwrap().remove(repo_name)?;
+    abort_handle.abort();
+    Some(watched)
+}
+
+/// The repos watched in the background.
+pub fn list_watches() -> Vec<WatchedRepo> {
+    let mut watches = WATCHERS
+        .lock()
+        .unwrap()
+        .values()
+        .map(|(watched, _)| watched.clone())
+        .collect::<Vec<_>>();
+    watches.sort_by(|a, b| a.repo_name.cmp(&b.repo_name));
+    watches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn touched(paths: &[&str]) -> HashSet<String> {
+        paths.iter().map(|path| path.to_string()).collect()
+    }
+
+    #[test]
+    fn test_changes() {
+        let dir = std::env::temp_dir().join(format!("ingestion-watch-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(dir.join("src/old")).unwrap();
+        fs::write(dir.join(".gitignore"), "*.log\n").unwrap();
+        fs::write(dir.join("src/lib.rs"), "pub fn lib() {}\n").unwrap();
+        fs::write(dir.join("src/main.rs"), "fn main() {}\n").unwrap();
+        fs::write(dir.join("src/old/a.rs"), "fn a() {}\n").unwrap();
+
+        let config = Config::new(
+            "repo".to_string(),
+            dir.to_string_lossy().to_string(),
+            String::new(),
+            String::new(),
+            String::new(),
+            "main".to_string(),
+            "v2".to_string(),
+            false,
+        )
+        .with_source(RepoSource::Directory);
+        let mut state = WatchState::read(dir.clone(), config).unwrap();
+        let lib = state.files["src/lib.rs"].clone();
+        let old = state.files["src/old/a.rs"].clone();
+
+        // saved without changes, modified, ignored, new and removed with its directory.
+        fs::write(dir.join("src/main.rs"), "fn main() {}\n").unwrap();
+        fs::write(dir.join("src/lib.rs"), "pub fn lib() { 1 }\n").unwrap();
+        fs::write(dir.join("debug.log"), "trace\n").unwrap();
+        fs::write(dir.join("src/new.rs"), "fn new() {}\n").unwrap();
+        fs::remove_dir_all(dir.join("src/old")).unwrap();
+
+        let (change_set, updates) = state.changes(&touched(&[
+            "src/main.rs",
+            "src/lib.rs",
+            "debug.log",
+            "src/new.rs",
+            "src/old",
+        ]));
+        assert_eq!(change_set.changed, touched(&["src/lib.rs", "src/new.rs"]));
+        assert_eq!(change_set.stale, vec![lib, old]);
+
+        state.apply(updates);
+        assert!(!state.files.contains_key("src/old/a.rs"));
+        // once applied, the same paths don't change anything.
+        let (change_set, _) = state.changes(&touched(&["src/lib.rs", "src/new.rs", "src"]));
+        assert!(change_set.is_empty());
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn test_next_batch() {
+        let (sender, mut events) = mpsc::unbounded_channel();
+        sender.send("a.rs".to_string()).unwrap();
+        sender.send("b.rs".to_string()).unwrap();
+        sender.send("a.rs".to_string()).unwrap();
+
+        let batch = next_batch(&mut events, Duration::from_millis(20)).await;
+        assert_eq!(batch, Some(touched(&["a.rs", "b.rs"])));
+
+        drop(sender);
+        assert_eq!(
+            next_batch(&mut events, Duration::from_millis(20)).await,
+            None
+        );
+    }
+
+    #[test]
+    fn test_event_paths() {
+        let root = Path::new("/repo");
+        let event = Event::new(EventKind::Any)
+            .add_path(PathBuf::from("/repo/src/lib.rs"))
+            .add_path(PathBuf::from("/repo/.git/index"))
+            .add_path(PathBuf::from("/elsewhere/file.rs"));
+        assert_eq!(event_paths(root, &event), vec!["src/lib.rs"]);
+    }
+}
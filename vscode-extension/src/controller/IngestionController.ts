@@ -1,27 +1,127 @@
-// TODO: Update this with actual ingestion process once things are in play
-export const startIngestionProcess = (
+const INGESTION_SERVER_URL = "http://localhost:3001";
+
+export type IngestionRequest = {
+  repoName: string;
+  repoPath: string;
+  branch?: string;
+};
+
+type IngestionStatus = {
+  task_id: string;
+};
+
+type IngestionProgressEvent = {
+  task_id: string;
+  task_status: "Queued" | "Running" | "Completed" | "Failed" | "Cancelled";
+  progress: number;
+  error: string | null;
+};
+
+// Queues the indexing of the repo on the ingestion server and follows its progress.
+// Runs in the extension host, the webviews go through the `startIngestion` view API.
+export const startIngestionProcess = async (
+  request: IngestionRequest,
   onUpdateProgress: (progress: number) => void,
-  onComplete: () => void
+  onComplete: () => void,
+  onError: (error: string) => void
 ) => {
-  simulateIngestionProcess(onUpdateProgress, onComplete);
+  try {
+    const response = await fetch(`${INGESTION_SERVER_URL}/index`, {
+      method: "POST",
+      headers: { "Content-Type": "application/json" },
+      body: JSON.stringify({
+        repo_name: request.repoName,
+        repo_path: request.repoPath,
+        branch: request.branch ?? "main",
+        source: "working_tree",
+      }),
+    });
+    if (!response.ok) {
+      throw new Error(`Indexing request failed: ${await response.text()}`);
+    }
+    const status: IngestionStatus = await response.json();
+
+    await streamIngestionProgress(
+      status.task_id,
+      onUpdateProgress,
+      onComplete,
+      onError
+    );
+  } catch (e: unknown) {
+    onError(e instanceof Error ? e.message : "Indexing failed");
+  }
 };
 
-const simulateIngestionProcess = (
+// Follows the progress of an indexing task through the ingestion server's event stream.
+// `EventSource` doesn't exist in the extension host, so the stream is read with `fetch`.
+// Resolves once the task finished or the stream closed.
+export const streamIngestionProgress = async (
+  taskId: string,
   onUpdateProgress: (progress: number) => void,
-  onComplete: () => void
+  onComplete: () => void,
+  onError: (error: string) => void
 ) => {
-  let progress = 0;
-  const interval = 300;
-  const increment = 100 / (3000 / interval);
-
-  const intervalId = setInterval(() => {
-    progress += increment;
-    if (progress >= 100) {
-      clearInterval(intervalId);
-      onUpdateProgress(100);
+  const response = await fetch(
+    `${INGESTION_SERVER_URL}/status/${taskId}/stream`,
+    { headers: { Accept: "text/event-stream" } }
+  );
+  if (!response.ok || !response.body) {
+    throw new Error(`Progress stream failed: ${await response.text()}`);
+  }
+
+  const reader = response.body.getReader();
+  const decoder = new TextDecoder();
+  let buffer = "";
+
+  // returns true once the task finished.
+  const onEvent = (raw: string) => {
+    const lines = raw.split("\n");
+    const event = lines
+      .find((line) => line.startsWith("event:"))
+      ?.slice("event:".length)
+      .trim();
+    const data = lines
+      .filter((line) => line.startsWith("data:"))
+      .map((line) => line.slice("data:".length).trim())
+      .join("\n");
+    if (event !== "progress" || !data) {
+      return false;
+    }
+
+    const update: IngestionProgressEvent = JSON.parse(data);
+    onUpdateProgress(update.progress);
+
+    if (update.task_status === "Completed") {
       onComplete();
-    } else {
-      onUpdateProgress(progress);
+      return true;
+    } else if (
+      update.task_status === "Failed" ||
+      update.task_status === "Cancelled"
+    ) {
+      onError(update.error ?? `Indexing ${update.task_status.toLowerCase()}`);
+      return true;
+    }
+    return false;
+  };
+
+  for (;;) {
+    const { done, value } = await reader.read();
+    if (done) {
+      onError("Progress stream closed before the indexing finished");
+      return;
+    }
+    buffer += decoder.decode(value, { stream: true }).replace(/\r\n/g, "\n");
+
+    // events are separated by a blank line.
+    let end = buffer.indexOf("\n\n");
+    while (end !== -1) {
+      const raw = buffer.slice(0, end);
+      buffer = buffer.slice(end + 2);
+      if (onEvent(raw)) {
+        await reader.cancel();
+        return;
+      }
+      end = buffer.indexOf("\n\n");
     }
-  }, interval);
+  }
 };
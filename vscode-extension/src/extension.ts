@@ -10,6 +10,19 @@ import {
   ViewEvents,
 } from "./viewApi";
 import fs from "node:fs/promises";
+import path from "node:path";
+import { startIngestionProcess } from "./controller/IngestionController";
+
+// The branch checked out in the repo, undefined if HEAD is detached or it isn't a git repo.
+const currentBranch = async (repoPath: string) => {
+  try {
+    const head = await fs.readFile(path.join(repoPath, ".git", "HEAD"), "utf-8");
+    const match = head.trim().match(/^ref: refs\/heads\/(.+)$/);
+    return match?.[1];
+  } catch {
+    return undefined;
+  }
+};
 
 // This is sort of the gateway to interact with native VS Code APIs n atuff.
 export const activate = async (ctx: vscode.ExtensionContext) => {
@@ -52,6 +65,24 @@ export const activate = async (ctx: vscode.ExtensionContext) => {
     sendMessageToExampleB: (msg: string) => {
       triggerEvent("exampleBMessage", msg);
     },
+    startIngestion: async () => {
+      const folder = vscode.workspace.workspaceFolders?.[0];
+      if (!folder) {
+        throw new Error("Open a folder to index it");
+      }
+
+      // the progress is reported through events, the request doesn't wait for the indexing.
+      void startIngestionProcess(
+        {
+          repoName: folder.name,
+          repoPath: folder.uri.fsPath,
+          branch: await currentBranch(folder.uri.fsPath),
+        },
+        (progress) => triggerEvent("ingestionProgress", progress),
+        () => triggerEvent("ingestionComplete"),
+        (error) => triggerEvent("ingestionFailed", error)
+      );
+    },
   };
 
   const isViewApiRequest = <K extends keyof ViewApi>(
@@ -27,8 +27,12 @@ export type ViewApi = {
   getFileContents: () => Promise<string>;
   showExampleViewB: () => void;
   sendMessageToExampleB: (msg: string) => void;
+  startIngestion: () => Promise<void>;
 };
 
 export type ViewEvents = {
   exampleBMessage: (a: string) => void;
+  ingestionProgress: (progress: number) => void;
+  ingestionComplete: () => void;
+  ingestionFailed: (error: string) => void;
 };
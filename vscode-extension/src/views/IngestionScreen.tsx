@@ -1,11 +1,12 @@
 import React, { useState, useContext, useEffect } from "react";
 import { WebviewContext } from "./WebviewContext";
-import { startIngestionProcess } from "../controller/IngestionController";
 import { PanelState } from "../schema/PanelState";
 
 export const IngestionScreen: React.FC = () => {
-  const { panelState, setPanelState } = useContext(WebviewContext);
+  const { panelState, setPanelState, callApi, addListener, removeListener } =
+    useContext(WebviewContext);
   const [isLoading, setIsLoading] = useState(false);
+  const [error, setError] = useState<string>();
 
   useEffect(() => {
     if (panelState?.ingestion?.status === "inProgress") {
@@ -15,34 +16,64 @@ export const IngestionScreen: React.FC = () => {
     }
   }, [panelState?.ingestion?.status]);
 
-  const handleStartIngestion = () => {
+  useEffect(() => {
+    const onProgress = (progress: number) => {
+      setPanelState((prevState: PanelState) => ({
+        ...prevState,
+        ingestion: {
+          ...(prevState.ingestion ?? {
+            status: "notStarted",
+            indexingProgress: 0,
+          }),
+          indexingProgress: progress,
+        },
+      }));
+    };
+    const onComplete = () => {
+      setPanelState((prevState) => ({
+        ...prevState,
+        ingestion: { indexingProgress: 100, status: "completed" },
+        settings: { view: "chat" },
+      }));
+    };
+    const onFailed = (message: string) => {
+      setError(message);
+      setPanelState((prevState: PanelState) => ({
+        ...prevState,
+        ingestion: {
+          indexingProgress: prevState.ingestion?.indexingProgress ?? 0,
+          status: "failed",
+        },
+      }));
+    };
+    addListener("ingestionProgress", onProgress);
+    addListener("ingestionComplete", onComplete);
+    addListener("ingestionFailed", onFailed);
+
+    return () => {
+      removeListener("ingestionProgress", onProgress);
+      removeListener("ingestionComplete", onComplete);
+      removeListener("ingestionFailed", onFailed);
+    };
+  }, []);
+
+  const handleStartIngestion = async () => {
     setIsLoading(true);
+    setError(undefined);
     setPanelState((prevState: PanelState) => ({
       ...prevState,
       ingestion: { indexingProgress: 0, status: "inProgress" },
     }));
 
-    startIngestionProcess(
-      (progress) => {
-        setPanelState((prevState: PanelState) => ({
-          ...prevState,
-          ingestion: {
-            ...(prevState.ingestion ?? {
-              status: "notStarted",
-              indexingProgress: 0,
-            }),
-            indexingProgress: progress,
-          },
-        }));
-      },
-      () => {
-        setPanelState((prevState) => ({
-          ...prevState,
-          ingestion: { indexingProgress: 100, status: "completed" },
-          settings: { view: "chat" },
-        }));
-      }
-    );
+    try {
+      await callApi("startIngestion");
+    } catch (e: unknown) {
+      setError(e instanceof Error ? e.message : "Indexing failed");
+      setPanelState((prevState: PanelState) => ({
+        ...prevState,
+        ingestion: { indexingProgress: 0, status: "failed" },
+      }));
+    }
   };
 
   return (
@@ -50,6 +81,7 @@ export const IngestionScreen: React.FC = () => {
       <div className="ingestion-component">
         {!isLoading && (
           <div className="start-ingestion">
+            {error && <div className="ingestion-error">{error}</div>}
             <button onClick={handleStartIngestion} disabled={isLoading}>
               Start Ingestion
             </button>